@@ -1,5 +1,6 @@
 #[allow(unused)]
 pub const ADDR_BLK      : usize = 0x10001000;
+pub const ADDR_NET      : usize = 0x10002000;
 #[allow(unused)]
 pub const ADDR_UART     : usize = 0x10000000;
 
@@ -8,6 +9,7 @@ pub const CLOCK_FREQ    : usize = 12_500_000;
 //MMIO
 pub const MMIO: &[(usize, usize)] = &[
     (0x10001000, 0x1000),   //virtio-blk
+    (0x10002000, 0x1000),   //virtio-net
     (0x10000000, 0x1000),   //uart
 ];
 
@@ -23,11 +23,43 @@ pub const MAX_CPU_NUM: usize = 16;
 /* FILE SYSTEM */
 pub const BLOCK_SIZE: usize = 512; 
 pub const BLOCK_CACHE_SIZE: usize = 128;
+pub const PAGE_CACHE_SIZE: usize = 4096;              /* 页缓存最多保留的页数, 脏页和被映射的页不会被淘汰 */
+pub const READAHEAD_MIN_PAGES: usize = 4;             /* 顺序读时的初始预读窗口 */
+pub const READAHEAD_MAX_PAGES: usize = 32;            /* 预读窗口每次加倍, 最大为READAHEAD_MAX_PAGES */
+pub const READAHEAD_BATCH_PAGES: usize = 4;           /* 每次向块设备请求的页数, 对应的块数不应超过BLOCK_CACHE_SIZE / 4 */
 pub const CLUSTER_CACHE_SIZE: usize = 4096;
+pub const DIRTY_EXPIRE_MS: usize = 5000;              /* 脏块和脏页最多在内存中保留的时间, 超过后由后台写回 */
+pub const FLUSH_INTERVAL_MS: usize = 1000;            /* 后台写回检查的间隔 */
 pub const MAX_LINK_RECURSE: usize = 32;
 pub const MAX_FILE_SIZE: usize = 3*1024*1024*1024;
-pub const PIPE_BUFFER_SIZE: usize = 512;
+pub const PIPE_BUFFER_SIZE: usize = 64 * 1024;         /* 管道的默认容量 */
+pub const PIPE_MAX_SIZE: usize = 1024 * 1024;         /* 非特权进程通过F_SETPIPE_SZ能设置的最大容量 */
+pub const PIPE_HARD_MAX_SIZE: usize = 16 * 1024 * 1024;   /* 包括root在内能设置的最大容量 */
+pub const TMPFS_SIZE: usize = 8 * 1024 * 1024;        /* 每个tmpfs挂载点能保存的文件内容总量, 内容在内核堆中 */
+pub const TMPFS_MAX_FILE_SIZE: usize = 4 * 1024 * 1024;   /* tmpfs中单个文件的最大长度 */
+pub const TMPFS_MAX_INODES: usize = 4096;             /* 每个tmpfs挂载点最多的inode数量, 包括根目录 */
+pub const POLL_RECHECK_MS: usize = 10;                /* 等待不会主动唤醒的文件(普通文件等)时重新poll的间隔 */
 pub const SYSLOG_SIZE: usize = 0x1;
+/* FAT32中保存的是本地时间, 只能表示1980-2107年 */
+pub const FAT32_EPOCH_OFFSET: isize = 315_532_800;   /* 内核时间0对应的unix时间, 默认为1980-01-01, 时钟为真实时间时设为0 */
+pub const FAT32_TIMEZONE_OFFSET: isize = 8 * 3600;   /* 本地时间与UTC相差的秒数 */
+
+/* NETWORK */
+/* qemu user network的默认配置 */
+pub const ETH_IP_ADDR: [u8; 4] = [10, 0, 2, 15];
+pub const ETH_PREFIX_LEN: u8 = 24;
+pub const ETH_GATEWAY: [u8; 4] = [10, 0, 2, 2];
+pub const ETH_MTU: usize = 1514;
+pub const TCP_SEND_BUFFER_SIZE: usize = 64 * 1024;
+pub const TCP_RECV_BUFFER_SIZE: usize = 64 * 1024;
+pub const UDP_SEND_BUFFER_SIZE: usize = 64 * 1024;
+pub const UDP_RECV_BUFFER_SIZE: usize = 64 * 1024;
+pub const UDP_METADATA_NUM: usize = 64;
+pub const MAX_LISTEN_BACKLOG: usize = 16;
+
+/* STRACE */
+pub const STRACE_BUFFER_SIZE: usize = 64 * 1024;     /* 每个进程的追踪缓冲区大小 */
+pub const STRACE_MAX_STR_LEN: usize = 64;           /* 字符串参数最多显示的长度 */
 
 //temporary
 pub const MAX_STR_LEN: usize = 512;
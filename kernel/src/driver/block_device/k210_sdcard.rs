@@ -755,12 +755,19 @@ impl SDCardWrapper {
 }
 
 impl SDCardWrapper {
+    pub fn block_size(&self) -> usize {
+        crate::config::BLOCK_SIZE
+    }
     pub fn read_block(&self, block_id: usize, buf: &mut [u8]) {
         self.inner
             .lock()
             .read_sector(buf, block_id as u32)
             .unwrap();
     }
+    /* 多个扇区时read_sector使用CMD18一次读入 */
+    pub fn read_blocks(&self, block_id: usize, buf: &mut [u8]) {
+        self.read_block(block_id, buf)
+    }
     pub fn write_block(&self, block_id: usize, buf: &[u8]) {
         self.inner
             .lock()
@@ -753,12 +753,19 @@ impl SDCardWrapper {
 }
 
 impl SDCardWrapper {
+    pub fn block_size(&self) -> usize {
+        crate::config::BLOCK_SIZE
+    }
     pub fn read_block<'a>(&'a self, block_id: usize, buf: &'a mut [u8]) {
         let lock = &mut *self.inner.lock();
         if let Err(_) = lock.read_sector(buf, (block_id + 0) as u32) {
             panic!("read_block invalid {}", block_id);
         }
     }
+    /* read_sector支持多个扇区 */
+    pub fn read_blocks(&self, block_id: usize, buf: &mut [u8]) {
+        self.read_block(block_id, buf)
+    }
     pub fn write_block<'a>(&'a self, block_id: usize, buf: &'a [u8]) {
         let lock = &mut *self.inner.lock();
         if let Err(_) = lock.write_sector(buf, (block_id + 0) as u32) {
@@ -1,44 +1,198 @@
-use virtio_drivers::{VirtIOBlk, VirtIOHeader};
+use virtio_drivers::VirtIOHeader;
 use spin::Mutex;
 use spin::lazy::Lazy;
 use alloc::vec::Vec;
+use core::ptr::{read_volatile, write_volatile};
+use core::sync::atomic::{fence, Ordering};
 use crate::memory::{PhysAddr, PhysPageNum, frame_alloc, frame_dealloc, 
     FrameTracker, VirtAddr, PageTable, kernel_token};
+use crate::config::PAGE_SIZE;
 use super::DevId;
 
+/* virtio-drivers的VirtIOBlk每个请求只能读写一个扇区, 这里直接驱动virtqueue,
+ * 一个请求可以覆盖多个连续的设备块 */
 pub struct VirtIOBlock {
     pub id: DevId,
-    inner: Mutex<VirtIOBlk<'static>>
+    block_size: usize,
+    inner: Mutex<BlkQueue>
 }
 
 const VIRT_VIRTIO0: usize = 0x10001000;
 
+/* 请求头中的扇区号总以512字节为单位, 与设备块大小无关 */
+const SECTOR_SIZE: usize = 512;
+const VIRTIO_BLK_F_BLK_SIZE: u64 = 1 << 6;
+const VIRTIO_BLK_T_IN: u32 = 0;
+const VIRTIO_BLK_T_OUT: u32 = 1;
+const VIRTIO_BLK_S_OK: u8 = 0;
+/* 配置空间中blk_size字段的偏移 */
+const CONFIG_BLK_SIZE: usize = 20;
+
+/* 每次只有一个请求(头, 数据, 状态三个描述符)在队列中 */
+const QUEUE_SIZE: usize = 4;
+const VIRTQ_DESC_F_NEXT: u16 = 1;
+const VIRTQ_DESC_F_WRITE: u16 = 2;
+
+#[repr(C)]
+struct BlkReq {
+    type_: u32,
+    reserved: u32,
+    sector: u64,
+}
+
+#[repr(C)]
+struct Descriptor {
+    addr: u64,
+    len: u32,
+    flags: u16,
+    next: u16,
+}
+
+#[repr(C)]
+struct AvailRing {
+    flags: u16,
+    idx: u16,
+    ring: [u16; QUEUE_SIZE],
+    used_event: u16,
+}
+
+#[repr(C)]
+struct UsedElem {
+    id: u32,
+    len: u32,
+}
+
+#[repr(C)]
+struct UsedRing {
+    flags: u16,
+    idx: u16,
+    ring: [UsedElem; QUEUE_SIZE],
+    avail_event: u16,
+}
+
+/* legacy virtio-mmio的队列布局: 描述符表和avail环在第一页, used环对齐到下一页 */
+struct BlkQueue {
+    header: &'static mut VirtIOHeader,
+    desc: *mut Descriptor,
+    avail: *mut AvailRing,
+    used: *const UsedRing,
+    last_used: u16,
+}
+
+unsafe impl Send for BlkQueue {}
+
 static QUEUE_FRAMES: Lazy<Mutex<Vec<FrameTracker>>> = Lazy::new(||{Mutex::new(Vec::new())});
 
+fn phys(ptr: usize) -> u64 {
+    virtio_virt_to_phys(VirtAddr(ptr)).0 as u64
+}
+
+impl BlkQueue {
+    /* 同步提交一个请求并等待完成, 内核地址空间恒等映射, 数据缓冲区在物理上连续 */
+    fn request(&mut self, type_: u32, sector: usize, data: usize, len: usize) -> Result<(), u8> {
+        let req = BlkReq { type_, reserved: 0, sector: sector as u64 };
+        let mut status: u8 = 0xff;
+        let data_flags = if type_ == VIRTIO_BLK_T_IN {
+            VIRTQ_DESC_F_NEXT | VIRTQ_DESC_F_WRITE
+        } else {
+            VIRTQ_DESC_F_NEXT
+        };
+        let descs = [
+            (phys(&req as *const _ as usize), core::mem::size_of::<BlkReq>(), VIRTQ_DESC_F_NEXT),
+            (phys(data), len, data_flags),
+            (phys(&mut status as *mut _ as usize), 1, VIRTQ_DESC_F_WRITE),
+        ];
+        unsafe {
+            for (i, &(addr, len, flags)) in descs.iter().enumerate() {
+                write_volatile(self.desc.add(i), Descriptor {
+                    addr,
+                    len: len as u32,
+                    flags,
+                    next: i as u16 + 1,
+                });
+            }
+            let idx = read_volatile(&(*self.avail).idx);
+            write_volatile(&mut (*self.avail).ring[idx as usize % QUEUE_SIZE], 0);
+            fence(Ordering::SeqCst);
+            write_volatile(&mut (*self.avail).idx, idx.wrapping_add(1));
+            fence(Ordering::SeqCst);
+            self.header.notify(0);
+            while read_volatile(&(*self.used).idx) == self.last_used {
+                core::hint::spin_loop();
+            }
+            fence(Ordering::SeqCst);
+            self.last_used = self.last_used.wrapping_add(1);
+            self.header.ack_interrupt();
+            match read_volatile(&status) {
+                VIRTIO_BLK_S_OK => Ok(()),
+                err => Err(err),
+            }
+        }
+    }
+}
+
 impl VirtIOBlock {
+    /* 设备的逻辑块大小, block_id以此为单位 */
+    pub fn block_size(&self) -> usize {
+        self.block_size
+    }
+
     pub fn read_block(&self, block_id: usize, buf: &mut [u8]) {
+        self.read_blocks(block_id, buf)
+    }
+
+    /* 连续的多个块在一个请求中读入 */
+    pub fn read_blocks(&self, block_id: usize, buf: &mut [u8]) {
+        assert!(!buf.is_empty() && buf.len() % self.block_size == 0);
         self.inner
         .lock()
-        .read_block(block_id, buf)
+        .request(VIRTIO_BLK_T_IN, block_id * self.block_size / SECTOR_SIZE, buf.as_mut_ptr() as usize, buf.len())
         .expect("Error when reading VirtIOBlk");
     }
 
     pub fn write_block(&self, block_id: usize, buf: &[u8]) {
+        assert!(!buf.is_empty() && buf.len() % self.block_size == 0);
         self.inner
         .lock()
-        .write_block(block_id, buf)
-        .expect("Error when reading VirtIOBlk");
+        .request(VIRTIO_BLK_T_OUT, block_id * self.block_size / SECTOR_SIZE, buf.as_ptr() as usize, buf.len())
+        .expect("Error when writing VirtIOBlk");
     }
 }
 
 impl VirtIOBlock {
     pub fn new() -> Self {
+        let header = unsafe { &mut *(VIRT_VIRTIO0 as *mut VirtIOHeader) };
+        let mut features = 0;
+        header.begin_init(|supported| {
+            features = supported & VIRTIO_BLK_F_BLK_SIZE;
+            features
+        });
+        let block_size = if features & VIRTIO_BLK_F_BLK_SIZE != 0 {
+            unsafe { read_volatile(header.config_space().add(CONFIG_BLK_SIZE) as *const u32) as usize }
+        } else {
+            SECTOR_SIZE
+        };
+        assert!(block_size.is_power_of_two() && block_size >= SECTOR_SIZE);
+
+        assert!(header.max_queue_size() as usize >= QUEUE_SIZE);
+        let pa = virtio_dma_alloc(2);
+        let base = virtio_phys_to_virt(pa).0;
+        unsafe { core::ptr::write_bytes(base as *mut u8, 0, 2 * PAGE_SIZE) };
+        let ppn: PhysPageNum = pa.into();
+        header.queue_set(0, QUEUE_SIZE as u32, PAGE_SIZE as u32, ppn.0 as u32);
+        header.finish_init();
+
+        let desc = base as *mut Descriptor;
         Self{
             id: DevId::new(),
-            inner: unsafe {
-                Mutex::new(
-                    VirtIOBlk::new(&mut *(VIRT_VIRTIO0 as *mut VirtIOHeader)).unwrap())
-            }
+            block_size,
+            inner: Mutex::new(BlkQueue {
+                header,
+                desc,
+                avail: unsafe { desc.add(QUEUE_SIZE) } as *mut AvailRing,
+                used: (base + PAGE_SIZE) as *const UsedRing,
+                last_used: 0,
+            })
         }
     }
 }
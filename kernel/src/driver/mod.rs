@@ -1,9 +1,11 @@
 pub mod block_device;
+pub mod net_device;
 pub mod serial;
 //pub mod device_tree;
 
 use core::sync::atomic::{AtomicUsize, Ordering};
 pub use block_device::BLOCK_DEVICE;
+pub use net_device::NET_DEVICE;
 
 pub static CURRENT_DEV_ID: AtomicUsize = AtomicUsize::new(0);
 
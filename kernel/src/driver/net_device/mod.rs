@@ -0,0 +1,26 @@
+pub mod virtio_net;
+
+use spin::lazy::Lazy;
+use alloc::sync::Arc;
+use crate::utils::Error;
+
+/// 网卡驱动需要实现的接口，收发的都是完整的以太网帧
+pub trait NetDevice: Send + Sync {
+    fn mac(&self) -> [u8; 6];
+    fn can_send(&self) -> bool;
+    fn can_recv(&self) -> bool;
+    fn send(&self, buf: &[u8]) -> Result<(), Error>;
+    fn recv(&self, buf: &mut [u8]) -> Result<usize, Error>;
+}
+
+/// 只有qemu平台有网卡，探测不到设备时为None
+pub static NET_DEVICE: Lazy<Option<Arc<dyn NetDevice>>> = Lazy::new(||{
+    #[cfg(not(any(feature = "board_k210", feature = "board_sifive")))]
+    {
+        if let Some(device) = virtio_net::VirtIONetDevice::new(crate::board::ADDR_NET) {
+            println!("[kernel] net: find virtio-net device at {:#x}", crate::board::ADDR_NET);
+            return Some(Arc::new(device));
+        }
+    }
+    None
+});
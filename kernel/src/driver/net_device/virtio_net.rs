@@ -0,0 +1,55 @@
+use virtio_drivers::{VirtIONet, VirtIOHeader, DeviceType};
+use spin::Mutex;
+use crate::utils::Error;
+use super::NetDevice;
+use super::super::DevId;
+
+pub struct VirtIONetDevice {
+    pub id: DevId,
+    mac: [u8; 6],
+    inner: Mutex<VirtIONet<'static>>
+}
+
+impl VirtIONetDevice {
+    /// addr处不是virtio-net设备时返回None
+    pub fn new(addr: usize) -> Option<Self> {
+        let header = unsafe { &mut *(addr as *mut VirtIOHeader) };
+        if !header.verify() || header.device_type() != DeviceType::Network {
+            return None;
+        }
+        let net = VirtIONet::new(header).ok()?;
+        Some(Self {
+            id: DevId::new(),
+            mac: net.mac(),
+            inner: Mutex::new(net),
+        })
+    }
+}
+
+impl NetDevice for VirtIONetDevice {
+    fn mac(&self) -> [u8; 6] {
+        self.mac
+    }
+
+    fn can_send(&self) -> bool {
+        self.inner.lock().can_send()
+    }
+
+    fn can_recv(&self) -> bool {
+        self.inner.lock().can_recv()
+    }
+
+    fn send(&self, buf: &[u8]) -> Result<(), Error> {
+        self.inner
+        .lock()
+        .send(buf)
+        .map_err(|_| Error::EIO)
+    }
+
+    fn recv(&self, buf: &mut [u8]) -> Result<usize, Error> {
+        self.inner
+        .lock()
+        .recv(buf)
+        .map_err(|_| Error::EIO)
+    }
+}
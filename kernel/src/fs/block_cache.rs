@@ -1,43 +1,75 @@
 use crate::{
     config::*,
+    timer::get_time_ms,
 };
 use super::{
-    BlockFile
+    File, DeviceFile, BlockFile
 };
 use log::*;
 use spin::{RwLock, Mutex};
 use alloc::sync::Arc;
-use alloc::collections::{VecDeque};  
+use alloc::vec;
+use alloc::vec::Vec;
+use alloc::collections::{VecDeque};
 use spin::lazy::Lazy;
 
+// 块的大小由块设备决定(BlockFile::block_size), 默认为512字节
 pub struct BlockCache {
-    cache: [u8; BLOCK_SIZE],
-    block_id: usize,            
+    cache: Vec<u8>,
+    block_id: usize,
     block_file: Arc<dyn BlockFile>,
     modified: bool,
+    dirty_time: usize,      //第一次被修改的时间(ms), 后台写回时据此判断是否过期
 }
 
 impl BlockCache {
     pub fn new(block_id: usize, block_file: Arc<dyn BlockFile>) -> Self {
-        let mut cache = [0u8; BLOCK_SIZE];
+        let mut cache = vec![0u8; block_file.block_size()];
         block_file.read_block(block_id, &mut cache);
         Self {
             cache,
             block_id,
             block_file,
-            modified: false
+            modified: false,
+            dirty_time: 0,
+        }
+    }
+
+    fn mark_modified(&mut self) {
+        if !self.modified {
+            self.modified = true;
+            self.dirty_time = get_time_ms();
+        }
+    }
+
+    pub fn is_dirty(&self) -> bool {
+        self.modified
+    }
+
+    /* 用已经读入的数据构造, 用于预读 */
+    fn from_data(block_id: usize, block_file: Arc<dyn BlockFile>, cache: Vec<u8>) -> Self {
+        Self {
+            cache,
+            block_id,
+            block_file,
+            modified: false,
+            dirty_time: 0,
         }
     }
 
+    pub fn block_size(&self) -> usize {
+        self.cache.len()
+    }
+
     pub fn get_ref<T>(&self, offset: usize) -> &T where T: Sized {
-        assert!(offset + core::mem::size_of::<T>() <= BLOCK_SIZE);
+        assert!(offset + core::mem::size_of::<T>() <= self.cache.len());
         let addr = &self.cache[offset] as *const u8 as *const T;
         unsafe{&*(addr)}
     }
 
     pub fn get_mut<T>(&mut self, offset: usize) -> &mut T where T: Sized {
-        assert!(offset + core::mem::size_of::<T>() <= BLOCK_SIZE);
-        self.modified = true;
+        assert!(offset + core::mem::size_of::<T>() <= self.cache.len());
+        self.mark_modified();
         let addr = &mut self.cache[offset] as *mut u8 as *mut T;
         unsafe{&mut *(addr)}
     }
@@ -50,11 +82,24 @@ impl BlockCache {
         f(self.get_mut(offset))
     }
 
+    // 将整个块作为T的数组访问, 用于块大小在运行时才能确定的情况
+    pub fn read_slice<T, V>(&self, f: impl FnOnce(&[T]) -> V) -> V {
+        let len = self.cache.len() / core::mem::size_of::<T>();
+        f(unsafe{core::slice::from_raw_parts(self.cache.as_ptr() as *const T, len)})
+    }
+
+    pub fn modify_slice<T, V>(&mut self, f: impl FnOnce(&mut [T]) -> V) -> V {
+        let len = self.cache.len() / core::mem::size_of::<T>();
+        self.mark_modified();
+        f(unsafe{core::slice::from_raw_parts_mut(self.cache.as_mut_ptr() as *mut T, len)})
+    }
+
     #[allow(unused)]
     pub fn debug_print(&self) {
-        info!("print cache: addr = {} - {}", 
-            self.block_id * BLOCK_SIZE, (self.block_id + 1) * BLOCK_SIZE - 1);
-        for i in 0..BLOCK_SIZE {
+        let block_size = self.cache.len();
+        info!("print cache: addr = {} - {}",
+            self.block_id * block_size, (self.block_id + 1) * block_size - 1);
+        for i in 0..block_size {
             if i % 16 == 0 {
                 print!("{:<3x} - {:<3x}: ", i, i+ 15);
             }
@@ -65,7 +110,7 @@ impl BlockCache {
         }
     }
 
-    //sync方法仅仅会在BlockCache被drop时被调用
+    //修改只保存在缓存中, 在被淘汰, fsync/sync或者后台写回时写入块设备
     pub fn sync(&mut self) {
         if self.modified {
             self.modified = false;
@@ -82,8 +127,10 @@ impl Drop for BlockCache {
 }
 
 pub struct BlockCacheManager {
-    //tuple = (block_id, block_dev_id, BlockCache)
-    caches: VecDeque<(usize, usize, Arc<RwLock<BlockCache>>)>,
+    //tuple = (block_id, block_dev_id, block_size, BlockCache)
+    //同一个设备可能以不同的块大小访问(见ResizedBlockFile), 块大小也是键的一部分
+    //读入新块前写回并丢弃其它块大小下与之重叠的块(见invalidate_overlap), 同一段数据不会有两份不一致的缓存
+    caches: VecDeque<(usize, usize, usize, Arc<RwLock<BlockCache>>)>,
 }
 
 impl BlockCacheManager {
@@ -94,38 +141,111 @@ impl BlockCacheManager {
     }
 
     pub fn get_block_cache(
-        &mut self, 
+        &mut self,
         block_id: usize,
         block_file: Arc<dyn BlockFile>,
     ) -> Arc<RwLock<BlockCache>> {
+        let dev_id = block_file.get_id();
+        let block_size = block_file.block_size();
         let find_pair = self
             .caches
             .iter()
-            .find(|pair| pair.0 == block_id && pair.1 == block_file.get_id());
-        
+            .find(|pair| pair.0 == block_id && pair.1 == dev_id && pair.2 == block_size);
+
         if let Some(pair) = find_pair {
-            Arc::clone(&pair.2)
+            Arc::clone(&pair.3)
         } else {
+            self.invalidate_overlap(dev_id, block_size, block_id * block_size, (block_id + 1) * block_size);
             if self.caches.len() == BLOCK_CACHE_SIZE {
-                if let Some((idx, _)) = self
-                .caches
-                .iter()
-                .enumerate()
-                .find(|(_, pair)| Arc::strong_count(&pair.2) == 1) 
-                {
-                    self.caches.drain(idx..=idx);        
-                } else {
-                panic!("Run out of BlockCache");
-                }
+                self.evict();
             }
             let block_cache = Arc::new(RwLock::new(BlockCache::new(
                 block_id,
                 Arc::clone(&block_file)
             )));
-            self.caches.push_back((block_id, block_file.get_id(), Arc::clone(&block_cache)));
+            self.caches.push_back((block_id, dev_id, block_size, Arc::clone(&block_cache)));
             block_cache
         }
     }
+
+    /* 以block_size读入设备上[start, end)字节之前, 处理其它块大小下与之重叠的块:
+     * 没有被使用的块写回后丢弃, 下次访问时重新读入;
+     * 正在使用的块只写回, 持有它的任务可能正在等待BLOCK_CACHE_MANAGER, 不能阻塞等待它的锁 */
+    fn invalidate_overlap(&mut self, dev_id: usize, block_size: usize, start: usize, end: usize) {
+        self.caches.retain(|(block_id, id, size, cache)| {
+            if *id != dev_id || *size == block_size
+            || block_id * size >= end || (block_id + 1) * size <= start {
+                return true;
+            }
+            if Arc::strong_count(cache) == 1 {
+                /* 被丢弃时写回(BlockCache::drop) */
+                return false;
+            }
+            if let Some(mut cache) = cache.try_write() {
+                cache.sync();
+            }
+            true
+        });
+    }
+
+    fn contains(&self, block_id: usize, dev_id: usize, block_size: usize) -> bool {
+        self.caches
+            .iter()
+            .any(|pair| pair.0 == block_id && pair.1 == dev_id && pair.2 == block_size)
+    }
+
+    /* 淘汰一个没有被使用的块, 优先淘汰干净的块, 避免写回 */
+    fn evict(&mut self) {
+        let unused = |pair: &&(usize, usize, usize, Arc<RwLock<BlockCache>>)| {
+            Arc::strong_count(&pair.3) == 1
+        };
+        if let Some((idx, _)) = self
+        .caches
+        .iter()
+        .enumerate()
+        .find(|(_, pair)| unused(pair) && !pair.3.read().is_dirty())
+        .or_else(|| self.caches.iter().enumerate().find(|(_, pair)| unused(pair)))
+        {
+            self.caches.drain(idx..=idx);
+        } else {
+            panic!("Run out of BlockCache");
+        }
+    }
+
+    /// 预读[start, start + count)中不在缓存中的块, 连续缺失的块合并为一次设备请求
+    /// 为了不挤掉其它正在使用的块, 一次最多预读BLOCK_CACHE_SIZE / 4个块
+    pub fn prefetch(&mut self, start: usize, count: usize, block_file: Arc<dyn BlockFile>) {
+        let dev_id = block_file.get_id();
+        let block_size = block_file.block_size();
+        let end = start + count.min(BLOCK_CACHE_SIZE / 4);
+        let mut block_id = start;
+        while block_id < end {
+            if self.contains(block_id, dev_id, block_size) {
+                block_id += 1;
+                continue;
+            }
+            let mut run_end = block_id + 1;
+            while run_end < end && !self.contains(run_end, dev_id, block_size) {
+                run_end += 1;
+            }
+
+            self.invalidate_overlap(dev_id, block_size, block_id * block_size, run_end * block_size);
+            let mut data = vec![0u8; (run_end - block_id) * block_size];
+            block_file.read_blocks(block_id, &mut data);
+            for (i, chunk) in data.chunks(block_size).enumerate() {
+                if self.caches.len() == BLOCK_CACHE_SIZE {
+                    self.evict();
+                }
+                let block_cache = Arc::new(RwLock::new(BlockCache::from_data(
+                    block_id + i,
+                    block_file.clone(),
+                    chunk.to_vec()
+                )));
+                self.caches.push_back((block_id + i, dev_id, block_size, block_cache));
+            }
+            block_id = run_end;
+        }
+    }
 }
 
 pub static BLOCK_CACHE_MANAGER: Lazy<Mutex<BlockCacheManager>> = Lazy::new(||{
@@ -141,11 +261,131 @@ pub fn get_block_cache (
         .get_block_cache(block_id, block_file)
 }
 
-#[allow(unused)]
-pub fn block_cache_sync_all() {
-    let manager = BLOCK_CACHE_MANAGER.lock();
-    for (_, _, cache) in manager.caches.iter() {
+pub fn prefetch_blocks(start: usize, count: usize, block_file: Arc<dyn BlockFile>) {
+    BLOCK_CACHE_MANAGER
+        .lock()
+        .prefetch(start, count, block_file)
+}
+
+/* 当前缓存的块数 */
+pub fn block_cache_num() -> usize {
+    BLOCK_CACHE_MANAGER.lock().caches.len()
+}
+
+/* 当前缓存占用的字节数 */
+pub fn block_cache_bytes() -> usize {
+    BLOCK_CACHE_MANAGER.lock().caches.iter().map(|pair| pair.2).sum()
+}
+
+/// 写回满足filter(dev_id, BlockCache)的脏块
+/// 按照设备和块在设备上的位置排序后写回, 减少寻道; 写回时不持有BLOCK_CACHE_MANAGER的锁
+fn block_cache_sync_with(filter: impl Fn(usize, &BlockCache) -> bool) {
+    let mut dirty: Vec<(usize, usize, Arc<RwLock<BlockCache>>)> = BLOCK_CACHE_MANAGER
+        .lock()
+        .caches
+        .iter()
+        .map(|(block_id, dev_id, block_size, cache)| (*dev_id, block_id * block_size, cache.clone()))
+        .collect();
+    dirty.retain(|(dev_id, _, cache)| {
+        let cache = cache.read();
+        cache.is_dirty() && filter(*dev_id, &cache)
+    });
+    dirty.sort_by_key(|(dev_id, pos, _)| (*dev_id, *pos));
+    for (_, _, cache) in dirty {
         cache.write().sync();
     }
 }
 
+/* 写回所有脏块 */
+pub fn block_cache_sync_all() {
+    block_cache_sync_with(|_, _| true);
+}
+
+/* 写回块设备dev_id上的脏块 */
+pub fn block_cache_sync_dev(dev_id: usize) {
+    block_cache_sync_with(|id, _| id == dev_id);
+}
+
+/* 写回在before之前就被修改的脏块, 用于后台写回 */
+pub fn block_cache_flush_expired(before: usize) {
+    block_cache_sync_with(|_, cache| cache.dirty_time <= before);
+}
+
+/// 以另一种块大小访问块设备, 比如在512字节扇区的设备上使用4K扇区的FAT32镜像
+/// 新的块大小和设备的块大小都必须是2的幂
+pub struct ResizedBlockFile {
+    inner: Arc<dyn BlockFile>,
+    block_size: usize,
+}
+
+impl ResizedBlockFile {
+    pub fn new(inner: Arc<dyn BlockFile>, block_size: usize) -> Arc<Self> {
+        assert!(block_size.is_power_of_two() && inner.block_size().is_power_of_two());
+        Arc::new(Self { inner, block_size })
+    }
+}
+
+impl File for ResizedBlockFile {
+    fn as_file<'a>(self: Arc<Self>) -> Arc<dyn File + 'a> where Self: 'a {
+        self
+    }
+    fn as_block<'a>(self: Arc<Self>) -> Result<Arc<dyn BlockFile + 'a>, crate::utils::Error> where Self: 'a {
+        Ok(self)
+    }
+}
+
+impl DeviceFile for ResizedBlockFile {
+    fn get_id(&self) -> usize {
+        self.inner.get_id()
+    }
+}
+
+impl BlockFile for ResizedBlockFile {
+    fn block_size(&self) -> usize {
+        self.block_size
+    }
+
+    fn read_block(&self, block_id: usize, buf: &mut [u8]) {
+        let dev_size = self.inner.block_size();
+        let start = block_id * self.block_size;
+        if self.block_size >= dev_size {
+            /* 一个块由多个设备块组成 */
+            for (i, chunk) in buf.chunks_mut(dev_size).enumerate() {
+                self.inner.read_block(start / dev_size + i, chunk);
+            }
+        } else {
+            /* 一个设备块包含多个块 */
+            let mut dev_buf = vec![0u8; dev_size];
+            self.inner.read_block(start / dev_size, &mut dev_buf);
+            let offset = start % dev_size;
+            buf.copy_from_slice(&dev_buf[offset..offset + self.block_size]);
+        }
+    }
+
+    fn read_blocks(&self, block_id: usize, buf: &mut [u8]) {
+        let dev_size = self.inner.block_size();
+        if self.block_size >= dev_size {
+            self.inner.read_blocks(block_id * self.block_size / dev_size, buf);
+        } else {
+            for (i, chunk) in buf.chunks_mut(self.block_size).enumerate() {
+                self.read_block(block_id + i, chunk);
+            }
+        }
+    }
+
+    fn write_block(&self, block_id: usize, buf: &[u8]) {
+        let dev_size = self.inner.block_size();
+        let start = block_id * self.block_size;
+        if self.block_size >= dev_size {
+            for (i, chunk) in buf.chunks(dev_size).enumerate() {
+                self.inner.write_block(start / dev_size + i, chunk);
+            }
+        } else {
+            let mut dev_buf = vec![0u8; dev_size];
+            self.inner.read_block(start / dev_size, &mut dev_buf);
+            let offset = start % dev_size;
+            dev_buf[offset..offset + self.block_size].copy_from_slice(buf);
+            self.inner.write_block(start / dev_size, &dev_buf);
+        }
+    }
+}
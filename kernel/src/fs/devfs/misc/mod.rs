@@ -29,7 +29,7 @@ impl File for MiscDir {
     fn read_stat(&self) -> Result<FileStat, Error> {
         let mut fstat: FileStat = Default::default();
         fstat.st_nlink = 1;
-        fstat.st_mode = StMode::DIR as u32;
+        fstat.st_mode = StMode::DIR as u32 | 0o755;
         Ok(fstat)
     }
     fn as_dir<'a>(self: Arc<Self>) -> Result<Arc<dyn DirFile + 'a>, Error> where Self: 'a {
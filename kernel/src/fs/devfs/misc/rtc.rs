@@ -44,7 +44,7 @@ impl File for Rtc {
     fn read_stat(&self) -> Result<crate::fs::FileStat, crate::utils::Error> {
         let mut fstat: FileStat = Default::default();
         fstat.st_nlink = 1;
-        fstat.st_mode = StMode::CHR as u32;
+        fstat.st_mode = StMode::CHR as u32 | 0o644;
         Ok(fstat)
     }
     fn as_device<'a>(self: Arc<Self>) -> Result<Arc<dyn DeviceFile + 'a>, Error> where Self: 'a {
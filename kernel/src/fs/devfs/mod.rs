@@ -3,12 +3,14 @@ pub mod pts;
 pub mod null;
 pub mod zero;
 pub mod misc;
+pub mod shm;
 
 pub use sda2::SDA2;
-pub use pts::PTS;
+pub use pts::{PTS, poll_input};
 pub use null::Null;
 pub use zero::Zero;
 pub use misc::MiscDir;
+pub use shm::ShmDir;
 use super::*;
 use crate::utils::{Error, Path};
 use alloc::{
@@ -25,7 +27,6 @@ pub struct DevFS{
 
 impl DevFS {
     pub fn init(id: FSid, path: Path) -> Arc<Self> {
-        let _result = mknod("/shm".into(), FileType::Directory, FilePerm::NONE);
         Arc::new(
             Self {
                 id,
@@ -36,13 +37,15 @@ impl DevFS {
 }
 
 pub struct DevDir {
+    id: FSid,
     cursor: Mutex<usize>,
     mode: FileOpenMode,
 }
 
 impl DevDir {
-    pub fn new(mode: FileOpenMode) -> Arc<Self> {
+    pub fn new(id: FSid, mode: FileOpenMode) -> Arc<Self> {
         Arc::new(Self {
+            id,
             cursor: Mutex::new(0),
             mode: mode
         })
@@ -57,7 +60,7 @@ impl VFS for DevFS {
         self.mount_path.clone()
     }
     fn root_dir(&self, mode: FileOpenMode) -> Result<Arc<dyn DirFile>, Error> {
-        Ok(DevDir::new(mode).as_dir()?)
+        Ok(DevDir::new(self.id, mode).as_dir()?)
     }
 }
 
@@ -65,7 +68,7 @@ impl File for DevDir {
     fn read_stat(&self) -> Result<FileStat, Error> {
         let mut fstat: FileStat = Default::default();
         fstat.st_nlink = 1;
-        fstat.st_mode = StMode::DIR as u32;
+        fstat.st_mode = StMode::DIR as u32 | 0o755;
         Ok(fstat)
     }
     fn as_dir<'a>(self: Arc<Self>) -> Result<Arc<dyn DirFile + 'a>, Error> where Self: 'a {
@@ -99,8 +102,8 @@ impl DirFile for DevDir {
             "zero" => {
                 Ok(Zero::new(mode).as_file())
             }
-            "shm" => {  
-                open("/shm".into(), FileOpenMode::SYS)
+            "shm" => {
+                Ok(ShmDir::new(self.id).as_file())
             }
             "misc" => {
                 Ok(MiscDir::new(mode).as_file())
@@ -158,6 +161,14 @@ impl DirFile for DevDir {
             }
         );
 
+        dentrys.push(
+            Dentry {
+                d_ino: 0,
+                d_type: FileType::Directory,
+                d_name: String::from("shm"),
+            }
+        );
+
         *cursor = dentrys.len();
         Ok(dentrys)
     }
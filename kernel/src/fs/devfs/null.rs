@@ -1,7 +1,7 @@
 use alloc::sync::Arc;
 use alloc::vec::Vec;
 use super::{FileOpenMode, File, FileStat, StMode, FileIndex, DeviceFile};
-use crate::{utils::Error};
+use crate::utils::{Error, Path};
 use log::*;
 pub struct Null {
     mode: FileOpenMode
@@ -27,6 +27,9 @@ impl File for Null {
     fn get_index(&self) -> Result<FileIndex, Error> {
         Err(Error::EINDEX)
     }
+    fn get_path(&self) -> Result<Path, Error> {
+        Ok("/dev/null".into())
+    }
     fn read(&self, _len : usize) -> Result<Vec<u8>, Error> {
         Err(Error::EINVAL)
     }
@@ -36,7 +39,7 @@ impl File for Null {
     fn read_stat(&self) -> Result<FileStat, Error> {
         let mut fstat: FileStat = Default::default();
         fstat.st_nlink = 1;
-        fstat.st_mode = StMode::CHR as u32;
+        fstat.st_mode = StMode::CHR as u32 | 0o666;
         Ok(fstat)
     }
     fn as_device<'a>(self: Arc<Self>) -> Result<Arc<dyn DeviceFile + 'a>, Error> where Self: 'a {
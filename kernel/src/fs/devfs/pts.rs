@@ -6,8 +6,8 @@ use crate::fs::vfs::FSid;
 use crate::utils::mem_buffer::MemBuffer;
 use crate::{memory::copyout};
 use crate::proc::get_current_user_token;
-use crate::utils::Error;
-use crate::proc::suspend_current;
+use crate::utils::{Error, Path};
+use crate::proc::{wait_on, WaitQueue, Waker};
 use crate::driver::serial::STDIO;
 use super::{FileOpenMode, File, FileStat, CharFile, DeviceFile, StMode};
 use lazy_static::*;
@@ -43,6 +43,29 @@ lazy_static! {
     pub static ref STDIO_BUF: Mutex<VecDeque<u8>> = {
         Mutex::new(VecDeque::new())
     };
+    /* 等待终端输入的任务和epoll监听项 */
+    static ref STDIO_QUEUE: WaitQueue = WaitQueue::new();
+}
+
+/// 把串口上已经到达的字符读入STDIO_BUF, 有新的输入时唤醒等待者
+/// 串口没有中断, 由读者和调度器的空闲循环调用
+pub fn poll_input() {
+    let mut buf = STDIO_BUF.lock();
+    let mut stdio = STDIO.lock();
+    let len = buf.len();
+    loop {
+        let c = stdio.getchar();
+        if c == 0 || c == 255 {
+            break;
+        }
+        buf.push_back(c);
+    }
+    let arrived = buf.len() > len;
+    drop(stdio);
+    drop(buf);
+    if arrived {
+        STDIO_QUEUE.wake_all();
+    }
 }
 
 
@@ -53,21 +76,15 @@ impl File for PTS {
         }
         let mut buf = Vec::new();
         for _ in 0..len {
-            let mut c: u8;
-            loop {
-                if let Some(ch) = STDIO_BUF.lock().pop_front() {
-                    c = ch;
-                    break;
-                }
-                c = STDIO.lock().getchar();
-                if c == 0 || c == 255 {
-                    suspend_current();
-                    continue;
-                } else {
-                    break;
-                }
-            }
-            let ch = c as u8;
+            let ch = match wait_on(&STDIO_QUEUE, || {
+                poll_input();
+                STDIO_BUF.lock().pop_front().map(Ok)
+            }) {
+                Ok(ch) => ch,
+                /* 已经读到的字符不能丢弃 */
+                Err(Error::EINTR) if !buf.is_empty() => break,
+                Err(err) => return Err(err),
+            };
             if ch == b'\n' || ch == b'\r' {
                 buf.push(b'\n');
                 break
@@ -110,10 +127,13 @@ impl File for PTS {
     fn get_index(&self) -> Result<FileIndex, Error> {
         Ok(FileIndex(FSid(0), Fileid(0)))
     }
+    fn get_path(&self) -> Result<Path, Error> {
+        Ok("/dev/tty".into())
+    }
     fn read_stat(&self) -> Result<FileStat, Error> {
         let mut fstat: FileStat = Default::default();
         fstat.st_nlink = 1;
-        fstat.st_mode = StMode::CHR as u32;
+        fstat.st_mode = StMode::CHR as u32 | 0o666;
         Ok(fstat)
     }
     fn as_dir<'a>(self: Arc<Self>) -> Result<Arc<dyn crate::fs::DirFile + 'a>, Error> where Self: 'a {
@@ -122,23 +142,21 @@ impl File for PTS {
     fn poll(&self, ptype: PollType) -> Result<bool, Error> {
         let ret = match ptype {
             PollType::READ => {
-                let mut buf = STDIO_BUF.lock();
-                if !buf.is_empty() {
-                    true;
-                }
-                let c = STDIO.lock().getchar(); 
-                if c == 0 || c == 255 {
-                    false
-                } else {
-                    buf.push_back(c);
-                    true
-                }
+                poll_input();
+                !STDIO_BUF.lock().is_empty()
             },
             PollType::WRITE => true,
-            PollType::ERR => false
+            PollType::ERR | PollType::HUP => false
         };
         Ok(ret)
     }
+    fn register_waker(&self, waker: &Arc<dyn Waker>) -> bool {
+        STDIO_QUEUE.register(waker);
+        true
+    }
+    fn unregister_waker(&self, waker: &Arc<dyn Waker>) {
+        STDIO_QUEUE.unregister(waker);
+    }
 }
 
 impl DeviceFile for PTS {
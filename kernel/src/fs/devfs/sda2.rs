@@ -44,7 +44,7 @@ impl File for SDA2 {
     fn read_stat(&self) -> Result<FileStat, Error> {
         let mut fstat: FileStat = Default::default();
         fstat.st_nlink = 1;
-        fstat.st_mode = StMode::BLK as u32;
+        fstat.st_mode = StMode::BLK as u32 | 0o660;
         Ok(fstat)
     }
     fn as_dir<'a>(self: Arc<Self>) -> Result<Arc<dyn crate::fs::DirFile + 'a>, Error> where Self: 'a {
@@ -63,6 +63,9 @@ impl DeviceFile for SDA2 {
 }
 
 impl BlockFile for SDA2 {
+    fn block_size(&self) -> usize {
+        BLOCK_DEVICE.block_size()
+    }
     fn read_block(&self, block_id: usize, buf: &mut [u8]) {
         if self.readable() {
             BLOCK_DEVICE.read_block(block_id, buf);
@@ -70,6 +73,13 @@ impl BlockFile for SDA2 {
             warn!("read_block: read fail");
         }
     }
+    fn read_blocks(&self, block_id: usize, buf: &mut [u8]) {
+        if self.readable() {
+            BLOCK_DEVICE.read_blocks(block_id, buf);
+        } else {
+            warn!("read_blocks: read fail");
+        }
+    }
     fn write_block(&self, block_id: usize, buf: &[u8]) {
         if self.writable() {
             BLOCK_DEVICE.write_block(block_id, buf);
@@ -0,0 +1,46 @@
+use alloc::{string::String, sync::Arc, vec::Vec};
+use super::{FileOpenMode, File, DirFile, FileStat, StMode, FileIndex, Fileid, Dentry, SeekMode};
+use crate::fs::vfs::FSid;
+use crate::utils::Error;
+
+/// /dev/shm: 只作为tmpfs的挂载点, 本身是一个空目录
+pub struct ShmDir {
+    id: FSid,
+}
+
+impl ShmDir {
+    pub fn new(id: FSid) -> Arc<Self> {
+        Arc::new(Self { id })
+    }
+}
+
+impl File for ShmDir {
+    fn read_stat(&self) -> Result<FileStat, Error> {
+        let mut fstat: FileStat = Default::default();
+        fstat.st_nlink = 2;
+        fstat.st_mode = StMode::DIR as u32 | 0o1777;
+        Ok(fstat)
+    }
+    fn as_dir<'a>(self: Arc<Self>) -> Result<Arc<dyn DirFile + 'a>, Error> where Self: 'a {
+        Ok(self)
+    }
+    fn as_file<'a>(self: Arc<Self>) -> Arc<dyn File + 'a> where Self: 'a {
+        self
+    }
+    /* 挂载表以FileIndex为键, 需要与devfs中其它文件区分 */
+    fn get_index(&self) -> Result<FileIndex, Error> {
+        Ok(FileIndex(self.id, Fileid(1)))
+    }
+    fn seek(&self, _pos : usize, _mode: SeekMode) -> Result<isize, Error> {
+        Ok(_pos as isize)
+    }
+}
+
+impl DirFile for ShmDir {
+    fn openat(&self, _name: String, _mode: FileOpenMode) -> Result<Arc<dyn File>, Error> {
+        Err(Error::ENOENT)
+    }
+    fn getdent(&self) -> Result<Vec<Dentry>, Error> {
+        Ok(Vec::new())
+    }
+}
@@ -2,7 +2,7 @@ use alloc::{sync::Arc, vec::Vec};
 
 use crate::{
     fs::{File, FileOpenMode, FileIndex, FileStat, StMode, DirFile, DeviceFile},
-    utils::Error,
+    utils::{Error, Path},
 };
 
 pub struct Zero {
@@ -34,6 +34,9 @@ impl File for Zero {
     fn get_index(&self) -> Result<FileIndex, Error> {
         Err(Error::EINDEX)
     }
+    fn get_path(&self) -> Result<Path, Error> {
+        Ok("/dev/zero".into())
+    }
     fn read(&self, len : usize) -> Result<Vec<u8>, Error> {
         let mut zero = Vec::with_capacity(len);
         for _ in 0..len {
@@ -49,7 +52,7 @@ impl File for Zero {
     fn read_stat(&self) -> Result<FileStat, Error> {
         let mut fstat: FileStat = Default::default();
         fstat.st_nlink = 1;
-        fstat.st_mode = StMode::CHR as u32;
+        fstat.st_mode = StMode::CHR as u32 | 0o666;
         Ok(fstat)
     }
 }
@@ -0,0 +1,348 @@
+use alloc::{
+    collections::BTreeMap,
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use core::sync::atomic::{AtomicBool, Ordering};
+use spin::Mutex;
+
+use super::{File, FileIndex, FileStat, FileType, SeekMode};
+use super::file::PollType;
+use crate::config::POLL_RECHECK_MS;
+use crate::proc::{get_current_task, TaskWaker, WaitQueue, Waker};
+use crate::syscall::time::Timespec;
+use crate::utils::Error;
+
+pub const EPOLLIN: u32 = 0x001;
+pub const EPOLLPRI: u32 = 0x002;
+pub const EPOLLOUT: u32 = 0x004;
+pub const EPOLLERR: u32 = 0x008;
+pub const EPOLLHUP: u32 = 0x010;
+pub const EPOLLRDNORM: u32 = 0x040;
+pub const EPOLLWRNORM: u32 = 0x100;
+pub const EPOLLRDHUP: u32 = 0x2000;
+pub const EPOLLONESHOT: u32 = 1 << 30;
+pub const EPOLLET: u32 = 1 << 31;
+
+pub const EPOLL_CTL_ADD: usize = 1;
+pub const EPOLL_CTL_DEL: usize = 2;
+pub const EPOLL_CTL_MOD: usize = 3;
+
+/* riscv上epoll_event不是packed的, 大小为16字节 */
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct EpollEvent {
+    pub events: u32,
+    pub data: u64,
+}
+
+/// 监听项注册在文件等待队列上的waker
+/// 文件状态变化时记录一次边沿, 并唤醒在epoll上等待的任务
+struct ItemWaker {
+    ready: AtomicBool,
+    epoll: Weak<Epoll>,
+}
+
+impl Waker for ItemWaker {
+    fn wake(&self) {
+        self.ready.store(true, Ordering::Release);
+        if let Some(epoll) = self.epoll.upgrade() {
+            epoll.queue.wake_all();
+        }
+    }
+}
+
+struct EpollItem {
+    /* 不持有文件的引用, 文件被关闭后监听项自动删除 */
+    file: Weak<dyn File>,
+    events: u32,
+    data: u64,
+    waker: Arc<ItemWaker>,
+    /* 文件是否会主动唤醒, 否则等待时需要定时重新poll */
+    wakeable: bool,
+    /* EPOLLONESHOT报告一次后禁用, 直到EPOLL_CTL_MOD重新启用 */
+    disabled: bool,
+    /* 上次检查时的就绪事件, 用于不会主动唤醒的文件判断边沿 */
+    last: u32,
+}
+
+impl EpollItem {
+    /* 返回需要报告的事件, consume为false时只查询, 不改变边沿触发的状态 */
+    fn check(&mut self, file: &Arc<dyn File>, consume: bool) -> Result<u32, Error> {
+        if self.disabled {
+            return Ok(0);
+        }
+        let edge = match consume {
+            true => self.waker.ready.swap(false, Ordering::AcqRel),
+            false => self.waker.ready.load(Ordering::Acquire),
+        };
+        let revents = poll_events(file, self.events)?;
+        if self.events & EPOLLET == 0 {
+            return Ok(revents);
+        }
+        let rising = revents & !self.last != 0;
+        if consume {
+            self.last = revents;
+        }
+        if edge || (!self.wakeable && rising) {
+            Ok(revents)
+        } else {
+            Ok(0)
+        }
+    }
+}
+
+/* 通过File::poll查询events中关心的事件, EPOLLERR和EPOLLHUP总是报告; poll的事件位与epoll相同, ppoll也使用 */
+pub fn poll_events(file: &Arc<dyn File>, events: u32) -> Result<u32, Error> {
+    let mut revents = 0;
+    if events & (EPOLLIN | EPOLLRDNORM) != 0 && file.poll(PollType::READ)? {
+        revents |= events & (EPOLLIN | EPOLLRDNORM);
+    }
+    if events & (EPOLLOUT | EPOLLWRNORM) != 0 && file.poll(PollType::WRITE)? {
+        revents |= events & (EPOLLOUT | EPOLLWRNORM);
+    }
+    if file.poll(PollType::ERR)? {
+        revents |= EPOLLERR;
+    }
+    if file.poll(PollType::HUP)? {
+        revents |= EPOLLHUP;
+    }
+    Ok(revents)
+}
+
+/// epoll实例, 以(fd, 文件对象地址)标识监听项, 与linux一样同一文件的不同fd可以分别监听
+/// 1, 监听的文件支持register_waker时, 状态变化通过ItemWaker唤醒等待者
+/// 2, 不支持唤醒的文件(普通文件等)在等待时每POLL_RECHECK_MS重新poll
+/// 3, 水平触发每次都poll文件, 边沿触发只在文件被唤醒(或poll结果新出现了事件)后报告
+pub struct Epoll {
+    me: Weak<Epoll>,
+    items: Mutex<BTreeMap<(usize, usize), EpollItem>>,
+    /* 有监听项可能就绪时唤醒epoll_wait和监听本epoll的等待者 */
+    queue: WaitQueue,
+}
+
+impl Epoll {
+    pub fn new() -> Arc<Self> {
+        Arc::new_cyclic(|me| Self {
+            me: me.clone(),
+            items: Mutex::new(BTreeMap::new()),
+            queue: WaitQueue::new(),
+        })
+    }
+
+    fn key(fd: usize, file: &Arc<dyn File>) -> (usize, usize) {
+        (fd, Arc::as_ptr(file) as *const () as usize)
+    }
+
+    /* 从self出发能否到达target, 用于拒绝形成环的嵌套 */
+    fn reaches(&self, target: &Epoll) -> bool {
+        let files: Vec<Arc<dyn File>> = self.items
+            .lock()
+            .values()
+            .filter_map(|item| item.file.upgrade())
+            .collect();
+        files.into_iter().any(|file| match file.as_epoll() {
+            Ok(epoll) => core::ptr::eq(epoll.as_ref(), target) || epoll.reaches(target),
+            Err(_) => false,
+        })
+    }
+
+    pub fn ctl(&self, op: usize, fd: usize, file: Arc<dyn File>, event: EpollEvent) -> Result<(), Error> {
+        if let Ok(epoll) = file.clone().as_epoll() {
+            if core::ptr::eq(epoll.as_ref(), self) || epoll.reaches(self) {
+                return Err(Error::ELOOP);
+            }
+        }
+        let key = Self::key(fd, &file);
+        let events = event.events | EPOLLERR | EPOLLHUP;
+        let mut items = self.items.lock();
+        items.retain(|_, item| item.file.strong_count() > 0);
+        match op {
+            EPOLL_CTL_ADD => {
+                if items.contains_key(&key) {
+                    return Err(Error::EEXIST);
+                }
+                /* 加入时文件可能已经就绪, 边沿触发也要报告一次 */
+                let waker = Arc::new(ItemWaker {
+                    ready: AtomicBool::new(true),
+                    epoll: self.me.clone(),
+                });
+                let dyn_waker: Arc<dyn Waker> = waker.clone();
+                let wakeable = file.register_waker(&dyn_waker);
+                items.insert(key, EpollItem {
+                    file: Arc::downgrade(&file),
+                    events,
+                    data: event.data,
+                    waker,
+                    wakeable,
+                    disabled: false,
+                    last: 0,
+                });
+            }
+            EPOLL_CTL_MOD => {
+                let item = items.get_mut(&key).ok_or(Error::ENOENT)?;
+                item.events = events;
+                item.data = event.data;
+                item.disabled = false;
+                item.last = 0;
+                item.waker.ready.store(true, Ordering::Release);
+            }
+            EPOLL_CTL_DEL => {
+                let item = items.remove(&key).ok_or(Error::ENOENT)?;
+                let dyn_waker: Arc<dyn Waker> = item.waker;
+                file.unregister_waker(&dyn_waker);
+            }
+            _ => return Err(Error::EINVAL),
+        }
+        drop(items);
+        self.queue.wake_all();
+        Ok(())
+    }
+
+    /* 收集最多max个就绪事件, 文件已经关闭的监听项被删除 */
+    fn collect(&self, max: usize) -> Result<Vec<EpollEvent>, Error> {
+        let mut items = self.items.lock();
+        items.retain(|_, item| item.file.strong_count() > 0);
+        let mut events = Vec::new();
+        for item in items.values_mut() {
+            if events.len() >= max {
+                break;
+            }
+            let file = match item.file.upgrade() {
+                Some(file) => file,
+                None => continue,
+            };
+            let revents = item.check(&file, true)?;
+            if revents == 0 {
+                continue;
+            }
+            events.push(EpollEvent { events: revents, data: item.data });
+            if item.events & EPOLLONESHOT != 0 {
+                item.disabled = true;
+            }
+        }
+        Ok(events)
+    }
+
+    /* 文件能否主动唤醒可能变化(嵌套的epoll之后加入了不能唤醒的文件), 每次都重新注册并检查 */
+    fn need_recheck(&self) -> bool {
+        let mut items = self.items.lock();
+        let mut recheck = false;
+        for item in items.values_mut() {
+            if let Some(file) = item.file.upgrade() {
+                let dyn_waker: Arc<dyn Waker> = item.waker.clone();
+                item.wakeable = file.register_waker(&dyn_waker);
+            }
+            recheck |= !item.wakeable && !item.disabled;
+        }
+        recheck
+    }
+
+    /// 等待至少一个事件就绪, deadline为None时一直等待, 被信号中断时返回EINTR
+    pub fn wait(&self, max: usize, deadline: Option<Timespec>) -> Result<Vec<EpollEvent>, Error> {
+        let waker = TaskWaker::new(deadline);
+        let dyn_waker = waker.as_waker();
+        self.queue.register(&dyn_waker);
+        let ret = loop {
+            let events = match self.collect(max) {
+                Ok(events) => events,
+                Err(err) => break Err(err),
+            };
+            if !events.is_empty() || deadline.map_or(false, |deadline| deadline.pass()) {
+                break Ok(events);
+            }
+            if get_current_task().unwrap().has_signal() {
+                break Err(Error::EINTR);
+            }
+            let tick = match self.need_recheck() {
+                true => Some(Timespec::now() + Timespec::from_ms(POLL_RECHECK_MS)),
+                false => None,
+            };
+            waker.sleep(tick);
+        };
+        self.queue.unregister(&dyn_waker);
+        ret
+    }
+}
+
+impl File for Epoll {
+    fn close(&self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn get_index(&self) -> Result<FileIndex, Error> {
+        Err(Error::EINDEX)
+    }
+
+    fn read(&self, _len: usize) -> Result<Vec<u8>, Error> {
+        Err(Error::EINVAL)
+    }
+
+    fn write(&self, _data: Vec<u8>) -> Result<usize, Error> {
+        Err(Error::EINVAL)
+    }
+
+    fn readable(&self) -> bool {
+        false
+    }
+
+    fn writable(&self) -> bool {
+        false
+    }
+
+    fn seek(&self, _pos: usize, _mode: SeekMode) -> Result<isize, Error> {
+        Err(Error::ESPIPE)
+    }
+
+    fn get_size(&self) -> Result<usize, Error> {
+        Ok(0)
+    }
+
+    fn get_type(&self) -> Result<FileType, Error> {
+        Ok(FileType::UNKNOWN)
+    }
+
+    fn read_stat(&self) -> Result<FileStat, Error> {
+        let mut fstat: FileStat = Default::default();
+        fstat.st_nlink = 1;
+        fstat.st_mode = 0o600;
+        Ok(fstat)
+    }
+
+    /* 有监听项就绪时可读, 可以被其他epoll, ppoll监听 */
+    fn poll(&self, ptype: PollType) -> Result<bool, Error> {
+        if !matches!(ptype, PollType::READ) {
+            return Ok(false);
+        }
+        let mut items = self.items.lock();
+        for item in items.values_mut() {
+            if let Some(file) = item.file.upgrade() {
+                if item.check(&file, false)? != 0 {
+                    return Ok(true);
+                }
+            }
+        }
+        Ok(false)
+    }
+
+    fn register_waker(&self, waker: &Arc<dyn Waker>) -> bool {
+        self.queue.register(waker);
+        !self.need_recheck()
+    }
+
+    fn unregister_waker(&self, waker: &Arc<dyn Waker>) {
+        self.queue.unregister(waker);
+    }
+
+    fn as_epoll(self: Arc<Self>) -> Result<Arc<Epoll>, Error> {
+        Ok(self)
+    }
+
+    fn as_file<'a>(self: Arc<Self>) -> Arc<dyn File + 'a> where Self: 'a {
+        self
+    }
+
+    fn as_any<'a>(self: Arc<Self>) -> Arc<dyn core::any::Any + Send + Sync + 'a> where Self: 'a {
+        self
+    }
+}
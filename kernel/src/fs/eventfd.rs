@@ -0,0 +1,149 @@
+use alloc::{sync::Arc, vec::Vec};
+use core::sync::atomic::{AtomicBool, Ordering};
+use spin::Mutex;
+
+use super::{File, FileIndex, FileStat, FileType, SeekMode};
+use super::file::PollType;
+use crate::proc::{wait_on, WaitQueue, Waker};
+use crate::utils::Error;
+
+pub const EFD_SEMAPHORE: usize = 1;
+pub const EFD_NONBLOCK: usize = 0o4000;
+pub const EFD_CLOEXEC: usize = 0o2000000;
+
+/* 计数器的最大值, 写入后超过该值时阻塞 */
+const EVENTFD_MAX: u64 = u64::MAX - 1;
+
+/// eventfd: 内核中的一个u64计数器, 读写都是8字节
+/// 1, write把值加到计数器上, read读出并清零, EFD_SEMAPHORE时每次读出1并减1
+/// 2, 计数器为0时read阻塞, 加上写入的值会溢出时write阻塞
+pub struct EventFd {
+    count: Mutex<u64>,
+    semaphore: bool,
+    nonblock: AtomicBool,
+    queue: WaitQueue,
+}
+
+impl EventFd {
+    pub fn new(initval: u32, flags: usize) -> Arc<Self> {
+        Arc::new(Self {
+            count: Mutex::new(initval as u64),
+            semaphore: flags & EFD_SEMAPHORE != 0,
+            nonblock: AtomicBool::new(flags & EFD_NONBLOCK != 0),
+            queue: WaitQueue::new(),
+        })
+    }
+
+    fn block_on<T>(&self, mut f: impl FnMut() -> Option<Result<T, Error>>) -> Result<T, Error> {
+        if self.nonblock.load(Ordering::Relaxed) {
+            return f().unwrap_or(Err(Error::EAGAIN));
+        }
+        wait_on(&self.queue, f)
+    }
+}
+
+impl File for EventFd {
+    fn close(&self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn get_index(&self) -> Result<FileIndex, Error> {
+        Err(Error::EINDEX)
+    }
+
+    fn read(&self, len: usize) -> Result<Vec<u8>, Error> {
+        if len < 8 {
+            return Err(Error::EINVAL);
+        }
+        let value = self.block_on(|| {
+            let mut count = self.count.lock();
+            match *count {
+                0 => None,
+                _ if self.semaphore => {
+                    *count -= 1;
+                    Some(Ok(1))
+                }
+                value => {
+                    *count = 0;
+                    Some(Ok(value))
+                }
+            }
+        })?;
+        self.queue.wake_all();
+        Ok(value.to_ne_bytes().to_vec())
+    }
+
+    fn write(&self, data: Vec<u8>) -> Result<usize, Error> {
+        if data.len() < 8 {
+            return Err(Error::EINVAL);
+        }
+        let value = u64::from_ne_bytes(data[..8].try_into().unwrap());
+        if value == u64::MAX {
+            return Err(Error::EINVAL);
+        }
+        self.block_on(|| {
+            let mut count = self.count.lock();
+            if EVENTFD_MAX - *count < value {
+                return None;
+            }
+            *count += value;
+            Some(Ok(()))
+        })?;
+        self.queue.wake_all();
+        Ok(8)
+    }
+
+    fn readable(&self) -> bool {
+        true
+    }
+
+    fn writable(&self) -> bool {
+        true
+    }
+
+    fn seek(&self, _pos: usize, _mode: SeekMode) -> Result<isize, Error> {
+        Err(Error::ESPIPE)
+    }
+
+    fn get_size(&self) -> Result<usize, Error> {
+        Ok(0)
+    }
+
+    fn get_type(&self) -> Result<FileType, Error> {
+        Ok(FileType::UNKNOWN)
+    }
+
+    fn read_stat(&self) -> Result<FileStat, Error> {
+        let mut fstat: FileStat = Default::default();
+        fstat.st_nlink = 1;
+        fstat.st_mode = 0o600;
+        Ok(fstat)
+    }
+
+    fn poll(&self, ptype: PollType) -> Result<bool, Error> {
+        let count = *self.count.lock();
+        let ret = match ptype {
+            PollType::READ => count > 0,
+            PollType::WRITE => count < EVENTFD_MAX,
+            PollType::ERR | PollType::HUP => false,
+        };
+        Ok(ret)
+    }
+
+    fn register_waker(&self, waker: &Arc<dyn Waker>) -> bool {
+        self.queue.register(waker);
+        true
+    }
+
+    fn unregister_waker(&self, waker: &Arc<dyn Waker>) {
+        self.queue.unregister(waker);
+    }
+
+    fn as_file<'a>(self: Arc<Self>) -> Arc<dyn File + 'a> where Self: 'a {
+        self
+    }
+
+    fn as_any<'a>(self: Arc<Self>) -> Arc<dyn core::any::Any + Send + Sync + 'a> where Self: 'a {
+        self
+    }
+}
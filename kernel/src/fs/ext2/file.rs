@@ -0,0 +1,306 @@
+use alloc::{string::String, sync::Arc, vec, vec::Vec};
+use core::any::Any;
+use spin::Mutex;
+use log::*;
+
+use crate::fs::{
+    FileOpenMode, DirFile, File, LinkFile, FileStat, FilePerm, FileType, SeekMode,
+    FileIndex, Fileid, Dentry, PollType, resolve_link};
+use crate::fs::vfs::VFS;
+use crate::utils::{Error, Path};
+use super::inode::{Ext2Inode, mode_to_file_type, file_type_to_mode};
+use super::layout::*;
+
+pub struct Ext2File {
+    pub inode: Arc<Ext2Inode>,
+    pub mode: FileOpenMode,
+    pub path: Path,             // 打开时的绝对路径, 用于/proc/<pid>/fd和解析相对路径的软连接
+    cursor: Mutex<usize>,
+}
+
+impl Ext2File {
+    pub fn new(inode: Arc<Ext2Inode>, mode: FileOpenMode, path: Path) -> Arc<Self> {
+        Arc::new(Self {
+            inode,
+            mode,
+            path,
+            cursor: Mutex::new(0),
+        })
+    }
+
+    fn child_path(&self, name: &str) -> Path {
+        let mut path = self.path.clone();
+        path.push_back(String::from(name));
+        path
+    }
+
+    fn remove_dir(&self, name: &str, child: &Arc<Ext2Inode>) -> Result<(), Error> {
+        if !child.is_empty_dir()? {
+            return Err(Error::ENOTEMPTY);
+        }
+        self.inode.remove_entry(name)?;
+        child.clear_links();
+        /* 子目录的".."不再指向当前目录 */
+        self.inode.dec_links();
+        Ok(())
+    }
+}
+
+impl File for Ext2File {
+    fn get_index(&self) -> Result<FileIndex, Error> {
+        Ok(FileIndex(self.inode.fs.id, Fileid(self.inode.ino as usize)))
+    }
+
+    fn read(&self, len: usize) -> Result<Vec<u8>, Error> {
+        if !self.readable() {
+            return Err(Error::EBADF);
+        }
+        match self.inode.file_type() {
+            FileType::RegularFile => {},
+            FileType::Directory => return Err(Error::EISDIR),
+            _ => return Err(Error::EINVAL),
+        }
+        let mut cursor = self.cursor.lock();
+        let mut buf = vec![0u8; len];
+        let read = self.inode.read_at(*cursor, &mut buf)?;
+        buf.truncate(read);
+        *cursor += read;
+        Ok(buf)
+    }
+
+    fn write(&self, data: Vec<u8>) -> Result<usize, Error> {
+        if !self.writable() {
+            return Err(Error::EBADF);
+        }
+        match self.inode.file_type() {
+            FileType::RegularFile => {},
+            FileType::Directory => return Err(Error::EISDIR),
+            _ => return Err(Error::EINVAL),
+        }
+        let mut cursor = self.cursor.lock();
+        if self.mode.contains(FileOpenMode::APPEND) {
+            *cursor = self.inode.size();
+        }
+        let written = self.inode.write_at(*cursor, &data)?;
+        *cursor += written;
+        Ok(written)
+    }
+
+    fn readable(&self) -> bool {
+        !self.mode.contains(FileOpenMode::WRITE)
+    }
+
+    fn writable(&self) -> bool {
+        self.mode.contains(FileOpenMode::WRITE) || self.mode.contains(FileOpenMode::RDWR)
+        || self.mode.contains(FileOpenMode::SYS)
+    }
+
+    fn seek(&self, pos: usize, mode: SeekMode) -> Result<isize, Error> {
+        let mut cursor = self.cursor.lock();
+        let new = match mode {
+            SeekMode::SET => pos as isize,
+            SeekMode::CUR => *cursor as isize + pos as isize,
+            SeekMode::END => self.inode.size() as isize + pos as isize,
+        };
+        if new < 0 {
+            return Err(Error::EINVAL);
+        }
+        *cursor = new as usize;
+        Ok(new)
+    }
+
+    fn get_size(&self) -> Result<usize, Error> {
+        Ok(self.inode.size())
+    }
+
+    fn get_name(&self) -> Result<String, Error> {
+        match self.path.is_root() {
+            true => Ok(String::from("/")),
+            false => Ok(self.path.last().clone()),
+        }
+    }
+
+    fn get_path(&self) -> Result<Path, Error> {
+        Ok(self.path.clone())
+    }
+
+    fn get_type(&self) -> Result<FileType, Error> {
+        Ok(self.inode.file_type())
+    }
+
+    fn read_stat(&self) -> Result<FileStat, Error> {
+        Ok(self.inode.stat(self.inode.fs.id.0 as u64))
+    }
+
+    fn write_stat(&self, stat: &FileStat) -> Result<(), Error> {
+        self.inode.set_stat(stat);
+        Ok(())
+    }
+
+    fn poll(&self, ptype: PollType) -> Result<bool, Error> {
+        match ptype {
+            PollType::READ => Ok(self.readable()),
+            PollType::WRITE => Ok(self.writable()),
+            PollType::ERR | PollType::HUP => Ok(false),
+        }
+    }
+
+    fn copy(&self) -> Arc<dyn File> {
+        let file = Ext2File::new(self.inode.clone(), self.mode, self.path.clone());
+        *file.cursor.lock() = *self.cursor.lock();
+        file
+    }
+
+    fn vfs(&self) -> Arc<dyn VFS> {
+        self.inode.fs.clone()
+    }
+
+    fn fsync(&self) -> Result<(), Error> {
+        self.inode.fs.sync()
+    }
+
+    fn as_dir<'a>(self: Arc<Self>) -> Result<Arc<dyn DirFile + 'a>, Error> where Self: 'a {
+        match self.inode.is_dir() {
+            true => Ok(self),
+            false => Err(Error::ENOTDIR),
+        }
+    }
+
+    fn as_link<'a>(self: Arc<Self>) -> Result<Arc<dyn LinkFile + 'a>, Error> where Self: 'a {
+        match self.inode.file_type() {
+            FileType::LinkFile => Ok(self),
+            _ => Err(Error::EPERM),
+        }
+    }
+
+    fn as_file<'a>(self: Arc<Self>) -> Arc<dyn File + 'a> where Self: 'a {
+        self
+    }
+
+    fn as_any<'a>(self: Arc<Self>) -> Arc<dyn Any + Send + Sync + 'a> where Self: 'a {
+        self
+    }
+}
+
+impl DirFile for Ext2File {
+    fn openat(&self, name: String, mode: FileOpenMode) -> Result<Arc<dyn File>, Error> {
+        let ino = self.inode.lookup(&name)?.ok_or(Error::ENOENT)?;
+        let inode = self.inode.fs.get_inode(ino)?;
+        if mode.contains(FileOpenMode::TRUNC) && inode.file_type() == FileType::RegularFile {
+            inode.truncate(0)?;
+        }
+        Ok(Ext2File::new(inode, mode, self.child_path(&name)))
+    }
+
+    fn mknod(&self, name: String, perm: FilePerm, file_type: FileType) -> Result<Arc<dyn File>, Error> {
+        let _guard = self.inode.lock_dir();
+        if self.inode.lookup(&name)?.is_some() {
+            return Err(Error::EEXIST);
+        }
+        let mode = file_type_to_mode(file_type)? | (perm.bits() as u16 & 0o7777);
+        let inode = Ext2Inode::create(&self.inode.fs, mode)?;
+        if file_type == FileType::Directory {
+            inode.init_dir(self.inode.ino)?;
+        }
+        if let Err(err) = self.inode.add_entry(&name, inode.ino, inode.dir_entry_type()) {
+            /* 添加目录项失败, inode在drop时被释放 */
+            inode.clear_links();
+            return Err(err);
+        }
+        if file_type == FileType::Directory {
+            self.inode.inc_links();
+        }
+        trace!("ext2_mknod: {:?}/{}, ino = {}", self.path, name, inode.ino);
+        Ok(Ext2File::new(inode, FileOpenMode::SYS, self.child_path(&name)))
+    }
+
+    fn delete(&self, name: String) -> Result<(), Error> {
+        let _guard = self.inode.lock_dir();
+        let ino = self.inode.lookup(&name)?.ok_or(Error::ENOENT)?;
+        let child = self.inode.fs.get_inode(ino)?;
+        if child.is_dir() {
+            return self.remove_dir(&name, &child);
+        }
+        /* 数据块在最后一个打开的文件关闭后才会被释放 */
+        self.inode.remove_entry(&name)?;
+        child.dec_links();
+        Ok(())
+    }
+
+    fn getdent(&self) -> Result<Vec<Dentry>, Error> {
+        let mut cursor = self.cursor.lock();
+        if *cursor != 0 {
+            return Ok(Vec::new())
+        }
+
+        let dentrys: Vec<Dentry> = self.inode
+            .list()?
+            .into_iter()
+            .filter(|entry| entry.name != "." && entry.name != "..")
+            .map(|entry| Dentry {
+                d_ino: entry.ino as usize,
+                d_type: match entry.file_type {
+                    FT_REG_FILE => FileType::RegularFile,
+                    FT_DIR => FileType::Directory,
+                    FT_CHRDEV => FileType::CharDevice,
+                    FT_BLKDEV => FileType::BlockDevice,
+                    FT_FIFO => FileType::FIFOFile,
+                    FT_SOCK => FileType::SocketFile,
+                    FT_SYMLINK => FileType::LinkFile,
+                    /* 没有INCOMPAT_FILETYPE时需要读取inode */
+                    _ => self.inode.fs.get_inode(entry.ino)
+                        .map(|inode| mode_to_file_type(inode.disk().file_type()))
+                        .unwrap_or(FileType::UNKNOWN),
+                },
+                d_name: entry.name,
+            })
+            .collect();
+
+        *cursor = dentrys.len().max(1);
+        Ok(dentrys)
+    }
+
+    /* 目标存在时被替换, 与linux相同, 不能用目录替换非空目录 */
+    fn rename(&self, old: String, new: String) -> Result<(), Error> {
+        if old == new {
+            return Ok(());
+        }
+        let fs = self.inode.fs.clone();
+        let _guard = self.inode.lock_dir();
+        let ino = self.inode.lookup(&old)?.ok_or(Error::ENOENT)?;
+        let inode = fs.get_inode(ino)?;
+
+        if let Some(target_ino) = self.inode.lookup(&new)? {
+            let target = fs.get_inode(target_ino)?;
+            match (inode.is_dir(), target.is_dir()) {
+                (true, true) => self.remove_dir(&new, &target)?,
+                (true, false) => return Err(Error::ENOTDIR),
+                (false, true) => return Err(Error::EISDIR),
+                (false, false) => {
+                    self.inode.replace_entry(&new, ino, inode.dir_entry_type())?;
+                    target.dec_links();
+                    self.inode.remove_entry(&old)?;
+                    return Ok(());
+                }
+            }
+        }
+
+        self.inode.add_entry(&new, ino, inode.dir_entry_type())?;
+        self.inode.remove_entry(&old)?;
+        Ok(())
+    }
+}
+
+impl LinkFile for Ext2File {
+    fn read_link(&self) -> Result<Path, Error> {
+        resolve_link(&self.path, self.inode.read_link()?.as_str())
+    }
+
+    fn write_link(&self, target: &str) -> Result<(), Error> {
+        self.inode.write_link(target)
+    }
+
+    fn link_name(&self) -> Result<String, Error> {
+        self.inode.read_link()
+    }
+}
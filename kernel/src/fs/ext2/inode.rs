@@ -0,0 +1,682 @@
+use alloc::{string::String, sync::Arc, vec, vec::Vec};
+use spin::{Mutex, MutexGuard, RwLock};
+use log::*;
+use crate::fs::{FileStat, FileType};
+use crate::syscall::time::Timespec;
+use crate::utils::Error;
+use super::{layout::*, Ext2FileSystem};
+
+/// 内存中的ext2 inode, 每次修改后立即写回块缓存
+pub struct Ext2Inode {
+    pub fs: Arc<Ext2FileSystem>,
+    pub ino: u32,
+    disk: RwLock<DiskInode>,
+    /* 修改目录时持有, 保证查找目录项和添加/删除目录项之间目录不被其它任务修改 */
+    dir_lock: Mutex<()>,
+}
+
+/// 目录中的一项
+pub struct DirEntry {
+    pub ino: u32,
+    pub file_type: u8,
+    pub name: String,
+}
+
+fn now() -> u32 {
+    Timespec::now().tv_sec as u32
+}
+
+/* 与linux的relatime相同, atime不晚于mtime/ctime或超过一天没有更新时才写回 */
+const RELATIME_INTERVAL: u32 = 24 * 60 * 60;
+
+impl Ext2Inode {
+    pub fn new(fs: Arc<Ext2FileSystem>, ino: u32, disk: DiskInode) -> Arc<Self> {
+        Arc::new(Self {
+            fs,
+            ino,
+            disk: RwLock::new(disk),
+            dir_lock: Mutex::new(()),
+        })
+    }
+
+    /* 修改目录(mknod, delete, rename, link)时需要先取得该锁 */
+    pub fn lock_dir(&self) -> MutexGuard<'_, ()> {
+        self.dir_lock.lock()
+    }
+
+    /* 新建一个inode并写入磁盘 */
+    pub fn create(fs: &Arc<Ext2FileSystem>, mode: u16) -> Result<Arc<Self>, Error> {
+        let is_dir = mode & S_IFMT == S_IFDIR;
+        let ino = fs.alloc_inode(is_dir)?;
+        let time = now();
+        let disk = DiskInode {
+            mode,
+            links_count: 1,
+            atime: time,
+            ctime: time,
+            mtime: time,
+            ..Default::default()
+        };
+        fs.write_disk_inode(ino, &disk);
+        trace!("ext2: create inode {}, mode = {:o}", ino, mode);
+        fs.get_inode(ino)
+    }
+
+    fn sync(&self, disk: &DiskInode) {
+        self.fs.write_disk_inode(self.ino, disk);
+    }
+
+    pub fn disk(&self) -> DiskInode {
+        *self.disk.read()
+    }
+
+    pub fn is_dir(&self) -> bool {
+        self.disk.read().is_dir()
+    }
+
+    pub fn size(&self) -> usize {
+        self.disk.read().size()
+    }
+
+    pub fn file_type(&self) -> FileType {
+        mode_to_file_type(self.disk.read().file_type())
+    }
+
+    pub fn dir_entry_type(&self) -> u8 {
+        match self.disk.read().file_type() {
+            S_IFREG => FT_REG_FILE,
+            S_IFDIR => FT_DIR,
+            S_IFCHR => FT_CHRDEV,
+            S_IFBLK => FT_BLKDEV,
+            S_IFIFO => FT_FIFO,
+            S_IFSOCK => FT_SOCK,
+            S_IFLNK => FT_SYMLINK,
+            _ => FT_UNKNOWN,
+        }
+    }
+
+    pub fn stat(&self, dev: u64) -> FileStat {
+        let disk = self.disk.read();
+        let rdev = match disk.file_type() {
+            /* 设备号保存在i_block[0]中 */
+            S_IFCHR | S_IFBLK => disk.block[0] as u64,
+            _ => 0,
+        };
+        FileStat {
+            st_dev: dev,
+            st_ino: self.ino as u64,
+            st_mode: disk.mode as u32,
+            st_nlink: disk.links_count as u32,
+            st_uid: disk.uid(),
+            st_gid: disk.gid(),
+            st_rdev: rdev,
+            st_size: disk.size() as u64,
+            st_blksize: self.fs.block_size() as u32,
+            st_blocks: disk.blocks as u64,
+            st_atime_sec: disk.atime as i64,
+            st_atime_nsec: 0,
+            st_mtime_sec: disk.mtime as i64,
+            st_mtime_nsec: 0,
+            st_ctime_sec: disk.ctime as i64,
+            st_ctime_nsec: 0,
+            __pad1: 0,
+            __pad2: 0,
+            __pad3: 0,
+        }
+    }
+
+    /* chmod, chown, utimensat */
+    pub fn set_stat(&self, stat: &FileStat) {
+        let mut disk = self.disk.write();
+        disk.mode = (disk.mode & S_IFMT) | (stat.st_mode as u16 & 0o7777);
+        disk.set_uid(stat.st_uid);
+        disk.set_gid(stat.st_gid);
+        disk.atime = stat.st_atime_sec as u32;
+        disk.mtime = stat.st_mtime_sec as u32;
+        disk.ctime = now();
+        self.sync(&disk);
+    }
+
+    pub fn set_perm(&self, perm: u32) {
+        let mut disk = self.disk.write();
+        disk.mode = (disk.mode & S_IFMT) | (perm as u16 & 0o7777);
+        self.sync(&disk);
+    }
+
+    pub fn inc_links(&self) {
+        let mut disk = self.disk.write();
+        disk.links_count = disk.links_count.saturating_add(1);
+        disk.ctime = now();
+        self.sync(&disk);
+    }
+
+    pub fn dec_links(&self) {
+        let mut disk = self.disk.write();
+        disk.links_count = disk.links_count.saturating_sub(1);
+        disk.ctime = now();
+        self.sync(&disk);
+    }
+
+    /* 删除目录时, 目录本身和"."的连接同时消失 */
+    pub fn clear_links(&self) {
+        let mut disk = self.disk.write();
+        disk.links_count = 0;
+        disk.ctime = now();
+        self.sync(&disk);
+    }
+
+    fn ptrs_per_block(&self) -> usize {
+        self.fs.block_size() / 4
+    }
+
+    /// 将文件中的第idx块映射为磁盘块号, create为false时空洞返回0
+    fn map_block(&self, disk: &mut DiskInode, idx: usize, create: bool) -> Result<u32, Error> {
+        let fs = &self.fs;
+        let sectors = (fs.block_size() / 512) as u32;
+        if idx < DIRECT_BLOCKS {
+            if disk.block[idx] == 0 && create {
+                disk.block[idx] = fs.alloc_block()?;
+                disk.blocks += sectors;
+            }
+            return Ok(disk.block[idx]);
+        }
+
+        let ptrs = self.ptrs_per_block();
+        let mut offset = idx - DIRECT_BLOCKS;
+        let (root, level) = if offset < ptrs {
+            (IND_BLOCK, 1)
+        } else if offset - ptrs < ptrs * ptrs {
+            offset -= ptrs;
+            (DIND_BLOCK, 2)
+        } else if offset - ptrs - ptrs * ptrs < ptrs * ptrs * ptrs {
+            offset -= ptrs + ptrs * ptrs;
+            (TIND_BLOCK, 3)
+        } else {
+            return Err(Error::EFBIG);
+        };
+
+        if disk.block[root] == 0 {
+            if !create {
+                return Ok(0);
+            }
+            disk.block[root] = fs.alloc_block()?;
+            disk.blocks += sectors;
+        }
+        let mut block = disk.block[root];
+        for l in (0..level).rev() {
+            let span = ptrs.pow(l);
+            let i = offset / span;
+            offset %= span;
+            let mut next = fs.read_block_u32(block, i);
+            if next == 0 {
+                if !create {
+                    return Ok(0);
+                }
+                next = fs.alloc_block()?;
+                fs.write_block_u32(block, i, next);
+                disk.blocks += sectors;
+            }
+            block = next;
+        }
+        Ok(block)
+    }
+
+    /* 只查找不分配, 不需要持有inode的写锁 */
+    fn get_block(&self, disk: &DiskInode, idx: usize) -> Result<u32, Error> {
+        let mut disk = *disk;
+        self.map_block(&mut disk, idx, false)
+    }
+
+    /// 释放以block为根的(level级)间接块树中, 下标不小于start的数据块
+    /// 返回整棵树是否已经被释放
+    fn truncate_tree(&self, block: u32, level: u32, start: usize, freed: &mut u32) -> bool {
+        let fs = &self.fs;
+        if level == 0 {
+            fs.free_block(block);
+            *freed += 1;
+            return true;
+        }
+        let ptrs = self.ptrs_per_block();
+        let span = ptrs.pow(level - 1);
+        for i in start / span..ptrs {
+            let child = fs.read_block_u32(block, i);
+            if child == 0 {
+                continue;
+            }
+            let child_start = start.saturating_sub(i * span);
+            if self.truncate_tree(child, level - 1, child_start, freed) {
+                fs.write_block_u32(block, i, 0);
+            }
+        }
+        if start == 0 {
+            fs.free_block(block);
+            *freed += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    /* 释放文件第start块之后的所有块 */
+    fn truncate_blocks(&self, disk: &mut DiskInode, start: usize) {
+        let mut freed = 0;
+        for idx in start.min(DIRECT_BLOCKS)..DIRECT_BLOCKS {
+            if disk.block[idx] != 0 {
+                self.fs.free_block(disk.block[idx]);
+                disk.block[idx] = 0;
+                freed += 1;
+            }
+        }
+        let ptrs = self.ptrs_per_block();
+        let mut base = DIRECT_BLOCKS;
+        for (root, level) in [(IND_BLOCK, 1), (DIND_BLOCK, 2), (TIND_BLOCK, 3)] {
+            let span = ptrs.pow(level);
+            if disk.block[root] != 0 && start < base + span {
+                let tree_start = start.saturating_sub(base);
+                if self.truncate_tree(disk.block[root], level, tree_start, &mut freed) {
+                    disk.block[root] = 0;
+                }
+            }
+            base += span;
+        }
+        disk.blocks -= freed * (self.fs.block_size() / 512) as u32;
+    }
+
+    pub fn truncate(&self, size: usize) -> Result<(), Error> {
+        let mut disk = self.disk.write();
+        let block_size = self.fs.block_size();
+        if size < disk.size() {
+            /* 内核的工具链还没有div_ceil, 最后一块的长度tail单独计算 */
+            let tail = size % block_size;
+            self.truncate_blocks(&mut disk, size / block_size + (tail != 0) as usize);
+            /* 清除最后一块中新的文件末尾之后的数据, 以免再次扩展文件时读到旧数据 */
+            if tail != 0 {
+                let block = self.map_block(&mut disk, size / block_size, false)?;
+                if block != 0 {
+                    let zero = vec![0u8; block_size - tail];
+                    self.fs.write_block(block, tail, &zero);
+                }
+            }
+        }
+        disk.set_size(size);
+        disk.mtime = now();
+        disk.ctime = disk.mtime;
+        self.sync(&disk);
+        Ok(())
+    }
+
+    pub fn read_at(&self, offset: usize, buf: &mut [u8]) -> Result<usize, Error> {
+        let disk = self.disk.read();
+        let size = disk.size();
+        if offset >= size {
+            return Ok(0);
+        }
+        let end = size.min(offset + buf.len());
+        let block_size = self.fs.block_size();
+        let mut pos = offset;
+        while pos < end {
+            let block_off = pos % block_size;
+            let len = (block_size - block_off).min(end - pos);
+            let dst = &mut buf[pos - offset..pos - offset + len];
+            let block = self.get_block(&disk, pos / block_size)?;
+            if block == 0 {
+                dst.fill(0);
+            } else {
+                self.fs.read_block(block, block_off, dst);
+            }
+            pos += len;
+        }
+        drop(disk);
+        self.touch_atime();
+        Ok(end - offset)
+    }
+
+    /* 读操作只在需要时才取得写锁更新atime */
+    fn touch_atime(&self) {
+        let time = now();
+        let stale = |disk: &DiskInode| {
+            disk.atime <= disk.mtime || disk.atime <= disk.ctime
+                || time.wrapping_sub(disk.atime) >= RELATIME_INTERVAL
+        };
+        if !stale(&self.disk.read()) {
+            return;
+        }
+        let mut disk = self.disk.write();
+        if stale(&disk) && disk.atime != time {
+            disk.atime = time;
+            self.sync(&disk);
+        }
+    }
+
+    pub fn write_at(&self, offset: usize, buf: &[u8]) -> Result<usize, Error> {
+        let mut disk = self.disk.write();
+        let block_size = self.fs.block_size();
+        let end = offset + buf.len();
+        let mut pos = offset;
+        while pos < end {
+            let block_off = pos % block_size;
+            let len = (block_size - block_off).min(end - pos);
+            let block = match self.map_block(&mut disk, pos / block_size, true) {
+                Ok(block) => block,
+                /* 空间不足时返回已经写入的长度 */
+                Err(err) if pos == offset => {
+                    self.sync(&disk);
+                    return Err(err);
+                }
+                Err(_) => break,
+            };
+            self.fs.write_block(block, block_off, &buf[pos - offset..pos - offset + len]);
+            pos += len;
+        }
+        if pos > disk.size() {
+            disk.set_size(pos);
+        }
+        disk.mtime = now();
+        disk.ctime = disk.mtime;
+        self.sync(&disk);
+        Ok(pos - offset)
+    }
+
+    /* 软连接的目标路径 */
+    pub fn read_link(&self) -> Result<String, Error> {
+        let disk = self.disk();
+        if disk.file_type() != S_IFLNK {
+            return Err(Error::EINVAL);
+        }
+        let size = disk.size();
+        let data = if disk.is_fast_symlink(self.fs.block_size()) {
+            let bytes: Vec<u8> = disk.block.iter().flat_map(|b| b.to_le_bytes()).collect();
+            bytes[..size].to_vec()
+        } else {
+            let mut data = vec![0u8; size];
+            self.read_at(0, &mut data)?;
+            data
+        };
+        String::from_utf8(data).map_err(|_| Error::EINVAL)
+    }
+
+    pub fn write_link(&self, target: &str) -> Result<(), Error> {
+        let bytes = target.as_bytes();
+        if bytes.len() < FAST_SYMLINK_MAX {
+            let mut disk = self.disk.write();
+            self.truncate_blocks(&mut disk, 0);
+            let mut raw = [0u8; FAST_SYMLINK_MAX];
+            raw[..bytes.len()].copy_from_slice(bytes);
+            for (i, block) in disk.block.iter_mut().enumerate() {
+                *block = u32::from_le_bytes(raw[i * 4..i * 4 + 4].try_into().unwrap());
+            }
+            disk.set_size(bytes.len());
+            disk.mtime = now();
+            disk.ctime = disk.mtime;
+            self.sync(&disk);
+            Ok(())
+        } else {
+            self.truncate(0)?;
+            self.write_at(0, bytes)?;
+            Ok(())
+        }
+    }
+
+    /* 遍历目录中的所有目录项, f返回true时停止遍历 */
+    fn for_each_entry(
+        &self,
+        mut f: impl FnMut(u32, usize, &mut Vec<u8>) -> Result<bool, Error>
+    ) -> Result<bool, Error> {
+        let block_size = self.fs.block_size();
+        let size = self.size();
+        let mut buf = vec![0u8; block_size];
+        let tail = size % block_size;
+        let blocks = size / block_size + (tail != 0) as usize;
+        for idx in 0..blocks {
+            let block = self.get_block(&self.disk.read(), idx)?;
+            if block == 0 {
+                continue;
+            }
+            self.fs.read_block(block, 0, &mut buf);
+            if f(block, idx, &mut buf)? {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    pub fn list(&self) -> Result<Vec<DirEntry>, Error> {
+        let mut entries = Vec::new();
+        self.for_each_entry(|_, _, buf| {
+            for (_, entry) in parse_block(buf) {
+                if entry.ino != 0 {
+                    entries.push(entry);
+                }
+            }
+            Ok(false)
+        })?;
+        Ok(entries)
+    }
+
+    pub fn lookup(&self, name: &str) -> Result<Option<u32>, Error> {
+        let mut found = None;
+        self.for_each_entry(|_, _, buf| {
+            for (_, entry) in parse_block(buf) {
+                if entry.ino != 0 && entry.name == name {
+                    found = Some(entry.ino);
+                    return Ok(true);
+                }
+            }
+            Ok(false)
+        })?;
+        Ok(found)
+    }
+
+    /* 目录被修改后, htree索引不再有效 */
+    fn dir_modified(&self) {
+        let mut disk = self.disk.write();
+        disk.flags &= !INDEX_FL;
+        disk.mtime = now();
+        disk.ctime = disk.mtime;
+        self.sync(&disk);
+    }
+
+    /* 以下修改目录项的函数要求调用者持有lock_dir */
+    pub fn add_entry(&self, name: &str, ino: u32, file_type: u8) -> Result<(), Error> {
+        if name.len() > 255 {
+            return Err(Error::ENAMETOOLONG);
+        }
+        if self.lookup(name)?.is_some() {
+            return Err(Error::EEXIST);
+        }
+        let need = dir_entry_len(name.len());
+        let fs = self.fs.clone();
+
+        /* 在已有的块中寻找空间: 空闲的目录项, 或者目录项之后的空余部分 */
+        let done = self.for_each_entry(|block, _, buf| {
+            for (off, entry) in parse_block(buf) {
+                let rec_len = read_u16(buf, off + 4) as usize;
+                let used = match entry.ino {
+                    0 => 0,
+                    _ => dir_entry_len(entry.name.len()),
+                };
+                if rec_len - used >= need {
+                    if used != 0 {
+                        write_u16(buf, off + 4, used as u16);
+                    }
+                    write_entry(buf, off + used, ino, rec_len - used, file_type, name);
+                    fs.write_block(block, 0, buf);
+                    return Ok(true);
+                }
+            }
+            Ok(false)
+        })?;
+
+        /* 没有空间时在目录末尾添加一个块 */
+        if !done {
+            let block_size = fs.block_size();
+            let size = self.size();
+            let mut buf = vec![0u8; block_size];
+            write_entry(&mut buf, 0, ino, block_size, file_type, name);
+            let mut disk = self.disk.write();
+            let block = self.map_block(&mut disk, size / block_size, true)?;
+            fs.write_block(block, 0, &buf);
+            disk.set_size(size + block_size);
+            self.sync(&disk);
+        }
+        self.dir_modified();
+        Ok(())
+    }
+
+    pub fn remove_entry(&self, name: &str) -> Result<u32, Error> {
+        let fs = self.fs.clone();
+        let mut removed = None;
+        self.for_each_entry(|block, _, buf| {
+            let mut prev: Option<usize> = None;
+            for (off, entry) in parse_block(buf) {
+                if entry.ino != 0 && entry.name == name {
+                    /* 与前一项合并, 块中的第一项则标记为空闲 */
+                    match prev {
+                        Some(prev) => {
+                            let len = read_u16(buf, prev + 4) + read_u16(buf, off + 4);
+                            write_u16(buf, prev + 4, len);
+                        }
+                        None => write_u32(buf, off, 0),
+                    }
+                    fs.write_block(block, 0, buf);
+                    removed = Some(entry.ino);
+                    return Ok(true);
+                }
+                prev = Some(off);
+            }
+            Ok(false)
+        })?;
+        let ino = removed.ok_or(Error::ENOENT)?;
+        self.dir_modified();
+        Ok(ino)
+    }
+
+    /* 修改目录项指向的inode, 用于rename覆盖已有文件和移动目录时修改".." */
+    pub fn replace_entry(&self, name: &str, ino: u32, file_type: u8) -> Result<(), Error> {
+        let fs = self.fs.clone();
+        let found = self.for_each_entry(|block, _, buf| {
+            for (off, entry) in parse_block(buf) {
+                if entry.ino != 0 && entry.name == name {
+                    write_u32(buf, off, ino);
+                    buf[off + 7] = file_type;
+                    fs.write_block(block, 0, buf);
+                    return Ok(true);
+                }
+            }
+            Ok(false)
+        })?;
+        if !found {
+            return Err(Error::ENOENT);
+        }
+        self.dir_modified();
+        Ok(())
+    }
+
+    /* 目录中只有"."和".." */
+    pub fn is_empty_dir(&self) -> Result<bool, Error> {
+        Ok(self.list()?.iter().all(|entry| entry.name == "." || entry.name == ".."))
+    }
+
+    /* 新建目录的第一个块, 包含"."和".." */
+    pub fn init_dir(&self, parent: u32) -> Result<(), Error> {
+        let block_size = self.fs.block_size();
+        let mut buf = vec![0u8; block_size];
+        let dot_len = dir_entry_len(1);
+        write_entry(&mut buf, 0, self.ino, dot_len, FT_DIR, ".");
+        write_entry(&mut buf, dot_len, parent, block_size - dot_len, FT_DIR, "..");
+        let mut disk = self.disk.write();
+        let block = self.map_block(&mut disk, 0, true)?;
+        self.fs.write_block(block, 0, &buf);
+        disk.set_size(block_size);
+        disk.links_count = 2;
+        self.sync(&disk);
+        Ok(())
+    }
+}
+
+impl Drop for Ext2Inode {
+    /* 最后一个引用消失时, 如果已经没有硬连接指向该inode, 释放inode和数据块 */
+    fn drop(&mut self) {
+        let mut disk = *self.disk.read();
+        if disk.links_count == 0 && disk.mode != 0 {
+            trace!("ext2: free inode {}", self.ino);
+            /* 设备文件的i_block中保存的是设备号, 快速软连接中保存的是路径 */
+            match disk.file_type() {
+                S_IFREG | S_IFDIR => self.truncate_blocks(&mut disk, 0),
+                S_IFLNK if !disk.is_fast_symlink(self.fs.block_size()) => {
+                    self.truncate_blocks(&mut disk, 0)
+                }
+                _ => {}
+            }
+            disk.set_size(0);
+            disk.dtime = now().max(1);
+            self.sync(&disk);
+            self.fs.free_inode(self.ino, disk.is_dir());
+        }
+        self.fs.remove_cached_inode(self.ino);
+    }
+}
+
+pub fn mode_to_file_type(mode: u16) -> FileType {
+    match mode & S_IFMT {
+        S_IFREG => FileType::RegularFile,
+        S_IFDIR => FileType::Directory,
+        S_IFLNK => FileType::LinkFile,
+        S_IFCHR => FileType::CharDevice,
+        S_IFBLK => FileType::BlockDevice,
+        S_IFIFO => FileType::FIFOFile,
+        S_IFSOCK => FileType::SocketFile,
+        _ => FileType::UNKNOWN,
+    }
+}
+
+pub fn file_type_to_mode(file_type: FileType) -> Result<u16, Error> {
+    match file_type {
+        FileType::RegularFile => Ok(S_IFREG),
+        FileType::Directory => Ok(S_IFDIR),
+        FileType::LinkFile => Ok(S_IFLNK),
+        FileType::FIFOFile => Ok(S_IFIFO),
+        FileType::SocketFile => Ok(S_IFSOCK),
+        _ => Err(Error::EINVAL),
+    }
+}
+
+fn read_u16(buf: &[u8], off: usize) -> u16 {
+    u16::from_le_bytes([buf[off], buf[off + 1]])
+}
+
+fn write_u16(buf: &mut [u8], off: usize, value: u16) {
+    buf[off..off + 2].copy_from_slice(&value.to_le_bytes());
+}
+
+fn write_u32(buf: &mut [u8], off: usize, value: u32) {
+    buf[off..off + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+fn write_entry(buf: &mut [u8], off: usize, ino: u32, rec_len: usize, file_type: u8, name: &str) {
+    write_u32(buf, off, ino);
+    write_u16(buf, off + 4, rec_len as u16);
+    buf[off + 6] = name.len() as u8;
+    buf[off + 7] = file_type;
+    buf[off + DIR_ENTRY_HEADER..off + DIR_ENTRY_HEADER + name.len()].copy_from_slice(name.as_bytes());
+}
+
+/* 解析一个目录块中的所有目录项, 返回(块内偏移, 目录项) */
+fn parse_block(buf: &[u8]) -> Vec<(usize, DirEntry)> {
+    let mut entries = Vec::new();
+    let mut off = 0;
+    while off + DIR_ENTRY_HEADER <= buf.len() {
+        let ino = u32::from_le_bytes(buf[off..off + 4].try_into().unwrap());
+        let rec_len = read_u16(buf, off + 4) as usize;
+        let name_len = buf[off + 6] as usize;
+        if rec_len < DIR_ENTRY_HEADER || off + rec_len > buf.len()
+            || DIR_ENTRY_HEADER + name_len > rec_len {
+            warn!("ext2: corrupted dir entry at offset {}", off);
+            break;
+        }
+        let name = String::from_utf8_lossy(&buf[off + DIR_ENTRY_HEADER..off + DIR_ENTRY_HEADER + name_len]).into_owned();
+        entries.push((off, DirEntry { ino, file_type: buf[off + 7], name }));
+        off += rec_len;
+    }
+    entries
+}
@@ -0,0 +1,188 @@
+// ext2磁盘上的数据结构, 参考: https://www.nongnu.org/ext2-doc/ext2.html
+// 所有字段都是小端序, 与riscv相同, 可以直接按结构体读写
+
+pub const EXT2_MAGIC: u16 = 0xEF53;
+pub const SUPERBLOCK_OFFSET: usize = 1024;
+/* 块大小最大为64K */
+pub const MAX_LOG_BLOCK_SIZE: u32 = 6;
+pub const ROOT_INO: u32 = 2;
+
+/* i_block中直接块的数量, 之后依次是一级, 二级, 三级间接块 */
+pub const DIRECT_BLOCKS: usize = 12;
+pub const IND_BLOCK: usize = 12;
+pub const DIND_BLOCK: usize = 13;
+pub const TIND_BLOCK: usize = 14;
+
+/* 快速软连接: 目标路径小于60字节时直接保存在i_block中 */
+pub const FAST_SYMLINK_MAX: usize = 60;
+
+pub const INCOMPAT_FILETYPE: u32 = 0x0002;
+pub const RO_COMPAT_SPARSE_SUPER: u32 = 0x0001;
+pub const RO_COMPAT_LARGE_FILE: u32 = 0x0002;
+pub const SUPPORTED_INCOMPAT: u32 = INCOMPAT_FILETYPE;
+pub const SUPPORTED_RO_COMPAT: u32 = RO_COMPAT_SPARSE_SUPER | RO_COMPAT_LARGE_FILE;
+
+/* 目录使用htree索引, 修改目录后索引失效, 需要清除该标志 */
+pub const INDEX_FL: u32 = 0x1000;
+
+// i_mode中的文件类型
+pub const S_IFMT: u16 = 0o170000;
+pub const S_IFSOCK: u16 = 0o140000;
+pub const S_IFLNK: u16 = 0o120000;
+pub const S_IFREG: u16 = 0o100000;
+pub const S_IFBLK: u16 = 0o060000;
+pub const S_IFDIR: u16 = 0o040000;
+pub const S_IFCHR: u16 = 0o020000;
+pub const S_IFIFO: u16 = 0o010000;
+
+// 目录项中的文件类型(INCOMPAT_FILETYPE)
+pub const FT_UNKNOWN: u8 = 0;
+pub const FT_REG_FILE: u8 = 1;
+pub const FT_DIR: u8 = 2;
+pub const FT_CHRDEV: u8 = 3;
+pub const FT_BLKDEV: u8 = 4;
+pub const FT_FIFO: u8 = 5;
+pub const FT_SOCK: u8 = 6;
+pub const FT_SYMLINK: u8 = 7;
+
+// superblock
+// at: 磁盘偏移1024字节处
+// 只包含需要用到的字段(rev 1的前104字节)
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct SuperBlock {
+    pub inodes_count        :u32,
+    pub blocks_count        :u32,
+    pub r_blocks_count      :u32,  //为超级用户保留的块数
+    pub free_blocks_count   :u32,
+    pub free_inodes_count   :u32,
+    pub first_data_block    :u32,  //块大小为1024时为1, 否则为0
+    pub log_block_size      :u32,  //block_size = 1024 << log_block_size
+    pub log_frag_size       :u32,
+    pub blocks_per_group    :u32,
+    pub frags_per_group     :u32,
+    pub inodes_per_group    :u32,
+    pub mtime               :u32,
+    pub wtime               :u32,
+    pub mnt_count           :u16,
+    pub max_mnt_count       :u16,
+    pub magic               :u16,
+    pub state               :u16,
+    pub errors              :u16,
+    pub minor_rev_level     :u16,
+    pub lastcheck           :u32,
+    pub checkinterval       :u32,
+    pub creator_os          :u32,
+    pub rev_level           :u32,  //0时inode大小固定为128
+    pub def_resuid          :u16,
+    pub def_resgid          :u16,
+    pub first_ino           :u32,  //第一个非保留的inode
+    pub inode_size          :u16,
+    pub block_group_nr      :u16,
+    pub feature_compat      :u32,
+    pub feature_incompat    :u32,
+    pub feature_ro_compat   :u32,
+}
+
+// block group descriptor
+// at: superblock之后的第一个块, 每个块组一项
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct GroupDesc {
+    pub block_bitmap        :u32,
+    pub inode_bitmap        :u32,
+    pub inode_table         :u32,
+    pub free_blocks_count   :u16,
+    pub free_inodes_count   :u16,
+    pub used_dirs_count     :u16,
+    pub pad                 :u16,
+    pub reserved            :[u32; 3],
+}
+
+// inode
+// at: 所在块组的inode table, 大小为superblock中的inode_size
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DiskInode {
+    pub mode                :u16,
+    pub uid                 :u16,
+    pub size                :u32,
+    pub atime               :u32,
+    pub ctime               :u32,
+    pub mtime               :u32,
+    pub dtime               :u32,  //删除时间
+    pub gid                 :u16,
+    pub links_count         :u16,
+    pub blocks              :u32,  //以512字节为单位
+    pub flags               :u32,
+    pub osd1                :u32,
+    pub block               :[u32; 15],
+    pub generation          :u32,
+    pub file_acl            :u32,
+    pub size_high           :u32,  //普通文件大小的高32位(RO_COMPAT_LARGE_FILE)
+    pub faddr               :u32,
+    pub frag                :u16,
+    pub pad1                :u16,
+    pub uid_high            :u16,
+    pub gid_high            :u16,
+    pub reserved2           :u32,
+}
+
+impl DiskInode {
+    pub fn file_type(&self) -> u16 {
+        self.mode & S_IFMT
+    }
+
+    pub fn is_dir(&self) -> bool {
+        self.file_type() == S_IFDIR
+    }
+
+    pub fn size(&self) -> usize {
+        match self.file_type() {
+            S_IFREG => (self.size as usize) | ((self.size_high as usize) << 32),
+            _ => self.size as usize,
+        }
+    }
+
+    pub fn set_size(&mut self, size: usize) {
+        self.size = size as u32;
+        if self.file_type() == S_IFREG {
+            self.size_high = (size >> 32) as u32;
+        }
+    }
+
+    pub fn uid(&self) -> u32 {
+        self.uid as u32 | (self.uid_high as u32) << 16
+    }
+
+    pub fn gid(&self) -> u32 {
+        self.gid as u32 | (self.gid_high as u32) << 16
+    }
+
+    pub fn set_uid(&mut self, uid: u32) {
+        self.uid = uid as u16;
+        self.uid_high = (uid >> 16) as u16;
+    }
+
+    pub fn set_gid(&mut self, gid: u32) {
+        self.gid = gid as u16;
+        self.gid_high = (gid >> 16) as u16;
+    }
+
+    /* 目标路径保存在i_block中的软连接不占用数据块(扩展属性块除外) */
+    pub fn is_fast_symlink(&self, block_size: usize) -> bool {
+        let acl_blocks = match self.file_acl {
+            0 => 0,
+            _ => (block_size / 512) as u32,
+        };
+        self.file_type() == S_IFLNK && self.size() < FAST_SYMLINK_MAX
+            && self.blocks == acl_blocks
+    }
+}
+
+/* 目录项的头部, 之后是name_len字节的文件名, 整个目录项按4字节对齐 */
+pub const DIR_ENTRY_HEADER: usize = 8;
+
+pub fn dir_entry_len(name_len: usize) -> usize {
+    (DIR_ENTRY_HEADER + name_len + 3) & !3
+}
@@ -0,0 +1,424 @@
+mod layout;
+mod inode;
+mod file;
+
+use spin::Mutex;
+use log::*;
+use alloc::{
+    sync::{Arc, Weak},
+    collections::BTreeMap,
+    vec,
+    vec::Vec,
+};
+use core::mem::size_of;
+use self::{
+    layout::*,
+    inode::Ext2Inode,
+    file::Ext2File,
+};
+use crate::utils::{Error, Path};
+use super::{get_block_cache, BlockFile, VFS, File, FSid, FileOpenMode, DirFile, Statvfs};
+use super::block_cache::block_cache_sync_dev;
+
+/// ext2文件系统:
+/// 1, 支持inode, 一/二/三级间接块, 目录, 软连接, 硬连接
+/// 2, 目录只按线性方式查找, 修改使用htree索引的目录时清除索引标志
+/// 3, 不维护superblock和块组描述符的备份
+/// 4, 不支持扩展属性, 已有的扩展属性块保持不变
+pub struct Ext2FileSystem {
+    id: FSid,
+    mount_path: Path,
+    block_file: Arc<dyn BlockFile>,
+
+    block_size: usize,
+    inode_size: usize,
+    inodes_per_group: u32,
+    blocks_per_group: u32,
+    first_data_block: u32,
+    group_desc_block: u32,
+
+    /* 分配和释放块或inode时修改, 修改后写回磁盘 */
+    meta: Mutex<Ext2Meta>,
+    /* 已打开的inode, 同一个inode只在内存中存在一份 */
+    inode_cache: Mutex<BTreeMap<u32, Weak<Ext2Inode>>>,
+    this: Weak<Ext2FileSystem>,
+}
+
+struct Ext2Meta {
+    sb: SuperBlock,
+    groups: Vec<GroupDesc>,
+}
+
+impl Ext2FileSystem {
+    pub fn init(block_file: Arc<dyn BlockFile>, id: FSid, mount_path: Path) -> Result<Arc<Self>, Error> {
+        info!("ext2 file system initing");
+
+        let mut sb_buf = [0u8; size_of::<SuperBlock>()];
+        read_disk(&block_file, SUPERBLOCK_OFFSET, &mut sb_buf);
+        let sb = unsafe { (sb_buf.as_ptr() as *const SuperBlock).read_unaligned() };
+
+        if sb.magic != EXT2_MAGIC {
+            warn!("ext2: bad magic {:#x}", sb.magic);
+            return Err(Error::EINVAL);
+        }
+        /* rev 0的superblock中没有特性字段 */
+        let (inode_size, incompat, ro_compat) = match sb.rev_level {
+            0 => (128, 0, 0),
+            _ => (sb.inode_size as usize, sb.feature_incompat, sb.feature_ro_compat),
+        };
+        if incompat & !SUPPORTED_INCOMPAT != 0 {
+            warn!("ext2: unsupported incompat features {:#x}", incompat);
+            return Err(Error::EINVAL);
+        }
+        if ro_compat & !SUPPORTED_RO_COMPAT != 0 {
+            warn!("ext2: unsupported ro_compat features {:#x}", ro_compat);
+            return Err(Error::EINVAL);
+        }
+
+        /* 以下字段用于计算位置, 损坏的superblock会导致移位溢出, 除0或越界 */
+        if sb.log_block_size > MAX_LOG_BLOCK_SIZE {
+            warn!("ext2: bad log_block_size {}", sb.log_block_size);
+            return Err(Error::EINVAL);
+        }
+        let block_size = 1024 << sb.log_block_size;
+        /* 每个块组的位图只占一个块 */
+        let bits_per_block = (block_size * 8) as u32;
+        if sb.blocks_per_group == 0 || sb.blocks_per_group > bits_per_block
+            || sb.inodes_per_group == 0 || sb.inodes_per_group > bits_per_block {
+            warn!("ext2: bad blocks_per_group {} or inodes_per_group {}",
+                sb.blocks_per_group, sb.inodes_per_group);
+            return Err(Error::EINVAL);
+        }
+        if inode_size < 128 || inode_size > block_size || !inode_size.is_power_of_two() {
+            warn!("ext2: bad inode_size {}", inode_size);
+            return Err(Error::EINVAL);
+        }
+        if sb.first_data_block >= sb.blocks_count {
+            warn!("ext2: bad first_data_block {}, blocks_count = {}", sb.first_data_block, sb.blocks_count);
+            return Err(Error::EINVAL);
+        }
+        let group_num = ((sb.blocks_count - sb.first_data_block - 1) / sb.blocks_per_group + 1) as usize;
+        if sb.inodes_count as usize > group_num * sb.inodes_per_group as usize {
+            warn!("ext2: inodes_count {} exceeds {} groups", sb.inodes_count, group_num);
+            return Err(Error::EINVAL);
+        }
+        let group_desc_block = sb.first_data_block + 1;
+
+        let mut groups = vec![GroupDesc::default(); group_num];
+        read_disk(&block_file, group_desc_block as usize * block_size, unsafe {
+            core::slice::from_raw_parts_mut(
+                groups.as_mut_ptr() as *mut u8,
+                group_num * size_of::<GroupDesc>())
+        });
+
+        info!("ext2: block_size = {}, inode_size = {}, groups = {}, blocks = {}, inodes = {}",
+            block_size, inode_size, group_num, sb.blocks_count, sb.inodes_count);
+
+        Ok(Arc::new_cyclic(|this| Self {
+            id,
+            mount_path,
+            block_file,
+            block_size,
+            inode_size,
+            inodes_per_group: sb.inodes_per_group,
+            blocks_per_group: sb.blocks_per_group,
+            first_data_block: sb.first_data_block,
+            group_desc_block,
+            meta: Mutex::new(Ext2Meta { sb, groups }),
+            inode_cache: Mutex::new(BTreeMap::new()),
+            this: this.clone(),
+        }))
+    }
+
+    pub fn block_size(&self) -> usize {
+        self.block_size
+    }
+
+    fn get_self(&self) -> Arc<Self> {
+        self.this.upgrade().unwrap()
+    }
+
+    pub fn read_block(&self, block: u32, offset: usize, buf: &mut [u8]) {
+        assert!(offset + buf.len() <= self.block_size);
+        read_disk(&self.block_file, block as usize * self.block_size + offset, buf);
+    }
+
+    pub fn write_block(&self, block: u32, offset: usize, buf: &[u8]) {
+        assert!(offset + buf.len() <= self.block_size);
+        write_disk(&self.block_file, block as usize * self.block_size + offset, buf);
+    }
+
+    pub fn read_block_u32(&self, block: u32, idx: usize) -> u32 {
+        let mut buf = [0u8; 4];
+        self.read_block(block, idx * 4, &mut buf);
+        u32::from_le_bytes(buf)
+    }
+
+    pub fn write_block_u32(&self, block: u32, idx: usize, value: u32) {
+        self.write_block(block, idx * 4, &value.to_le_bytes());
+    }
+
+    fn inode_pos(&self, ino: u32) -> usize {
+        let group = ((ino - 1) / self.inodes_per_group) as usize;
+        let index = ((ino - 1) % self.inodes_per_group) as usize;
+        let table = self.meta.lock().groups[group].inode_table as usize;
+        table * self.block_size + index * self.inode_size
+    }
+
+    pub fn read_disk_inode(&self, ino: u32) -> DiskInode {
+        let mut disk_inode = DiskInode::default();
+        read_disk(&self.block_file, self.inode_pos(ino), as_bytes_mut(&mut disk_inode));
+        disk_inode
+    }
+
+    pub fn write_disk_inode(&self, ino: u32, disk_inode: &DiskInode) {
+        write_disk(&self.block_file, self.inode_pos(ino), as_bytes(disk_inode));
+    }
+
+    /* 获取inode, 已经打开的inode直接从缓存中返回 */
+    pub fn get_inode(&self, ino: u32) -> Result<Arc<Ext2Inode>, Error> {
+        if ino == 0 || ino > self.meta.lock().sb.inodes_count {
+            return Err(Error::EINVAL);
+        }
+        let mut cache = self.inode_cache.lock();
+        if let Some(inode) = cache.get(&ino).and_then(|inode| inode.upgrade()) {
+            return Ok(inode);
+        }
+        let inode = Ext2Inode::new(self.get_self(), ino, self.read_disk_inode(ino));
+        cache.insert(ino, Arc::downgrade(&inode));
+        Ok(inode)
+    }
+
+    pub fn remove_cached_inode(&self, ino: u32) {
+        let mut cache = self.inode_cache.lock();
+        if let Some(inode) = cache.get(&ino) {
+            if inode.strong_count() == 0 {
+                cache.remove(&ino);
+            }
+        }
+    }
+
+    fn sync_meta(&self, meta: &Ext2Meta, group: usize) {
+        write_disk(&self.block_file, SUPERBLOCK_OFFSET, as_bytes(&meta.sb));
+        write_disk(&self.block_file,
+            self.group_desc_block as usize * self.block_size + group * size_of::<GroupDesc>(),
+            as_bytes(&meta.groups[group]));
+    }
+
+    /* 在位图中找到第一个为0的位并置1 */
+    fn alloc_bit(&self, bitmap: u32, limit: usize) -> Option<usize> {
+        let mut buf = vec![0u8; self.block_size];
+        self.read_block(bitmap, 0, &mut buf);
+        for (idx, byte) in buf.iter_mut().enumerate() {
+            if *byte == 0xff {
+                continue;
+            }
+            let bit = byte.trailing_ones() as usize;
+            let pos = idx * 8 + bit;
+            if pos >= limit {
+                return None;
+            }
+            *byte |= 1 << bit;
+            self.write_block(bitmap, idx, &[*byte]);
+            return Some(pos);
+        }
+        None
+    }
+
+    fn free_bit(&self, bitmap: u32, pos: usize) {
+        let mut byte = [0u8];
+        self.read_block(bitmap, pos / 8, &mut byte);
+        if byte[0] & (1 << (pos % 8)) == 0 {
+            warn!("ext2: free a free bit {} in bitmap {}", pos, bitmap);
+        }
+        byte[0] &= !(1 << (pos % 8));
+        self.write_block(bitmap, pos / 8, &byte);
+    }
+
+    /* 分配一个块并清零 */
+    pub fn alloc_block(&self) -> Result<u32, Error> {
+        let mut meta = self.meta.lock();
+        if meta.sb.free_blocks_count == 0 {
+            return Err(Error::ENOSPC);
+        }
+        let blocks_count = meta.sb.blocks_count;
+        for group in 0..meta.groups.len() {
+            if meta.groups[group].free_blocks_count == 0 {
+                continue;
+            }
+            let group_start = self.first_data_block + group as u32 * self.blocks_per_group;
+            let limit = (blocks_count - group_start).min(self.blocks_per_group) as usize;
+            if let Some(pos) = self.alloc_bit(meta.groups[group].block_bitmap, limit) {
+                meta.groups[group].free_blocks_count -= 1;
+                meta.sb.free_blocks_count -= 1;
+                self.sync_meta(&meta, group);
+                drop(meta);
+
+                let block = group_start + pos as u32;
+                let zero = vec![0u8; self.block_size];
+                self.write_block(block, 0, &zero);
+                return Ok(block);
+            }
+        }
+        Err(Error::ENOSPC)
+    }
+
+    pub fn free_block(&self, block: u32) {
+        let group = ((block - self.first_data_block) / self.blocks_per_group) as usize;
+        let pos = ((block - self.first_data_block) % self.blocks_per_group) as usize;
+        let mut meta = self.meta.lock();
+        self.free_bit(meta.groups[group].block_bitmap, pos);
+        meta.groups[group].free_blocks_count += 1;
+        meta.sb.free_blocks_count += 1;
+        self.sync_meta(&meta, group);
+    }
+
+    /* 分配一个inode号, inode的内容由调用者初始化 */
+    pub fn alloc_inode(&self, is_dir: bool) -> Result<u32, Error> {
+        let mut meta = self.meta.lock();
+        if meta.sb.free_inodes_count == 0 {
+            return Err(Error::ENOSPC);
+        }
+        let inodes_count = meta.sb.inodes_count;
+        for group in 0..meta.groups.len() {
+            if meta.groups[group].free_inodes_count == 0 {
+                continue;
+            }
+            let group_start = group as u32 * self.inodes_per_group;
+            let limit = (inodes_count - group_start).min(self.inodes_per_group) as usize;
+            if let Some(pos) = self.alloc_bit(meta.groups[group].inode_bitmap, limit) {
+                meta.groups[group].free_inodes_count -= 1;
+                if is_dir {
+                    meta.groups[group].used_dirs_count += 1;
+                }
+                meta.sb.free_inodes_count -= 1;
+                self.sync_meta(&meta, group);
+                return Ok(group_start + pos as u32 + 1);
+            }
+        }
+        Err(Error::ENOSPC)
+    }
+
+    pub fn free_inode(&self, ino: u32, is_dir: bool) {
+        let group = ((ino - 1) / self.inodes_per_group) as usize;
+        let pos = ((ino - 1) % self.inodes_per_group) as usize;
+        let mut meta = self.meta.lock();
+        self.free_bit(meta.groups[group].inode_bitmap, pos);
+        meta.groups[group].free_inodes_count += 1;
+        if is_dir {
+            meta.groups[group].used_dirs_count -= 1;
+        }
+        meta.sb.free_inodes_count += 1;
+        self.sync_meta(&meta, group);
+    }
+
+    /* 在文件系统内部按路径查找inode, 不跟随软连接 */
+    fn lookup(&self, path: &Path) -> Result<Arc<Ext2Inode>, Error> {
+        let mut inode = self.get_inode(ROOT_INO)?;
+        for name in path.iter() {
+            let ino = inode.lookup(name)?.ok_or(Error::ENOENT)?;
+            inode = self.get_inode(ino)?;
+        }
+        Ok(inode)
+    }
+}
+
+impl VFS for Ext2FileSystem {
+    fn root_dir(&self, mode: FileOpenMode) -> Result<Arc<dyn DirFile>, Error> {
+        Ext2File::new(self.get_inode(ROOT_INO)?, mode, self.mount_path.clone()).as_dir()
+    }
+
+    fn as_vfs<'a>(self: Arc<Self>) -> Arc<dyn VFS + 'a> where Self: 'a {
+        self
+    }
+
+    fn mount_path(&self) -> Path {
+        self.mount_path.clone()
+    }
+
+    /* 硬连接: 在path的父目录中添加一项, 指向dst_file的inode */
+    fn link(&self, path: Path, dst_file: Arc<dyn File>) -> Result<Arc<dyn File>, Error> {
+        let dst_file = dst_file
+            .as_any()
+            .downcast::<Ext2File>()
+            .map_err(|_| Error::EXDEV)?;
+        let inode = dst_file.inode.clone();
+        if inode.is_dir() {
+            return Err(Error::EPERM);
+        }
+
+        let parent = self.lookup(&path.remove_tail())?;
+        let _guard = parent.lock_dir();
+        parent.add_entry(path.last(), inode.ino, inode.dir_entry_type())?;
+        inode.inc_links();
+
+        let mut abs_path = self.mount_path.clone();
+        abs_path.extend(path.iter().cloned());
+        Ok(Ext2File::new(inode, FileOpenMode::SYS, abs_path))
+    }
+
+    fn statvfs(&self) -> Result<Statvfs, Error> {
+        let meta = self.meta.lock();
+        let sb = &meta.sb;
+        Ok(Statvfs {
+            bsize: self.block_size,
+            frsize: self.block_size,
+            blocks: sb.blocks_count as usize,
+            bfree: sb.free_blocks_count as usize,
+            bavail: sb.free_blocks_count.saturating_sub(sb.r_blocks_count) as usize,
+            files: sb.inodes_count as usize,
+            ffree: sb.free_inodes_count as usize,
+            favail: sb.free_inodes_count as usize,
+            namemax: 255,
+            fsid: self.id.0,
+            flag: 0,
+        })
+    }
+
+    /* inode, 位图和数据都经过块缓存读写, 写回设备上的脏块即可 */
+    fn sync(&self) -> Result<(), Error> {
+        block_cache_sync_dev(self.block_file.get_id());
+        Ok(())
+    }
+}
+
+/* 按字节读写块设备, 通过块缓存访问, 与设备的块大小无关 */
+fn read_disk(block_file: &Arc<dyn BlockFile>, offset: usize, buf: &mut [u8]) {
+    let dev_block_size = block_file.block_size();
+    let mut done = 0;
+    while done < buf.len() {
+        let pos = offset + done;
+        let start = pos % dev_block_size;
+        let len = (dev_block_size - start).min(buf.len() - done);
+        get_block_cache(pos / dev_block_size, block_file.clone())
+        .read()
+        .read_slice(|data: &[u8]| {
+            buf[done..done + len].copy_from_slice(&data[start..start + len]);
+        });
+        done += len;
+    }
+}
+
+fn write_disk(block_file: &Arc<dyn BlockFile>, offset: usize, buf: &[u8]) {
+    let dev_block_size = block_file.block_size();
+    let mut done = 0;
+    while done < buf.len() {
+        let pos = offset + done;
+        let start = pos % dev_block_size;
+        let len = (dev_block_size - start).min(buf.len() - done);
+        get_block_cache(pos / dev_block_size, block_file.clone())
+        .write()
+        .modify_slice(|data: &mut [u8]| {
+            data[start..start + len].copy_from_slice(&buf[done..done + len]);
+        });
+        done += len;
+    }
+}
+
+fn as_bytes<T>(value: &T) -> &[u8] {
+    unsafe { core::slice::from_raw_parts(value as *const T as *const u8, size_of::<T>()) }
+}
+
+fn as_bytes_mut<T>(value: &mut T) -> &mut [u8] {
+    unsafe { core::slice::from_raw_parts_mut(value as *mut T as *mut u8, size_of::<T>()) }
+}
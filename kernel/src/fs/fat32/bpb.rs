@@ -4,7 +4,7 @@
 #[repr(packed)]
 #[derive(Clone, Copy, Debug)]
 pub struct BPB {
-    pub bytes_per_sector        :u16,  //only support 512, otherwise panic
+    pub bytes_per_sector        :u16,  //512-4096, the file system accesses the device in sectors of this size
     pub sectors_per_cluster     :u8,
     pub reserved_sectors_num    :u16,  //The number of logical sectors before the first FAT in the file system image. 
     pub fat_num                 :u8,   //Number of File Allocation Tables. Almost always 2;
@@ -25,7 +25,7 @@ pub struct BPB {
 #[derive(Clone, Copy, Debug)]
 pub struct EBPB {
     pub sectors_per_table   :u32,
-    pub omit_1              :u16, //Drive description / mirroring flags 
+    pub ext_flags           :u16, //bit7为1时只使用bit0-3指定的FAT表, 否则所有FAT表互为镜像
     pub omit_2              :u16, //Version
     pub root_dir_cluster    :u32, //Cluster number of root directory start, typically 2 
     pub fsinfo_sector       :u16, //Logical sector number of FS Information Sector, typically 1
@@ -35,3 +35,160 @@ pub struct EBPB {
 } 
 
 
+
+// FS Information Sector
+// at: ebpb.fsinfo_sector
+// 保存空闲簇的数量和下一个空闲簇的提示, 值为0xFFFFFFFF时表示未知
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct FSInfo {
+    pub lead_sig            :u32, //0x41615252
+    pub reserved_1          :[u8; 480],
+    pub struct_sig          :u32, //0x61417272
+    pub free_count          :u32, //Last known free cluster count
+    pub next_free           :u32, //Hint for the next free cluster
+    pub reserved_2          :[u8; 12],
+    pub trail_sig           :u32, //0xAA550000
+}
+
+impl FSInfo {
+    pub const LEAD_SIG: u32 = 0x41615252;
+    pub const STRUCT_SIG: u32 = 0x61417272;
+    pub const TRAIL_SIG: u32 = 0xAA550000;
+    pub const UNKNOWN: u32 = 0xFFFFFFFF;
+
+    pub fn is_valid(&self) -> bool {
+        self.lead_sig == Self::LEAD_SIG && self.struct_sig == Self::STRUCT_SIG 
+            && self.trail_sig == Self::TRAIL_SIG
+    }
+}
+
+
+// FAT表项, 高4位是保留位
+pub const CLUSTER_MASK: u32 = 0x0FFF_FFFF;
+pub const CLUSTER_FREE: u32 = 0x0000_0000;
+pub const CLUSTER_END: u32 = 0x0FFF_FFF8;
+
+#[derive(Debug, PartialEq)]
+pub enum ClusterType {
+    Free,   //0x?0000000
+    Data,   //0x?0000002 - 0x?FFFFFEF
+    Bad,    //0x?FFFFFF7
+    End,    //0x?FFFFFF8 - 0x?FFFFFFF
+    Other,  //reserved / unuesd
+}
+
+pub fn cluster_type(cluster: u32) -> ClusterType {
+    let cluster = cluster & CLUSTER_MASK;
+    if cluster == CLUSTER_FREE {
+        ClusterType::Free
+    } else if (0x0000_0002..=0x0FFF_FFEF).contains(&cluster) {
+        ClusterType::Data
+    } else if cluster == 0x0FFF_FFF7 {
+        ClusterType::Bad
+    } else if cluster >= CLUSTER_END {
+        ClusterType::End
+    } else {
+        ClusterType::Other
+    }
+}
+
+
+/// 从boot sector中解析出的文件系统布局, 单位都是sector
+/// 挂载(FAT32FileSystem::init)和一致性检查(fsck)使用同一份解析和检查
+#[derive(Clone, Debug)]
+pub struct Geometry {
+    pub sector_size: usize,
+    pub sectors_per_cluster: usize,
+    pub total_sectors: usize,
+    pub fat_start: usize,           //第一个FAT表的起始sector, 即保留区的大小
+    pub fat_num: usize,
+    pub sectors_per_fat: usize,
+    pub active_fat: usize,          //使用中的FAT表
+    pub mirror: bool,               //其它FAT表是否是使用中的FAT表的镜像
+    pub data_start: usize,
+    pub cluster_count: u32,         //簇号范围为2..cluster_count+2
+    pub root_cluster: u32,
+    pub fsinfo_sector: Option<usize>,
+}
+
+impl Geometry {
+    const BPB_OFFSET: usize = 0x0B;
+    const EBPB_OFFSET: usize = 0x24;
+    const FLAG_OFFSET: usize = 0x1FE;
+
+    /// boot是boot sector的前512字节, BPB不合法时返回None
+    pub fn from_boot_sector(boot: &[u8]) -> Option<Self> {
+        if boot.len() < 512 {
+            return None;
+        }
+        let (bpb, ebpb, flag) = unsafe {(
+            *(&boot[Self::BPB_OFFSET] as *const u8 as *const BPB),
+            *(&boot[Self::EBPB_OFFSET] as *const u8 as *const EBPB),
+            u16::from_le_bytes([boot[Self::FLAG_OFFSET], boot[Self::FLAG_OFFSET + 1]]),
+        )};
+
+        let sector_size = bpb.bytes_per_sector as usize;
+        let sectors_per_cluster = bpb.sectors_per_cluster as usize;
+        let reserved = bpb.reserved_sectors_num as usize;
+        let fat_num = bpb.fat_num as usize;
+        let total_sectors = bpb.totol_sectors_num as usize;
+        let sectors_per_fat = ebpb.sectors_per_table as usize;
+        let data_start = reserved + fat_num * sectors_per_fat;
+        if flag != 0xAA55
+            || !sector_size.is_power_of_two() || !(512..=4096).contains(&sector_size)
+            || !sectors_per_cluster.is_power_of_two()
+            || reserved == 0 || fat_num == 0 || sectors_per_fat == 0
+            || total_sectors <= data_start
+        {
+            return None;
+        }
+
+        let cluster_count = ((total_sectors - data_start) / sectors_per_cluster) as u32;
+        let root_cluster = ebpb.root_dir_cluster;
+        if root_cluster < 2 || root_cluster >= cluster_count + 2
+            || sectors_per_fat * sector_size / 4 < cluster_count as usize + 2
+        {
+            return None;
+        }
+
+        /* ext_flags的bit7为1时不使用镜像, 只使用bit0-3指定的FAT表 */
+        let ext_flags = ebpb.ext_flags;
+        let mirror = ext_flags & 0x80 == 0;
+        let active_fat = if mirror { 0 } else { (ext_flags & 0xf) as usize };
+        if active_fat >= fat_num {
+            return None;
+        }
+        Some(Self {
+            sector_size,
+            sectors_per_cluster,
+            total_sectors,
+            fat_start: reserved,
+            fat_num,
+            sectors_per_fat,
+            active_fat,
+            mirror,
+            data_start,
+            cluster_count,
+            root_cluster,
+            fsinfo_sector: match ebpb.fsinfo_sector {
+                0 | 0xFFFF => None,
+                sector => Some(sector as usize),
+            },
+        })
+    }
+
+    pub fn cluster_bytes(&self) -> usize {
+        self.sectors_per_cluster * self.sector_size
+    }
+
+    //返回cluster的第一个sector
+    pub fn cluster_sector(&self, cluster: u32) -> usize {
+        self.data_start + (cluster - 2) as usize * self.sectors_per_cluster
+    }
+
+    //返回第fat个FAT表的起始sector
+    pub fn fat_sector(&self, fat: usize) -> usize {
+        self.fat_start + fat * self.sectors_per_fat
+    }
+}
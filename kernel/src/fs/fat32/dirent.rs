@@ -1,225 +1,22 @@
 use super::file::Fat32FileInner;
-use super::{BytesTable, FAT32FileSystem, SECTOR_SIZE, DirentTable};
+use super::FAT32FileSystem;
+use super::disk_dirent::{Attribute, RawDiskDirEntry, DiskDirEntry, DiskLongDirEntry, TimeStamp};
 use crate::fs::{FileType, BlockFile, get_block_cache};
+use crate::fs::block_cache::prefetch_blocks;
 use crate::utils::Error;
 use crate::utils::mem_buffer::MemBuffer;
 use alloc::{
+    vec,
     vec::Vec,
     sync::Arc,
     string::String,
+    format,
 };
 use spin::{RwLock, MutexGuard};
 use log::*;
 use crate::config::MAX_FILE_SIZE;
 use crate::syscall::time::Timespec;
 
-#[derive(Debug, Clone, Copy, PartialEq)]
-pub struct Attribute(pub u8);
-
-#[repr(packed)]
-#[derive(Clone, Copy, Debug)]
-pub struct RawDiskDirEntry(pub [u8;32]);
-
-
-#[repr(packed)]
-#[derive(Clone, Copy, Debug, Default)]
-#[allow(unused)]
-pub struct DiskDirEntry {
-    name: [u8; 8],
-    ext:  [u8; 3],
-    attribute   :u8,
-    reserved    :u8,
-    omit        :u8,
-    create_time :u16,
-    create_date :u16,
-    access_date :u16,
-    cluster_high:u16,
-    modify_time :u16,
-    modify_date :u16,
-    cluster_low :u16,
-    size        :u32,      
-}
-
-
-#[repr(packed)]
-#[derive(Clone, Copy, Debug, Default)]
-#[allow(unused)]
-pub struct DiskLongDirEntry {
-    seq_num     :u8,
-    name_1      :[u8; 10],
-    attribute   :u8,        //always 0x0F
-    dir_type    :u8,        //always 0x00 for VFAT LFN
-    check_sum   :u8,
-    name_2      :[u8; 12],
-    cluster     :u16,       //always 0x0000
-    name_3      :[u8; 4],
-}
-
-#[allow(unused)]
-impl RawDiskDirEntry {
-    pub fn new() -> Self {
-        Self([0;32])
-    }
-
-    pub fn is_lfn(&self) -> bool {
-        pub const ATTRIBUTE_OFFSET: usize = 0xB;
-        pub const LFN: u8 = 0xf;
-        Attribute::from(self.0[ATTRIBUTE_OFFSET]).is_lfn()
-    }
-
-    //是否是有效目录项（没有被删除，不是空闲）
-    pub fn is_valid(&self) -> bool {
-        self.is_lfn() || !(self.0[0] == 0x00 || self.0[0] == 0xE5)
-    }
-
-    pub fn is_delete(&self) -> bool {
-        !self.is_lfn() && (self.0[0] == 0xE5)
-    }
-
-    pub fn is_empty(&self) -> bool {
-        !self.is_lfn() && (self.0[0] == 0x00)
-    }
-}
-
-
-#[allow(unused)]
-impl DiskDirEntry {
-    pub fn new() -> Self {
-        let mut new: Self = Default::default();
-        new.name[0] = 0xab; //将第一个字节设置为随机数，防止当成空闲页表项
-        new
-    }
-
-    pub fn is_dir(&self) -> bool {
-        Attribute::from(self.attribute).is_dir()
-    }
-
-    pub fn is_free(&self) -> bool {
-        self.name[0] == 0xE5 || self.name[0] == 0x00
-    }   
-
-    pub fn is_delete(&self) -> bool {
-        self.name[0] == 0xE5
-    }
-
-    pub fn is_empty(&self) -> bool {
-        self.name[0] == 0x00
-    }
-
-    pub fn get_name(&self) -> Result<String, Error> {
-        pub const SPACE: u8 = 0x20;
-
-        let mut name = String::new();
-        let mut ext =  String::new();
-        match core::str::from_utf8(&self.name) {
-            Ok(name1) => name += name1.trim(),
-            Err(_) => return Err(Error::ESTRING),
-        }
-
-        match core::str::from_utf8(&self.ext) {
-            Ok(ext1) => ext += ext1.trim(),
-            Err(_) => return Err(Error::ESTRING),
-        } 
-        
-        if ext.len() > 0 {
-            name += ".";
-            name += &ext;
-        }
-        
-        Ok(name)
-    }
-
-    pub fn set_name(&mut self, base: &[u8], ext: &[u8]) {
-        assert!(base.len() == 8);
-        assert!(ext.len() == 3);
-        self.name.copy_from_slice(base);
-        self.ext.copy_from_slice(ext);
-    }
-
-    pub fn set_size(&mut self, size: u32) {
-        self.size = size;
-    }
-
-    pub fn set_attr(&mut self, attr: u8) {
-        self.attribute = attr;
-    } 
-
-    pub fn set_delete(&mut self) {
-        *self = Default::default();
-        self.name[0] = 0xE5;
-    }
-
-    // 只有在创建文件的时候才会调用
-    pub fn set_cluster(&mut self, cluster: u32) {
-        self.cluster_high = ((cluster & 0xFFFF0000) >> 16) as u16;
-        self.cluster_low = (cluster & 0x0000FFFF) as u16;
-    }
-
-    pub fn get_cluster(&self) -> u32 {
-        ((self.cluster_high as u32) << 16) + (self.cluster_low as u32)
-    }   
-}   
-
-
-impl DiskLongDirEntry {
-    const END: u8 = 0x40;
-
-    pub fn new() -> Self {
-        let mut dentry: Self = Default::default();
-        dentry.attribute = 0x0F;
-        dentry.dir_type = 0x00;
-        dentry.cluster = 0x0000;
-        dentry
-    }
-
-    pub fn is_end(&self) -> bool {
-        self.seq_num & DiskLongDirEntry::END != 0
-    }
-
-    pub fn set_end(&mut self) {
-        self.seq_num |= DiskLongDirEntry::END;
-    }
-
-    pub fn get_name(&self) -> Vec<u8> {
-        const END: u8 = 0xff;
-        let mut name = Vec::with_capacity(26);
-        for c in self.name_1 {
-            if c == END {
-                return name;
-            } else {
-                name.push(c);
-            }
-        }
-
-        for c in self.name_2 {
-            if c == END {
-                return name;
-            } else {
-                name.push(c);
-            }
-        }
-
-        for c in self.name_3 {
-            if c == END {
-                return name;
-            } else {
-                name.push(c);
-            }
-        }
-
-        return name
-    }
-
-    pub fn set_name(&mut self, name: &[u8]) {
-        assert!(name.len() == 26);
-        self.name_1.copy_from_slice(&name[0..10]);
-        self.name_2.copy_from_slice(&name[10..22]);
-        self.name_3.copy_from_slice(&name[22..26]);
-    }
-
-
-}
-
 pub struct Dirent {
     pub name: String,
     pub sector: usize,              //direntry所在sector
@@ -232,21 +29,59 @@ pub struct Dirent {
     pub mtime: Timespec,
     pub ctime: Timespec,
     pub delete: bool,       
-    pub cluster_list: RwLock<Vec<u32>>,  
+    pub cluster_list: RwLock<ClusterList>,  
     pub fs: Option<Arc<FAT32FileSystem>>,
     //pub path
 }
 
-// clusterlist是文件的簇缓存
-// list[i]保存了文件第i个块的簇号
-// list是动态生成的, 如果要获取文件的第i个簇, 先在clusterlist中寻找,
-// 如果没找到，就去FAT表中遍历，并把结果写入ClusterList中。
-// 然而k210只有8M的内存，如果文件过大，那么clusterlist会占用过多内存
-// 所以如果list.len()过大，那么就暂停缓存。
-// 而是用last记录上一次的访问的块号及其簇号
-struct ClusterList {
-    pub list: Vec<u32>,
-    pub last: Option<(u32, u32)>,
+// ClusterList是文件的簇链缓存
+// 簇链按连续的簇分段保存, extents[i] = (段中第一个簇在文件中的序号, 起始簇号, 簇数)
+// 大多数文件的簇是连续分配的, 只需要很少的段, k210只有8M内存, 不能为每个簇保存一项
+// 簇链是动态生成的, 如果要获取文件的第i个簇, 先在ClusterList中二分查找,
+// 如果没找到，就从已知的最后一个簇开始遍历FAT表，并把结果写入ClusterList中。
+pub struct ClusterList {
+    extents: Vec<(usize, u32, usize)>,
+}
+
+impl ClusterList {
+    pub fn new(start_cluster: u32) -> Self {
+        Self {
+            extents: vec![(0, start_cluster, 1)],
+        }
+    }
+
+    // 已知的簇数
+    pub fn len(&self) -> usize {
+        self.extents.last().map_or(0, |&(index, _, len)| index + len)
+    }
+
+    pub fn last(&self) -> u32 {
+        let &(_, start, len) = self.extents.last().unwrap();
+        start + len as u32 - 1
+    }
+
+    pub fn push(&mut self, cluster: u32) {
+        let len = self.len();
+        match self.extents.last_mut() {
+            Some(last) if last.1 + last.2 as u32 == cluster => last.2 += 1,
+            _ => self.extents.push((len, cluster, 1)),
+        }
+    }
+
+    // 文件第num个簇的簇号, 以及从它开始连续的已知簇数
+    pub fn get(&self, num: usize) -> Option<(u32, usize)> {
+        let i = match self.extents.binary_search_by_key(&num, |&(index, _, _)| index) {
+            Ok(i) => i,
+            Err(0) => return None,
+            Err(i) => i - 1,
+        };
+        let (index, start, len) = self.extents[i];
+        if num < index + len {
+            Some((start + (num - index) as u32, index + len - num))
+        } else {
+            None
+        }
+    }
 }
 
 
@@ -265,7 +100,7 @@ impl Dirent {
             atime: Timespec::ZERO,
             mtime: Timespec::ZERO,
             ctime: Timespec::ZERO,
-            cluster_list: RwLock::new(Vec::new()),
+            cluster_list: RwLock::new(ClusterList { extents: Vec::new() }),
             fs: None,
         }
     } 
@@ -274,14 +109,12 @@ impl Dirent {
         trace!("init fat32 root dirent");
         assert!(self.sector == 0 && self.offset == 0);
 
-        let mut root_cluster_list = Vec::new();
-        root_cluster_list.push(2);
-
+        let root_cluster = fs.root_cluster();
         self.fs = Some(fs);
         self.name = String::from("/");
-        self.start_cluster = 2;
+        self.start_cluster = root_cluster;
         self.attribute = 0x10;      //dir
-        self.cluster_list.write().push(2);
+        *self.cluster_list.write() = ClusterList::new(root_cluster);
         self.size = usize::MAX;
         
         let mut offset = 0;
@@ -317,6 +150,7 @@ impl Dirent {
     fn get_raw_dentry(&self, mut abs_offset: usize) -> Result<(Vec<RawDiskDirEntry>, usize), Error> {
         const DIRENT_SIZE: usize = 0x20;
         assert!(abs_offset % DIRENT_SIZE == 0);
+        let sector_size = self.get_fs().sector_size();
 
         let mut raw_direntry_buf: Vec<RawDiskDirEntry> = Vec::new();
         let mut is_finish: bool = false;
@@ -327,10 +161,10 @@ impl Dirent {
 
             get_block_cache(sector, self.get_fs().block_file.clone())
             .read()
-            .read(0, |dirent_table: &DirentTable|{
+            .read_slice(|dirent_table: &[RawDiskDirEntry]|{
                 let start_idx = sector_offset / DIRENT_SIZE;
 
-                for idx in start_idx..16 {  
+                for idx in start_idx..dirent_table.len() {  
                     sector_offset += 0x20;
                     //trace!("idx = {}", idx);
                     if dirent_table[idx].is_lfn() {
@@ -353,13 +187,13 @@ impl Dirent {
                 if raw_direntry_buf.len() == 0 {
                     return Err(Error::DENTRYEND);
                 } else {
-                    let sector_start_offsrt = (abs_offset / SECTOR_SIZE) * SECTOR_SIZE;
+                    let sector_start_offsrt = (abs_offset / sector_size) * sector_size;
                     return Ok((raw_direntry_buf, sector_start_offsrt + sector_offset));
                 }
             }
 
             //将abs_offset设置为下一个sector的起始位置
-            abs_offset = ((abs_offset / SECTOR_SIZE) + 1) * SECTOR_SIZE;
+            abs_offset = ((abs_offset / sector_size) + 1) * sector_size;
         }
     }
 
@@ -372,6 +206,10 @@ impl Dirent {
         let mut short_dentry = DiskDirEntry::new();
         short_dentry.set_cluster(cluster);
         short_dentry.set_attr(attr);
+        let now = Timespec::now();
+        short_dentry.set_atime(&now);
+        short_dentry.set_mtime(&now);
+        short_dentry.set_ctime(&now);
 
         if let Some((base, ext)) = split_shortname(name) {
             trace!("{} is splited into {} and {}", name, base, ext);
@@ -387,11 +225,18 @@ impl Dirent {
             let len = names.len();
 
             trace!("{} is splited into a vec, len = {}", name, len);
+
+            /* 短文件名由簇号生成, 同一目录下不会重复 */
+            let alias = format!("~{:07X}", cluster);
+            short_dentry.set_name(alias.as_bytes(), b"   ");
+            let check_sum = short_dentry.check_sum();
             
             for i in 0..len {
                 let name = names.pop().unwrap();
                 let mut long_dentry = DiskLongDirEntry::new();
                 long_dentry.set_name(name.as_slice());
+                long_dentry.set_seq((len - i) as u8);
+                long_dentry.set_check_sum(check_sum);
                 if i == 0 {
                     long_dentry.set_end();
                 }
@@ -437,8 +282,7 @@ impl Dirent {
         let mut long_name = Vec::new();
 
         let raw_short_dentry = raw_dentry_vec.pop().unwrap();
-        let short_dentry = 
-            unsafe{&*(&raw_short_dentry as * const RawDiskDirEntry as *const DiskDirEntry)};
+        let short_dentry = raw_short_dentry.as_short();
         assert!(!raw_short_dentry.is_lfn());
     
         //如果vec长度为1，那么名字仅由一个short_name_dentry构成。否则由多个long_name_dentry构成
@@ -455,8 +299,7 @@ impl Dirent {
                 }
                 let raw_long_dentry = raw_dentry_vec.pop().unwrap();
                 assert!(raw_long_dentry.is_lfn());
-                let long_dentry = 
-                    unsafe{&*(&raw_long_dentry as * const RawDiskDirEntry as *const DiskLongDirEntry)};
+                let long_dentry = raw_long_dentry.as_long();
                 long_name.append(&mut long_dentry.get_name());
                 if long_dentry.is_end() {
                     //assert!(raw_dentry_vec.len() == 0);
@@ -484,21 +327,20 @@ impl Dirent {
         let (sector, offset) = self.pos_of_offset_byte(short_dentry_offset).unwrap();
         
         let start_cluster = short_dentry.get_cluster();
-        let mut cluster_list = Vec::new();
-        cluster_list.push(start_cluster);    
+        let (atime, mtime, ctime) = short_dentry.get_time();
         let dirent = Dirent {
             name,
             sector,
             offset,
-            attribute: short_dentry.attribute,
+            attribute: short_dentry.get_attr().into(),
             start_cluster: start_cluster,
             long_direntry_num: (len - 1) as usize,
-            size: short_dentry.size as usize,
-            atime: Timespec::ZERO,
-            mtime: Timespec::ZERO,
-            ctime: Timespec::ZERO,
+            size: short_dentry.get_size() as usize,
+            atime,
+            mtime,
+            ctime,
             delete: false,
-            cluster_list: RwLock::new(cluster_list),
+            cluster_list: RwLock::new(ClusterList::new(start_cluster)),
             fs: Some(self.fs.clone().unwrap())
         };
         
@@ -543,14 +385,18 @@ impl Dirent {
 
     pub fn read_at(&self, offset: usize, buf: &mut [u8]) -> Result<usize, Error> {
         let mut start = offset;
+        let sector_size = self.get_fs().sector_size();
         let end = (offset + buf.len()).min(self.size);
         let mut read_size = 0;
+        if start >= end {
+            return Ok(0);
+        }
         loop {
             let (sector, block_start) = self.pos_of_offset_byte(start).unwrap();
-            let block_end = (block_start + end - start).min(512);
+            let block_end = (block_start + end - start).min(sector_size);
             get_block_cache(sector, self.get_fs().block_file.clone())
             .read()
-            .read(0, |byte_table: &BytesTable|{
+            .read_slice(|byte_table: &[u8]|{
                 let src = &byte_table[block_start..block_end];
                 let dst = &mut buf[read_size..(read_size + block_end - block_start)];
                 dst.copy_from_slice(src);
@@ -566,20 +412,50 @@ impl Dirent {
         Ok(read_size)
     }
 
-    pub fn write_at(&mut self, offset: usize, buf: &[u8]) -> Result<usize, Error> {
+    // 将[offset, offset + len)所在的块预读到块缓存中, 每段连续的簇合并为一次设备请求
+    pub fn prefetch(&self, offset: usize, len: usize) {
+        let fs = self.get_fs();
+        let bytes_per_cluster = fs.get_bytes_per_cluster();
+        let sector_size = fs.sector_size();
+        let end = offset.saturating_add(len).min(self.size);
+        if offset >= end || self.get_cluster((end - 1) / bytes_per_cluster).is_err() {
+            return;
+        }
         let mut start = offset;
+        while start < end {
+            let (cluster, run) = self.get_cluster_run(start / bytes_per_cluster).unwrap();
+            let run_end = ((start / bytes_per_cluster + run) * bytes_per_cluster).min(end);
+            let sector = fs.get_cluster_start_sector(cluster) + (start % bytes_per_cluster) / sector_size;
+            let count = (run_end - start + start % sector_size + sector_size - 1) / sector_size;
+            prefetch_blocks(sector, count, fs.block_file.clone());
+            start = run_end;
+        }
+    }
+
+    pub fn write_at(&mut self, offset: usize, buf: &[u8]) -> Result<usize, Error> {
         self.increase_size_to(offset + buf.len())?;
+        let write_size = self.write_data_at(offset, buf);
+        self.touch_mtime();
+        Ok(write_size)
+    }
 
+    // 写入文件已有的部分, 不改变文件长度和修改时间, 用于页缓存的写回
+    pub fn write_data_at(&self, offset: usize, buf: &[u8]) -> usize {
+        let mut start = offset;
+        let sector_size = self.get_fs().sector_size();
         let end = (offset + buf.len()).min(self.size);
         let mut write_size = 0;
+        if start >= end {
+            return 0;
+        }
         
         loop {
             let (sector, block_start) = self.pos_of_offset_byte(start).unwrap();
-            let block_end = (block_start + end - start).min(512);
+            let block_end = (block_start + end - start).min(sector_size);
 
             get_block_cache(sector, self.get_fs().block_file.clone())
             .write()
-            .modify(0, |byte_table: &mut BytesTable|{
+            .modify_slice(|byte_table: &mut [u8]|{
                 let src = &buf[write_size..(write_size + block_end - block_start)];
                 let dst = &mut byte_table[block_start..block_end];
                 dst.copy_from_slice(src);
@@ -592,21 +468,21 @@ impl Dirent {
                 break;
             }
         }
-
-        Ok(write_size)
+        write_size
     }
 
     pub fn read_to_buffer(&self, offset: usize, mut buf: MemBuffer) -> Result<usize, Error> {
         let mut start = offset;
+        let sector_size = self.get_fs().sector_size();
         let end = (offset + buf.len()).min(self.size);
         let mut read_size = 0;
         loop {
             let (sector, block_start) = self.pos_of_offset_byte(start).unwrap();
-            let block_end = (block_start + end - start).min(512);
+            let block_end = (block_start + end - start).min(sector_size);
 
             get_block_cache(sector, self.get_fs().block_file.clone())
             .read()
-            .read(0, |byte_table: &BytesTable|{
+            .read_slice(|byte_table: &[u8]|{
                 let src = &byte_table[block_start..block_end];
                 buf.read_data_to_buffer(src);
             });
@@ -623,6 +499,7 @@ impl Dirent {
 
     pub fn write_from_buffer(&mut self, offset: usize, mut buf: MemBuffer) -> Result<usize, Error> {
         let mut start = offset;
+        let sector_size = self.get_fs().sector_size();
         self.increase_size_to(offset + buf.len())?;
 
         let end = (offset + buf.len()).min(self.size);
@@ -630,11 +507,11 @@ impl Dirent {
         
         loop {
             let (sector, block_start) = self.pos_of_offset_byte(start).unwrap();
-            let block_end = (block_start + end - start).min(512);
+            let block_end = (block_start + end - start).min(sector_size);
 
             get_block_cache(sector, self.get_fs().block_file.clone())
             .write()
-            .modify(0, |byte_table: &mut BytesTable|{
+            .modify_slice(|byte_table: &mut [u8]|{
                 let dst = &mut byte_table[block_start..block_end];
                 buf.write_data_from_buffer(dst);
             });
@@ -647,20 +524,22 @@ impl Dirent {
             }
         }
     
+        self.touch_mtime();
         Ok(write_size)
     }
 
     pub fn clear_at(&mut self, offset: usize, len: usize) -> Result<(), Error> {
         let mut start = offset;
+        let sector_size = self.get_fs().sector_size();
         let end = (offset + len).min(self.size);
 
         loop {
             let (sector, block_start) = self.pos_of_offset_byte(start).unwrap();
-            let block_end = (block_start + end - start).min(512);
+            let block_end = (block_start + end - start).min(sector_size);
 
             get_block_cache(sector, self.get_fs().block_file.clone())
             .write()
-            .modify(0, |byte_table: &mut BytesTable|{
+            .modify_slice(|byte_table: &mut [u8]|{
                 byte_table[block_start..block_end].fill(0);
             });
 
@@ -708,9 +587,46 @@ impl Dirent {
         self.increse_size(size - self.size)
     }
     
-    // 这是目前唯一的会改变自己的存储在磁盘上的目录项的方法
+    // 根目录没有目录项, 不保存时间信息
+    fn is_root(&self) -> bool {
+        self.sector == 0 && self.offset == 0
+    }
+
+    // 将内存中的时间写回磁盘上的目录项
+    pub fn sync_time(&self) {
+        if self.is_root() {
+            return;
+        }
+        get_block_cache(self.sector, self.get_fs().block_file.clone())
+        .write()
+        .modify(self.offset, |dentry: &mut DiskDirEntry|{
+            dentry.set_atime(&self.atime);
+            dentry.set_mtime(&self.mtime);
+            dentry.set_ctime(&self.ctime);
+        });
+    }
+
+    pub fn touch_mtime(&mut self) {
+        self.mtime = Timespec::now();
+        self.sync_time();
+    }
+
+    // 磁盘上的访问时间只精确到天, 日期变化时才需要写回
+    pub fn touch_atime(&mut self) {
+        let old = TimeStamp::from_timespec(&self.atime);
+        self.atime = Timespec::now();
+        let new = TimeStamp::from_timespec(&self.atime);
+        if (old.year, old.month, old.day) != (new.year, new.month, new.day) {
+            self.sync_time();
+        }
+    }
+
+    // 改变自己的存储在磁盘上的目录项中的文件长度
     pub fn set_size(&mut self, new_size: usize) {
         self.size = new_size;
+        if self.is_root() {
+            return;
+        }
         get_block_cache(self.sector, self.get_fs().block_file.clone())
         .write()
         .modify(self.offset, |dentry: &mut DiskDirEntry|{
@@ -776,9 +692,10 @@ impl Dirent {
     pub fn pos_of_offset_byte(&self, offset: usize) -> Result<(usize, usize), Error> {
         let cluster = self.cluster_of_offset_byte(offset)?;
         let start_sector = self.get_fs().get_cluster_start_sector(cluster);
-        let sector_offset = (offset % self.get_fs().get_bytes_per_cluster()) / 512;
+        let sector_size = self.get_fs().sector_size();
+        let sector_offset = (offset % self.get_fs().get_bytes_per_cluster()) / sector_size;
         let sector = start_sector + sector_offset;
-        let offset = offset % 512;
+        let offset = offset % sector_size;
         Ok((sector, offset))
     }
 
@@ -792,21 +709,24 @@ impl Dirent {
 
     // 获取文件第num个cluster的序号 (num从0开始数)
     pub fn get_cluster(&self, num: usize) -> Result<u32, Error> {
-        let list = self.cluster_list.upgradeable_read();
-        let list_len = list.len();
+        Ok(self.get_cluster_run(num)?.0)
+    }
 
-        if list_len > num {
-            return Ok(list[num])
+    // 获取文件第num个cluster的序号, 以及从它开始在簇链缓存中连续的簇数
+    pub fn get_cluster_run(&self, num: usize) -> Result<(u32, usize), Error> {
+        let list = self.cluster_list.upgradeable_read();
+        if let Some(run) = list.get(num) {
+            return Ok(run)
         }
 
         let mut list = list.upgrade();
-        let mut start_cluster = list[list_len - 1];
-        for _ in list_len..num + 1 {
+        let mut start_cluster = list.last();
+        for _ in list.len()..num + 1 {
             let cluster = self.get_fs().get_next_cluster(start_cluster)?;
             list.push(cluster);
             start_cluster = cluster;
         }
-        Ok(start_cluster)
+        Ok(list.get(num).unwrap())
     }
 
     pub fn get_dirents(&self, cursor: &mut MutexGuard<Fat32FileInner>) -> Result<Vec<Dirent>, Error> {
@@ -860,24 +780,14 @@ impl Dirent {
 
 
 
-impl From<u8> for Attribute {
-    fn from(u: u8) -> Self {
-        Self(u)
-    } 
-}
-
-impl From<Attribute> for u8 {
-    fn from(u: Attribute) -> u8 {
-        u.0
-    }
-}
-
 impl From<Attribute> for FileType {
     fn from(a: Attribute) -> FileType {
         if a.is_dir() {
             FileType::Directory
         } else if a.is_link() {
             FileType::LinkFile
+        } else if a.is_sock() {
+            FileType::SocketFile
         } else if !a.is_lfn() {
             FileType::RegularFile
         } else {
@@ -887,52 +797,6 @@ impl From<Attribute> for FileType {
 }
 
 
-impl Attribute {
-    const DIR   : u8 = 0b00010000;
-    const LFN   : u8 = 0b00001111;
-    const LINK  : u8 = 0b01000000;
-    // dir  :0bxxx1xxxx
-    // lfn  :0bxxxx1111
-    // link :0bx1xxxxxx
-
-    pub fn new() -> Self{
-        Self(0)
-    }
-
-    pub fn clear(&mut self) {
-        self.0 = 0
-    }
-
-    pub fn is_dir(&self) -> bool {
-        (!self.is_lfn()) && (self.0 & Attribute::DIR != 0)
-    }
-
-    pub fn is_lfn(&self) -> bool {
-        (self.0 & 0xf) == Attribute::LFN
-    }
-
-    pub fn is_link(&self) -> bool {
-        (self.0 & Attribute::LINK) != 0
-    }
-
-    pub fn set_dir(&mut self) {
-        // assert_eq!(self.0, 0);
-        self.0 |= Attribute::DIR;
-        //assert!(!self.is_lfn());
-    }
-
-    pub fn set_lfn(&mut self) {
-        // assert_eq!(self.0, 0);
-        self.0 |= Attribute::LFN;
-    }
-
-    pub fn set_link(&mut self) {
-        // assert_eq!(self.0, 0);
-        self.0 |= Attribute::LINK;
-    }
-
-}
-
 
 
 //split long name into LFN vector
@@ -1005,17 +869,3 @@ pub fn split_shortname(name: &str) -> Option<(String, String)> {
 
     Some((base, ext))
 }
-
-
-
-
-#[repr(packed)]
-#[derive(Clone, Copy, Debug)]
-pub struct TimeStamp {
-    pub data    :u8,
-    pub mouth   :u8,
-    pub day     :u8,
-    pub hour    :u8,
-    pub minute  :u8,
-    pub secound :u8,
-}
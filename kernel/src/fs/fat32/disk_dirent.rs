@@ -0,0 +1,468 @@
+// FAT32目录项在磁盘上的格式
+// 与内核的其它部分无关, tools/fat32-fsck也直接包含这个文件
+
+use crate::utils::Error;
+use crate::config::{FAT32_EPOCH_OFFSET, FAT32_TIMEZONE_OFFSET};
+use crate::syscall::time::Timespec;
+use alloc::{
+    vec::Vec,
+    string::String,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Attribute(pub u8);
+
+#[repr(packed)]
+#[derive(Clone, Copy, Debug)]
+pub struct RawDiskDirEntry(pub [u8;32]);
+
+
+#[repr(packed)]
+#[derive(Clone, Copy, Debug, Default)]
+#[allow(unused)]
+pub struct DiskDirEntry {
+    name: [u8; 8],
+    ext:  [u8; 3],
+    attribute   :u8,
+    reserved    :u8,
+    create_time_tenth :u8,  //创建时间中10ms的部分, 0-199
+    create_time :u16,
+    create_date :u16,
+    access_date :u16,
+    cluster_high:u16,
+    modify_time :u16,
+    modify_date :u16,
+    cluster_low :u16,
+    size        :u32,      
+}
+
+
+#[repr(packed)]
+#[derive(Clone, Copy, Debug, Default)]
+#[allow(unused)]
+pub struct DiskLongDirEntry {
+    seq_num     :u8,
+    name_1      :[u8; 10],
+    attribute   :u8,        //always 0x0F
+    dir_type    :u8,        //always 0x00 for VFAT LFN
+    check_sum   :u8,
+    name_2      :[u8; 12],
+    cluster     :u16,       //always 0x0000
+    name_3      :[u8; 4],
+}
+
+#[allow(unused)]
+impl RawDiskDirEntry {
+    pub fn new() -> Self {
+        Self([0;32])
+    }
+
+    pub fn is_lfn(&self) -> bool {
+        pub const ATTRIBUTE_OFFSET: usize = 0xB;
+        pub const LFN: u8 = 0xf;
+        Attribute::from(self.0[ATTRIBUTE_OFFSET]).is_lfn()
+    }
+
+    //是否是有效目录项（没有被删除，不是空闲）
+    pub fn is_valid(&self) -> bool {
+        self.is_lfn() || !(self.0[0] == 0x00 || self.0[0] == 0xE5)
+    }
+
+    pub fn is_delete(&self) -> bool {
+        !self.is_lfn() && (self.0[0] == 0xE5)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        !self.is_lfn() && (self.0[0] == 0x00)
+    }
+
+    pub fn as_short(&self) -> &DiskDirEntry {
+        unsafe{&*(self as *const RawDiskDirEntry as *const DiskDirEntry)}
+    }
+
+    pub fn as_short_mut(&mut self) -> &mut DiskDirEntry {
+        unsafe{&mut *(self as *mut RawDiskDirEntry as *mut DiskDirEntry)}
+    }
+
+    pub fn as_long(&self) -> &DiskLongDirEntry {
+        unsafe{&*(self as *const RawDiskDirEntry as *const DiskLongDirEntry)}
+    }
+
+    // 将一段磁盘数据看作目录项数组, 长度必须是0x20的整数倍
+    pub fn table(bytes: &[u8]) -> &[RawDiskDirEntry] {
+        assert_eq!(bytes.len() % 0x20, 0);
+        unsafe{core::slice::from_raw_parts(bytes.as_ptr() as *const RawDiskDirEntry, bytes.len() / 0x20)}
+    }
+
+    pub fn table_mut(bytes: &mut [u8]) -> &mut [RawDiskDirEntry] {
+        assert_eq!(bytes.len() % 0x20, 0);
+        unsafe{core::slice::from_raw_parts_mut(bytes.as_mut_ptr() as *mut RawDiskDirEntry, bytes.len() / 0x20)}
+    }
+}
+
+
+#[allow(unused)]
+impl DiskDirEntry {
+    pub fn new() -> Self {
+        let mut new: Self = Default::default();
+        new.name[0] = 0xab; //将第一个字节设置为随机数，防止当成空闲页表项
+        new
+    }
+
+    pub fn is_dir(&self) -> bool {
+        Attribute::from(self.attribute).is_dir()
+    }
+
+    pub fn is_free(&self) -> bool {
+        self.name[0] == 0xE5 || self.name[0] == 0x00
+    }   
+
+    pub fn is_delete(&self) -> bool {
+        self.name[0] == 0xE5
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.name[0] == 0x00
+    }
+
+    pub fn get_name(&self) -> Result<String, Error> {
+        pub const SPACE: u8 = 0x20;
+
+        let mut name = String::new();
+        let mut ext =  String::new();
+        match core::str::from_utf8(&self.name) {
+            Ok(name1) => name += name1.trim(),
+            Err(_) => return Err(Error::ESTRING),
+        }
+
+        match core::str::from_utf8(&self.ext) {
+            Ok(ext1) => ext += ext1.trim(),
+            Err(_) => return Err(Error::ESTRING),
+        } 
+        
+        if ext.len() > 0 {
+            name += ".";
+            name += &ext;
+        }
+        
+        Ok(name)
+    }
+
+    pub fn set_name(&mut self, base: &[u8], ext: &[u8]) {
+        assert!(base.len() == 8);
+        assert!(ext.len() == 3);
+        self.name.copy_from_slice(base);
+        self.ext.copy_from_slice(ext);
+    }
+
+    pub fn get_size(&self) -> u32 {
+        self.size
+    }
+
+    pub fn set_size(&mut self, size: u32) {
+        self.size = size;
+    }
+
+    pub fn get_attr(&self) -> Attribute {
+        Attribute::from(self.attribute)
+    }
+
+    pub fn set_attr(&mut self, attr: u8) {
+        self.attribute = attr;
+    } 
+
+    // 长文件名目录项中保存的短文件名校验和
+    pub fn check_sum(&self) -> u8 {
+        self.name.iter().chain(self.ext.iter()).fold(0u8, |sum, &ch| {
+            ((sum & 1) << 7).wrapping_add(sum >> 1).wrapping_add(ch)
+        })
+    }
+
+    pub fn set_delete(&mut self) {
+        *self = Default::default();
+        self.name[0] = 0xE5;
+    }
+
+    // 只有在创建文件的时候才会调用
+    pub fn set_cluster(&mut self, cluster: u32) {
+        self.cluster_high = ((cluster & 0xFFFF0000) >> 16) as u16;
+        self.cluster_low = (cluster & 0x0000FFFF) as u16;
+    }
+
+    pub fn get_cluster(&self) -> u32 {
+        ((self.cluster_high as u32) << 16) + (self.cluster_low as u32)
+    }   
+
+    // 返回(访问时间, 修改时间, 创建时间), 访问时间只精确到天
+    pub fn get_time(&self) -> (Timespec, Timespec, Timespec) {
+        let atime = TimeStamp::from_fat(self.access_date, 0).to_timespec();
+        let mtime = TimeStamp::from_fat(self.modify_date, self.modify_time).to_timespec();
+        let mut ctime = TimeStamp::from_fat(self.create_date, self.create_time).to_timespec();
+        let tenth = self.create_time_tenth.min(199) as isize;
+        ctime.tv_sec += tenth / 100;
+        ctime.tv_nsec = (tenth % 100) * 10_000_000;
+        (atime, mtime, ctime)
+    }
+
+    pub fn set_atime(&mut self, atime: &Timespec) {
+        let (date, _) = TimeStamp::from_timespec(atime).to_fat();
+        self.access_date = date;
+    }
+
+    pub fn set_mtime(&mut self, mtime: &Timespec) {
+        let (date, time) = TimeStamp::from_timespec(mtime).to_fat();
+        self.modify_date = date;
+        self.modify_time = time;
+    }
+
+    pub fn set_ctime(&mut self, ctime: &Timespec) {
+        let stamp = TimeStamp::from_timespec(ctime);
+        let (date, time) = stamp.to_fat();
+        self.create_date = date;
+        self.create_time = time;
+        self.create_time_tenth = (stamp.second % 2) * 100 + (ctime.tv_nsec / 10_000_000) as u8;
+    }
+}   
+
+
+impl DiskLongDirEntry {
+    const END: u8 = 0x40;
+
+    pub fn new() -> Self {
+        let mut dentry: Self = Default::default();
+        dentry.attribute = 0x0F;
+        dentry.dir_type = 0x00;
+        dentry.cluster = 0x0000;
+        dentry
+    }
+
+    pub fn is_end(&self) -> bool {
+        self.seq_num & DiskLongDirEntry::END != 0
+    }
+
+    pub fn set_end(&mut self) {
+        self.seq_num |= DiskLongDirEntry::END;
+    }
+
+    pub fn get_seq(&self) -> u8 {
+        self.seq_num & !DiskLongDirEntry::END
+    }
+
+    // 序号从1开始, 最后一个长目录项(最先写入)的序号最大
+    pub fn set_seq(&mut self, seq: u8) {
+        self.seq_num = seq | (self.seq_num & DiskLongDirEntry::END);
+    }
+
+    pub fn get_check_sum(&self) -> u8 {
+        self.check_sum
+    }
+
+    pub fn set_check_sum(&mut self, check_sum: u8) {
+        self.check_sum = check_sum;
+    }
+
+    pub fn get_name(&self) -> Vec<u8> {
+        const END: u8 = 0xff;
+        let mut name = Vec::with_capacity(26);
+        for c in self.name_1 {
+            if c == END {
+                return name;
+            } else {
+                name.push(c);
+            }
+        }
+
+        for c in self.name_2 {
+            if c == END {
+                return name;
+            } else {
+                name.push(c);
+            }
+        }
+
+        for c in self.name_3 {
+            if c == END {
+                return name;
+            } else {
+                name.push(c);
+            }
+        }
+
+        return name
+    }
+
+    pub fn set_name(&mut self, name: &[u8]) {
+        assert!(name.len() == 26);
+        self.name_1.copy_from_slice(&name[0..10]);
+        self.name_2.copy_from_slice(&name[10..22]);
+        self.name_3.copy_from_slice(&name[22..26]);
+    }
+}
+
+
+impl From<u8> for Attribute {
+    fn from(u: u8) -> Self {
+        Self(u)
+    } 
+}
+
+impl From<Attribute> for u8 {
+    fn from(u: Attribute) -> u8 {
+        u.0
+    }
+}
+
+
+impl Attribute {
+    const VOLUME: u8 = 0b00001000;
+    const DIR   : u8 = 0b00010000;
+    const LFN   : u8 = 0b00001111;
+    const LINK  : u8 = 0b01000000;
+    const SOCK  : u8 = 0b10000000;
+    // dir  :0bxxx1xxxx
+    // lfn  :0bxxxx1111
+    // link :0bx1xxxxxx
+    // sock :0b1xxxxxxx
+
+    pub fn new() -> Self{
+        Self(0)
+    }
+
+    pub fn clear(&mut self) {
+        self.0 = 0
+    }
+
+    pub fn is_dir(&self) -> bool {
+        (!self.is_lfn()) && (self.0 & Attribute::DIR != 0)
+    }
+
+    pub fn is_lfn(&self) -> bool {
+        (self.0 & 0xf) == Attribute::LFN
+    }
+
+    pub fn is_volume(&self) -> bool {
+        (!self.is_lfn()) && (self.0 & Attribute::VOLUME != 0)
+    }
+
+    pub fn is_link(&self) -> bool {
+        (self.0 & Attribute::LINK) != 0
+    }
+
+    pub fn is_sock(&self) -> bool {
+        (!self.is_lfn()) && (self.0 & Attribute::SOCK != 0)
+    }
+
+    pub fn set_dir(&mut self) {
+        // assert_eq!(self.0, 0);
+        self.0 |= Attribute::DIR;
+        //assert!(!self.is_lfn());
+    }
+
+    pub fn set_lfn(&mut self) {
+        // assert_eq!(self.0, 0);
+        self.0 |= Attribute::LFN;
+    }
+
+    pub fn set_link(&mut self) {
+        // assert_eq!(self.0, 0);
+        self.0 |= Attribute::LINK;
+    }
+
+    pub fn set_sock(&mut self) {
+        self.0 |= Attribute::SOCK;
+    }
+
+}
+
+
+
+
+/// FAT32目录项中的日期和时间, 是本地时间
+/// date: bit15-9 年(从1980开始), bit8-5 月, bit4-0 日
+/// time: bit15-11 时, bit10-5 分, bit4-0 秒/2
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TimeStamp {
+    pub year    :u16,
+    pub month   :u8,
+    pub day     :u8,
+    pub hour    :u8,
+    pub minute  :u8,
+    pub second  :u8,
+}
+
+impl TimeStamp {
+    const MIN: Self = Self { year: 1980, month: 1, day: 1, hour: 0, minute: 0, second: 0 };
+    const MAX: Self = Self { year: 2107, month: 12, day: 31, hour: 23, minute: 59, second: 58 };
+
+    pub fn from_fat(date: u16, time: u16) -> Self {
+        Self {
+            year:   1980 + (date >> 9),
+            month:  (((date >> 5) & 0xf) as u8).max(1),     //未初始化的目录项中日期为0
+            day:    ((date & 0x1f) as u8).max(1),
+            hour:   (time >> 11) as u8,
+            minute: ((time >> 5) & 0x3f) as u8,
+            second: ((time & 0x1f) * 2) as u8,
+        }
+    }
+
+    pub fn to_fat(&self) -> (u16, u16) {
+        let date = ((self.year - 1980) << 9) | ((self.month as u16) << 5) | self.day as u16;
+        let time = ((self.hour as u16) << 11) | ((self.minute as u16) << 5) | (self.second as u16 / 2);
+        (date, time)
+    }
+
+    /// 内核时间转换为本地时间, 超出FAT32表示范围时取边界值
+    pub fn from_timespec(ts: &Timespec) -> Self {
+        let local = ts.tv_sec as i64 + FAT32_EPOCH_OFFSET as i64 + FAT32_TIMEZONE_OFFSET as i64;
+        let (year, month, day) = civil_from_days(local.div_euclid(86400));
+        let secs = local.rem_euclid(86400);
+        if year < 1980 {
+            return Self::MIN;
+        }
+        if year > 2107 {
+            return Self::MAX;
+        }
+        Self {
+            year: year as u16,
+            month,
+            day,
+            hour: (secs / 3600) as u8,
+            minute: (secs % 3600 / 60) as u8,
+            second: (secs % 60) as u8,
+        }
+    }
+
+    pub fn to_timespec(&self) -> Timespec {
+        let days = days_from_civil(self.year as i64, self.month, self.day);
+        let local = days * 86400 + self.hour as i64 * 3600 + self.minute as i64 * 60 + self.second as i64;
+        Timespec {
+            tv_sec: (local - FAT32_EPOCH_OFFSET as i64 - FAT32_TIMEZONE_OFFSET as i64) as isize,
+            tv_nsec: 0,
+        }
+    }
+}
+
+// 1970-01-01到指定日期的天数, 参考: http://howardhinnant.github.io/date_algorithms.html
+fn days_from_civil(year: i64, month: u8, day: u8) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year - era * 400;
+    let mp = (month as i64 + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+// days_from_civil的逆运算, 返回(年, 月, 日)
+fn civil_from_days(days: i64) -> (i64, u8, u8) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let doe = days - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u8;
+    let month = (if mp < 10 { mp + 3 } else { mp - 9 }) as u8;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
@@ -1,19 +1,26 @@
-use alloc::{sync::Arc, vec::Vec, string::String};
+use alloc::{sync::{Arc, Weak}, vec::Vec, string::String};
 use spin::{RwLock, Mutex};
 use log::*;
 
+use crate::config::{PAGE_SIZE, MAX_FILE_SIZE};
 use crate::fs::{
     FileOpenMode, DirFile, File, FileStat, FilePerm, FileType, SeekMode,
     get_block_cache, FileIndex, Fileid, Dentry, StMode, PollType};
+use crate::fs::page_cache::{PageCache, PageBackend, ReadAhead, get_page_cache, remove_page_cache};
+use crate::fs::block_cache::block_cache_sync_dev;
+use crate::fs::vfs::VFS;
 use crate::syscall::time::Timespec;
 use crate::utils::mem_buffer::MemBuffer;
-use crate::utils::{Error, UPSafeCell};
+use crate::utils::{Error, Path, UPSafeCell};
 use super::{Dirent, DiskDirEntry, Attribute};
 
 pub struct Fat32File {
     pub mode: FileOpenMode,     
     pub file_type: FileType,
     pub dirent: Arc<RwLock<Dirent>>,
+    pub path: Path,             // 打开时的绝对路径, 用于/proc/<pid>/fd
+    // 普通文件的数据通过页缓存读写, 同一个文件的所有Fat32File共享
+    page_cache: Option<Arc<PageCache>>,
 
     // 可变的数据放在inner里
     // 因为Fat32File不会在进程间共享，所以用UPSafeCell
@@ -22,12 +29,35 @@ pub struct Fat32File {
 
 pub struct Fat32FileInner{
     pub cursor: usize,
+    pub readahead: ReadAhead,
 }
 
 impl Fat32File {
-    pub fn new(dirent: Arc<RwLock<Dirent>>, mode: FileOpenMode) -> Arc<Self> {
+    pub fn new(dirent: Arc<RwLock<Dirent>>, mode: FileOpenMode, path: Path) -> Arc<Self> {
+        let dirent_read = dirent.read();
+        let file_type: FileType = Attribute::from(dirent_read.attribute).into();
+        let index = FileIndex(dirent_read.get_fs().id, Fileid(dirent_read.start_cluster as usize));
+        drop(dirent_read);
+
+        let page_cache = match file_type {
+            FileType::RegularFile => {
+                let backend: Weak<dyn PageBackend> = Arc::downgrade(&dirent);
+                Some(get_page_cache(index, backend))
+            }
+            _ => None,
+        };
+        Self::with_page_cache(dirent, mode, path, page_cache)
+    }
+
+    fn with_page_cache(
+        dirent: Arc<RwLock<Dirent>>,
+        mode: FileOpenMode,
+        path: Path,
+        page_cache: Option<Arc<PageCache>>
+    ) -> Arc<Self> {
         let inner = Fat32FileInner {
-            cursor: 0
+            cursor: 0,
+            readahead: ReadAhead::default(),
         };
 
         let dirent_read = dirent.read();
@@ -38,11 +68,78 @@ impl Fat32File {
             mode,
             file_type: Attribute::from(attr).into(),
             dirent,
+            path,
+            page_cache,
             inner: Mutex::new(inner),
         };
 
         Arc::new(new)
     }
+
+    // 从页缓存中读出[offset, offset + len)与文件重叠的部分, f依次得到每一段数据, 返回读出的长度
+    // 顺序读时先将预读窗口中的页批量读入页缓存
+    fn read_cached(
+        &self,
+        offset: usize,
+        len: usize,
+        readahead: &mut ReadAhead,
+        f: impl FnMut(&[u8])
+    ) -> Result<usize, Error> {
+        let size = self.dirent.read().size;
+        let start = offset.min(size);
+        let end = offset.saturating_add(len).min(size);
+        let cache = self.page_cache.as_ref().unwrap();
+        if let Some(range) = readahead.on_read(start, end - start) {
+            let last = (size + PAGE_SIZE - 1) / PAGE_SIZE;
+            cache.readahead(range.start, range.end.min(last));
+        }
+        cache.read(start, end - start, f)?;
+        self.dirent.write().touch_atime();
+        Ok(end - start)
+    }
+
+    // 先写入页缓存再增加文件长度, 写入文件末尾之后的页在读入时被清零
+    fn write_cached(&self, offset: usize, len: usize, f: impl FnMut(&mut [u8])) -> Result<usize, Error> {
+        let end = offset + len;
+        if end > MAX_FILE_SIZE {
+            return Err(Error::EFBIG);
+        }
+        let cache = self.page_cache.as_ref().unwrap();
+        let old_size = self.dirent.read().size;
+        cache.write(offset, len, f)?;
+
+        let mut dirent = self.dirent.write();
+        if let Err(err) = dirent.increase_size_to(end) {
+            if end > old_size {
+                let start = offset.max(old_size);
+                cache.zero(start, end - start);
+            }
+            return Err(err);
+        }
+        dirent.touch_mtime();
+        Ok(len)
+    }
+}
+
+/* 页缓存通过FAT32的目录项读写文件数据 */
+impl PageBackend for RwLock<Dirent> {
+    fn read_page(&self, index: usize, buf: &mut [u8]) -> Result<(), Error> {
+        self.read().read_at(index * PAGE_SIZE, buf)?;
+        Ok(())
+    }
+
+    fn write_page(&self, index: usize, buf: &[u8]) -> Result<(), Error> {
+        let dirent = self.read();
+        if dirent.delete {
+            return Ok(());
+        }
+        dirent.write_data_at(index * PAGE_SIZE, buf);
+        Ok(())
+    }
+
+    fn readahead(&self, offset: usize, len: usize) {
+        self.read().prefetch(offset, len);
+    }
 }
 
 impl File for Fat32File {
@@ -63,19 +160,11 @@ impl File for Fat32File {
         }
 
         let mut inner = self.inner.lock();
-        let dirent = self.dirent.read();
-        if dirent.is_dir() {
-            return Err(Error::EPERM);
-        }
-
         let mut data: Vec<u8> = Vec::with_capacity(len);
-        data.resize(len, 0);
-
-        let len = dirent.read_at(inner.cursor, data.as_mut_slice())?;
+        let cursor = inner.cursor;
+        let len = self.read_cached(cursor, len, &mut inner.readahead, |chunk| data.extend_from_slice(chunk))?;
         inner.cursor += len;
 
-        data.truncate(len);
-
         Ok(data)
     }
 
@@ -86,13 +175,12 @@ impl File for Fat32File {
             return Err(Error::EACCES);
         }
         let mut inner = self.inner.lock();
-        let mut dirent = self.dirent.write();
-        if dirent.is_dir() {
-            return Err(Error::EPERM);
-        }
-        let len = dirent.write_at(inner.cursor, data.as_slice())?;
+        let mut written = 0;
+        let len = self.write_cached(inner.cursor, data.len(), |chunk| {
+            chunk.copy_from_slice(&data[written..written + chunk.len()]);
+            written += chunk.len();
+        })?;
         inner.cursor += len;
-        drop(dirent);
         Ok(len)
     }
 
@@ -105,11 +193,9 @@ impl File for Fat32File {
         }
 
         let mut inner = self.inner.lock();
-        let dirent = self.dirent.read();
-        if dirent.is_dir() {
-            return Err(Error::EPERM);
-        }
-        let len = dirent.read_to_buffer(inner.cursor, buf)?;
+        let mut buf = buf;
+        let cursor = inner.cursor;
+        let len = self.read_cached(cursor, buf.len(), &mut inner.readahead, |chunk| buf.read_data_to_buffer(chunk))?;
         inner.cursor += len;
         Ok(len)
     }
@@ -122,11 +208,8 @@ impl File for Fat32File {
         }
 
         let mut inner = self.inner.lock();
-        let mut dirent = self.dirent.write();
-        if dirent.is_dir() {
-            return Err(Error::EPERM);
-        }
-        let len = dirent.write_from_buffer(inner.cursor, buf)?;
+        let mut buf = buf;
+        let len = self.write_cached(inner.cursor, buf.len(), |chunk| buf.write_data_from_buffer(chunk))?;
         inner.cursor += len;
         Ok(len)
     }
@@ -172,11 +255,12 @@ impl File for Fat32File {
     fn write_stat(&self, stat: &FileStat) -> Result<(), Error> {
         trace!("fat32file.write_stat: stat = {:?}", stat);        
         let mut dirent = self.dirent.write();
-        /* 通过write_stat，只能修改FAT32文件的时间 */
+        /* 通过write_stat，只能修改FAT32文件的时间, ctime对应FAT32中的创建时间 */
         
         dirent.atime = Timespec {tv_sec: stat.st_atime_sec as isize, tv_nsec: stat.st_atime_nsec as isize};
         dirent.mtime = Timespec {tv_sec: stat.st_mtime_sec as isize, tv_nsec: stat.st_mtime_nsec as isize};
         dirent.ctime = Timespec {tv_sec: stat.st_ctime_sec as isize, tv_nsec: stat.st_ctime_nsec as isize};
+        dirent.sync_time();
         Ok(())
     }
 
@@ -186,6 +270,8 @@ impl File for Fat32File {
         let st_mode = match self.file_type {
             FileType::Directory => StMode::DIR,
             FileType::RegularFile => StMode::REG,
+            FileType::SocketFile => StMode::SOCK,
+            FileType::LinkFile => StMode::LNK,
             _ => panic!()
         };
         let nlink = match dirent.delete {
@@ -196,14 +282,13 @@ impl File for Fat32File {
         let fstat = FileStat {
             st_dev: fs.block_file.get_id() as u64,
             st_ino: dirent.start_cluster as u64,
-            st_mode: st_mode as u32 | 0o7777,
+            st_mode: st_mode as u32 | 0o777,   //fat32没有权限位, 所有人可读写执行
             st_nlink:   nlink,  //fat32不支持
             st_uid:     0,      //fat32不支持
             st_gid:     0,      //fat32不支持
             st_size: dirent.size as _,
-            st_blksize: 512,
-            st_blocks: (dirent.size + 511 / 512) as u64,
-            // 暂时不支持时间
+            st_blksize: fs.sector_size() as u32,
+            st_blocks: ((dirent.size + 511) / 512) as u64,
             st_atime_sec : dirent.atime.tv_sec as _, 
             st_atime_nsec: dirent.atime.tv_nsec as _,  
             st_mtime_sec : dirent.mtime.tv_sec as _,  
@@ -222,18 +307,41 @@ impl File for Fat32File {
         match ptype {
             PollType::READ => Ok(self.readable()),
             PollType::WRITE => Ok(self.writable()),
-            PollType::ERR => Ok(false)
+            PollType::ERR | PollType::HUP => Ok(false)
         }
     }
 
+    fn page_cache(&self) -> Option<Arc<PageCache>> {
+        self.page_cache.clone()
+    }
+
+    /* 目录项和文件数据在同一个设备上, 写回页缓存后写回整个设备的脏块 */
+    fn fsync(&self) -> Result<(), Error> {
+        if let Some(cache) = &self.page_cache {
+            cache.sync()?;
+        }
+        let block_file = self.dirent.read().block_file();
+        block_cache_sync_dev(block_file.get_id());
+        Ok(())
+    }
+
+    fn vfs(&self) -> Arc<dyn VFS> {
+        self.dirent.read().get_fs()
+    }
+
     fn copy(&self) -> Arc<dyn File> {
-        let new_file = Fat32File::new(self.dirent.clone(), self.mode);
+        let new_file = Fat32File::with_page_cache(
+            self.dirent.clone(), self.mode, self.path.clone(), self.page_cache.clone());
         let mut inner = self.inner.lock();
         let mut new_inner = new_file.inner.lock();
         new_inner.cursor = inner.cursor;
         drop(new_inner);
         new_file
     }
+
+    fn get_path(&self) -> Result<Path, Error> {
+        Ok(self.path.clone())
+    }
     
     fn as_dir<'a>(self: Arc<Self>) -> Result<Arc<dyn DirFile + 'a>, Error> where Self: 'a {
         if self.file_type == FileType::Directory {
@@ -253,7 +361,9 @@ impl DirFile for Fat32File {
     fn openat(&self, name: String, mode: FileOpenMode) -> Result<Arc<dyn File>, Error> {
         let dirent = self.dirent.read();
         let open_dirent = dirent.open_at(&name)?;
-        Ok(Fat32File::new(open_dirent, mode))
+        let mut path = self.path.clone();
+        path.push_back(name);
+        Ok(Fat32File::new(open_dirent, mode, path))
     }
 
     // 不管FilePerm
@@ -263,11 +373,14 @@ impl DirFile for Fat32File {
             FileType::RegularFile => {},
             FileType::Directory => attr.set_dir(),
             FileType::LinkFile => attr.set_link(),
+            FileType::SocketFile => attr.set_sock(),
             _ => return Err(Error::TYPEWRONG)
         }
         let mut dirent = self.dirent.write();
         let new_dirent = dirent.create_file(name.as_str(), attr.into())?;
-        Ok(Fat32File::new(new_dirent, FileOpenMode::SYS))
+        let mut path = self.path.clone();
+        path.push_back(name);
+        Ok(Fat32File::new(new_dirent, FileOpenMode::SYS, path))
     }
 
     // deletes a name from the file system
@@ -276,7 +389,7 @@ impl DirFile for Fat32File {
     // file is deleted and the space it was 
     // using is made available for reuse.
     fn delete(&self, name: String) -> Result<(), Error> {
-        let dirent = self.dirent.write();
+        let mut dirent = self.dirent.write();
         let sub_dirent = dirent.open_at(name.as_str())?;
         
         trace!("fat32file_delete: at dir: {}, try to delete: {}", dirent.name, name);
@@ -289,6 +402,8 @@ impl DirFile for Fat32File {
         // 在此处只做一个标记，并不释放该文件占用的空间, 再文件被drop的时候再删除
         let mut sub_dirent_lock = sub_dirent.write();
         sub_dirent_lock.delete = true;
+        /* 簇可能被新文件使用, 以后打开的文件不能再使用这个页缓存 */
+        remove_page_cache(FileIndex(dirent.get_fs().id, Fileid(sub_dirent_lock.start_cluster as usize)));
         
         // 因为打开文件必须获取父目录的锁，而这里已经持有了父目录的锁
         // 所以能够保证其它进程不可能在此时打开要删除的文件
@@ -302,6 +417,8 @@ impl DirFile for Fat32File {
         .modify(offset, |dentry: &mut DiskDirEntry|{
             dentry.set_delete();
         });
+        drop(sub_dirent_lock);
+        dirent.touch_mtime();
         
         Ok(())
     }
@@ -346,6 +463,17 @@ impl Drop for Fat32File {
     // 判断引用计数和删除cache中的dirent时，要全程拥有cache的锁
     fn drop(&mut self) {        
         if Arc::strong_count(&self.dirent) == 2 {
+            /* 最后一个打开的文件被关闭时写回页缓存, 被删除的文件直接丢弃 */
+            if let Some(cache) = self.page_cache.as_ref() {
+                match self.dirent.read().delete {
+                    true => cache.invalidate(),
+                    false => {
+                        if let Err(err) = cache.sync() {
+                            warn!("fat32file_drop: fail to write back page cache: {:?}", err);
+                        }
+                    }
+                }
+            }
             /* 将文件从cache中删除 */
             let mut dirent = self.dirent.write();
             let fs = dirent.get_fs();
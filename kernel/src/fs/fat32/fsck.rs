@@ -0,0 +1,436 @@
+// FAT32一致性检查和修复
+// 内核在挂载时通过FAT32FileSystem::fsck调用, tools/fat32-fsck直接包含这个文件, 在主机上检查磁盘镜像
+// 布局, FAT表项和目录项的解析使用驱动的bpb和disk_dirent, 不单独实现
+//
+// 检查的内容:
+// 1, 从根目录开始遍历目录树, 检查每个文件的簇链: 越界, 指向空闲簇, 循环, 与其它文件交叉
+// 2, 文件长度与簇链长度是否一致
+// 3, 长文件名目录项的序号和校验和, 以及没有对应短目录项的长文件名目录项
+// 4, 没有被任何文件使用的簇(lost cluster)
+// 5, FAT表的各个副本是否一致
+// 6, info sector中的空闲簇数量
+// 修复时, 错误的目录项被删除, 簇链在出错的位置截断, lost cluster被释放
+
+use super::bpb::{FSInfo, Geometry, ClusterType, cluster_type, CLUSTER_MASK, CLUSTER_FREE, CLUSTER_END};
+use super::disk_dirent::{RawDiskDirEntry, DiskDirEntry};
+use alloc::{vec, vec::Vec, string::String, format};
+
+const LFN_MAX_ORDINAL: u8 = 20;     //255个字符最多需要20个长文件名目录项
+
+/// 被检查的磁盘, 以sector为单位读写, buf的长度等于Geometry::sector_size
+pub trait FsckDisk {
+    fn read_sector(&self, sector: usize, buf: &mut [u8]);
+    fn write_sector(&self, sector: usize, buf: &[u8]);
+}
+
+/// 检查的结果
+#[derive(Debug, Default)]
+pub struct FsckReport {
+    pub problems: Vec<String>,      //发现的问题, 修复模式下都已经被修复
+    pub lost_clusters: usize,
+    pub repaired: bool,             //是否修改了磁盘
+}
+
+impl FsckReport {
+    pub fn is_clean(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+/// 检查文件系统, repair为true时修复发现的问题
+pub fn fsck<D: FsckDisk>(disk: &D, geo: &Geometry, repair: bool) -> FsckReport {
+    let mut checker = Checker::new(disk, geo, repair);
+    checker.check_tree();
+    checker.check_lost_clusters();
+    checker.sync_fat();
+    checker.check_fsinfo();
+    checker.report
+}
+
+// 目录项在磁盘上的位置
+#[derive(Clone, Copy)]
+struct EntryPos {
+    sector: usize,
+    index: usize,       //sector中的第几个目录项
+}
+
+struct Checker<'a, D: FsckDisk> {
+    disk: &'a D,
+    geo: &'a Geometry,
+    repair: bool,
+    fat_raw: Vec<u8>,       //使用中的FAT表的内容, 修复时在内存中修改, 最后写回
+    fat_dirty: Vec<bool>,   //FAT表中被修改过的sector
+    used: Vec<bool>,        //被目录树引用的簇
+    report: FsckReport,
+}
+
+impl<'a, D: FsckDisk> Checker<'a, D> {
+    fn new(disk: &'a D, geo: &'a Geometry, repair: bool) -> Self {
+        let mut fat_raw = vec![0u8; geo.sectors_per_fat * geo.sector_size];
+        let start = geo.fat_sector(geo.active_fat);
+        for (i, sector) in fat_raw.chunks_mut(geo.sector_size).enumerate() {
+            disk.read_sector(start + i, sector);
+        }
+        Self {
+            disk,
+            geo,
+            repair,
+            fat_raw,
+            fat_dirty: vec![false; geo.sectors_per_fat],
+            used: vec![false; geo.cluster_count as usize + 2],
+            report: FsckReport::default(),
+        }
+    }
+
+    fn problem(&mut self, msg: String) {
+        self.report.problems.push(msg);
+    }
+
+    fn get_fat(&self, cluster: u32) -> u32 {
+        read_u32(&self.fat_raw, cluster as usize * 4) & CLUSTER_MASK
+    }
+
+    // 高4位是保留位, 保持不变
+    fn set_fat(&mut self, cluster: u32, value: u32) {
+        let pos = cluster as usize * 4;
+        let old = read_u32(&self.fat_raw, pos);
+        write_u32(&mut self.fat_raw, pos, (old & !CLUSTER_MASK) | (value & CLUSTER_MASK));
+        self.fat_dirty[pos / self.geo.sector_size] = true;
+    }
+
+    fn is_valid_cluster(&self, cluster: u32) -> bool {
+        cluster >= 2 && cluster < self.geo.cluster_count + 2
+    }
+
+    // 修改一个目录项, 只在修复模式下写回磁盘
+    fn modify_entry(&mut self, pos: EntryPos, f: impl FnOnce(&mut DiskDirEntry)) {
+        if !self.repair {
+            return;
+        }
+        let mut buf = vec![0u8; self.geo.sector_size];
+        self.disk.read_sector(pos.sector, &mut buf);
+        f(RawDiskDirEntry::table_mut(&mut buf)[pos.index].as_short_mut());
+        self.disk.write_sector(pos.sector, &buf);
+        self.report.repaired = true;
+    }
+
+    fn delete_entry(&mut self, pos: EntryPos) {
+        self.modify_entry(pos, |entry| entry.set_delete());
+    }
+
+    /// 沿着簇链前进, 返回链上的所有簇
+    /// 遇到错误时在出错的位置截断簇链
+    fn walk_chain(&mut self, start: u32, path: &str) -> Vec<u32> {
+        let mut chain = Vec::new();
+        let mut cluster = start;
+        loop {
+            if self.used[cluster as usize] {
+                /* start已经由调用者检查过, 所以chain不为空 */
+                self.problem(format!("{}: cluster {} is cross-linked, truncated", path, cluster));
+                let last = *chain.last().unwrap();
+                self.set_fat(last, CLUSTER_END);
+                break;
+            }
+            self.used[cluster as usize] = true;
+            chain.push(cluster);
+
+            let next = self.get_fat(cluster);
+            match cluster_type(next) {
+                ClusterType::End => break,
+                ClusterType::Free => {
+                    self.problem(format!("{}: cluster {} in chain is marked free", path, cluster));
+                    self.set_fat(cluster, CLUSTER_END);
+                    break;
+                }
+                ClusterType::Data if self.is_valid_cluster(next) => cluster = next,
+                _ => {
+                    self.problem(format!("{}: cluster {} points to invalid cluster {:#x}, truncated",
+                        path, cluster, next));
+                    self.set_fat(cluster, CLUSTER_END);
+                    break;
+                }
+            }
+        }
+        chain
+    }
+
+    /// 释放簇链中keep之后的部分, 这些簇会在检查lost cluster时被释放
+    fn truncate_chain(&mut self, chain: &[u32], keep: usize) {
+        self.set_fat(chain[keep - 1], CLUSTER_END);
+        for &cluster in &chain[keep..] {
+            self.used[cluster as usize] = false;
+        }
+    }
+
+    fn check_tree(&mut self) {
+        let root = self.geo.root_cluster;
+        if self.get_fat(root) == CLUSTER_FREE {
+            self.problem(format!("/: root cluster {} is marked free", root));
+            self.set_fat(root, CLUSTER_END);
+        }
+        let chain = self.walk_chain(root, "/");
+        /* 为了避免在内核栈上递归, 用队列保存待检查的目录 */
+        let mut dirs = vec![(chain, String::new())];
+        while let Some((chain, path)) = dirs.pop() {
+            self.check_dir(&chain, &path, &mut dirs);
+        }
+    }
+
+    /// 检查目录中的每一项, 子目录被加入dirs
+    fn check_dir(&mut self, chain: &[u32], path: &str, dirs: &mut Vec<(Vec<u32>, String)>) {
+        let mut lfn: Vec<EntryPos> = Vec::new();    //当前的长文件名目录项
+        let mut lfn_next: u8 = 0;                   //下一个长文件名目录项的序号
+        let mut lfn_checksum: u8 = 0;
+        let mut buf = vec![0u8; self.geo.sector_size];
+
+        for &cluster in chain {
+            let first_sector = self.geo.cluster_sector(cluster);
+            for sector in first_sector..first_sector + self.geo.sectors_per_cluster {
+                self.disk.read_sector(sector, &mut buf);
+                for (index, raw) in RawDiskDirEntry::table(&buf).iter().enumerate() {
+                    let pos = EntryPos { sector, index };
+                    let entry = raw.as_short();
+                    let attr = entry.get_attr();
+
+                    /* 删除的长目录项仍然保留LFN属性, 先检查第一个字节 */
+                    if entry.is_free() {
+                        self.drop_lfn(&mut lfn, path, "not followed by a short entry");
+                        if entry.is_empty() {
+                            return;
+                        }
+                        continue;
+                    }
+
+                    if attr.is_lfn() {
+                        let long = raw.as_long();
+                        let ordinal = long.get_seq();
+                        let checksum = long.get_check_sum();
+                        if long.is_end() {
+                            self.drop_lfn(&mut lfn, path, "not followed by a short entry");
+                            if ordinal == 0 || ordinal > LFN_MAX_ORDINAL {
+                                self.problem(format!("{}: long name entry with invalid ordinal {}", path, ordinal));
+                                self.delete_entry(pos);
+                                continue;
+                            }
+                            lfn.push(pos);
+                            lfn_next = ordinal - 1;
+                            lfn_checksum = checksum;
+                        } else if lfn.is_empty() || ordinal != lfn_next || ordinal == 0
+                            || checksum != lfn_checksum
+                        {
+                            lfn.push(pos);
+                            self.drop_lfn(&mut lfn, path, "out of sequence");
+                        } else {
+                            lfn.push(pos);
+                            lfn_next -= 1;
+                        }
+                        continue;
+                    }
+
+                    /* 短目录项 */
+                    let name = entry.get_name()
+                        .unwrap_or_else(|_| String::from_utf8_lossy(&raw.0[..11]).into_owned());
+                    let child_path = format!("{}/{}", path, name);
+                    if !lfn.is_empty() {
+                        if lfn_next != 0 || lfn_checksum != entry.check_sum() {
+                            let reason = format!("does not match {}", name);
+                            self.drop_lfn(&mut lfn, path, &reason);
+                        }
+                        lfn.clear();
+                    }
+                    if attr.is_volume() || name == "." || name == ".." {
+                        continue;
+                    }
+
+                    let start = entry.get_cluster();
+                    let size = entry.get_size() as usize;
+                    self.check_entry(pos, &child_path, start, size, attr.is_dir(), dirs);
+                }
+            }
+        }
+        self.drop_lfn(&mut lfn, path, "not followed by a short entry");
+    }
+
+    fn drop_lfn(&mut self, lfn: &mut Vec<EntryPos>, path: &str, reason: &str) {
+        if lfn.is_empty() {
+            return;
+        }
+        self.problem(format!("{}: {} long name entries {}, removed", path, lfn.len(), reason));
+        for pos in lfn.drain(..) {
+            self.delete_entry(pos);
+        }
+    }
+
+    fn check_entry(
+        &mut self,
+        pos: EntryPos,
+        path: &str,
+        start: u32,
+        size: usize,
+        is_dir: bool,
+        dirs: &mut Vec<(Vec<u32>, String)>,
+    ) {
+        if start == 0 {
+            /* 空文件可以没有簇, 目录至少有一个簇 */
+            if is_dir {
+                self.problem(format!("{}: directory without cluster, removed", path));
+                self.delete_entry(pos);
+            } else if size != 0 {
+                self.problem(format!("{}: size is {} but no cluster, set to 0", path, size));
+                self.modify_entry(pos, |entry| entry.set_size(0));
+            }
+            return;
+        }
+        if !self.is_valid_cluster(start) || self.used[start as usize]
+            || matches!(cluster_type(self.get_fat(start)), ClusterType::Free | ClusterType::Bad)
+        {
+            let reason = match self.is_valid_cluster(start) && self.used[start as usize] {
+                true => "is cross-linked",
+                false => "is invalid",
+            };
+            self.problem(format!("{}: first cluster {} {}, removed", path, start, reason));
+            self.delete_entry(pos);
+            return;
+        }
+
+        let chain = self.walk_chain(start, path);
+        if is_dir {
+            if size != 0 {
+                self.problem(format!("{}: directory has size {}, set to 0", path, size));
+                self.modify_entry(pos, |entry| entry.set_size(0));
+            }
+            dirs.push((chain, String::from(path)));
+            return;
+        }
+
+        /* 内核在文件长度正好是簇大小的整数倍时会多分配一个簇 */
+        let cluster_bytes = self.geo.cluster_bytes();
+        let needed = match size % cluster_bytes {
+            0 => size / cluster_bytes,
+            _ => size / cluster_bytes + 1,
+        };
+        let allowed = size / cluster_bytes + 1;
+        if chain.len() < needed {
+            let new_size = chain.len() * cluster_bytes;
+            self.problem(format!("{}: size {} is larger than the cluster chain, set to {}",
+                path, size, new_size));
+            self.modify_entry(pos, |entry| entry.set_size(new_size as u32));
+        } else if chain.len() > allowed {
+            self.problem(format!("{}: cluster chain has {} clusters but size is {}, truncated",
+                path, chain.len(), size));
+            self.truncate_chain(&chain, allowed);
+        }
+    }
+
+    /// 没有被任何文件使用, 但在FAT表中不是空闲的簇
+    fn check_lost_clusters(&mut self) {
+        let mut lost = Vec::new();
+        for cluster in 2..self.geo.cluster_count + 2 {
+            let value = self.get_fat(cluster);
+            if !self.used[cluster as usize] && !matches!(cluster_type(value), ClusterType::Free | ClusterType::Bad) {
+                lost.push(cluster);
+            }
+        }
+        if lost.is_empty() {
+            return;
+        }
+        self.problem(format!("{} lost clusters, first is {}, freed", lost.len(), lost[0]));
+        self.report.lost_clusters = lost.len();
+        for cluster in lost {
+            self.set_fat(cluster, CLUSTER_FREE);
+        }
+    }
+
+    /// 写回修改过的FAT表, 并检查其它FAT副本
+    fn sync_fat(&mut self) {
+        let sector_size = self.geo.sector_size;
+        if self.repair {
+            let start = self.geo.fat_sector(self.geo.active_fat);
+            for i in 0..self.geo.sectors_per_fat {
+                if self.fat_dirty[i] {
+                    self.disk.write_sector(start + i, &self.fat_raw[i * sector_size..(i + 1) * sector_size]);
+                    self.report.repaired = true;
+                }
+            }
+        }
+        if !self.geo.mirror {
+            return;
+        }
+
+        let mut buf = vec![0u8; sector_size];
+        for fat in 0..self.geo.fat_num {
+            if fat == self.geo.active_fat {
+                continue;
+            }
+            let start = self.geo.fat_sector(fat);
+            let mut differ = 0;
+            for i in 0..self.geo.sectors_per_fat {
+                let expect = &self.fat_raw[i * sector_size..(i + 1) * sector_size];
+                self.disk.read_sector(start + i, &mut buf);
+                if buf != expect {
+                    differ += 1;
+                    if self.repair {
+                        self.disk.write_sector(start + i, expect);
+                        self.report.repaired = true;
+                    }
+                }
+            }
+            /* 修复了使用中的FAT表后, 副本必然不同, 只报告原本就不同的部分 */
+            let already = self.fat_dirty.iter().filter(|dirty| **dirty).count();
+            if differ > already || (differ > 0 && !self.repair) {
+                self.problem(format!("FAT copy {} differs from FAT {} in {} sectors",
+                    fat, self.geo.active_fat, differ));
+            }
+        }
+    }
+
+    /// 检查info sector中的空闲簇数量和下一个空闲簇
+    fn check_fsinfo(&mut self) {
+        let sector = match self.geo.fsinfo_sector {
+            Some(sector) => sector,
+            None => return,
+        };
+        let free = (2..self.geo.cluster_count + 2)
+            .filter(|cluster| self.get_fat(*cluster) == CLUSTER_FREE)
+            .count() as u32;
+
+        /* sector缓冲区不保证4字节对齐 */
+        let mut buf = vec![0u8; self.geo.sector_size];
+        self.disk.read_sector(sector, &mut buf);
+        let mut info = unsafe { core::ptr::read_unaligned(buf.as_ptr() as *const FSInfo) };
+        let free_count = info.free_count;
+        let next_free = info.next_free;
+
+        let mut bad = false;
+        if !info.is_valid() {
+            self.problem(String::from("info sector has invalid signature"));
+            bad = true;
+        } else if free_count != FSInfo::UNKNOWN && free_count != free {
+            self.problem(format!("info sector free count is {}, should be {}", free_count, free));
+            bad = true;
+        } else if next_free != FSInfo::UNKNOWN && !self.is_valid_cluster(next_free) {
+            self.problem(format!("info sector next free cluster {:#x} is invalid", next_free));
+            bad = true;
+        }
+        if bad && self.repair {
+            info.lead_sig = FSInfo::LEAD_SIG;
+            info.struct_sig = FSInfo::STRUCT_SIG;
+            info.trail_sig = FSInfo::TRAIL_SIG;
+            info.free_count = free;
+            if !self.is_valid_cluster(next_free) {
+                info.next_free = 2;
+            }
+            unsafe { core::ptr::write_unaligned(buf.as_mut_ptr() as *mut FSInfo, info) };
+            self.disk.write_sector(sector, &buf);
+            self.report.repaired = true;
+        }
+    }
+}
+
+fn read_u32(buf: &[u8], pos: usize) -> u32 {
+    u32::from_le_bytes([buf[pos], buf[pos + 1], buf[pos + 2], buf[pos + 3]])
+}
+
+fn write_u32(buf: &mut [u8], pos: usize, value: u32) {
+    buf[pos..pos + 4].copy_from_slice(&value.to_le_bytes());
+}
@@ -1,37 +1,39 @@
 mod bpb;
+mod disk_dirent;
 mod dirent;
 mod file;
+pub mod fsck;
 
 use spin::{RwLock, Mutex};
 use log::*;
 use alloc::{
     sync::Arc, 
+    vec::Vec,
     collections::{BTreeMap, VecDeque}, 
 };
 use self::{
-    dirent::{RawDiskDirEntry, Dirent, Attribute, DiskDirEntry},
-    bpb::{BPB, EBPB}, 
+    dirent::Dirent,
+    disk_dirent::{Attribute, DiskDirEntry},
+    bpb::{FSInfo, Geometry, ClusterType, cluster_type, CLUSTER_MASK, CLUSTER_END},
     file::Fat32File,
+    fsck::{FsckDisk, FsckReport},
 };
 use crate::config::*;
 use crate::utils::{Error, Path};
 use super::{get_block_cache, BlockFile, VFS, File, FSid, FileOpenMode, DirFile, Statvfs};
+use super::block_cache::{ResizedBlockFile, block_cache_sync_dev};
+use super::page_cache::sync_page_caches;
 
 
-const CLUSTER_END: u32 = 0x0FFF_FFF8;
-pub const SECTOR_SIZE: usize =  512;
-
-pub type ClusterTable = [u32; 128];
-pub type DirentTable = [RawDiskDirEntry; 16];
-pub type BytesTable = [u8; 512];
-
 /// 简化的FAT32文件系统：
 /// 1，假定文件系统只有1个分区
-/// 2，忽略info sector，不维护valid number of free clusters等信息
-/// 3，只使用第一个FAT表，并且不维护其它的FAT表
+/// 2，在内存中维护info sector中的空闲簇数量和下一个空闲簇，每次修改后写回
+/// 3，修改FAT表时同时修改所有的FAT副本（关闭镜像时只修改当前使用的FAT表）
 /// 4，将BPB和EBPB中一些次要的信息忽略，比如：Volume ID, Drive description, Version等
-/// 5，对一些信息进行假定：比如bytes_per_sector = 512, root_dir_cluster = 2，否则报错
-/// 6, 不支持时间信息
+/// 5，sector大小(512-4096)和根目录的簇号从BPB中读取，BPB不合法时返回EINVAL
+/// 6, 时间信息按本地时间保存, 时区和时间起点见config中的FAT32_*_OFFSET, 访问时间只精确到天
+/// 7, 普通文件的数据经过页缓存(fs::page_cache)读写, 与文件映射共享物理页, 最后一次关闭时写回
+/// 8, 顺序读时按打开的文件预读, 目录项中缓存按连续簇分段的簇链, 连续的簇一次向设备请求
 #[allow(unused)]
 pub struct FAT32FileSystem {
     id: FSid,
@@ -42,58 +44,71 @@ pub struct FAT32FileSystem {
     root_dirent: Arc<RwLock<Dirent>>,
 
     //belows are meta data
+    sector_size: usize,           //bytes per sector, block_file的块大小与之相同
     sectors_number: usize,        //totol sectors_number
     sectors_per_clusters: usize,
+    root_cluster: u32,
     
     //reserved field 
     res_sector_num: usize,
     
     //file allocation table field
-    table_start_sector: usize,
+    table_start_sector: usize,    //当前使用的FAT表
     table_end_sector: usize,
     sectors_per_table: usize,     //sectors of per fail allocation table
     table_num: usize,
+    mirror_tables: Vec<usize>,    //需要与当前FAT表保持一致的其它FAT表的起始sector
     
     //cluster field
     data_start_sector: usize,     //the sector number of the first cluster
+    cluster_count: u32,           //数据区的簇数, 簇号范围为2..cluster_count+2
+
+    //fs info sector field
+    fsinfo_sector: Option<usize>,
+    free_info: Mutex<FreeInfo>,
 }
 
-impl FAT32FileSystem {
-    #[allow(unaligned_references)]
-    pub fn init(block_file: Arc<dyn BlockFile>, id: FSid, mount_path: Path) -> Arc<Self> {
-        const BPB_OFFSET: usize = 0x0B;
-        const EBPB_OFFSET: usize = 0x24;
+// info sector中的信息, 在内存中维护, 修改后写回磁盘
+struct FreeInfo {
+    free_count: u32,
+    next_free: u32,
+}
 
+impl FAT32FileSystem {
+    pub fn init(block_file: Arc<dyn BlockFile>, id: FSid, mount_path: Path) -> Result<Arc<Self>, Error> {
         info!("FAT32 file system initing");
 
-        let bpb = get_block_cache(0, block_file.clone())
-        .read()
-        .read(BPB_OFFSET, |bpb: &BPB|{
-            *bpb
-        });
-
-
-        let ebpb = get_block_cache(0, block_file.clone())
-        .read()
-        .read(EBPB_OFFSET, |ebpb: &EBPB|{
-            *ebpb
-        });
-        
+        let geometry = Self::read_geometry(&block_file)?;
+        let block_file = Self::sector_file(block_file, &geometry);
+        let sector_size = geometry.sector_size;
+        let cluster_count = geometry.cluster_count;
+        let root_cluster = geometry.root_cluster;
+
+        let table_start_sector = geometry.fat_sector(geometry.active_fat);
+        let table_end_sector = table_start_sector + geometry.sectors_per_fat;
+        let mirror_tables: Vec<usize> = match geometry.mirror {
+            true => (0..geometry.fat_num)
+                .filter(|fat| *fat != geometry.active_fat)
+                .map(|fat| geometry.fat_sector(fat))
+                .collect(),
+            false => Vec::new(),
+        };
 
-        let flag = get_block_cache(0, block_file.clone())
-        .read()
-        .read(BLOCK_SIZE - 2, |flag: &u16|{
-            *flag
-        });
-        
-        assert_eq!(ebpb.root_dir_cluster, 2);
-        assert_eq!(bpb.bytes_per_sector, 512);
-        assert_eq!(flag, 0xAA55);        
-        
-        let data_start_sector = bpb.reserved_sectors_num as usize 
-            + bpb.fat_num as usize * ebpb.sectors_per_table as usize;
-        let table_end_sector = bpb.reserved_sectors_num as usize 
-            + ebpb.sectors_per_table as usize;
+        /* 读取info sector, 无效时在初始化完成后扫描FAT表 */
+        let fsinfo_sector = geometry.fsinfo_sector;
+        let fsinfo = fsinfo_sector.map(|sector| {
+            get_block_cache(sector, block_file.clone())
+            .read()
+            .read(0, |info: &FSInfo| *info)
+        }).filter(|info| info.is_valid());
+        let next_free = match fsinfo {
+            Some(info) if info.next_free >= 2 && info.next_free < cluster_count + 2 => info.next_free,
+            _ => 2,
+        };
+        let free_count = match fsinfo {
+            Some(info) if info.free_count <= cluster_count => Some(info.free_count),
+            _ => None,
+        };
         
         let fat32 = Self {
             id,
@@ -103,17 +118,27 @@ impl FAT32FileSystem {
             cluster_cache: Mutex::new(VecDeque::new()),
             root_dirent: Arc::new(RwLock::new(Dirent::new())),
 
-            sectors_number: bpb.totol_sectors_num as usize,
-            sectors_per_clusters: bpb.sectors_per_cluster as usize,
+            sector_size,
+            sectors_number: geometry.total_sectors,
+            sectors_per_clusters: geometry.sectors_per_cluster,
+            root_cluster,
 
-            res_sector_num: bpb.reserved_sectors_num as usize,
+            res_sector_num: geometry.fat_start,
 
-            table_start_sector: bpb.reserved_sectors_num as usize,
+            table_start_sector,
             table_end_sector,
-            sectors_per_table: ebpb.sectors_per_table as usize, 
-            table_num: bpb.fat_num as usize,
+            sectors_per_table: geometry.sectors_per_fat, 
+            table_num: geometry.fat_num,
+            mirror_tables,
             
-            data_start_sector,
+            data_start_sector: geometry.data_start,
+            cluster_count,
+
+            fsinfo_sector,
+            free_info: Mutex::new(FreeInfo {
+                free_count: free_count.unwrap_or(0),
+                next_free,
+            }),
         };
         
         let arc_fat32 = Arc::new(fat32);
@@ -125,10 +150,63 @@ impl FAT32FileSystem {
         
         /* 将root_dirent 加入 dirent_cache */
         let mut cache = arc_fat32.dirent_cache.lock();
-        cache.insert(2, arc_fat32.root_dirent.clone());
+        cache.insert(root_cluster, arc_fat32.root_dirent.clone());
         drop(cache);
 
-        arc_fat32
+        if free_count.is_none() {
+            let num = arc_fat32.count_free_clusters();
+            info!("fat32: invalid free cluster count in info sector, recount: {}", num);
+            arc_fat32.free_info.lock().free_count = num as u32;
+            arc_fat32.sync_fsinfo(&arc_fat32.free_info.lock());
+        }
+
+        Ok(arc_fat32)
+    }
+
+    /// 在挂载前检查文件系统, repair为true时修复发现的问题
+    /// 检查通过块缓存读写磁盘, 之后的init会看到修复后的内容
+    pub fn fsck(block_file: Arc<dyn BlockFile>, repair: bool) -> Result<FsckReport, Error> {
+        let geometry = Self::read_geometry(&block_file)?;
+        let block_file = Self::sector_file(block_file, &geometry);
+        let report = fsck::fsck(&CachedDisk(block_file), &geometry, repair);
+        for problem in report.problems.iter() {
+            warn!("fat32 fsck: {}", problem);
+        }
+        info!("fat32 fsck: {} problems, {}", report.problems.len(),
+            if report.repaired { "repaired" } else { "not repaired" });
+        Ok(report)
+    }
+
+    /* boot sector的前512字节中包含BPB, 设备的块不小于512字节 */
+    fn read_geometry(block_file: &Arc<dyn BlockFile>) -> Result<Geometry, Error> {
+        get_block_cache(0, block_file.clone())
+            .read()
+            .read_slice(|bytes: &[u8]| Geometry::from_boot_sector(bytes))
+            .ok_or_else(|| {
+                warn!("fat32: bad bpb");
+                Error::EINVAL
+            })
+    }
+
+    /* 以sector为单位访问块设备 */
+    fn sector_file(block_file: Arc<dyn BlockFile>, geometry: &Geometry) -> Arc<dyn BlockFile> {
+        match block_file.block_size() == geometry.sector_size {
+            true => block_file,
+            false => ResizedBlockFile::new(block_file, geometry.sector_size),
+        }
+    }
+
+    pub fn sector_size(&self) -> usize {
+        self.sector_size
+    }
+
+    pub fn root_cluster(&self) -> u32 {
+        self.root_cluster
+    }
+
+    // 每个sector中FAT表项的数量
+    fn entries_per_sector(&self) -> usize {
+        self.sector_size / 4
     }
 
     pub fn get_root_dirent(&self) -> Arc<RwLock<Dirent>> {
@@ -136,21 +214,22 @@ impl FAT32FileSystem {
     }
 
     pub fn get_root_file(&self, mode: FileOpenMode) -> Arc<dyn File> {
-        Fat32File::new(self.get_root_dirent(), mode)
+        Fat32File::new(self.get_root_dirent(), mode, self.mount_path.clone())
     }
 
     pub fn get_bytes_per_cluster(&self) -> usize {
-        self.sectors_per_clusters * SECTOR_SIZE
+        self.sectors_per_clusters * self.sector_size
     }
 
     //返回cluster项在FAT表的位置
     pub fn get_cluster_entry_pos(&self, cluster: u32) -> Result<(usize, usize), Error> {
-        let sector = self.table_start_sector + (cluster / 128) as usize;
-        if sector > self.table_end_sector {
+        let per_sector = self.entries_per_sector();
+        let sector = self.table_start_sector + cluster as usize / per_sector;
+        if sector >= self.table_end_sector || cluster >= self.cluster_count + 2 {
             return Err(Error::ENFILE)
         }
-        let offset = (cluster % 128) * 4;
-        Ok((sector, offset as usize))
+        let offset = (cluster as usize % per_sector) * 4;
+        Ok((sector, offset))
     }
 
     //返回cluster的第一个sector
@@ -161,16 +240,58 @@ impl FAT32FileSystem {
     pub fn alloc_free_cluster(&self) -> Result<u32, Error> {
         let cluster = self.alloc_cluster_in_cache()?;
 
-        let (sector, offset) = self.get_cluster_entry_pos(cluster).unwrap();
-        get_block_cache(sector, self.block_file.clone())
-        .write()
-        .modify(offset, |u: &mut u32|{
+        self.modify_cluster_entry(cluster, |u: &mut u32|{
             assert!(cluster_type(*u) == ClusterType::Free);
             *u = CLUSTER_END;
-        });
+        }).unwrap();
+
+        let mut free_info = self.free_info.lock();
+        free_info.free_count = free_info.free_count.saturating_sub(1);
+        free_info.next_free = match cluster + 1 {
+            next if next < self.cluster_count + 2 => next,
+            _ => 2,
+        };
+        self.sync_fsinfo(&free_info);
         Ok(cluster)
     }
 
+    // 修改cluster在FAT表中的项, 并将结果写入所有的FAT镜像
+    // FAT表项的高4位是保留位, 需要保持不变
+    fn modify_cluster_entry(&self, cluster: u32, f: impl FnOnce(&mut u32)) -> Result<(), Error> {
+        let (sector, offset) = self.get_cluster_entry_pos(cluster)?;
+        let value = get_block_cache(sector, self.block_file.clone())
+        .write()
+        .modify(offset, |u: &mut u32|{
+            let mut new = *u;
+            f(&mut new);
+            *u = (*u & !CLUSTER_MASK) | (new & CLUSTER_MASK);
+            *u
+        });
+        for table in self.mirror_tables.iter() {
+            let mirror_sector = table + sector - self.table_start_sector;
+            get_block_cache(mirror_sector, self.block_file.clone())
+            .write()
+            .modify(offset, |u: &mut u32|{
+                *u = value;
+            });
+        }
+        Ok(())
+    }
+
+    fn sync_fsinfo(&self, free_info: &FreeInfo) {
+        if let Some(sector) = self.fsinfo_sector {
+            get_block_cache(sector, self.block_file.clone())
+            .write()
+            .modify(0, |info: &mut FSInfo|{
+                info.lead_sig = FSInfo::LEAD_SIG;
+                info.struct_sig = FSInfo::STRUCT_SIG;
+                info.trail_sig = FSInfo::TRAIL_SIG;
+                info.free_count = free_info.free_count;
+                info.next_free = free_info.next_free;
+            });
+        }
+    }
+
     pub fn free_cluster_chain(&self, mut start: u32) -> Result<usize, Error> {
         trace!("free_cluster_chain from {}", start);
 
@@ -188,15 +309,19 @@ impl FAT32FileSystem {
     }
 
     pub fn free_cluster(&self, cluster: u32) -> Result<(), Error> {
-        let (sector, offset) = self.get_cluster_entry_pos(cluster)?;
-        get_block_cache(sector, self.block_file.clone())
-        .write()
-        .modify(offset, |u: &mut u32|{
+        let mut already_free = false;
+        self.modify_cluster_entry(cluster, |u: &mut u32|{
             if cluster_type(*u) == ClusterType::Free {
                 warn!("free a free cluster, might be an error");
+                already_free = true;
             } 
             *u = 0;
-        });
+        })?;
+        if !already_free {
+            let mut free_info = self.free_info.lock();
+            free_info.free_count += 1;
+            self.sync_fsinfo(&free_info);
+        }
         Ok(())
     }
 
@@ -212,25 +337,27 @@ impl FAT32FileSystem {
         }
 
         assert!(cache.len() == 0);
-        const CLUSTER_NUM: usize = 128;
-
-        let start_sector = self.table_start_sector;
-        let end_sector = self.table_end_sector;
-
-        for sector_id in start_sector ..end_sector {
-            get_block_cache(sector_id, self.block_file.clone())
-            .read()
-            .read(0, |table: &ClusterTable|{
-                for (idx, cluster) in table.iter().enumerate() {
-                    if cluster_type(*cluster) == ClusterType::Free {
-                        let id = (sector_id - start_sector) * CLUSTER_NUM + idx;
-                        cache.push_back(id as u32);
+        let per_sector = self.entries_per_sector();
+
+        /* 从info sector中的next_free开始扫描, 到达末尾后从头开始 */
+        let next_free = self.free_info.lock().next_free;
+        let end = self.cluster_count + 2;
+        for (from, to) in [(next_free, end), (2, next_free)] {
+            let mut cluster = from;
+            while cluster < to && cache.len() < CLUSTER_CACHE_SIZE {
+                let (sector_id, _) = self.get_cluster_entry_pos(cluster)?;
+                let first = cluster as usize % per_sector;
+                let last = (first + (to - cluster) as usize).min(per_sector);
+                get_block_cache(sector_id, self.block_file.clone())
+                .read()
+                .read_slice(|table: &[u32]|{
+                    for idx in first..last {
+                        if cluster_type(table[idx]) == ClusterType::Free {
+                            cache.push_back(cluster + (idx - first) as u32);
+                        }
                     }
-                }
-            });
-
-            if cache.len() > CLUSTER_CACHE_SIZE {
-                break;       
+                });
+                cluster += (last - first) as u32;
             }
         }
         
@@ -243,17 +370,19 @@ impl FAT32FileSystem {
     }
 
     pub fn free_cluster_num(&self) -> usize {
-        let start_sector = self.table_start_sector;
-        let end_sector = self.table_end_sector;
+        self.free_info.lock().free_count as usize
+    }
+
+    // 扫描FAT表, 统计空闲簇的数量, 只在info sector无效时使用
+    fn count_free_clusters(&self) -> usize {
         let mut num = 0;
-        for sector_id in start_sector ..end_sector - 2 {    
-            get_block_cache(sector_id, self.block_file.clone())
+        for cluster in 2..self.cluster_count + 2 {
+            let (sector, offset) = self.get_cluster_entry_pos(cluster).unwrap();
+            get_block_cache(sector, self.block_file.clone())
             .read()
-            .read(0, |table: &ClusterTable|{
-                for (_idx, cluster) in table.iter().enumerate() {
-                    if cluster_type(*cluster) == ClusterType::Free {
-                        num += 1;
-                    }
+            .read(offset, |u: &u32|{
+                if cluster_type(*u) == ClusterType::Free {
+                    num += 1;
                 }
             });
         }
@@ -263,13 +392,10 @@ impl FAT32FileSystem {
     // set the next cluster of 'curren't to 'next'
     // 注：在调用这个函数之前，current和next必须已经被分配出去
     pub fn set_next_cluster(&self, current: u32, next: u32) {
-        let (sector, offset) = self.get_cluster_entry_pos(current).unwrap();
-        get_block_cache(sector, self.block_file.clone())
-        .write()
-        .modify(offset, |cluster: &mut u32|{
+        self.modify_cluster_entry(current, |cluster: &mut u32|{
             assert!(cluster_type(*cluster) != ClusterType::Free);
             *cluster = next;
-        });
+        }).unwrap();
     }
 
     pub fn get_next_cluster(&self, cluster: u32) -> Result<u32, Error> {
@@ -359,17 +485,17 @@ impl VFS for FAT32FileSystem {
     fn mount_path(&self) -> Path {
         self.mount_path.clone()
     }
+    /* 目录项中直接保存起始簇号, 没有链接计数, 不支持硬连接 */
     fn link(&self, _path: Path, _dst_path: Arc<dyn File>) -> Result<Arc<dyn File>, Error> {
-        todo!()
+        Err(Error::EPERM)
     }
     fn statvfs(&self) -> Result<Statvfs, Error> {
-        /* 为了方便，就不获取空闲块s数量了 */
+        /* 空闲簇数量在info sector中维护 */
         let num = self.free_cluster_num();
-        //let num = 1908350;
         Ok(Statvfs {
-            bsize: SECTOR_SIZE,
-            frsize: self.sectors_per_clusters * SECTOR_SIZE,
-            blocks: self.sectors_number / self.sectors_per_clusters,
+            bsize: self.sector_size,
+            frsize: self.get_bytes_per_cluster(),
+            blocks: self.cluster_count as usize,
             bfree: num,
             bavail: num,
             files: num,
@@ -381,27 +507,11 @@ impl VFS for FAT32FileSystem {
 
         })
     }
-
-}
-
-#[derive(Debug, PartialEq)]
-pub enum ClusterType {
-    Free,   //0x?0000000
-    Data,   //0x?0000002 - 0x?FFFFFEF
-    End,    //0x?FFFFFF8 - 0x?FFFFFFF
-    Other,  //reserved / unuesd / bad
-}
-
-pub fn cluster_type(cluster: u32) -> ClusterType {
-    let cluster = cluster & 0x0fff_ffff;
-    if cluster == 0 {
-        ClusterType::Free
-    } else if cluster >= 0x0000_0002 && cluster <= 0x0FFF_FFEF {
-        ClusterType::Data
-    } else if cluster >= 0x0FFF_FFF8 && cluster <= 0x0FFF_FFFF {
-        ClusterType::End
-    } else {
-        ClusterType::Other
+    /* 先写回页缓存中的文件数据, 再按块号顺序写回目录项, FAT表和info sector所在的块 */
+    fn sync(&self) -> Result<(), Error> {
+        let result = sync_page_caches(Some(self.id));
+        block_cache_sync_dev(self.block_file.get_id());
+        result
     }
 }
 
@@ -453,3 +563,20 @@ impl DirentCache {
         self.0.remove(&cluster).unwrap();
     }
 }
+
+/* fsck通过块缓存访问磁盘, 块大小等于sector大小 */
+struct CachedDisk(Arc<dyn BlockFile>);
+
+impl FsckDisk for CachedDisk {
+    fn read_sector(&self, sector: usize, buf: &mut [u8]) {
+        get_block_cache(sector, self.0.clone())
+            .read()
+            .read_slice(|data: &[u8]| buf.copy_from_slice(data));
+    }
+
+    fn write_sector(&self, sector: usize, buf: &[u8]) {
+        get_block_cache(sector, self.0.clone())
+            .write()
+            .modify_slice(|data: &mut [u8]| data.copy_from_slice(buf));
+    }
+}
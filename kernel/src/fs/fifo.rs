@@ -1,90 +1,271 @@
 // from rCore_tutorial v3   fixme: license
 
 
-use super::{File, SeekMode};
+use super::{File, FIFOFile, SeekMode};
 use super::file::PollType;
-use spin::Mutex;
+use spin::{Mutex, MutexGuard};
+use alloc::collections::VecDeque;
 use alloc::vec::Vec;
 use alloc::sync::{Arc,Weak};
 use crate::utils::Error;
-use crate::proc::{suspend_current, get_current_task};
-use crate::config::PIPE_BUFFER_SIZE;
+use crate::proc::{wait_on, wait_on_all, WaitQueue, Waker};
+use crate::config::{PAGE_SIZE, PIPE_BUFFER_SIZE, PIPE_HARD_MAX_SIZE};
+use crate::memory::{FrameTracker, frame_safe_alloc};
 use crate::utils::mem_buffer::MemBuffer;
 use log::*;
 
-#[derive(Copy, Clone, PartialEq)]
-enum PipeBufferStatus {
-    FULL,
-    EMPTY,
-    NORMAL
+/// 管道中的一段数据, 引用一个物理页中的[offset, offset + len)
+/// splice和tee在管道之间移动或共享页, 文件的页缓存中的页也可以直接放入管道
+#[derive(Clone)]
+pub struct PipeBuf {
+    frame: FrameTracker,
+    offset: usize,
+    len: usize,
 }
 
+impl PipeBuf {
+    pub fn new(frame: FrameTracker, offset: usize, len: usize) -> Self {
+        assert!(offset + len <= PAGE_SIZE);
+        Self { frame, offset, len }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn data(&self) -> &'static mut [u8] {
+        &mut self.frame.ppn.get_byte_array()[self.offset..self.offset + self.len]
+    }
+
+    /* 前n字节 */
+    fn split(&self, n: usize) -> Self {
+        Self { frame: self.frame.clone(), offset: self.offset, len: n }
+    }
+
+    fn consume(&mut self, n: usize) {
+        self.offset += n;
+        self.len -= n;
+    }
+}
+
+/// 管道和unix socket的缓冲区, 数据保存在按页分配的PipeBuf中
+/// 1, 容量以字节计, 默认PIPE_BUFFER_SIZE, 可以通过F_SETPIPE_SZ修改
+/// 2, 写入时追加到最后一页; 最后一页同时被其它管道或页缓存引用时不能追加, 使用新的页
 pub struct RingBuffer<T> {
-    buffer: [u8; PIPE_BUFFER_SIZE],
-    head: usize,
-    tail: usize,
-    status: PipeBufferStatus,
-    write_end: Option<Weak<T>>,
+    bufs: VecDeque<PipeBuf>,
+    len: usize,
+    capacity: usize,
+    /* 有名管道可以被多次打开, 每个打开的端都要记录 */
+    write_ends: Vec<Weak<T>>,
+    read_ends: Vec<Weak<T>>,
+    /* 读写和关闭时唤醒ppoll, epoll的等待者 */
+    queue: Arc<WaitQueue>,
 }
 
 impl<T> RingBuffer<T> {
     pub fn new() -> Self {
         Self {
-            buffer: [0; PIPE_BUFFER_SIZE],
-            head: 0,
-            tail: 0,
-            status: PipeBufferStatus::EMPTY,
-            write_end: None,
+            bufs: VecDeque::new(),
+            len: 0,
+            capacity: PIPE_BUFFER_SIZE,
+            write_ends: Vec::new(),
+            read_ends: Vec::new(),
+            queue: Arc::new(WaitQueue::new()),
         }
     }
 
+    pub fn queue(&self) -> &Arc<WaitQueue> {
+        &self.queue
+    }
+
+    /// 有名管道在写端打开之前没有记录写端, 此时读端等待写端出现
     pub fn all_wirte_ends_closed(&self) -> bool {
-        self.write_end.as_ref().unwrap().upgrade().is_none()
+        Self::all_closed(&self.write_ends)
     }
 
     pub fn record_write_end(&mut self, write_pipe: &Arc<T>) {
-        self.write_end = Some(Arc::downgrade(write_pipe));
+        Self::record(&mut self.write_ends, write_pipe);
+    }
+
+    /// 没有记录读端时认为读端一直存在
+    pub fn all_read_ends_closed(&self) -> bool {
+        Self::all_closed(&self.read_ends)
+    }
+
+    pub fn record_read_end(&mut self, read_end: &Arc<T>) {
+        Self::record(&mut self.read_ends, read_end);
+    }
+
+    fn all_closed(ends: &[Weak<T>]) -> bool {
+        !ends.is_empty() && ends.iter().all(|end| end.upgrade().is_none())
+    }
+
+    /* 顺便去掉已经关闭的端, 记录之后列表不为空 */
+    fn record(ends: &mut Vec<Weak<T>>, end: &Arc<T>) {
+        ends.retain(|end| end.strong_count() > 0);
+        ends.push(Arc::downgrade(end));
     }
 
     pub fn available_read_bytes(&self) -> usize {
-        if self.status == PipeBufferStatus::EMPTY {
-            0
-        } else if self.tail > self.head {
-            self.tail - self.head
-        } else {
-            self.tail + PIPE_BUFFER_SIZE - self.head
-        }
+        self.len
     }
 
     pub fn available_write_bytes(&self) -> usize {
-        if self.status == PipeBufferStatus::FULL {
-            0
-        } else {
-            PIPE_BUFFER_SIZE - self.available_read_bytes()
+        self.capacity.saturating_sub(self.len)
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// 容量向上取整为2的幂个页, 小于已有的数据时返回EBUSY
+    pub fn set_capacity(&mut self, size: usize) -> Result<usize, Error> {
+        if size == 0 || size > PIPE_HARD_MAX_SIZE {
+            return Err(Error::EINVAL);
+        }
+        let pages = ((size + PAGE_SIZE - 1) / PAGE_SIZE).next_power_of_two();
+        let capacity = pages * PAGE_SIZE;
+        if capacity < self.len {
+            return Err(Error::EBUSY);
+        }
+        self.capacity = capacity;
+        self.queue.wake_all();
+        Ok(capacity)
+    }
+
+    /* 保证最后一页可以追加数据 */
+    fn tail_room(&mut self) -> Result<(), Error> {
+        let full = match self.bufs.back() {
+            Some(buf) => !buf.frame.is_only_owner() || buf.offset + buf.len == PAGE_SIZE,
+            None => true,
+        };
+        if full {
+            let frame = frame_safe_alloc().ok_or(Error::ENOMEM)?;
+            self.bufs.push_back(PipeBuf::new(frame, 0, 0));
+        }
+        Ok(())
+    }
+
+    /* 最多追加len字节, fill每次填满给出的一段; 一个页都分配不到时返回ENOMEM */
+    fn fill(&mut self, len: usize, mut fill: impl FnMut(&mut [u8])) -> Result<usize, Error> {
+        let len = len.min(self.available_write_bytes());
+        let mut written = 0;
+        while written < len {
+            if let Err(err) = self.tail_room() {
+                if written == 0 {
+                    return Err(err);
+                }
+                break;
+            }
+            let buf = self.bufs.back_mut().unwrap();
+            let start = buf.offset + buf.len;
+            let n = (PAGE_SIZE - start).min(len - written);
+            fill(&mut buf.frame.ppn.get_byte_array()[start..start + n]);
+            buf.len += n;
+            written += n;
         }
+        self.len += written;
+        Ok(written)
     }
 
-    pub fn read_one_byte(&mut self) -> u8 {
-        self.status = PipeBufferStatus::NORMAL;
-        let byte = self.buffer[self.head];
-        self.head = (self.head + 1) % PIPE_BUFFER_SIZE;
-        if self.head == self.tail {
-            self.status = PipeBufferStatus::EMPTY;
+    /* 最多取出len字节, drain依次处理每一段 */
+    fn drain(&mut self, len: usize, mut drain: impl FnMut(&[u8])) -> usize {
+        let len = len.min(self.len);
+        let mut read = 0;
+        while read < len {
+            let buf = self.bufs.front_mut().unwrap();
+            let n = buf.len.min(len - read);
+            drain(&buf.data()[..n]);
+            buf.consume(n);
+            if buf.len == 0 {
+                self.bufs.pop_front();
+            }
+            read += n;
         }
-        byte
+        self.len -= read;
+        read
+    }
+
+    pub fn write(&mut self, data: &[u8]) -> Result<usize, Error> {
+        let mut pos = 0;
+        self.fill(data.len(), |buf| {
+            buf.copy_from_slice(&data[pos..pos + buf.len()]);
+            pos += buf.len();
+        })
     }
 
-    pub fn write_one_byte(&mut self, byte: u8) {
-        self.status = PipeBufferStatus::NORMAL;
-        self.buffer[self.tail] = byte;
-        self.tail = (self.tail + 1) % PIPE_BUFFER_SIZE;
-        if self.tail == self.head {
-            self.status = PipeBufferStatus::FULL;
+    pub fn read(&mut self, len: usize) -> Vec<u8> {
+        let mut data = Vec::with_capacity(len.min(self.len));
+        self.drain(len, |buf| data.extend_from_slice(buf));
+        data
+    }
+
+    /// 从mem中写入, 用于vmsplice
+    pub fn write_from_buffer(&mut self, mem: &mut MemBuffer) -> Result<usize, Error> {
+        self.fill(mem.len(), |buf| mem.write_data_from_buffer(buf))
+    }
+
+    /// 读出到mem中, 用于vmsplice
+    pub fn read_to_buffer(&mut self, mem: &mut MemBuffer) -> usize {
+        self.drain(mem.len(), |buf| mem.read_data_to_buffer(buf))
+    }
+
+    /// 丢弃开头最多len字节, 用于数据已经通过peek_bufs写出之后
+    pub fn consume(&mut self, len: usize) -> usize {
+        self.drain(len, |_| {})
+    }
+
+    /// 取出最多len字节的页, 不复制数据
+    pub fn take_bufs(&mut self, len: usize) -> Vec<PipeBuf> {
+        let mut bufs = Vec::new();
+        let mut left = len.min(self.len);
+        self.len -= left;
+        while left > 0 {
+            let front = self.bufs.front_mut().unwrap();
+            if front.len > left {
+                bufs.push(front.split(left));
+                front.consume(left);
+                break;
+            }
+            /* 整段移动时直接使用原来的PipeBuf, 不增加页的引用, 目标管道可以继续追加 */
+            left -= front.len;
+            bufs.push(self.bufs.pop_front().unwrap());
+        }
+        bufs
+    }
+
+    /// 最多len字节的页的引用, 数据仍然留在缓冲区中, 用于tee
+    pub fn peek_bufs(&self, len: usize) -> Vec<PipeBuf> {
+        let mut bufs = Vec::new();
+        let mut left = len.min(self.len);
+        for buf in self.bufs.iter() {
+            if left == 0 {
+                break;
+            }
+            let n = buf.len.min(left);
+            bufs.push(buf.split(n));
+            left -= n;
+        }
+        bufs
+    }
+
+    /// 放入页, 超过可写空间的部分被截断, 返回放入的字节数
+    pub fn push_bufs(&mut self, bufs: Vec<PipeBuf>) -> usize {
+        let mut pushed = 0;
+        for buf in bufs {
+            let n = buf.len.min(self.available_write_bytes());
+            if n == 0 {
+                break;
+            }
+            self.bufs.push_back(buf.split(n));
+            self.len += n;
+            pushed += n;
         }
+        pushed
     }
 }
 
-type PipeRingBuffer = RingBuffer<Pipe>;
+pub type PipeRingBuffer = RingBuffer<Pipe>;
 
 pub struct Pipe {
     readable: bool,
@@ -107,6 +288,145 @@ impl Pipe{
             buffer:buffer.clone(),
         }
     }
+    /* 以O_RDWR打开的有名管道, 同时是读端和写端 */
+    pub fn set_read_write(buffer: Arc<Mutex<PipeRingBuffer>>) -> Self{
+        Self{
+            readable:true,
+            writable:true,
+            buffer:buffer.clone(),
+        }
+    }
+
+    fn queue(&self) -> Arc<WaitQueue> {
+        self.buffer.lock().queue().clone()
+    }
+
+    pub fn pipe_size(&self) -> usize {
+        self.buffer.lock().capacity()
+    }
+
+    pub fn set_pipe_size(&self, size: usize) -> Result<usize, Error> {
+        self.buffer.lock().set_capacity(size)
+    }
+
+    fn block_on<T>(&self, nonblock: bool, mut f: impl FnMut() -> Option<Result<T, Error>>) -> Result<T, Error> {
+        if nonblock {
+            return f().unwrap_or(Err(Error::EAGAIN));
+        }
+        wait_on(&self.queue(), f)
+    }
+
+    /// 等待管道中有数据, 返回None表示写端已经全部关闭
+    pub fn wait_data(&self, nonblock: bool) -> Result<Option<MutexGuard<PipeRingBuffer>>, Error> {
+        if !self.readable {
+            return Err(Error::EBADF);
+        }
+        let buffer = &self.buffer;
+        self.block_on(nonblock, move || {
+            let guard = buffer.lock();
+            if guard.available_read_bytes() != 0 {
+                Some(Ok(Some(guard)))
+            } else if guard.all_wirte_ends_closed() {
+                Some(Ok(None))
+            } else {
+                None
+            }
+        })
+    }
+
+    /// 等待管道中有空间, 读端已经全部关闭时返回EPIPE
+    pub fn wait_room(&self, nonblock: bool) -> Result<MutexGuard<PipeRingBuffer>, Error> {
+        if !self.writable {
+            return Err(Error::EBADF);
+        }
+        let buffer = &self.buffer;
+        self.block_on(nonblock, move || {
+            let guard = buffer.lock();
+            if guard.all_read_ends_closed() {
+                Some(Err(Error::EPIPE))
+            } else if guard.available_write_bytes() != 0 {
+                Some(Ok(guard))
+            } else {
+                None
+            }
+        })
+    }
+
+    /// 丢弃开头最多len字节并唤醒写端
+    pub fn consume(&self, len: usize) {
+        let mut buffer = self.buffer.lock();
+        buffer.consume(len);
+        buffer.queue().wake_all();
+    }
+
+    /// 放入全部的页, 空间不足时等待; 已经放入部分数据后被中断时返回放入的字节数
+    pub fn push_bufs(&self, bufs: Vec<PipeBuf>, nonblock: bool) -> Result<usize, Error> {
+        let mut pushed = 0;
+        for mut buf in bufs {
+            while buf.len > 0 {
+                let mut buffer = match self.wait_room(nonblock) {
+                    Ok(buffer) => buffer,
+                    Err(_) if pushed > 0 => return Ok(pushed),
+                    Err(err) => return Err(err),
+                };
+                let n = buffer.push_bufs(alloc::vec![buf.clone()]);
+                buffer.queue().wake_all();
+                buf.consume(n);
+                pushed += n;
+            }
+        }
+        Ok(pushed)
+    }
+
+    /// 管道之间移动(consume)或复制页的引用(tee), 不复制数据
+    /// 源管道为空且写端全部关闭时返回0
+    pub fn splice_to(&self, dst: &Pipe, len: usize, nonblock: bool, consume: bool) -> Result<usize, Error> {
+        if !self.readable || !dst.writable {
+            return Err(Error::EBADF);
+        }
+        if Arc::ptr_eq(&self.buffer, &dst.buffer) {
+            return Err(Error::EINVAL);
+        }
+        let (src_queue, dst_queue) = (self.queue(), dst.queue());
+        let transfer = || {
+            /* 按地址顺序加锁, 避免反方向的splice死锁 */
+            let (mut from, mut to) = match Arc::as_ptr(&self.buffer) < Arc::as_ptr(&dst.buffer) {
+                true => {
+                    let from = self.buffer.lock();
+                    (from, dst.buffer.lock())
+                }
+                false => {
+                    let to = dst.buffer.lock();
+                    (self.buffer.lock(), to)
+                }
+            };
+            if to.all_read_ends_closed() {
+                return Some(Err(Error::EPIPE));
+            }
+            if from.available_read_bytes() == 0 {
+                return match from.all_wirte_ends_closed() {
+                    true => Some(Ok(0)),
+                    false => None,
+                };
+            }
+            let n = len.min(to.available_write_bytes());
+            if n == 0 {
+                return None;
+            }
+            let bufs = match consume {
+                true => from.take_bufs(n),
+                false => from.peek_bufs(n),
+            };
+            let moved = to.push_bufs(bufs);
+            from.queue().wake_all();
+            to.queue().wake_all();
+            Some(Ok(moved))
+        };
+        match nonblock {
+            true => transfer().unwrap_or(Err(Error::EAGAIN)),
+            false => wait_on_all(&[src_queue.as_ref(), dst_queue.as_ref()], transfer),
+        }
+    }
 }
 
 pub fn create_pipe() -> (Arc<Pipe>, Arc<Pipe>){
@@ -123,43 +443,21 @@ impl File for Pipe {
         Ok(())
     }
 
+    /* 没有数据则会阻塞 */
     fn read(&self, len: usize) -> Result<Vec<u8>, Error> {
         if !self.readable {
             warn!("pipe_read: This pipe is not readable for current process!");
             return Err(Error::EPERM);
         }
-
-        let mut read_buf: Vec<u8> = Vec::new();
-        let mut has_read: usize = 0;
-        let mut loop_read ;
-        /* 没有数据则会阻塞 */
-        loop {
-            let task = get_current_task().unwrap();
-
-            let buffer = self.buffer.lock();
-            loop_read = buffer.available_read_bytes();
-            if loop_read != 0 {
-                break;
-            } else if buffer.all_wirte_ends_closed() {
-                return Ok(read_buf)
-            } else if task.has_signal() {
-                //task.set_interrupted();
-                return Err(Error::EINTR);
-            } else {
-                drop(buffer);
-                drop(task);
-                suspend_current();
-            }
-        }
-        let mut buffer = self.buffer.lock();
-        for _ in 0..loop_read {
-            if has_read == len {
-                break;
+        let data = match self.wait_data(false)? {
+            Some(mut buffer) => {
+                let data = buffer.read(len);
+                buffer.queue().wake_all();
+                data
             }
-            read_buf.push(buffer.read_one_byte());
-            has_read += 1;
-        }
-        return Ok(read_buf);
+            None => Vec::new(),
+        };
+        Ok(data)
     }
 
     fn write(&self, data: Vec<u8>) -> Result<usize, Error> {
@@ -170,30 +468,21 @@ impl File for Pipe {
         let len:usize = data.len();
         let mut has_write:usize = 0;
 
-        while  has_write < len {
-            let mut buffer = self.buffer.lock();
-            let loop_write = buffer.available_write_bytes();
-            trace!("pipe_write: loop_write = {}", loop_write);
-            let task = get_current_task().unwrap();
-            if task.has_signal() {
-                //task.set_interrupted();
-                return Err(Error::EINTR);
-            } 
-            if loop_write == 0{
-                drop(task);
-                drop(buffer);
-                suspend_current();
-                continue;
-            }
-            for _ in 0..loop_write{
-                if has_write == len {
-                    return Ok(len);
-                }
-                buffer.write_one_byte(data[has_write]);
-                has_write += 1;
+        while has_write < len {
+            let written = self.wait_room(false).and_then(|mut buffer| {
+                let written = buffer.write(&data[has_write..])?;
+                trace!("pipe_write: written = {}", written);
+                buffer.queue().wake_all();
+                Ok(written)
+            });
+            match written {
+                Ok(written) => has_write += written,
+                /* 已经写入了部分数据, 返回写入的长度 */
+                Err(_) if has_write > 0 => break,
+                Err(err) => return Err(err),
             }
         }
-        return Ok(len);
+        Ok(has_write)
     }
 
     fn readable(&self) -> bool {
@@ -206,15 +495,43 @@ impl File for Pipe {
 
     fn poll(&self, ptype: PollType) -> Result<bool, Error> {
         let buffer = self.buffer.lock();
+        /* 写端全部关闭时读到EOF, 读端全部关闭时写返回错误, 都不会阻塞 */
         let ret = match ptype {
-            PollType::READ => buffer.available_read_bytes() != 0,
-            PollType::WRITE => buffer.available_write_bytes() != 0,
-            PollType::ERR => false
+            PollType::READ => buffer.available_read_bytes() != 0 || buffer.all_wirte_ends_closed(),
+            PollType::WRITE => buffer.available_write_bytes() != 0 || buffer.all_read_ends_closed(),
+            PollType::ERR => self.writable && buffer.all_read_ends_closed(),
+            PollType::HUP => self.readable && buffer.all_wirte_ends_closed(),
         };
         Ok(ret)
     }
 
+    fn register_waker(&self, waker: &Arc<dyn Waker>) -> bool {
+        self.buffer.lock().queue().register(waker);
+        true
+    }
+
+    fn unregister_waker(&self, waker: &Arc<dyn Waker>) {
+        self.buffer.lock().queue().unregister(waker);
+    }
+
     fn seek(&self, _pos : usize, _mode: SeekMode) -> Result<isize, Error> {
         Ok(0)
     }
-}
\ No newline at end of file
+
+    fn as_fifo<'a>(self: Arc<Self>) -> Result<Arc<dyn FIFOFile + 'a>, Error> where Self: 'a {
+        Ok(self)
+    }
+
+    fn as_pipe(self: Arc<Self>) -> Result<Arc<Pipe>, Error> {
+        Ok(self)
+    }
+}
+
+impl FIFOFile for Pipe {}
+
+/* 关闭一端后另一端的等待者可能已经可以读写 */
+impl Drop for Pipe {
+    fn drop(&mut self) {
+        self.buffer.lock().queue().wake_all();
+    }
+}
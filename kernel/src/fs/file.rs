@@ -4,6 +4,14 @@ use super::{
     vfs::{VFS, FSid}
 };
 use crate::utils::{Error, Path, mem_buffer::MemBuffer};
+use crate::net::SockAddr;
+use crate::proc::Waker;
+use super::page_cache::PageCache;
+use super::epoll::Epoll;
+use super::signalfd::SignalFd;
+use super::timerfd::TimerFd;
+use super::inotify::Inotify;
+use super::fifo::Pipe;
 use alloc::{string::String, sync::Arc, vec::Vec};
 use log::*;
 
@@ -24,6 +32,8 @@ pub enum PollType {
     READ,
     WRITE,
     ERR,
+    /* 对端已经关闭(管道的写端全部关闭, socket连接断开), 总是报告给poll和epoll */
+    HUP,
 }
 
 #[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
@@ -38,8 +48,9 @@ pub trait File: Send + Sync {
     fn close(&self) -> Result<(), Error> {
         unimplemented!(); 
     }
+    /* 没有FileIndex的文件(管道, socket等)返回EINDEX */
     fn get_index(&self) -> Result<FileIndex, Error> {
-        unimplemented!();
+        Err(Error::EINDEX)
     }
     fn read(&self, _len : usize) -> Result<Vec<u8>, Error> {
         unimplemented!();
@@ -72,18 +83,41 @@ pub trait File: Send + Sync {
     fn get_name(&self) -> Result<String, Error> {
         unimplemented!();
     }
+    /* 文件打开时的绝对路径, 不是所有文件都有路径 */
+    fn get_path(&self) -> Result<Path, Error> {
+        Err(Error::ENOENT)
+    }
     fn get_type(&self) -> Result<FileType, Error> {
         unimplemented!();
     }
+    /* 将文件的数据和元数据写回块设备, 用于fsync和fdatasync */
+    fn fsync(&self) -> Result<(), Error> {
+        Ok(())
+    }
+    /* 文件数据的页缓存, 文件映射直接使用缓存中的物理页, 没有页缓存的文件映射时复制数据 */
+    fn page_cache(&self) -> Option<Arc<PageCache>> {
+        None
+    }
+    /* chmod, chown, utimensat通过write_stat修改文件属性, 不支持的文件返回EPERM */
     fn write_stat(&self, _stat: &FileStat) -> Result<(), Error> {
-        unimplemented!();
+        Err(Error::EPERM)
     }
     fn read_stat(&self) -> Result<FileStat, Error> {
         unimplemented!();
     }
+    /* 没有实现poll的文件(设备, procfs等)与普通文件一样总是可以读写 */
     fn poll(&self, ptype: PollType) -> Result<bool, Error> {
-        unimplemented!();
+        match ptype {
+            PollType::READ | PollType::WRITE => Ok(true),
+            PollType::ERR | PollType::HUP => Ok(false),
+        }
+    }
+    /* 在文件的等待队列上注册waker, poll的结果可能变化时调用waker.wake()
+     * 返回false表示文件不会主动唤醒, 等待者需要定时重新poll */
+    fn register_waker(&self, _waker: &Arc<dyn Waker>) -> bool {
+        false
     }
+    fn unregister_waker(&self, _waker: &Arc<dyn Waker>) {}
     fn copy(&self) -> Arc<dyn File> {
         unimplemented!();
     }
@@ -117,6 +151,21 @@ pub trait File: Send + Sync {
     fn as_socket<'a>(self: Arc<Self>) -> Result<Arc<dyn SocketFile + 'a>, Error> where Self: 'a, {
         Err(Error::EPERM)
     }
+    fn as_epoll(self: Arc<Self>) -> Result<Arc<Epoll>, Error> {
+        Err(Error::EPERM)
+    }
+    fn as_timerfd(self: Arc<Self>) -> Result<Arc<TimerFd>, Error> {
+        Err(Error::EPERM)
+    }
+    fn as_signalfd(self: Arc<Self>) -> Result<Arc<SignalFd>, Error> {
+        Err(Error::EPERM)
+    }
+    fn as_inotify(self: Arc<Self>) -> Result<Arc<Inotify>, Error> {
+        Err(Error::EPERM)
+    }
+    fn as_pipe(self: Arc<Self>) -> Result<Arc<Pipe>, Error> {
+        Err(Error::EPERM)
+    }
 }
 
 pub trait DirFile: File {
@@ -147,9 +196,20 @@ pub trait DeviceFile: File {
 }
 
 pub trait BlockFile: DeviceFile {
+    /* 一次读写的字节数, read_block和write_block的buf长度与之相同 */
+    fn block_size(&self) -> usize {
+        crate::config::BLOCK_SIZE
+    }
     fn read_block(&self, _block_id: usize, _buf: &mut [u8]) {
         unimplemented!();
     }
+    /* 读入从block_id开始的连续多个块, buf长度为块大小的整数倍, 支持多块读的设备可以一次完成 */
+    fn read_blocks(&self, block_id: usize, buf: &mut [u8]) {
+        let block_size = self.block_size();
+        for (i, chunk) in buf.chunks_mut(block_size).enumerate() {
+            self.read_block(block_id + i, chunk);
+        }
+    }
     fn write_block(&self, _block_id: usize, _buf: &[u8]) {
         unimplemented!();
     }
@@ -165,19 +225,29 @@ pub trait CharFile: DeviceFile {
 }
 
 pub trait LinkFile     : File {
+    /* 解析后的绝对路径 */
     fn read_link(&self) -> Result<Path, Error>;
-    fn write_link(&self, path: &Path) -> Result<(), Error>;
+    /* 原样保存symlink传入的目标, 相对路径在read_link时才解析 */
+    fn write_link(&self, target: &str) -> Result<(), Error>;
+    /* readlink读出的内容 */
+    fn link_name(&self) -> Result<String, Error> {
+        Ok(self.read_link()?.to_string())
+    }
 }
+
+pub use super::link::resolve_link;
 pub trait FIFOFile     : File {}
 
 pub trait SocketFile : File{
-    fn listen(&self)                    -> Result<usize, Error>;
-    fn accept(&self)                    -> Result<usize, Error>;
-    fn connect(&self)                   -> Result<usize, Error>;
-    fn getsockname(&self)               -> Result<usize, Error>;
-    fn sendto(&self)                    -> Result<usize, Error>;
-    fn recvfrom(&self,len:usize)        -> Result<Vec<u8>, Error>;
-    fn setsockopt(&self)                -> Result<usize, Error>;
+    fn bind(&self, addr: SockAddr)                          -> Result<(), Error>;
+    fn listen(&self, backlog: usize)                        -> Result<(), Error>;
+    fn accept(&self)                                        -> Result<(Arc<dyn File>, SockAddr), Error>;
+    fn connect(&self, addr: SockAddr)                       -> Result<(), Error>;
+    fn getsockname(&self)                                   -> Result<SockAddr, Error>;
+    fn getpeername(&self)                                   -> Result<SockAddr, Error>;
+    fn sendto(&self, data: &[u8], addr: Option<SockAddr>)   -> Result<usize, Error>;
+    fn recvfrom(&self, len: usize)                          -> Result<(Vec<u8>, Option<SockAddr>), Error>;
+    fn setsockopt(&self, level: usize, optname: usize, optval: &[u8]) -> Result<(), Error>;
 }
 
 bitflags! {
@@ -211,6 +281,9 @@ bitflags! {
         const OTHER_R = 0o004;
         const OTHER_W = 0o002;
         const OTHER_X = 0o001;
+        const SET_UID = 0o4000;
+        const SET_GID = 0o2000;
+        const STICKY  = 0o1000;
     }
 }
  
@@ -261,6 +334,8 @@ pub struct FileStat {
 // refer to man/inode
 #[allow(unused)]
 pub enum StMode {
+    SOCK = 0o140000,
+    LNK = 0o120000,
     REG = 0o100000,
     BLK = 0o060000,
     DIR = 0o040000,
@@ -292,4 +367,4 @@ impl TryFrom<u32> for SeekMode {
             _ => Err(Error::EINVAL)
         }
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,351 @@
+use alloc::{
+    collections::{BTreeMap, VecDeque},
+    string::String,
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use spin::{Mutex, lazy::Lazy};
+
+use super::{File, FileIndex, FileStat, FileType, SeekMode};
+use super::file::PollType;
+use crate::proc::{wait_on, WaitQueue, Waker};
+use crate::utils::Error;
+
+pub const IN_ACCESS: u32 = 0x00000001;
+pub const IN_MODIFY: u32 = 0x00000002;
+pub const IN_ATTRIB: u32 = 0x00000004;
+pub const IN_CLOSE_WRITE: u32 = 0x00000008;
+pub const IN_CLOSE_NOWRITE: u32 = 0x00000010;
+pub const IN_OPEN: u32 = 0x00000020;
+pub const IN_MOVED_FROM: u32 = 0x00000040;
+pub const IN_MOVED_TO: u32 = 0x00000080;
+pub const IN_CREATE: u32 = 0x00000100;
+pub const IN_DELETE: u32 = 0x00000200;
+pub const IN_DELETE_SELF: u32 = 0x00000400;
+pub const IN_MOVE_SELF: u32 = 0x00000800;
+pub const IN_ALL_EVENTS: u32 = 0x00000fff;
+
+pub const IN_Q_OVERFLOW: u32 = 0x00004000;
+pub const IN_IGNORED: u32 = 0x00008000;
+
+pub const IN_ONLYDIR: u32 = 0x01000000;
+pub const IN_DONT_FOLLOW: u32 = 0x02000000;
+pub const IN_EXCL_UNLINK: u32 = 0x04000000;
+pub const IN_MASK_CREATE: u32 = 0x10000000;
+pub const IN_MASK_ADD: u32 = 0x20000000;
+pub const IN_ISDIR: u32 = 0x40000000;
+pub const IN_ONESHOT: u32 = 0x80000000;
+
+pub const IN_NONBLOCK: usize = 0o4000;
+pub const IN_CLOEXEC: usize = 0o2000000;
+
+/* 每个inotify实例最多缓存的事件数, 超过后丢弃并报告IN_Q_OVERFLOW */
+const INOTIFY_MAX_EVENTS: usize = 16384;
+/* struct inotify_event { int wd; uint32_t mask; uint32_t cookie; uint32_t len; char name[]; } */
+const EVENT_HEADER_SIZE: usize = 16;
+
+/* 被监听的文件 -> 监听它的inotify实例和对应的wd */
+static WATCHES: Lazy<Mutex<BTreeMap<FileIndex, Vec<(Weak<Inotify>, i32)>>>> = Lazy::new(|| Mutex::new(BTreeMap::new()));
+/* 同一次rename的IN_MOVED_FROM和IN_MOVED_TO使用相同的cookie */
+static NEXT_COOKIE: AtomicU32 = AtomicU32::new(1);
+
+#[derive(PartialEq)]
+struct InotifyEvent {
+    wd: i32,
+    mask: u32,
+    cookie: u32,
+    name: Option<String>,
+}
+
+impl InotifyEvent {
+    /* name以'\0'结尾并补齐到EVENT_HEADER_SIZE的整数倍, len包括补齐的部分 */
+    fn name_len(&self) -> usize {
+        match self.name.as_ref() {
+            Some(name) => (name.len() + 1 + EVENT_HEADER_SIZE - 1) / EVENT_HEADER_SIZE * EVENT_HEADER_SIZE,
+            None => 0,
+        }
+    }
+
+    fn size(&self) -> usize {
+        EVENT_HEADER_SIZE + self.name_len()
+    }
+
+    fn to_bytes(&self, data: &mut Vec<u8>) {
+        let name_len = self.name_len();
+        data.extend_from_slice(&self.wd.to_ne_bytes());
+        data.extend_from_slice(&self.mask.to_ne_bytes());
+        data.extend_from_slice(&self.cookie.to_ne_bytes());
+        data.extend_from_slice(&(name_len as u32).to_ne_bytes());
+        if let Some(name) = self.name.as_ref() {
+            data.extend_from_slice(name.as_bytes());
+            data.resize(data.len() + name_len - name.len(), 0);
+        }
+    }
+}
+
+struct Watch {
+    index: FileIndex,
+    mask: u32,
+}
+
+struct InotifyInner {
+    watches: BTreeMap<i32, Watch>,
+    next_wd: i32,
+    events: VecDeque<InotifyEvent>,
+}
+
+/// inotify实例, 监听项以被监听文件的FileIndex为键登记在WATCHES中
+/// 文件系统的修改操作通过notify()投递事件, read按struct inotify_event的格式读出
+pub struct Inotify {
+    me: Weak<Inotify>,
+    inner: Mutex<InotifyInner>,
+    nonblock: AtomicBool,
+    queue: WaitQueue,
+}
+
+impl Inotify {
+    pub fn new(flags: usize) -> Arc<Self> {
+        Arc::new_cyclic(|me| Self {
+            me: me.clone(),
+            inner: Mutex::new(InotifyInner {
+                watches: BTreeMap::new(),
+                next_wd: 1,
+                events: VecDeque::new(),
+            }),
+            nonblock: AtomicBool::new(flags & IN_NONBLOCK != 0),
+            queue: WaitQueue::new(),
+        })
+    }
+
+    /* 已经监听的文件修改原来的mask并返回原来的wd */
+    pub fn add_watch(&self, index: FileIndex, mask: u32) -> Result<i32, Error> {
+        if mask & IN_ALL_EVENTS == 0 {
+            return Err(Error::EINVAL);
+        }
+        let mut inner = self.inner.lock();
+        if let Some((wd, watch)) = inner.watches.iter_mut().find(|(_, watch)| watch.index == index) {
+            if mask & IN_MASK_CREATE != 0 {
+                return Err(Error::EEXIST);
+            }
+            watch.mask = match mask & IN_MASK_ADD {
+                0 => mask,
+                _ => watch.mask | mask,
+            };
+            return Ok(*wd);
+        }
+        let wd = inner.next_wd;
+        inner.next_wd += 1;
+        inner.watches.insert(wd, Watch { index, mask });
+        drop(inner);
+        WATCHES.lock().entry(index).or_insert_with(Vec::new).push((self.me.clone(), wd));
+        Ok(wd)
+    }
+
+    pub fn rm_watch(&self, wd: i32) -> Result<(), Error> {
+        let watch = self.inner.lock().watches.remove(&wd).ok_or(Error::EINVAL)?;
+        unregister(watch.index, &self.me, wd);
+        self.push(InotifyEvent { wd, mask: IN_IGNORED, cookie: 0, name: None });
+        Ok(())
+    }
+
+    /* 与上一个未读事件相同的事件被合并 */
+    fn push(&self, event: InotifyEvent) {
+        let mut inner = self.inner.lock();
+        if inner.events.back() == Some(&event) {
+            return;
+        }
+        if inner.events.len() >= INOTIFY_MAX_EVENTS {
+            let overflow = InotifyEvent { wd: -1, mask: IN_Q_OVERFLOW, cookie: 0, name: None };
+            if inner.events.back() != Some(&overflow) {
+                inner.events.push_back(overflow);
+            }
+        } else {
+            inner.events.push_back(event);
+        }
+        drop(inner);
+        self.queue.wake_all();
+    }
+
+    /* 向wd投递事件, 监听项被删除(IN_ONESHOT或者文件被删除)时随后投递IN_IGNORED */
+    fn deliver(&self, wd: i32, mask: u32, cookie: u32, name: Option<&str>) {
+        let mut inner = self.inner.lock();
+        let watch = match inner.watches.get(&wd) {
+            Some(watch) => watch,
+            None => return,
+        };
+        let index = watch.index;
+        let matched = watch.mask & mask & IN_ALL_EVENTS != 0;
+        let remove = (matched && watch.mask & IN_ONESHOT != 0) || mask & IN_DELETE_SELF != 0;
+        if remove {
+            inner.watches.remove(&wd);
+        }
+        drop(inner);
+        if matched {
+            self.push(InotifyEvent { wd, mask, cookie, name: name.map(String::from) });
+        }
+        if remove {
+            unregister(index, &self.me, wd);
+            self.push(InotifyEvent { wd, mask: IN_IGNORED, cookie: 0, name: None });
+        }
+    }
+}
+
+fn unregister(index: FileIndex, inotify: &Weak<Inotify>, wd: i32) {
+    let mut watches = WATCHES.lock();
+    if let Some(list) = watches.get_mut(&index) {
+        list.retain(|(weak, watch_wd)| weak.strong_count() > 0 && !(weak.ptr_eq(inotify) && *watch_wd == wd));
+        if list.is_empty() {
+            watches.remove(&index);
+        }
+    }
+}
+
+/// 向监听index的inotify实例投递事件, 目录中的事件带有子项的名字
+pub fn notify(index: FileIndex, mask: u32, cookie: u32, name: Option<&str>) {
+    let targets: Vec<(Arc<Inotify>, i32)> = {
+        let watches = WATCHES.lock();
+        match watches.get(&index) {
+            Some(list) => list.iter().filter_map(|(weak, wd)| weak.upgrade().map(|inotify| (inotify, *wd))).collect(),
+            None => return,
+        }
+    };
+    for (inotify, wd) in targets {
+        inotify.deliver(wd, mask, cookie, name);
+    }
+}
+
+/// 文件自身的事件, 没有FileIndex的文件(管道, socket等)不能被监听
+pub fn notify_file(file: &Arc<dyn File>, mask: u32) {
+    if WATCHES.lock().is_empty() {
+        return;
+    }
+    if let Ok(index) = file.get_index() {
+        notify(index, mask, 0, None);
+    }
+}
+
+/// 目录dir中名为name的子项发生的事件
+pub fn notify_dir(dir: &Arc<dyn File>, mask: u32, name: &str, is_dir: bool) {
+    if WATCHES.lock().is_empty() {
+        return;
+    }
+    let mask = if is_dir { mask | IN_ISDIR } else { mask };
+    if let Ok(index) = dir.get_index() {
+        notify(index, mask, 0, Some(name));
+    }
+}
+
+/* 关闭文件的最后一个引用时, 按打开方式报告IN_CLOSE_WRITE或IN_CLOSE_NOWRITE */
+pub fn notify_close(file: &Arc<dyn File>) {
+    if Arc::strong_count(file) == 1 {
+        let mask = if file.writable() { IN_CLOSE_WRITE } else { IN_CLOSE_NOWRITE };
+        notify_file(file, mask);
+    }
+}
+
+pub fn new_cookie() -> u32 {
+    NEXT_COOKIE.fetch_add(1, Ordering::Relaxed)
+}
+
+impl Drop for Inotify {
+    fn drop(&mut self) {
+        let watches: Vec<(i32, FileIndex)> = self.inner.get_mut().watches.iter().map(|(wd, watch)| (*wd, watch.index)).collect();
+        for (wd, index) in watches {
+            unregister(index, &self.me, wd);
+        }
+    }
+}
+
+impl File for Inotify {
+    fn close(&self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn get_index(&self) -> Result<FileIndex, Error> {
+        Err(Error::EINDEX)
+    }
+
+    /* 只读出完整的事件, 缓冲区放不下第一个事件时返回EINVAL */
+    fn read(&self, len: usize) -> Result<Vec<u8>, Error> {
+        let take = || {
+            let mut inner = self.inner.lock();
+            let first = inner.events.front()?;
+            if first.size() > len {
+                return Some(Err(Error::EINVAL));
+            }
+            let mut data = Vec::new();
+            while let Some(event) = inner.events.front() {
+                if data.len() + event.size() > len {
+                    break;
+                }
+                inner.events.pop_front().unwrap().to_bytes(&mut data);
+            }
+            Some(Ok(data))
+        };
+        match self.nonblock.load(Ordering::Relaxed) {
+            true => take().unwrap_or(Err(Error::EAGAIN)),
+            false => wait_on(&self.queue, take),
+        }
+    }
+
+    fn write(&self, _data: Vec<u8>) -> Result<usize, Error> {
+        Err(Error::EINVAL)
+    }
+
+    fn readable(&self) -> bool {
+        true
+    }
+
+    fn writable(&self) -> bool {
+        false
+    }
+
+    fn seek(&self, _pos: usize, _mode: SeekMode) -> Result<isize, Error> {
+        Err(Error::ESPIPE)
+    }
+
+    fn get_size(&self) -> Result<usize, Error> {
+        Ok(0)
+    }
+
+    fn get_type(&self) -> Result<FileType, Error> {
+        Ok(FileType::UNKNOWN)
+    }
+
+    fn read_stat(&self) -> Result<FileStat, Error> {
+        let mut fstat: FileStat = Default::default();
+        fstat.st_nlink = 1;
+        fstat.st_mode = 0o600;
+        Ok(fstat)
+    }
+
+    fn poll(&self, ptype: PollType) -> Result<bool, Error> {
+        let ret = match ptype {
+            PollType::READ => !self.inner.lock().events.is_empty(),
+            PollType::WRITE | PollType::ERR | PollType::HUP => false,
+        };
+        Ok(ret)
+    }
+
+    fn register_waker(&self, waker: &Arc<dyn Waker>) -> bool {
+        self.queue.register(waker);
+        true
+    }
+
+    fn unregister_waker(&self, waker: &Arc<dyn Waker>) {
+        self.queue.unregister(waker);
+    }
+
+    fn as_inotify(self: Arc<Self>) -> Result<Arc<Inotify>, Error> {
+        Ok(self)
+    }
+
+    fn as_file<'a>(self: Arc<Self>) -> Arc<dyn File + 'a> where Self: 'a {
+        self
+    }
+
+    fn as_any<'a>(self: Arc<Self>) -> Arc<dyn core::any::Any + Send + Sync + 'a> where Self: 'a {
+        self
+    }
+}
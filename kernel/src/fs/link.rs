@@ -0,0 +1,14 @@
+//! 软连接目标的解析, 不依赖文件接口, 主机上的ext2测试也直接使用这份源码
+
+use crate::utils::{Error, Path};
+
+/// 解析软连接的目标, 相对路径以软连接所在的目录(link的父目录)为起点
+pub fn resolve_link(link: &Path, target: &str) -> Result<Path, Error> {
+    if target.starts_with('/') {
+        return Path::from_str(target);
+    }
+    let mut path = link.remove_tail().to_string();
+    path.push('/');
+    path.push_str(target);
+    Path::from_string(path)
+}
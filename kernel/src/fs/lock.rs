@@ -0,0 +1,311 @@
+use alloc::{
+    collections::{BTreeMap, BTreeSet},
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use core::sync::atomic::{AtomicU64, Ordering};
+use spin::{Mutex, lazy::Lazy};
+use log::*;
+
+use crate::config::POLL_RECHECK_MS;
+use crate::proc::{get_current_task, TaskWaker, WaitQueue};
+use crate::syscall::time::Timespec;
+use crate::utils::Error;
+use super::{File, FileIndex};
+
+/// 建议性文件锁: 以FileIndex为键记录每个文件上的锁
+/// 1, flock锁属于打开的文件(open file description), 文件的最后一个引用关闭或释放时释放
+/// 2, fcntl记录锁属于进程, 进程关闭该文件的任意一个fd或退出时释放
+/// 3, 两种锁互不冲突; 等待锁时在文件的等待队列上睡眠, 解锁时唤醒, 记录锁在等待前检测死锁
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LockType {
+    Read,
+    Write,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LockOwner {
+    /* flock: 打开的文件对象的编号, 见FLOCK_OWNERS */
+    File(u64),
+    /* fcntl: 进程的pid */
+    Process(i32),
+}
+
+impl LockOwner {
+    pub fn of_file(file: &Arc<dyn File>) -> Self {
+        let mut owners = FLOCK_OWNERS.lock();
+        let (_, id) = owners
+            .entry(file_addr(file))
+            .or_insert_with(|| (Arc::downgrade(file), NEXT_OWNER_ID.fetch_add(1, Ordering::Relaxed)));
+        LockOwner::File(*id)
+    }
+
+    fn same_kind(&self, other: &LockOwner) -> bool {
+        matches!((self, other), (LockOwner::File(_), LockOwner::File(_)) | (LockOwner::Process(_), LockOwner::Process(_)))
+    }
+}
+
+/* 锁住[start, end)字节, flock锁住整个文件, end为u64::MAX表示到文件末尾 */
+#[derive(Debug, Clone, Copy)]
+pub struct FileLock {
+    pub owner: LockOwner,
+    pub ltype: LockType,
+    pub start: u64,
+    pub end: u64,
+}
+
+impl FileLock {
+    fn conflicts(&self, other: &FileLock) -> bool {
+        self.owner != other.owner
+            && self.owner.same_kind(&other.owner)
+            && self.start < other.end && other.start < self.end
+            && (self.ltype == LockType::Write || other.ltype == LockType::Write)
+    }
+}
+
+static FILE_LOCKS: Lazy<Mutex<BTreeMap<FileIndex, Vec<FileLock>>>> = Lazy::new(|| Mutex::new(BTreeMap::new()));
+/* 加过flock锁的文件对象: 地址 -> (弱引用, 编号)
+ * 弱引用保证文件对象的内存在记录删除前不会被释放, 地址不会被新打开的文件复用
+ * 文件对象释放后(弱引用失效)它持有的flock锁在下一次加锁或释放时被清除 */
+static FLOCK_OWNERS: Lazy<Mutex<BTreeMap<usize, (Weak<dyn File>, u64)>>> = Lazy::new(|| Mutex::new(BTreeMap::new()));
+static NEXT_OWNER_ID: AtomicU64 = AtomicU64::new(1);
+/* 等待加锁的任务, 每个FileIndex一个队列, 没有任务等待时删除 */
+static LOCK_QUEUES: Lazy<Mutex<BTreeMap<FileIndex, Arc<WaitQueue>>>> = Lazy::new(|| Mutex::new(BTreeMap::new()));
+/* 正在等待记录锁的进程和它请求的锁, 用于死锁检测 */
+static LOCK_WAITERS: Lazy<Mutex<BTreeMap<i32, (FileIndex, FileLock)>>> = Lazy::new(|| Mutex::new(BTreeMap::new()));
+
+fn file_addr(file: &Arc<dyn File>) -> usize {
+    Arc::as_ptr(file) as *const () as usize
+}
+
+/* 清除已经释放的文件对象持有的flock锁 */
+fn purge_dead_files(table: &mut BTreeMap<FileIndex, Vec<FileLock>>) {
+    let mut owners = FLOCK_OWNERS.lock();
+    let dead: Vec<LockOwner> = owners
+        .values()
+        .filter(|(file, _)| file.strong_count() == 0)
+        .map(|(_, id)| LockOwner::File(*id))
+        .collect();
+    if dead.is_empty() {
+        return;
+    }
+    owners.retain(|_, (file, _)| file.strong_count() > 0);
+    table.retain(|_, locks| {
+        locks.retain(|lock| !dead.contains(&lock.owner));
+        !locks.is_empty()
+    });
+}
+
+fn lock_queue(index: FileIndex) -> Arc<WaitQueue> {
+    LOCK_QUEUES.lock().entry(index).or_insert_with(|| Arc::new(WaitQueue::new())).clone()
+}
+
+/* 文件上的锁被释放或降级后唤醒等待者; 等待者持有队列的引用, 只剩表中的引用时没有等待者 */
+fn wake_lock_waiters(index: FileIndex) {
+    let queue = {
+        let mut queues = LOCK_QUEUES.lock();
+        match queues.get(&index) {
+            Some(queue) if Arc::strong_count(queue) > 1 => queue.clone(),
+            Some(_) => {
+                queues.remove(&index);
+                return;
+            }
+            None => return,
+        }
+    };
+    queue.wake_all();
+}
+
+/* 管道没有FileIndex, 不支持加锁 */
+pub fn lock_index(file: &Arc<dyn File>) -> Result<FileIndex, Error> {
+    if file.clone().as_fifo().is_ok() {
+        return Err(Error::EINVAL);
+    }
+    file.get_index()
+}
+
+/* 返回与lock冲突的第一个锁, 用于F_GETLK */
+pub fn test_lock(index: FileIndex, lock: &FileLock) -> Option<FileLock> {
+    let mut table = FILE_LOCKS.lock();
+    purge_dead_files(&mut table);
+    table.get(&index)?.iter().find(|held| held.conflicts(lock)).copied()
+}
+
+/* 加锁, ltype为None时解锁; wait为false时遇到冲突返回EAGAIN */
+pub fn set_lock(index: FileIndex, lock: FileLock, ltype: Option<LockType>, wait: bool) -> Result<(), Error> {
+    let lock = match ltype {
+        Some(ltype) => FileLock { ltype, ..lock },
+        None => {
+            let mut table = FILE_LOCKS.lock();
+            if let Some(locks) = table.get_mut(&index) {
+                remove_range(locks, lock.owner, lock.start, lock.end);
+                if locks.is_empty() {
+                    table.remove(&index);
+                }
+            }
+            drop(table);
+            wake_lock_waiters(index);
+            return Ok(());
+        }
+    };
+
+    /* 先在队列上注册再检查, 检查和睡眠之间的解锁不会丢失 */
+    let queue = lock_queue(index);
+    let waker = match wait {
+        true => Some(TaskWaker::new(None)),
+        false => None,
+    };
+    if let Some(waker) = waker.as_ref() {
+        queue.register(&waker.as_waker());
+    }
+    let ret = wait_lock(index, lock, waker.as_ref());
+    if let Some(waker) = waker.as_ref() {
+        queue.unregister(&waker.as_waker());
+    }
+    drop(queue);
+    if let LockOwner::Process(pid) = lock.owner {
+        LOCK_WAITERS.lock().remove(&pid);
+    }
+    /* 新锁可能替换了本owner的写锁(降级), 或者加锁失败后队列需要清理 */
+    wake_lock_waiters(index);
+    ret
+}
+
+/* waker为None时不等待, 遇到冲突返回EAGAIN */
+fn wait_lock(index: FileIndex, lock: FileLock, waker: Option<&Arc<TaskWaker>>) -> Result<(), Error> {
+    loop {
+        let mut table = FILE_LOCKS.lock();
+        purge_dead_files(&mut table);
+        let locks = table.entry(index).or_insert_with(Vec::new);
+        let blockers: Vec<LockOwner> = locks.iter()
+            .filter(|held| held.conflicts(&lock))
+            .map(|held| held.owner)
+            .collect();
+        if blockers.is_empty() {
+            /* 新锁替换本owner在该范围内的旧锁 */
+            remove_range(locks, lock.owner, lock.start, lock.end);
+            locks.push(lock);
+            return Ok(());
+        }
+        let waker = waker.ok_or(Error::EAGAIN)?;
+        /* 持有flock锁的文件对象可能不经过close直接释放, 不会唤醒等待者, 需要定时重新检查 */
+        let recheck = blockers.iter().any(|owner| matches!(owner, LockOwner::File(_)));
+        if let LockOwner::Process(pid) = lock.owner {
+            let mut waiters = LOCK_WAITERS.lock();
+            if would_deadlock(&table, &waiters, pid, blockers) {
+                warn!("set_lock: deadlock detected, pid = {}", pid);
+                return Err(Error::EDEADLK);
+            }
+            waiters.insert(pid, (index, lock));
+        }
+        drop(table);
+
+        if get_current_task().unwrap().has_signal() {
+            return Err(Error::EINTR);
+        }
+        let tick = match recheck {
+            true => Some(Timespec::now() + Timespec::from_ms(POLL_RECHECK_MS)),
+            false => None,
+        };
+        waker.sleep(tick);
+    }
+}
+
+/* 沿着"等待-持有"关系查找, 如果最终等待的是pid自己持有的锁则会死锁 */
+fn would_deadlock(
+    table: &BTreeMap<FileIndex, Vec<FileLock>>,
+    waiters: &BTreeMap<i32, (FileIndex, FileLock)>,
+    pid: i32,
+    blockers: Vec<LockOwner>,
+) -> bool {
+    let mut visited = BTreeSet::new();
+    let mut stack: Vec<i32> = blockers.into_iter()
+        .filter_map(|owner| match owner {
+            LockOwner::Process(pid) => Some(pid),
+            LockOwner::File(_) => None,
+        })
+        .collect();
+    while let Some(owner) = stack.pop() {
+        if owner == pid {
+            return true;
+        }
+        if !visited.insert(owner) {
+            continue;
+        }
+        if let Some((index, wanted)) = waiters.get(&owner) {
+            if let Some(locks) = table.get(index) {
+                for held in locks.iter().filter(|held| held.conflicts(wanted)) {
+                    if let LockOwner::Process(next) = held.owner {
+                        stack.push(next);
+                    }
+                }
+            }
+        }
+    }
+    false
+}
+
+/* 删除owner在[start, end)内的锁, 跨越边界的锁被切分 */
+fn remove_range(locks: &mut Vec<FileLock>, owner: LockOwner, start: u64, end: u64) {
+    let mut remain = Vec::new();
+    for lock in locks.drain(..) {
+        if lock.owner != owner || lock.end <= start || end <= lock.start {
+            remain.push(lock);
+            continue;
+        }
+        if lock.start < start {
+            remain.push(FileLock { end: start, ..lock });
+        }
+        if end < lock.end {
+            remain.push(FileLock { start: end, ..lock });
+        }
+    }
+    *locks = remain;
+}
+
+fn remove_owner(owner: LockOwner, index: Option<FileIndex>) {
+    let mut table = FILE_LOCKS.lock();
+    purge_dead_files(&mut table);
+    let mut released = Vec::new();
+    table.retain(|key, locks| {
+        if index.map_or(true, |index| index == *key) {
+            let len = locks.len();
+            locks.retain(|lock| lock.owner != owner);
+            if locks.len() != len {
+                released.push(*key);
+            }
+        }
+        !locks.is_empty()
+    });
+    drop(table);
+    for index in released {
+        wake_lock_waiters(index);
+    }
+}
+
+/* 进程关闭一个fd时释放锁: 记录锁总是释放, flock锁只在这是文件的最后一个引用时释放 */
+pub fn release_locks(pid: i32, file: &Arc<dyn File>) {
+    /* 还有其它引用时, flock锁在文件对象释放后清除 */
+    let file_owner = match Arc::strong_count(file) {
+        1 => FLOCK_OWNERS.lock().remove(&file_addr(file)).map(|(_, id)| LockOwner::File(id)),
+        _ => None,
+    };
+    if FILE_LOCKS.lock().is_empty() {
+        return;
+    }
+    let index = match lock_index(file) {
+        Ok(index) => index,
+        Err(_) => return,
+    };
+    remove_owner(LockOwner::Process(pid), Some(index));
+    if let Some(owner) = file_owner {
+        remove_owner(owner, Some(index));
+    }
+}
+
+/* 进程退出时释放它的所有记录锁 */
+pub fn release_process_locks(pid: i32) {
+    LOCK_WAITERS.lock().remove(&pid);
+    remove_owner(LockOwner::Process(pid), None);
+}
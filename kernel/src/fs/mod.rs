@@ -1,12 +1,22 @@
 pub mod vfs;
 pub mod file;
+pub mod link;
 pub mod block_cache;
+pub mod page_cache;
+pub mod lock;
 pub mod fat32;
+pub mod ext2;
 pub mod fifo;
+pub mod epoll;
+pub mod eventfd;
+pub mod timerfd;
+pub mod signalfd;
+pub mod inotify;
 pub mod devfs;
 pub mod syslog;
 pub mod virt_file;
 pub mod procfs;
+pub mod tmpfs;
 mod mount_manager;
 
 
@@ -14,28 +24,62 @@ use syslog::*;
 use vfs::*;
 use block_cache::{get_block_cache};
 use fat32::FAT32FileSystem;
+use ext2::Ext2FileSystem;
 pub use file::*;
 pub use mount_manager::*;
 pub use devfs::*;
 pub use procfs::*;
+pub use tmpfs::TmpFS;
 
 use log::*;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use crate::config::{DIRTY_EXPIRE_MS, FLUSH_INTERVAL_MS};
+use crate::timer::get_time_ms;
 
 #[allow(unused)]
 pub fn fs_init() {
     println!("[kernel] fs: initing vfs");
     mount_manager::init().expect("monut_manager init fail");
     println!("[kernel] fs: make buffer file at /buf");
-    mknod("/buf".into(), FileType::RegularFile, FilePerm::NONE);
+    mknod("/buf".into(), FileType::RegularFile, FilePerm::from_bits_truncate(0o666));
 
     println!("[kernel] fs: make devfs, mount devfs to /dev");
-    mknod("/dev".into(), FileType::Directory, FilePerm::NONE);
-    mount("/dev".into(), "/".into(), "devfs");  //dev路径为“/”表示不需要块设备
+    mknod("/dev".into(), FileType::Directory, FilePerm::from_bits_truncate(0o755));
+    mount("/dev".into(), "/".into(), "devfs", "");  //dev路径为“/”表示不需要块设备
 
     println!("[kernel] fs: make procfs, mount devfs to /proc");
-    mknod("/proc".into(), FileType::Directory, FilePerm::NONE);
-    mount("/proc".into(), "/".into(), "procfs");  //dev路径为“/”表示不需要块设备
+    mknod("/proc".into(), FileType::Directory, FilePerm::from_bits_truncate(0o555));
+    mount("/proc".into(), "/".into(), "procfs", "");  //dev路径为“/”表示不需要块设备
+
+    println!("[kernel] fs: make tmpfs, mount tmpfs to /tmp and /dev/shm");
+    mknod("/tmp".into(), FileType::Directory, FilePerm::from_bits_truncate(0o1777));
+    mount("/tmp".into(), "/".into(), "tmpfs", "");
+    mount("/dev/shm".into(), "/".into(), "tmpfs", "");
 
     println!("[kernel] fs: make syslog at /");
-    mknod("/syslog".into(), FileType::RegularFile, FilePerm::NONE);
+    mknod("/syslog".into(), FileType::RegularFile, FilePerm::from_bits_truncate(0o644));
+}
+
+/// 写回所有文件系统的页缓存和块缓存, 用于sync和关机前
+pub fn sync_all() {
+    if let Err(err) = page_cache::sync_page_caches(None) {
+        warn!("sync_all: fail to write back page cache: {:?}", err);
+    }
+    block_cache::block_cache_sync_all();
+}
+
+static LAST_FLUSH: AtomicUsize = AtomicUsize::new(0);
+
+/// 后台写回, 在调度器的循环中调用, 不占用用户任务的时间, 所有任务都在睡眠时也会执行
+/// 每隔FLUSH_INTERVAL_MS检查一次, 写回脏了超过DIRTY_EXPIRE_MS的页和块
+pub fn flush_tick() {
+    let now = get_time_ms();
+    let last = LAST_FLUSH.load(Ordering::Relaxed);
+    if now < last + FLUSH_INTERVAL_MS
+    || LAST_FLUSH.compare_exchange(last, now, Ordering::AcqRel, Ordering::Relaxed).is_err() {
+        return;
+    }
+    let before = now.saturating_sub(DIRTY_EXPIRE_MS);
+    page_cache::flush_expired_pages(before);
+    block_cache::block_cache_flush_expired(before);
 }
@@ -1,18 +1,23 @@
 use alloc::{
     sync::Arc, 
+    vec::Vec,
     collections::{BTreeMap}, 
 };
 use spin::RwLock;
 use super::*;
 use crate::config::MAX_LINK_RECURSE;
 use crate::utils::{Error, Path};
+use crate::proc::{Access, Credentials, current_cred};
 use spin::lazy::Lazy;
 use log::*;
 
+/// options为mount的data参数, 以逗号分隔
+/// fat32支持: fsck(挂载前检查), fsck=repair(挂载前检查并修复)
 pub fn build_fs(
     fstype: &str, 
     blockfile: Option<Arc<dyn BlockFile>>,
-    path: Path
+    path: Path,
+    options: &str
 ) -> Result<Arc<dyn VFS>, Error> {
     match fstype {
         "fat32"
@@ -21,7 +26,21 @@ pub fn build_fs(
                 return Err(Error::ENOTBLK)
             }
             let blockfile = blockfile.unwrap();
-            return Ok(FAT32FileSystem::init(blockfile, FSid::new(), path));
+            for option in options.split(',') {
+                match option {
+                    "fsck" | "fsck=check" => { FAT32FileSystem::fsck(blockfile.clone(), false)?; }
+                    "fsck=repair" => { FAT32FileSystem::fsck(blockfile.clone(), true)?; }
+                    _ => {}
+                }
+            }
+            return Ok(FAT32FileSystem::init(blockfile, FSid::new(), path)?);
+        }
+        "ext2" => {
+            if blockfile.is_none() {
+                return Err(Error::ENOTBLK)
+            }
+            let blockfile = blockfile.unwrap();
+            return Ok(Ext2FileSystem::init(blockfile, FSid::new(), path)?);
         }
         "devfs" => {
             Ok(DevFS::init(FSid::new(), path))
@@ -29,6 +48,9 @@ pub fn build_fs(
         "procfs" => {
             Ok(ProcFS::init(FSid::new(), path))
         }
+        "tmpfs" => {
+            Ok(TmpFS::init(FSid::new(), path))
+        }
         _ => return Err(Error::ENODEV)
     }
 }
@@ -40,7 +62,7 @@ pub static MOUNT_MANAGER: Lazy<MountManager> = Lazy::new(||{
             SDA2::new(FileOpenMode::SYS), 
             FSid::new(), 
             "/".into()
-        ),
+        ).expect("root_fs is not a valid FAT32 image"),
         map: RwLock::new(BTreeMap::new())
     }
 });
@@ -52,8 +74,8 @@ pub struct MountManager {
 }
 
 impl MountManager {
-    fn mount(&self, path: Path, dev: Path, fstype: &str) -> Result<(), Error> {
-        trace!("mount manager mount: path = {:?}, dev = {:?}, fstype = {}", path, dev, fstype);
+    fn mount(&self, path: Path, dev: Path, fstype: &str, options: &str) -> Result<(), Error> {
+        trace!("mount manager mount: path = {:?}, dev = {:?}, fstype = {}, options = {}", path, dev, fstype, options);
         
         let dir = open(path.clone(), FileOpenMode::SYS)?.as_dir()?;
         let index = dir.get_index()?;
@@ -67,7 +89,7 @@ impl MountManager {
         }
         trace!("mount manager mount: get blockfile");
 
-        let fs = build_fs(fstype, blockfile, path)?;
+        let fs = build_fs(fstype, blockfile, path, options)?;
         trace!("mount manager mount: get fs");
 
         let mut map = self.map.write();
@@ -84,27 +106,59 @@ impl MountManager {
         let mountpoint = open(path,FileOpenMode::READ)?;
         let index = mountpoint.get_index().unwrap();
         let mut map = self.map.write(); 
-        if map.remove(&index).is_some(){
-            Ok(())
+        if let Some(vfs) = map.remove(&index) {
+            drop(map);
+            /* 卸载前写回文件系统的修改 */
+            vfs.sync()
         } else{
             Err(Error::ENOENT)
         }
     }
 
+    /// 写回file所在的文件系统, 通过FileIndex中的FSid找到挂载的文件系统
+    /// 管道等不属于任何文件系统的文件什么都不做
+    fn syncfs(&self, file: Arc<dyn File>) -> Result<(), Error> {
+        if file.clone().as_fifo().is_ok() {
+            return Ok(());
+        }
+        match self.vfs_of(&file) {
+            Ok(vfs) => vfs.sync(),
+            Err(_) => Ok(()),
+        }
+    }
+
     //从根目录开始解析路径
     pub fn open(&self, path: Path, mode: FileOpenMode) -> Result<Arc<dyn File>, Error> {
-        self.open_path(self.root_fs.root_dir(mode)?.as_file(), path, mode, 0)
+        self.open_at(self.root_fs.root_dir(mode)?.as_file(), path, mode)
     }
     //从指定的目录文件src开始解析路径
     pub fn open_at(&self, src: Arc<dyn File>, path: Path, mode: FileOpenMode) -> Result<Arc<dyn File>, Error> {
-        self.open_path(src, path, mode, 0)
+        /* SYS是内核自己打开文件, 不检查权限 */
+        let cred = match mode.contains(FileOpenMode::SYS) {
+            true => None,
+            false => current_cred(),
+        };
+        let file = self.open_path(src, path, mode, cred.as_ref(), 0)?;
+        if let Some(cred) = cred.as_ref() {
+            let access = if mode.contains(FileOpenMode::RDWR) {
+                Access::R | Access::W
+            } else if mode.contains(FileOpenMode::WRITE) {
+                Access::W
+            } else {
+                Access::R
+            };
+            check_access_with(cred, &file, access)?;
+        }
+        Ok(file)
     }
 
+    /* cred不为None时检查路径上每个目录的搜索(x)权限 */
     fn open_path(
         &self, 
         mut current: Arc<dyn File>, 
         mut path: Path, 
         mode: FileOpenMode, 
+        cred: Option<&Credentials>,
         recurse_count: usize
     ) -> Result<Arc<dyn File>, Error> {
         if recurse_count >= MAX_LINK_RECURSE {
@@ -123,23 +177,37 @@ impl MountManager {
             
             if let Ok(dir) = current.clone().as_dir() {
                 /* 如果current是目录，那么在目录下打开或创建指定文件 */
+                if let Some(cred) = cred.filter(|cred| !cred.is_root()) {
+                    check_access_with(cred, &current, Access::X)?;
+                }
                 let name = path.pop_front().unwrap();
                 current = dir.openat(name, mode)?;
             } else if let Ok(link) = current.clone().as_link() {
                 /* 如果current是软连接，那么跳转到连接指定的文件 */
-                if mode.contains(FileOpenMode::NOFOLLOW) {
-                    return Err(Error::ENOENT)
-                }
+                /* NOFOLLOW只对路径的最后一项生效，中间的软连接总是要跟随的 */
                 current = self.open_path(
                     self.root_fs.root_dir(mode)?.as_file(), 
                     link.read_link()?, 
-                    mode, 
+                    mode - FileOpenMode::NOFOLLOW, 
+                    cred,
                     recurse_count + 1)?;
             } else {
                 return Err(Error::ENOTDIR)
             }
         }
 
+        /* 路径的最后一项是软连接，除非指定了NOFOLLOW，否则跳转到连接指定的文件 */
+        if !mode.contains(FileOpenMode::NOFOLLOW) {
+            if let Ok(link) = current.clone().as_link() {
+                return self.open_path(
+                    self.root_fs.root_dir(mode)?.as_file(), 
+                    link.read_link()?, 
+                    mode, 
+                    cred,
+                    recurse_count + 1);
+            }
+        }
+
         if let Ok(index) = current.get_index() {
             let map = self.map.read();
             if map.contains_key(&index){
@@ -165,6 +233,7 @@ impl MountManager {
         let mut current = src;
         let mut path = path.clone();
         let mut level = 0;
+        let cred = current_cred();
 
         trace!("mount_manager_mknod: start, path = {:?}, len = {}", path, path.len());
         loop {
@@ -174,7 +243,20 @@ impl MountManager {
                 // 创建文件并返回
                 let file_name = path.pop_front().unwrap();
                 trace!("mount_manager_mknod: to create file: {}", file_name);
-                current = current.as_dir()?.mknod(file_name, perm, filetype)?;
+                let dir = current.clone().as_dir()?;
+                if let Some(cred) = cred.as_ref() {
+                    /* 已经存在的文件返回EEXIST, 由调用者决定是否打开 */
+                    if let Err(err) = check_access_with(cred, &current, Access::W | Access::X) {
+                        return match dir.openat(file_name, FileOpenMode::SYS) {
+                            Ok(_) => Err(Error::EEXIST),
+                            Err(_) => Err(err),
+                        };
+                    }
+                }
+                let parent = current.clone();
+                current = dir.mknod(file_name.clone(), perm, filetype)?;
+                set_owner(&current, cred.as_ref());
+                inotify::notify_dir(&parent, inotify::IN_CREATE, &file_name, filetype == FileType::Directory);
                 break;
             } else {
                 // 递归创建中间路径的目录
@@ -193,7 +275,13 @@ impl MountManager {
                     // 如果中间目录不存在，就创建
                     Err(Error::ENOENT) => {
                         trace!("mount_manager_mknod: to creat dir: {}, depth = {}", dir_name, level);
-                        current.as_dir()?.mknod(dir_name, FilePerm::NONE, FileType::Directory)?
+                        if let Some(cred) = cred.as_ref() {
+                            check_access_with(cred, &current, Access::W | Access::X)?;
+                        }
+                        let dir = current.clone().as_dir()?.mknod(dir_name.clone(), FilePerm::from_bits_truncate(0o755), FileType::Directory)?;
+                        set_owner(&dir, cred.as_ref());
+                        inotify::notify_dir(&current, inotify::IN_CREATE, &dir_name, true);
+                        dir
                     }
                     Err(err) => {
                         warn!("mount_manager_mknod: return err");
@@ -206,22 +294,122 @@ impl MountManager {
     }
 
     fn delete_at(&self, src: Arc<dyn File>, path: Path) -> Result<(), Error> {
-        let dir = self.open_at(src, path.remove_tail(), FileOpenMode::SYS)?.as_dir()?;
-        dir.delete(path.last().clone())?;
+        let parent = self.open_at(src, path.remove_tail(), FileOpenMode::SYS)?;
+        check_access(&parent, Access::W | Access::X)?;
+        let dir = parent.clone().as_dir()?;
+        let name = path.last().clone();
+        /* 删除前取得被删除文件的FileIndex, 用于通知监听它自身的watch */
+        let child = dir.openat(name.clone(), FileOpenMode::SYS).ok();
+        if let Some(child) = child.as_ref() {
+            check_sticky(&parent, child)?;
+        }
+        dir.delete(name.clone())?;
+        let is_dir = child.as_ref().map_or(false, |child| child.get_type().ok() == Some(FileType::Directory));
+        inotify::notify_dir(&parent, inotify::IN_DELETE, &name, is_dir);
+        if let Some(child) = child.as_ref() {
+            notify_unlinked(child);
+        }
         Ok(())
     }
 
-    #[allow(unused)]
-    fn link(&self, path: Path, dst_path: Path) -> Result<Arc<dyn File>, Error> {
-        let dest_file = self.open(dst_path, FileOpenMode::SYS)?;
-        let dest_vfs = dest_file.vfs();
-        let link_dir = self.open(path.remove_tail(), FileOpenMode::READ | FileOpenMode::WRITE)?.as_dir()?;
-        let link_vfs = link_dir.vfs();
-        if Arc::ptr_eq(&dest_vfs, &link_vfs) {
-            link_vfs.link(path.without_prefix(&link_vfs.mount_path()),dest_file )
-        } else {
-            Err(Error::EXDEV)
+    /// 在同一个目录中改名, 监听目录的watch收到cookie相同的IN_MOVED_FROM和IN_MOVED_TO
+    /// 目前不支持在不同目录之间移动, 返回EXDEV, 由调用者(如mv)改为复制后删除
+    fn rename_at(
+        &self,
+        old_src: Arc<dyn File>,
+        old_path: Path,
+        new_src: Arc<dyn File>,
+        new_path: Path
+    ) -> Result<(), Error> {
+        if old_path.is_root() || new_path.is_root() {
+            return Err(Error::EBUSY);
+        }
+        let parent = self.open_at(old_src, old_path.remove_tail(), FileOpenMode::SYS)?;
+        let new_parent = self.open_at(new_src, new_path.remove_tail(), FileOpenMode::SYS)?;
+        let same_dir = match (parent.get_index(), new_parent.get_index()) {
+            (Ok(index), Ok(new_index)) => index == new_index,
+            _ => parent.get_path()? == new_parent.get_path()?,
+        };
+        if !same_dir {
+            return Err(Error::EXDEV);
+        }
+        /* 源和目标在同一个目录中, 只需检查一次 */
+        check_access(&parent, Access::W | Access::X)?;
+        let dir = parent.clone().as_dir()?;
+        let (old_name, new_name) = (old_path.last().clone(), new_path.last().clone());
+        let child = dir.openat(old_name.clone(), FileOpenMode::SYS).ok();
+        /* 粘滞位目录中, 被移动的文件和被替换的文件都必须属于当前用户 */
+        if let Some(child) = child.as_ref() {
+            check_sticky(&parent, child)?;
+        }
+        let target = dir.openat(new_name.clone(), FileOpenMode::SYS).ok();
+        if let Some(target) = target.as_ref() {
+            check_sticky(&parent, target)?;
+        }
+        dir.rename(old_name.clone(), new_name.clone())?;
+        let is_dir = child.as_ref().map_or(false, |child| child.get_type().ok() == Some(FileType::Directory));
+        if let Ok(index) = parent.get_index() {
+            let cookie = inotify::new_cookie();
+            let isdir = if is_dir { inotify::IN_ISDIR } else { 0 };
+            inotify::notify(index, inotify::IN_MOVED_FROM | isdir, cookie, Some(&old_name));
+            inotify::notify(index, inotify::IN_MOVED_TO | isdir, cookie, Some(&new_name));
+        }
+        if let Some(child) = child.as_ref() {
+            inotify::notify_file(child, inotify::IN_MOVE_SELF);
         }
+        /* 被替换的文件少了一个链接 */
+        if let Some(target) = target.as_ref() {
+            notify_unlinked(target);
+        }
+        Ok(())
+    }
+
+    /// 硬连接: 在new_src下的new_path处创建一项, 指向old_src下的old_path
+    /// 两者必须在同一个文件系统中, 是否支持硬连接由文件系统决定
+    fn link_at(
+        &self,
+        old_src: Arc<dyn File>,
+        old_path: Path,
+        new_src: Arc<dyn File>,
+        new_path: Path,
+        mode: FileOpenMode
+    ) -> Result<Arc<dyn File>, Error> {
+        if new_path.is_root() {
+            return Err(Error::EEXIST);
+        }
+        let dest_file = self.open_at(old_src, old_path, mode)?;
+        let link_dir = self.open_at(new_src, new_path.remove_tail(), FileOpenMode::SYS)?.as_dir()?.as_file();
+        check_access(&link_dir, Access::W | Access::X)?;
+        let dest_vfs = self.vfs_of(&dest_file)?;
+        let link_vfs = self.vfs_of(&link_dir)?;
+        if !Arc::ptr_eq(&dest_vfs, &link_vfs) {
+            return Err(Error::EXDEV);
+        }
+
+        /* VFS::link使用相对于文件系统挂载点的路径 */
+        let mut path = link_dir.get_path()?;
+        let mount_path = link_vfs.mount_path();
+        if !path.starts_with(&mount_path) {
+            return Err(Error::EXDEV);
+        }
+        path.push_back(new_path.last().clone());
+        let file = link_vfs.link(path.without_prefix(&mount_path), dest_file.clone())?;
+        /* 链接数变化是目标文件的属性变化 */
+        inotify::notify_dir(&link_dir, inotify::IN_CREATE, new_path.last(), false);
+        inotify::notify_file(&dest_file, inotify::IN_ATTRIB);
+        Ok(file)
+    }
+
+    /* 通过FileIndex中的FSid找到文件所在的已挂载的文件系统 */
+    fn vfs_of(&self, file: &Arc<dyn File>) -> Result<Arc<dyn VFS>, Error> {
+        let fsid = file.get_index()?.0;
+        let fs_list: Vec<Arc<dyn VFS>> = self.map.read().values().cloned().collect();
+        for vfs in fs_list {
+            if vfs.root_dir(FileOpenMode::SYS)?.get_index()?.0 == fsid {
+                return Ok(vfs);
+            }
+        }
+        Err(Error::ENOENT)
     }
 
     fn get_vfs(&self, path: Path) -> Result<Arc<dyn VFS>, Error> {
@@ -239,18 +427,80 @@ impl MountManager {
 
 
 
-pub fn mount(path: Path, dev: Path, fstype: &str) -> Result<(), Error> {
-    MOUNT_MANAGER.mount(path, dev, fstype)
+pub fn mount(path: Path, dev: Path, fstype: &str, options: &str) -> Result<(), Error> {
+    MOUNT_MANAGER.mount(path, dev, fstype, options)
 }
 
 pub fn umount(path: Path) -> Result<(), Error> {
     MOUNT_MANAGER.umount(path)
 }
 
+/* 按照当前任务的身份检查对file的访问权限, 没有当前任务(内核初始化)时不检查 */
+pub fn check_access(file: &Arc<dyn File>, access: Access) -> Result<(), Error> {
+    match current_cred() {
+        Some(cred) => check_access_with(&cred, file, access),
+        None => Ok(()),
+    }
+}
+
+pub fn check_access_with(cred: &Credentials, file: &Arc<dyn File>, access: Access) -> Result<(), Error> {
+    /* root读写不需要检查mode */
+    if cred.is_root() && !access.contains(Access::X) {
+        return Ok(());
+    }
+    match cred.may_access(&file.read_stat()?, access) {
+        true => Ok(()),
+        false => Err(Error::EACCES),
+    }
+}
+
+/* 文件被删除了一个链接, 最后一个链接被删除时才报告IN_DELETE_SELF, 否则只是链接数变化 */
+fn notify_unlinked(file: &Arc<dyn File>) {
+    let removed = match file.read_stat() {
+        Ok(stat) => stat.st_nlink == 0 || file.get_type().ok() == Some(FileType::Directory),
+        Err(_) => true,
+    };
+    let mask = if removed { inotify::IN_DELETE_SELF } else { inotify::IN_ATTRIB };
+    inotify::notify_file(file, mask);
+}
+
+/* 带有粘滞位(如/tmp的0o1777)的目录中, 只有文件或目录的所有者和root能删除或移动其中的文件 */
+fn check_sticky(dir: &Arc<dyn File>, child: &Arc<dyn File>) -> Result<(), Error> {
+    let cred = match current_cred() {
+        Some(cred) if !cred.is_root() => cred,
+        _ => return Ok(()),
+    };
+    let dir_stat = dir.read_stat()?;
+    if dir_stat.st_mode & 0o1000 == 0 || dir_stat.st_uid == cred.euid {
+        return Ok(());
+    }
+    match child.read_stat()?.st_uid == cred.euid {
+        true => Ok(()),
+        false => Err(Error::EPERM),
+    }
+}
+
+/* 新建文件的所有者是创建者的有效id, 不支持修改所有者的文件系统(fat32)保持默认 */
+fn set_owner(file: &Arc<dyn File>, cred: Option<&Credentials>) {
+    let cred = match cred {
+        Some(cred) if cred.euid != 0 || cred.egid != 0 => cred,
+        _ => return,
+    };
+    if let Ok(mut stat) = file.read_stat() {
+        stat.st_uid = cred.euid;
+        stat.st_gid = cred.egid;
+        let _ = file.write_stat(&stat);
+    }
+}
+
 pub fn open(path: Path, mode: FileOpenMode) -> Result<Arc<dyn File>, Error> {
     MOUNT_MANAGER.open(path, mode)
 }
 
+pub fn syncfs(file: Arc<dyn File>) -> Result<(), Error> {
+    MOUNT_MANAGER.syncfs(file)
+}
+
 pub fn open_at(src: Arc<dyn File>, path: Path, mode: FileOpenMode) -> Result<Arc<dyn File>, Error> {
     MOUNT_MANAGER.open_at(src, path, mode)
 }
@@ -267,9 +517,23 @@ pub fn delete_at(src: Arc<dyn File>, path: Path) -> Result<(), Error> {
     MOUNT_MANAGER.delete_at(src, path)
 }
 
-#[allow(unused)]
-pub fn link(path: Path, dst_path: Path) -> Result<Arc<dyn File>, Error> {
-    MOUNT_MANAGER.link(path, dst_path)
+pub fn rename_at(
+    old_src: Arc<dyn File>,
+    old_path: Path,
+    new_src: Arc<dyn File>,
+    new_path: Path
+) -> Result<(), Error> {
+    MOUNT_MANAGER.rename_at(old_src, old_path, new_src, new_path)
+}
+
+pub fn link_at(
+    old_src: Arc<dyn File>,
+    old_path: Path,
+    new_src: Arc<dyn File>,
+    new_path: Path,
+    mode: FileOpenMode
+) -> Result<Arc<dyn File>, Error> {
+    MOUNT_MANAGER.link_at(old_src, old_path, new_src, new_path, mode)
 }
 
 pub fn get_vfs(path: Path) -> Result<Arc<dyn VFS>, Error> {
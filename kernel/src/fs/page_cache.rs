@@ -0,0 +1,399 @@
+use alloc::{
+    collections::BTreeMap,
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use core::ops::Range;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use spin::{Mutex, lazy::Lazy};
+use log::*;
+
+use crate::config::{PAGE_SIZE, PAGE_CACHE_SIZE, READAHEAD_MIN_PAGES, READAHEAD_MAX_PAGES, READAHEAD_BATCH_PAGES};
+use crate::memory::{FrameTracker, PhysPageNum, frame_safe_alloc};
+use crate::timer::get_time_ms;
+use crate::utils::Error;
+use super::{FileIndex, vfs::FSid};
+
+/// 页缓存: 以(FileIndex, 页号)为键缓存文件的数据
+/// 1, read/write和文件映射使用同一个物理页, 多个进程映射同一个文件时共享物理页
+/// 2, 页的读入和写回由文件系统实现的PageBackend完成
+/// 3, 写入的页被标记为脏页, 在文件最后一次被关闭, munmap, msync, fsync/sync或后台写回时写回
+///    被共享映射的页写回后仍然视为脏页, 因为之后通过映射的写入无法被发现
+/// 4, 缓存的页数超过PAGE_CACHE_SIZE或物理页不足时, 淘汰干净且没有被映射的页
+
+pub trait PageBackend: Send + Sync {
+    /// 读入第index页, buf已经被清零, 文件末尾之后的部分保持为0
+    fn read_page(&self, index: usize, buf: &mut [u8]) -> Result<(), Error>;
+    /// 写回第index页, 文件末尾之后的部分不写
+    fn write_page(&self, index: usize, buf: &[u8]) -> Result<(), Error>;
+    /// 预读提示: 随后会依次读入[offset, offset + len)中的页, backend可以一次向设备请求这些数据
+    fn readahead(&self, _offset: usize, _len: usize) {}
+}
+
+pub struct Page {
+    frame: FrameTracker,
+    dirty_time: AtomicUsize,    //成为脏页的时间(ms), 0表示干净的页
+    accessed: AtomicBool,   //淘汰时给最近访问过的页第二次机会
+}
+
+impl Page {
+    pub fn ppn(&self) -> PhysPageNum {
+        self.frame.ppn
+    }
+
+    /* 映射到地址空间时, 页表持有帧的一个引用 */
+    pub fn frame(&self) -> FrameTracker {
+        self.frame.clone()
+    }
+
+    pub fn data(&self) -> &'static mut [u8] {
+        self.frame.ppn.get_byte_array()
+    }
+
+    pub fn mark_dirty(&self) {
+        let now = get_time_ms().max(1);
+        let _ = self.dirty_time.compare_exchange(0, now, Ordering::AcqRel, Ordering::Acquire);
+    }
+
+    pub fn is_dirty(&self) -> bool {
+        self.dirty_time.load(Ordering::Acquire) != 0
+    }
+
+    fn is_mapped(&self) -> bool {
+        !self.frame.is_only_owner()
+    }
+}
+
+pub struct PageCache {
+    index: FileIndex,
+    pages: Mutex<BTreeMap<usize, Arc<Page>>>,
+    backend: Mutex<Weak<dyn PageBackend>>,
+}
+
+impl PageCache {
+    fn new(index: FileIndex, backend: Weak<dyn PageBackend>) -> Self {
+        Self {
+            index,
+            pages: Mutex::new(BTreeMap::new()),
+            backend: Mutex::new(backend),
+        }
+    }
+
+    fn backend(&self) -> Result<Arc<dyn PageBackend>, Error> {
+        self.backend.lock().upgrade().ok_or(Error::EIO)
+    }
+
+    /// 已经在缓存中的页
+    pub fn find_page(&self, index: usize) -> Option<Arc<Page>> {
+        self.pages.lock().get(&index).cloned()
+    }
+
+    /// 获取第index页, 不在缓存中时从backend读入
+    /// 读入时不持有锁, 两个线程同时读入同一页时, 后插入的一方使用已经存在的页
+    pub fn get_page(&self, index: usize) -> Result<Arc<Page>, Error> {
+        if let Some(page) = self.find_page(index) {
+            page.accessed.store(true, Ordering::Relaxed);
+            return Ok(page);
+        }
+
+        let frame = frame_safe_alloc()
+            .or_else(|| {
+                shrink_page_cache(usize::MAX);
+                frame_safe_alloc()
+            })
+            .ok_or(Error::ENOMEM)?;
+        self.backend()?.read_page(index, frame.ppn.get_byte_array())?;
+        let page = Arc::new(Page {
+            frame,
+            dirty_time: AtomicUsize::new(0),
+            accessed: AtomicBool::new(true),
+        });
+
+        let mut pages = self.pages.lock();
+        if let Some(exist) = pages.get(&index) {
+            return Ok(exist.clone());
+        }
+        pages.insert(index, page.clone());
+        drop(pages);
+
+        if CACHED_PAGES.fetch_add(1, Ordering::Relaxed) + 1 > PAGE_CACHE_SIZE {
+            shrink_page_cache(CACHED_PAGES.load(Ordering::Relaxed) - PAGE_CACHE_SIZE);
+        }
+        Ok(page)
+    }
+
+    /// 将[start, end)中不在缓存中的页读入, 连续缺失的页每READAHEAD_BATCH_PAGES页向backend发出一次预读提示
+    /// 预读失败不影响之后的读, 直接返回
+    pub fn readahead(&self, start: usize, end: usize) {
+        let missing: Vec<usize> = {
+            let pages = self.pages.lock();
+            (start..end).filter(|index| !pages.contains_key(index)).collect()
+        };
+        let backend = match self.backend() {
+            Ok(backend) => backend,
+            Err(_) => return,
+        };
+        let mut i = 0;
+        while i < missing.len() {
+            /* 一批连续的页 */
+            let first = missing[i];
+            let mut j = i + 1;
+            while j < missing.len() && j - i < READAHEAD_BATCH_PAGES && missing[j] == missing[j - 1] + 1 {
+                j += 1;
+            }
+            backend.readahead(first * PAGE_SIZE, (j - i) * PAGE_SIZE);
+            for index in missing[i..j].iter() {
+                if self.get_page(*index).is_err() {
+                    return;
+                }
+            }
+            i = j;
+        }
+    }
+
+    /// 按页访问[offset, offset + len), f依次得到每一页中的数据
+    pub fn read(&self, offset: usize, len: usize, mut f: impl FnMut(&[u8])) -> Result<(), Error> {
+        self.for_each_chunk(offset, len, |page, range| {
+            f(&page.data()[range]);
+        })
+    }
+
+    /// 与read相同, 被修改的页标记为脏页
+    pub fn write(&self, offset: usize, len: usize, mut f: impl FnMut(&mut [u8])) -> Result<(), Error> {
+        self.for_each_chunk(offset, len, |page, range| {
+            page.mark_dirty();
+            f(&mut page.data()[range]);
+        })
+    }
+
+    fn for_each_chunk(
+        &self,
+        offset: usize,
+        len: usize,
+        mut f: impl FnMut(&Page, core::ops::Range<usize>),
+    ) -> Result<(), Error> {
+        let end = offset + len;
+        let mut start = offset;
+        while start < end {
+            let page = self.get_page(start / PAGE_SIZE)?;
+            let page_start = start % PAGE_SIZE;
+            let page_end = (page_start + end - start).min(PAGE_SIZE);
+            f(&page, page_start..page_end);
+            start += page_end - page_start;
+        }
+        Ok(())
+    }
+
+    /// 将已经缓存的[offset, offset + len)清零, 用于撤销写入到文件末尾之后的数据
+    pub fn zero(&self, offset: usize, len: usize) {
+        let end = offset + len;
+        let pages = self.pages.lock();
+        for (index, page) in pages.range(offset / PAGE_SIZE..(end + PAGE_SIZE - 1) / PAGE_SIZE) {
+            let page_start = *index * PAGE_SIZE;
+            let start = offset.max(page_start) - page_start;
+            let stop = end.min(page_start + PAGE_SIZE) - page_start;
+            page.data()[start..stop].fill(0);
+        }
+    }
+
+    /// 写回所有脏页
+    pub fn sync(&self) -> Result<(), Error> {
+        self.sync_range(0, usize::MAX)
+    }
+
+    /// 写回页号在[start, end)中的脏页
+    pub fn sync_range(&self, start: usize, end: usize) -> Result<(), Error> {
+        self.sync_with(start, end, usize::MAX)
+    }
+
+    /// 写回在before之前就成为脏页的页, 用于后台写回
+    pub fn sync_expired(&self, before: usize) -> Result<(), Error> {
+        self.sync_with(0, usize::MAX, before)
+    }
+
+    fn sync_with(&self, start: usize, end: usize, before: usize) -> Result<(), Error> {
+        /* 写回时不持有锁, 先清除脏标记, 写回期间再次写入的页会被重新标记 */
+        let dirty: Vec<(usize, Arc<Page>)> = self.pages
+            .lock()
+            .range(start..end)
+            .filter(|(_, page)| {
+                let time = page.dirty_time.load(Ordering::Acquire);
+                time != 0 && time <= before
+                    && page.dirty_time.compare_exchange(time, 0, Ordering::AcqRel, Ordering::Acquire).is_ok()
+            })
+            .map(|(index, page)| (*index, page.clone()))
+            .collect();
+        if dirty.is_empty() {
+            return Ok(());
+        }
+        let backend = match self.backend() {
+            Ok(backend) => backend,
+            Err(err) => {
+                dirty.iter().for_each(|(_, page)| page.mark_dirty());
+                return Err(err);
+            }
+        };
+        let mut result = Ok(());
+        for (index, page) in dirty {
+            if let Err(err) = backend.write_page(index, page.data()) {
+                page.mark_dirty();
+                result = Err(err);
+            } else if page.is_mapped() {
+                page.mark_dirty();
+            }
+        }
+        result
+    }
+
+    /// 丢弃所有页, 用于被删除的文件, 已经映射的页在解除映射后释放
+    pub fn invalidate(&self) {
+        let mut pages = self.pages.lock();
+        CACHED_PAGES.fetch_sub(pages.len(), Ordering::Relaxed);
+        pages.clear();
+    }
+
+    /// 淘汰最多num个干净且没有被映射的页, 返回淘汰的页数
+    /// second_chance为true时跳过最近访问过的页, 并清除其访问标记
+    fn shrink(&self, num: usize, second_chance: bool) -> usize {
+        let mut pages = self.pages.lock();
+        let victims: Vec<usize> = pages
+            .iter()
+            .filter(|(_, page)| Arc::strong_count(page) == 1 && !page.is_dirty() && !page.is_mapped())
+            .filter(|(_, page)| !(second_chance && page.accessed.swap(false, Ordering::Relaxed)))
+            .map(|(index, _)| *index)
+            .take(num)
+            .collect();
+        for index in victims.iter() {
+            pages.remove(index);
+        }
+        CACHED_PAGES.fetch_sub(victims.len(), Ordering::Relaxed);
+        victims.len()
+    }
+}
+
+impl Drop for PageCache {
+    fn drop(&mut self) {
+        let pages = self.pages.get_mut();
+        if pages.values().any(|page| page.is_dirty()) {
+            warn!("page_cache: dirty pages of {:?} are dropped", self.index.1.0);
+        }
+        CACHED_PAGES.fetch_sub(pages.len(), Ordering::Relaxed);
+    }
+}
+
+/// 每个打开的文件的预读状态
+/// 连续的顺序读开启预读, 窗口从READAHEAD_MIN_PAGES开始, 每次预读后加倍, 最大为READAHEAD_MAX_PAGES
+/// 不连续的读关闭预读
+#[derive(Default)]
+pub struct ReadAhead {
+    next: usize,        //上一次读的结束位置, 下一次从这里开始读视为顺序读
+    window: usize,      //预读窗口的页数, 0表示没有开启预读
+    ahead: usize,       //已经预读到的页号(不含)
+}
+
+impl ReadAhead {
+    /// 读[offset, offset + len)之前调用, 返回需要读入页缓存的页号范围
+    /// 读到已预读部分的后一半时才开始下一次预读
+    pub fn on_read(&mut self, offset: usize, len: usize) -> Option<Range<usize>> {
+        let sequential = offset == self.next;
+        self.next = offset + len;
+        if !sequential || len == 0 {
+            self.window = 0;
+            return None;
+        }
+        let start = offset / PAGE_SIZE;
+        let end = (offset + len + PAGE_SIZE - 1) / PAGE_SIZE;
+        if self.window == 0 {
+            self.window = READAHEAD_MIN_PAGES;
+            self.ahead = start;
+        }
+        if end + self.window / 2 <= self.ahead {
+            return None;
+        }
+        let range = self.ahead.max(start)..end + self.window;
+        self.ahead = range.end;
+        self.window = (self.window * 2).min(READAHEAD_MAX_PAGES);
+        Some(range)
+    }
+}
+
+/* 当前缓存的页数 */
+static CACHED_PAGES: AtomicUsize = AtomicUsize::new(0);
+
+static PAGE_CACHES: Lazy<Mutex<BTreeMap<FileIndex, Arc<PageCache>>>> = Lazy::new(|| {
+    Mutex::new(BTreeMap::new())
+});
+
+/// 获取文件的页缓存, 不存在时创建
+/// 文件关闭后页缓存仍然保留, 再次打开时更新backend
+pub fn get_page_cache(index: FileIndex, backend: Weak<dyn PageBackend>) -> Arc<PageCache> {
+    let mut caches = PAGE_CACHES.lock();
+    match caches.get(&index) {
+        Some(cache) => {
+            *cache.backend.lock() = backend;
+            cache.clone()
+        }
+        None => {
+            let cache = Arc::new(PageCache::new(index, backend));
+            caches.insert(index, cache.clone());
+            cache
+        }
+    }
+}
+
+/// 文件被删除时调用, 之后以同样的FileIndex打开的文件使用新的页缓存
+/// 已经打开的文件继续使用原来的页缓存
+pub fn remove_page_cache(index: FileIndex) {
+    PAGE_CACHES.lock().remove(&index);
+}
+
+/// 淘汰最多num个页, 返回淘汰的页数
+pub fn shrink_page_cache(num: usize) -> usize {
+    let mut caches = PAGE_CACHES.lock();
+    let mut freed = 0;
+    for second_chance in [true, false] {
+        for cache in caches.values() {
+            if freed >= num {
+                break;
+            }
+            freed += cache.shrink(num - freed, second_chance);
+        }
+    }
+    /* 没有被打开的文件使用, 并且已经没有页的缓存 */
+    caches.retain(|_, cache| Arc::strong_count(cache) > 1 || !cache.pages.lock().is_empty());
+    if freed > 0 {
+        trace!("page_cache: {} pages are freed", freed);
+    }
+    freed
+}
+
+/// 写回属于文件系统fs的页缓存, fs为None时写回所有的页缓存
+pub fn sync_page_caches(fs: Option<FSid>) -> Result<(), Error> {
+    let caches: Vec<Arc<PageCache>> = PAGE_CACHES
+        .lock()
+        .values()
+        .filter(|cache| fs.map_or(true, |fs| cache.index.0 == fs))
+        .cloned()
+        .collect();
+    let mut result = Ok(());
+    for cache in caches {
+        if let Err(err) = cache.sync() {
+            warn!("page_cache: sync {:?} failed: {:?}", cache.index.1.0, err);
+            result = Err(err);
+        }
+    }
+    result
+}
+
+/* 写回在before之前就成为脏页的页 */
+pub fn flush_expired_pages(before: usize) {
+    let caches: Vec<Arc<PageCache>> = PAGE_CACHES.lock().values().cloned().collect();
+    for cache in caches {
+        let _ = cache.sync_expired(before);
+    }
+}
+
+/* 页缓存占用的字节数 */
+pub fn page_cache_bytes() -> usize {
+    CACHED_PAGES.load(Ordering::Relaxed) * PAGE_SIZE
+}
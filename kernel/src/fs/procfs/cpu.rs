@@ -0,0 +1,100 @@
+use alloc::{format, string::String};
+use core::fmt::Write;
+use core::sync::atomic::Ordering;
+use crate::board::CLOCK_FREQ;
+use crate::proc::{get_all_tasks, get_loadavg, get_task_count, CPU_STAT_LIST, FIXED_1, FSHIFT};
+use crate::timer::get_time;
+use super::to_clock_ticks;
+
+/* 内核以riscv64gc为目标编译, S态无法读取misa, 直接给出ISA字符串 */
+const ISA: &str = "rv64imafdc";
+const MMU: &str = "sv39";
+
+/* /proc/cpuinfo, 与riscv linux相同每个hart一段 */
+pub fn cpuinfo() -> String {
+    let mut info = String::new();
+    for (hartid, stat) in CPU_STAT_LIST.iter().enumerate() {
+        if !stat.online.load(Ordering::Acquire) {
+            continue;
+        }
+        writeln!(info, "processor\t: {}", hartid).unwrap();
+        writeln!(info, "hart\t\t: {}", hartid).unwrap();
+        writeln!(info, "isa\t\t: {}", ISA).unwrap();
+        writeln!(info, "mmu\t\t: {}", MMU).unwrap();
+        writeln!(info, "timebase\t: {}", CLOCK_FREQ).unwrap();
+        writeln!(info).unwrap();
+    }
+    info
+}
+
+/* 所有CPU的空闲时间之和, 单位为时钟周期 */
+fn total_idle() -> u64 {
+    CPU_STAT_LIST
+        .iter()
+        .filter(|stat| stat.online.load(Ordering::Acquire))
+        .map(|stat| stat.idle.load(Ordering::Relaxed))
+        .sum()
+}
+
+/* /proc/uptime: 启动以来的秒数, 以及所有CPU空闲的秒数 */
+pub fn uptime() -> String {
+    let now = get_time() as u64;
+    let idle = total_idle();
+    let freq = CLOCK_FREQ as u64;
+    format!(
+        "{}.{:02} {}.{:02}\n",
+        now / freq, now % freq * 100 / freq,
+        idle / freq, idle % freq * 100 / freq,
+    )
+}
+
+/* /proc/stat, cpu行的单位为USER_HZ */
+pub fn stat() -> String {
+    let mut stat = String::new();
+    let (mut user, mut system, mut idle, mut switches) = (0, 0, 0, 0);
+    let mut lines = String::new();
+    for (hartid, cpu) in CPU_STAT_LIST.iter().enumerate() {
+        if !cpu.online.load(Ordering::Acquire) {
+            continue;
+        }
+        let (u, s, i) = (
+            cpu.user.load(Ordering::Relaxed),
+            cpu.system.load(Ordering::Relaxed),
+            cpu.idle.load(Ordering::Relaxed),
+        );
+        user += u;
+        system += s;
+        idle += i;
+        switches += cpu.switches.load(Ordering::Relaxed);
+        writeln!(lines, "cpu{} {} 0 {} {} 0 0 0 0 0 0",
+            hartid, to_clock_ticks(u), to_clock_ticks(s), to_clock_ticks(i)).unwrap();
+    }
+    writeln!(stat, "cpu  {} 0 {} {} 0 0 0 0 0 0",
+        to_clock_ticks(user), to_clock_ticks(system), to_clock_ticks(idle)).unwrap();
+    stat.push_str(&lines);
+
+    let (running, _) = get_task_count();
+    /* tid按顺序分配, 最大的tid即为启动以来创建的任务数 */
+    let processes = get_all_tasks().iter().map(|task| task.tid).max().unwrap_or(0) + 1;
+    writeln!(stat, "intr 0").unwrap();
+    writeln!(stat, "ctxt {}", switches).unwrap();
+    writeln!(stat, "btime 0").unwrap();
+    writeln!(stat, "processes {}", processes).unwrap();
+    writeln!(stat, "procs_running {}", running).unwrap();
+    writeln!(stat, "procs_blocked 0").unwrap();
+    stat
+}
+
+/* /proc/loadavg: 1/5/15分钟平均负载, 可运行/总任务数, 最近创建的pid */
+pub fn loadavg() -> String {
+    let loads = get_loadavg();
+    let (running, total) = get_task_count();
+    let last_pid = get_all_tasks().iter().map(|task| task.tid).max().unwrap_or(0);
+    let mut avg = String::new();
+    for load in loads.iter() {
+        /* 定点小数保留两位 */
+        let load = load + FIXED_1 / 200;
+        write!(avg, "{}.{:02} ", load >> FSHIFT, ((load & (FIXED_1 - 1)) * 100) >> FSHIFT).unwrap();
+    }
+    format!("{}{}/{} {}\n", avg, running, total, last_pid)
+}
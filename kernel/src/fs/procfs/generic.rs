@@ -0,0 +1,160 @@
+use alloc::{format, string::String, sync::Arc, vec::Vec};
+use spin::Mutex;
+use super::*;
+
+/// proc文件系统中内容在打开时生成的只读文件(stat, status, maps等)
+/// 与linux的seq_file类似, 打开后内容不再变化, 重新打开才能看到最新的状态
+pub struct ProcFile {
+    data: Vec<u8>,
+    cursor: Mutex<usize>,
+    mode: FileOpenMode,
+}
+
+impl ProcFile {
+    pub fn new(data: Vec<u8>, mode: FileOpenMode) -> Arc<Self> {
+        Arc::new(Self {
+            data,
+            cursor: Mutex::new(0),
+            mode,
+        })
+    }
+
+    pub fn from_string(string: String, mode: FileOpenMode) -> Arc<Self> {
+        Self::new(string.into_bytes(), mode)
+    }
+}
+
+impl File for ProcFile {
+    fn get_index(&self) -> Result<FileIndex, Error> {
+        Err(Error::EINDEX)
+    }
+
+    fn read(&self, len: usize) -> Result<Vec<u8>, Error> {
+        let mut cursor = self.cursor.lock();
+        let start = (*cursor).min(self.data.len());
+        let end = (start + len).min(self.data.len());
+        *cursor = end;
+        Ok(self.data[start..end].to_vec())
+    }
+
+    fn seek(&self, pos: usize, mode: SeekMode) -> Result<isize, Error> {
+        let mut cursor = self.cursor.lock();
+        let new = match mode {
+            SeekMode::SET => pos as isize,
+            SeekMode::CUR => *cursor as isize + pos as isize,
+            SeekMode::END => self.data.len() as isize + pos as isize,
+        };
+        if new < 0 {
+            return Err(Error::EINVAL);
+        }
+        *cursor = new as usize;
+        Ok(new)
+    }
+
+    fn read_stat(&self) -> Result<FileStat, Error> {
+        let mut fstat: FileStat = Default::default();
+        fstat.st_nlink = 1;
+        fstat.st_mode = StMode::REG as u32 | 0o444;
+        Ok(fstat)
+    }
+
+    fn readable(&self) -> bool {
+        true
+    }
+
+    fn writable(&self) -> bool {
+        false
+    }
+
+    fn poll(&self, ptype: PollType) -> Result<bool, Error> {
+        match ptype {
+            PollType::READ => Ok(true),
+            PollType::WRITE => Ok(false),
+            PollType::ERR | PollType::HUP => Ok(false),
+        }
+    }
+
+    fn as_file<'a>(self: Arc<Self>) -> Arc<dyn File + 'a> where Self: 'a, {
+        self
+    }
+}
+
+enum LinkTarget {
+    Path(Path),
+    /* 没有路径的文件(管道, socket等), 只能通过readlink读出名字 */
+    Anon(String),
+}
+
+/// proc文件系统中的软连接: /proc/self, /proc/<pid>/exe, cwd, fd/<N>
+pub struct ProcLink {
+    target: LinkTarget,
+}
+
+impl ProcLink {
+    pub fn new(path: Path) -> Arc<Self> {
+        Arc::new(Self { target: LinkTarget::Path(path) })
+    }
+
+    /// 为fd表中的文件生成软连接, 与linux相同, 没有路径的文件显示为"type:[inode]"
+    pub fn from_file(file: Arc<dyn File>) -> Arc<Self> {
+        if let Ok(path) = file.get_path() {
+            return Self::new(path);
+        }
+        /* 没有inode号, 用文件对象的地址作为标识 */
+        let id = Arc::as_ptr(&file) as *const u8 as usize;
+        let name = if file.clone().as_socket().is_ok() {
+            format!("socket:[{}]", id)
+        } else if file.clone().as_fifo().is_ok() {
+            format!("pipe:[{}]", id)
+        } else {
+            String::from("anon_inode:[file]")
+        };
+        Arc::new(Self { target: LinkTarget::Anon(name) })
+    }
+}
+
+impl File for ProcLink {
+    fn get_index(&self) -> Result<FileIndex, Error> {
+        Err(Error::EINDEX)
+    }
+
+    fn read_stat(&self) -> Result<FileStat, Error> {
+        let mut fstat: FileStat = Default::default();
+        fstat.st_nlink = 1;
+        fstat.st_mode = StMode::LNK as u32 | 0o777;
+        fstat.st_size = self.link_name()?.len() as u64;
+        Ok(fstat)
+    }
+
+    fn get_type(&self) -> Result<FileType, Error> {
+        Ok(FileType::LinkFile)
+    }
+
+    fn as_link<'a>(self: Arc<Self>) -> Result<Arc<dyn LinkFile + 'a>, Error> where Self: 'a {
+        Ok(self)
+    }
+
+    fn as_file<'a>(self: Arc<Self>) -> Arc<dyn File + 'a> where Self: 'a, {
+        self
+    }
+}
+
+impl LinkFile for ProcLink {
+    fn read_link(&self) -> Result<Path, Error> {
+        match &self.target {
+            LinkTarget::Path(path) => Ok(path.clone()),
+            LinkTarget::Anon(_) => Err(Error::ENOENT),
+        }
+    }
+
+    fn write_link(&self, _target: &str) -> Result<(), Error> {
+        Err(Error::EPERM)
+    }
+
+    fn link_name(&self) -> Result<String, Error> {
+        match &self.target {
+            LinkTarget::Path(path) => Ok(path.to_string()),
+            LinkTarget::Anon(name) => Ok(name.clone()),
+        }
+    }
+}
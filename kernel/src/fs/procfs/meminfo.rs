@@ -1,46 +1,57 @@
-use alloc::{sync::Arc, vec::Vec};
-use super::*;
-
-pub struct MemInfo {
-    mode: FileOpenMode,
+use alloc::{format, string::String};
+use core::fmt::Write;
+use crate::config::PAGE_SIZE;
+use crate::fs::block_cache::block_cache_bytes;
+use crate::fs::page_cache::page_cache_bytes;
+use crate::memory::{get_available_frame_num, get_free_swap_num, get_heap_usage, get_total_frame_num, SWAP_FRAME_NUM};
+
+/// 内存使用情况的统计, 供/proc/meminfo和sysinfo使用, 单位为字节
+pub struct MemStat {
+    pub total: usize,
+    pub free: usize,
+    pub buffers: usize,
+    pub cached: usize,
+    pub heap_used: usize,
+    pub heap_total: usize,
+    pub swap_total: usize,
+    pub swap_free: usize,
 }
 
-impl MemInfo {
-    pub fn new(mode: FileOpenMode) -> Arc<Self> {
-        Arc::new(Self { mode })
+impl MemStat {
+    pub fn now() -> Self {
+        let (heap_used, heap_total) = get_heap_usage();
+        Self {
+            total: get_total_frame_num() * PAGE_SIZE,
+            free: get_available_frame_num() * PAGE_SIZE,
+            buffers: block_cache_bytes(),
+            cached: page_cache_bytes(),
+            heap_used,
+            heap_total,
+            swap_total: SWAP_FRAME_NUM * PAGE_SIZE,
+            swap_free: get_free_swap_num() * PAGE_SIZE,
+        }
     }
 }
 
-
-impl File for MemInfo {
-    fn get_index(&self) -> Result<FileIndex, crate::utils::Error> {
-        Err(Error::EINDEX)
-    }
-
-    fn read(&self, len: usize) -> Result<Vec<u8>, Error> {
-        Ok(Vec::new())
-    }
-
-    fn read_stat(&self) -> Result<FileStat, Error> {
-        let mut fstat: FileStat = Default::default();
-        fstat.st_nlink = 1;
-        fstat.st_mode = StMode::REG as u32;
-        Ok(fstat)
-    }
-
-    fn readable(&self) -> bool {
-        self.mode.contains(FileOpenMode::READ)
-            || self.mode.contains(FileOpenMode::RDWR)
-            || self.mode.contains(FileOpenMode::SYS)
-    }
-
-    fn writable(&self) -> bool {
-        self.mode.contains(FileOpenMode::WRITE)
-            || self.mode.contains(FileOpenMode::RDWR)
-            || self.mode.contains(FileOpenMode::SYS)
-    }
-
-    fn as_file<'a>(self: Arc<Self>) -> Arc<dyn File + 'a> where Self: 'a, {
-        self
-    }
+/* /proc/meminfo, 格式参考man 5 proc */
+pub fn meminfo() -> String {
+    let stat = MemStat::now();
+    let mut info = String::new();
+    let mut line = |name: &str, bytes: usize| {
+        writeln!(info, "{:<16}{:>8} kB", format!("{}:", name), bytes / 1024).unwrap();
+    };
+    line("MemTotal", stat.total);
+    line("MemFree", stat.free);
+    line("MemAvailable", stat.free + stat.buffers + stat.cached);
+    line("Buffers", stat.buffers);
+    line("Cached", stat.cached);
+    line("SwapCached", 0);
+    line("SwapTotal", stat.swap_total);
+    line("SwapFree", stat.swap_free);
+    line("Shmem", 0);
+    /* 内核堆 */
+    line("Slab", stat.heap_used);
+    line("SUnreclaim", stat.heap_used);
+    line("VmallocTotal", stat.heap_total);
+    info
 }
@@ -1,13 +1,31 @@
 mod mounts;
 mod meminfo;
+mod pid;
+mod generic;
+mod cpu;
 
 pub use meminfo::*;
 pub use mounts::*;
+pub use pid::*;
+pub use generic::*;
 use super::*;
 use crate::utils::{Error, Path};
-use alloc::{string::String, sync::Arc, vec::Vec};
+use alloc::{collections::BTreeSet, format, string::String, sync::Arc, vec::Vec};
 use log::*;
+use crate::proc::{get_all_tasks, get_current_task};
 use spin::{lazy::Lazy, Mutex};
+use crate::board::CLOCK_FREQ;
+
+/* 用户态看到的时钟频率(sysconf(_SC_CLK_TCK)) */
+const USER_HZ: u64 = 100;
+
+/* 内核的时间以时钟周期为单位, 转换为USER_HZ */
+fn to_clock_ticks(time: u64) -> u64 {
+    time / (CLOCK_FREQ as u64 / USER_HZ)
+}
+
+/* /proc下内容在打开时生成的文件 */
+const PROC_ENTRIES: [&str; 6] = ["mounts", "meminfo", "cpuinfo", "uptime", "stat", "loadavg"];
 
 pub struct ProcFS {
     pub id: FSid,
@@ -32,20 +50,22 @@ impl VFS for ProcFS {
         self.mount_path.clone()
     }
     fn root_dir(&self, mode: FileOpenMode) -> Result<Arc<dyn DirFile>, Error> {
-        Ok(ProcDir::new(mode).as_dir()?)
+        Ok(ProcDir::new(self.mount_path.clone(), mode).as_dir()?)
     }
 }
 
 
 pub struct ProcDir {
+    mount_path: Path,
     cursor: Mutex<usize>,
     mode: FileOpenMode,
 }
 
 impl ProcDir {
-    pub fn new(mode: FileOpenMode) -> Arc<Self> {
+    pub fn new(mount_path: Path, mode: FileOpenMode) -> Arc<Self> {
         Arc::new(
             ProcDir { 
+                mount_path,
                 cursor: Mutex::new(0), 
                 mode: mode 
             }
@@ -57,7 +77,7 @@ impl File for ProcDir {
     fn read_stat(&self) -> Result<FileStat, Error> {
         let mut fstat: FileStat = Default::default();
         fstat.st_nlink = 1;
-        fstat.st_mode = StMode::DIR as u32;
+        fstat.st_mode = StMode::DIR as u32 | 0o555;
         Ok(fstat)
     }
     fn as_dir<'a>(self: Arc<Self>) -> Result<Arc<dyn DirFile + 'a>, Error> where Self: 'a, {
@@ -75,26 +95,54 @@ impl DirFile for ProcDir {
     fn openat(&self, name: String, mode: FileOpenMode) -> Result<Arc<dyn File>, Error> {
         match name.as_str() {
             "mounts" => Ok(Mount::new(mode).as_file()),
-            "meminfo" => Ok(MemInfo::new(mode).as_file()),
-            _ => Err(Error::ENOENT),
+            "meminfo" => Ok(ProcFile::from_string(meminfo(), mode).as_file()),
+            "cpuinfo" => Ok(ProcFile::from_string(cpu::cpuinfo(), mode).as_file()),
+            "uptime" => Ok(ProcFile::from_string(cpu::uptime(), mode).as_file()),
+            "stat" => Ok(ProcFile::from_string(cpu::stat(), mode).as_file()),
+            "loadavg" => Ok(ProcFile::from_string(cpu::loadavg(), mode).as_file()),
+            /* /proc/self指向当前进程的/proc/<pid> */
+            "self" => {
+                let pid = get_current_task().unwrap().pid;
+                let mut path = self.mount_path.clone();
+                path.push_back(format!("{}", pid));
+                Ok(ProcLink::new(path).as_file())
+            }
+            _ => match name.parse::<i32>() {
+                Ok(pid) => Ok(ProcPidDir::new(pid, mode)?.as_file()),
+                Err(_) => Err(Error::ENOENT),
+            },
         }
     }
     fn getdent(&self) -> Result<Vec<Dentry>, Error> {
         let mut cursor = self.cursor.lock();
         let mut dentrys = Vec::new();
-        if *cursor == 0 {
+        if *cursor != 0 {
             return Ok(dentrys);
         }
+        for name in PROC_ENTRIES.iter() {
+            dentrys.push(Dentry {
+                d_ino: 0,
+                d_type: FileType::RegularFile,
+                d_name: String::from(*name),
+            });
+        }
         dentrys.push(Dentry {
             d_ino: 0,
-            d_type: FileType::RegularFile,
-            d_name: String::from("mounts"),
-        });
-        dentrys.push(Dentry {
-            d_ino: 0,
-            d_type: FileType::RegularFile,
-            d_name: String::from("meminfo"),
+            d_type: FileType::LinkFile,
+            d_name: String::from("self"),
         });
+        /* 每个进程一个目录 */
+        let pids: BTreeSet<i32> = get_all_tasks()
+            .iter()
+            .map(|task| task.pid)
+            .collect();
+        for pid in pids {
+            dentrys.push(Dentry {
+                d_ino: 0,
+                d_type: FileType::Directory,
+                d_name: format!("{}", pid),
+            });
+        }
         *cursor = dentrys.len();
         Ok(dentrys)
     }
@@ -25,7 +25,7 @@ impl File for Mount {
     fn read_stat(&self) -> Result<FileStat, Error> {
         let mut fstat: FileStat = Default::default();
         fstat.st_nlink = 1;
-        fstat.st_mode = StMode::REG as u32;
+        fstat.st_mode = StMode::REG as u32 | 0o444;
         Ok(fstat)
     }
 
@@ -0,0 +1,475 @@
+use alloc::{format, string::String, sync::Arc, vec::Vec};
+use core::fmt::Write;
+use spin::Mutex;
+use crate::config::PAGE_SIZE;
+use crate::memory::{MapProt, MemorySet, VirtAddr};
+use crate::proc::{get_task_by_pid, get_task_by_tid, TaskControlBlock, TaskStatus, SIG_DFL, SIG_IGN};
+use crate::syscall::strace::{strace_ctl, strace_read};
+use super::*;
+
+/* /proc/<pid>与/proc/<pid>/task/<tid>下共有的文件 */
+const PID_ENTRIES: [(&str, FileType); 8] = [
+    ("stat",    FileType::RegularFile),
+    ("status",  FileType::RegularFile),
+    ("cmdline", FileType::RegularFile),
+    ("environ", FileType::RegularFile),
+    ("maps",    FileType::RegularFile),
+    ("exe",     FileType::LinkFile),
+    ("cwd",     FileType::LinkFile),
+    ("fd",      FileType::Directory),
+];
+
+/* 只有/proc/<pid>才有的文件 */
+const TGID_ENTRIES: [(&str, FileType); 2] = [
+    ("task",    FileType::Directory),
+    ("strace",  FileType::RegularFile),
+];
+
+/// /proc/<pid>目录, 以及/proc/<pid>/task/<tid>目录
+pub struct ProcPidDir {
+    pid: i32,
+    tid: i32,
+    is_thread: bool,
+    cursor: Mutex<usize>,
+    mode: FileOpenMode,
+}
+
+impl ProcPidDir {
+    pub fn new(pid: i32, mode: FileOpenMode) -> Result<Arc<Self>, Error> {
+        get_task_by_pid(pid).ok_or(Error::ENOENT)?;
+        Ok(Arc::new(Self {
+            pid,
+            tid: pid,
+            is_thread: false,
+            cursor: Mutex::new(0),
+            mode,
+        }))
+    }
+
+    pub fn new_thread(pid: i32, tid: i32, mode: FileOpenMode) -> Result<Arc<Self>, Error> {
+        let task = get_task_by_tid(tid).ok_or(Error::ENOENT)?;
+        if task.pid != pid {
+            return Err(Error::ENOENT);
+        }
+        Ok(Arc::new(Self {
+            pid,
+            tid,
+            is_thread: true,
+            cursor: Mutex::new(0),
+            mode,
+        }))
+    }
+
+    /* 进程目录优先使用主线程, 主线程已经退出时使用线程组中任意一个线程 */
+    fn get_task(&self) -> Result<Arc<TaskControlBlock>, Error> {
+        let task = get_task_by_tid(self.tid);
+        match task {
+            Some(task) => Ok(task),
+            None if !self.is_thread => get_task_by_pid(self.pid).ok_or(Error::ENOENT),
+            None => Err(Error::ENOENT),
+        }
+    }
+}
+
+impl File for ProcPidDir {
+    fn read_stat(&self) -> Result<FileStat, Error> {
+        let mut fstat: FileStat = Default::default();
+        fstat.st_nlink = 1;
+        fstat.st_mode = StMode::DIR as u32 | 0o555;
+        Ok(fstat)
+    }
+    fn as_dir<'a>(self: Arc<Self>) -> Result<Arc<dyn DirFile + 'a>, Error> where Self: 'a, {
+        Ok(self)
+    }
+    fn as_file<'a>(self: Arc<Self>) -> Arc<dyn File + 'a> where Self: 'a, {
+        self
+    }
+    fn get_index(&self) -> Result<FileIndex, Error> {
+        Err(Error::EINDEX)
+    }
+}
+
+impl DirFile for ProcPidDir {
+    fn openat(&self, name: String, mode: FileOpenMode) -> Result<Arc<dyn File>, Error> {
+        let task = self.get_task()?;
+        match name.as_str() {
+            "stat"    => Ok(ProcFile::from_string(task_stat(&task, self.is_thread), mode).as_file()),
+            "status"  => Ok(ProcFile::from_string(task_status(&task, self.is_thread), mode).as_file()),
+            "cmdline" => Ok(ProcFile::new(join_strings(&task.get_memory().cmdline), mode).as_file()),
+            "environ" => Ok(ProcFile::new(join_strings(&task.get_memory().environ), mode).as_file()),
+            "maps"    => Ok(ProcFile::from_string(task_maps(&task.get_memory()), mode).as_file()),
+            "exe"     => Ok(ProcLink::new(task.get_memory().exe.clone()).as_file()),
+            "cwd"     => Ok(ProcLink::new(task.get_fs_info().cwd.clone()).as_file()),
+            "fd"      => Ok(ProcFdDir::new(self.pid, self.tid, mode).as_file()),
+            "task" if !self.is_thread => Ok(ProcTaskDir::new(self.pid, mode).as_file()),
+            "strace" if !self.is_thread => Ok(ProcStrace::new(self.pid, mode).as_file()),
+            _ => Err(Error::ENOENT),
+        }
+    }
+    fn getdent(&self) -> Result<Vec<Dentry>, Error> {
+        let mut cursor = self.cursor.lock();
+        let mut dentrys = Vec::new();
+        if *cursor != 0 {
+            return Ok(dentrys);
+        }
+        let extra: &[(&str, FileType)] = if self.is_thread { &[] } else { &TGID_ENTRIES };
+        for (name, d_type) in PID_ENTRIES.iter().chain(extra.iter()) {
+            dentrys.push(Dentry {
+                d_ino: 0,
+                d_type: *d_type,
+                d_name: String::from(*name),
+            });
+        }
+        *cursor = dentrys.len();
+        Ok(dentrys)
+    }
+}
+
+/// /proc/<pid>/task目录, 每个线程一个子目录
+pub struct ProcTaskDir {
+    pid: i32,
+    cursor: Mutex<usize>,
+    mode: FileOpenMode,
+}
+
+impl ProcTaskDir {
+    pub fn new(pid: i32, mode: FileOpenMode) -> Arc<Self> {
+        Arc::new(Self {
+            pid,
+            cursor: Mutex::new(0),
+            mode,
+        })
+    }
+}
+
+impl File for ProcTaskDir {
+    fn read_stat(&self) -> Result<FileStat, Error> {
+        let mut fstat: FileStat = Default::default();
+        fstat.st_nlink = 1;
+        fstat.st_mode = StMode::DIR as u32 | 0o555;
+        Ok(fstat)
+    }
+    fn as_dir<'a>(self: Arc<Self>) -> Result<Arc<dyn DirFile + 'a>, Error> where Self: 'a, {
+        Ok(self)
+    }
+    fn as_file<'a>(self: Arc<Self>) -> Arc<dyn File + 'a> where Self: 'a, {
+        self
+    }
+    fn get_index(&self) -> Result<FileIndex, Error> {
+        Err(Error::EINDEX)
+    }
+}
+
+impl DirFile for ProcTaskDir {
+    fn openat(&self, name: String, mode: FileOpenMode) -> Result<Arc<dyn File>, Error> {
+        let tid = name.parse::<i32>().map_err(|_| Error::ENOENT)?;
+        Ok(ProcPidDir::new_thread(self.pid, tid, mode)?.as_file())
+    }
+    fn getdent(&self) -> Result<Vec<Dentry>, Error> {
+        let mut cursor = self.cursor.lock();
+        let mut dentrys = Vec::new();
+        if *cursor != 0 {
+            return Ok(dentrys);
+        }
+        let task = get_task_by_pid(self.pid).ok_or(Error::ENOENT)?;
+        for thread in live_threads(&task) {
+            dentrys.push(Dentry {
+                d_ino: 0,
+                d_type: FileType::Directory,
+                d_name: format!("{}", thread.tid),
+            });
+        }
+        *cursor = dentrys.len();
+        Ok(dentrys)
+    }
+}
+
+/// /proc/<pid>/fd目录, fd表中每一项对应一个软连接
+pub struct ProcFdDir {
+    pid: i32,
+    tid: i32,
+    cursor: Mutex<usize>,
+    mode: FileOpenMode,
+}
+
+impl ProcFdDir {
+    pub fn new(pid: i32, tid: i32, mode: FileOpenMode) -> Arc<Self> {
+        Arc::new(Self {
+            pid,
+            tid,
+            cursor: Mutex::new(0),
+            mode,
+        })
+    }
+
+    fn get_task(&self) -> Result<Arc<TaskControlBlock>, Error> {
+        get_task_by_tid(self.tid)
+            .or_else(|| get_task_by_pid(self.pid))
+            .ok_or(Error::ENOENT)
+    }
+}
+
+impl File for ProcFdDir {
+    fn read_stat(&self) -> Result<FileStat, Error> {
+        let mut fstat: FileStat = Default::default();
+        fstat.st_nlink = 1;
+        fstat.st_mode = StMode::DIR as u32 | 0o500;
+        Ok(fstat)
+    }
+    fn as_dir<'a>(self: Arc<Self>) -> Result<Arc<dyn DirFile + 'a>, Error> where Self: 'a, {
+        Ok(self)
+    }
+    fn as_file<'a>(self: Arc<Self>) -> Arc<dyn File + 'a> where Self: 'a, {
+        self
+    }
+    fn get_index(&self) -> Result<FileIndex, Error> {
+        Err(Error::EINDEX)
+    }
+}
+
+impl DirFile for ProcFdDir {
+    fn openat(&self, name: String, _mode: FileOpenMode) -> Result<Arc<dyn File>, Error> {
+        let fd = name.parse::<u32>().map_err(|_| Error::ENOENT)?;
+        let file = self.get_task()?.get_file(fd).map_err(|_| Error::ENOENT)?;
+        Ok(ProcLink::from_file(file).as_file())
+    }
+    fn getdent(&self) -> Result<Vec<Dentry>, Error> {
+        let mut cursor = self.cursor.lock();
+        let mut dentrys = Vec::new();
+        if *cursor != 0 {
+            return Ok(dentrys);
+        }
+        let task = self.get_task()?;
+        for fd in task.get_fd_table().table.keys() {
+            dentrys.push(Dentry {
+                d_ino: 0,
+                d_type: FileType::LinkFile,
+                d_name: format!("{}", fd),
+            });
+        }
+        *cursor = dentrys.len();
+        Ok(dentrys)
+    }
+}
+
+/// /proc/<pid>/strace, 读取系统调用追踪的结果, 写入on/off/tid <tid>/clear控制追踪
+pub struct ProcStrace {
+    pid: i32,
+    mode: FileOpenMode,
+}
+
+impl ProcStrace {
+    pub fn new(pid: i32, mode: FileOpenMode) -> Arc<Self> {
+        Arc::new(Self { pid, mode })
+    }
+}
+
+impl File for ProcStrace {
+    fn get_index(&self) -> Result<FileIndex, Error> {
+        Err(Error::EINDEX)
+    }
+
+    /* 与trace_pipe一样, 读出的内容会从缓冲区中移除 */
+    fn read(&self, len: usize) -> Result<Vec<u8>, Error> {
+        Ok(strace_read(self.pid, len))
+    }
+
+    fn write(&self, data: Vec<u8>) -> Result<usize, Error> {
+        let cmd = core::str::from_utf8(data.as_slice()).map_err(|_| Error::EINVAL)?;
+        strace_ctl(self.pid, cmd.trim())?;
+        Ok(data.len())
+    }
+
+    fn read_stat(&self) -> Result<FileStat, Error> {
+        let mut fstat: FileStat = Default::default();
+        fstat.st_nlink = 1;
+        fstat.st_mode = StMode::REG as u32 | 0o600;
+        Ok(fstat)
+    }
+
+    fn readable(&self) -> bool {
+        self.mode.contains(FileOpenMode::READ)
+            || self.mode.contains(FileOpenMode::RDWR)
+            || self.mode.contains(FileOpenMode::SYS)
+    }
+
+    fn writable(&self) -> bool {
+        self.mode.contains(FileOpenMode::WRITE)
+            || self.mode.contains(FileOpenMode::RDWR)
+            || self.mode.contains(FileOpenMode::SYS)
+    }
+
+    fn as_file<'a>(self: Arc<Self>) -> Arc<dyn File + 'a> where Self: 'a, {
+        self
+    }
+}
+
+/* cmdline和environ中每个字符串以'\0'结尾 */
+fn join_strings(strings: &Vec<String>) -> Vec<u8> {
+    let mut data = Vec::new();
+    for string in strings.iter() {
+        data.extend_from_slice(string.as_bytes());
+        data.push(0);
+    }
+    data
+}
+
+/* 线程组中还没有退出的线程 */
+fn live_threads(task: &Arc<TaskControlBlock>) -> Vec<Arc<TaskControlBlock>> {
+    task.get_thread_group()
+        .list
+        .iter()
+        .filter(|thread| !thread.is_zombie())
+        .cloned()
+        .collect()
+}
+
+/* 与linux相同, 进程名为可执行文件名的前15个字符 */
+fn task_comm(memory: &MemorySet) -> String {
+    if memory.exe.is_root() {
+        return String::from("initproc");
+    }
+    memory.exe.last().chars().take(15).collect()
+}
+
+fn task_state(task: &TaskControlBlock) -> (char, &'static str) {
+    match task.get_status() {
+        TaskStatus::READY | TaskStatus::RUNNING => ('R', "running"),
+        TaskStatus::STOPPED => ('S', "sleeping"),
+        _ => ('Z', "zombie"),
+    }
+}
+
+fn task_ppid(task: &TaskControlBlock) -> i32 {
+    task.parent
+        .lock()
+        .as_ref()
+        .and_then(|parent| parent.upgrade())
+        .map(|parent| parent.pid)
+        .unwrap_or(0)
+}
+
+/* 返回(虚拟内存大小, 常驻内存的页数), 常驻页即页表持有物理页帧的页, 不逐页查页表 */
+fn memory_usage(memory: &MemorySet) -> (usize, usize) {
+    let mut vsize = 0;
+    let mut rss = 0;
+    for area in memory.areas.iter() {
+        let start = area.get_start();
+        let end = area.get_end();
+        vsize += (usize::from(end) - usize::from(start)) * PAGE_SIZE;
+        rss += memory.pagetable.data_frames.range(start..end).count();
+    }
+    (vsize, rss)
+}
+
+/* /proc/<pid>/stat, 格式参考man 5 proc */
+fn task_stat(task: &Arc<TaskControlBlock>, is_thread: bool) -> String {
+    let (comm, vsize, rss) = {
+        let memory = task.get_memory();
+        let (vsize, rss) = memory_usage(&memory);
+        (task_comm(&memory), vsize, rss)
+    };
+    let threads = live_threads(task);
+    /* 进程的CPU时间是所有线程之和 */
+    let (mut utime, mut stime, mut cutime, mut cstime) = (0, 0, 0, 0);
+    let counted = if is_thread { Vec::from([task.clone()]) } else { threads.clone() };
+    for thread in counted.iter() {
+        let time_info = thread.time_info.lock();
+        utime += time_info.utime;
+        stime += time_info.stime;
+        cutime += time_info.sum_of_dead_descenants_utime;
+        cstime += time_info.sum_of_dead_descenants_stime;
+    }
+    let id = if is_thread { task.tid } else { task.pid };
+
+    let mut stat = format!(
+        "{} ({}) {} {} {} {} 0 -1 0 0 0 0 0 {} {} {} {} 20 0 {} 0 0 {} {} {}",
+        id, comm, task_state(task).0, task_ppid(task), task.pid, task.pid,
+        to_clock_ticks(utime), to_clock_ticks(stime), to_clock_ticks(cutime), to_clock_ticks(cstime),
+        threads.len(), vsize, rss, usize::MAX,
+    );
+    /* 剩余的字段(startcode到exit_code)全部为0 */
+    for _ in 26..=52 {
+        stat.push_str(" 0");
+    }
+    stat.push('\n');
+    stat
+}
+
+/* /proc/<pid>/status */
+fn task_status(task: &Arc<TaskControlBlock>, is_thread: bool) -> String {
+    let (comm, vsize, rss) = {
+        let memory = task.get_memory();
+        let (vsize, rss) = memory_usage(&memory);
+        (task_comm(&memory), vsize, rss)
+    };
+    let (state, state_name) = task_state(task);
+
+    let to_mask = |pendings: &Vec<usize>| {
+        pendings.iter().fold(0usize, |mask, signo| mask | 1 << (signo - 1))
+    };
+    let sig_pending = to_mask(&task.t_pending.lock().pendings);
+    let shd_pending = to_mask(&task.p_pending.lock().pendings);
+    let sig_blocked = *task.sig_mask.lock();
+    let (mut sig_ignored, mut sig_caught) = (0usize, 0usize);
+    for (signo, action) in task.get_handlers().table.iter() {
+        match action.sa_handler {
+            SIG_DFL => {}
+            SIG_IGN => sig_ignored |= 1 << (signo - 1),
+            _ => sig_caught |= 1 << (signo - 1),
+        }
+    }
+
+    let mut status = String::new();
+    writeln!(status, "Name:\t{}", comm).unwrap();
+    writeln!(status, "Umask:\t{:04o}", task.get_fs_info().umask).unwrap();
+    writeln!(status, "State:\t{} ({})", state, state_name).unwrap();
+    writeln!(status, "Tgid:\t{}", task.pid).unwrap();
+    writeln!(status, "Pid:\t{}", if is_thread { task.tid } else { task.pid }).unwrap();
+    writeln!(status, "PPid:\t{}", task_ppid(task)).unwrap();
+    writeln!(status, "TracerPid:\t0").unwrap();
+    /* 不区分文件系统id, 第四列与有效id相同 */
+    let cred = task.get_cred().clone();
+    writeln!(status, "Uid:\t{}\t{}\t{}\t{}", cred.ruid, cred.euid, cred.suid, cred.euid).unwrap();
+    writeln!(status, "Gid:\t{}\t{}\t{}\t{}", cred.rgid, cred.egid, cred.sgid, cred.egid).unwrap();
+    writeln!(status, "FDSize:\t{}", task.get_max_fd()).unwrap();
+    writeln!(status, "VmSize:\t{:8} kB", vsize / 1024).unwrap();
+    writeln!(status, "VmRSS:\t{:8} kB", rss * PAGE_SIZE / 1024).unwrap();
+    writeln!(status, "Threads:\t{}", live_threads(task).len()).unwrap();
+    writeln!(status, "SigPnd:\t{:016x}", sig_pending).unwrap();
+    writeln!(status, "ShdPnd:\t{:016x}", shd_pending).unwrap();
+    writeln!(status, "SigBlk:\t{:016x}", sig_blocked).unwrap();
+    writeln!(status, "SigIgn:\t{:016x}", sig_ignored).unwrap();
+    writeln!(status, "SigCgt:\t{:016x}", sig_caught).unwrap();
+    status
+}
+
+/* /proc/<pid>/maps */
+fn task_maps(memory: &MemorySet) -> String {
+    let mut maps = String::new();
+    let mut areas: Vec<_> = memory.areas.iter().collect();
+    areas.sort_by_key(|area| area.get_start());
+
+    for area in areas {
+        let start: usize = VirtAddr::from(area.get_start()).into();
+        let end: usize = VirtAddr::from(area.get_end()).into();
+        let prot = area.map_prot;
+        let perm = format!(
+            "{}{}{}{}",
+            if prot.contains(MapProt::READ) { 'r' } else { '-' },
+            if prot.contains(MapProt::WRITE) { 'w' } else { '-' },
+            if prot.contains(MapProt::EXEC) { 'x' } else { '-' },
+            if area.share { 's' } else { 'p' },
+        );
+        /* 用户栈紧挨在程序段之上, 堆从program_end增长到current_end */
+        let name = match &area.file {
+            Some(file) => file.get_path().map(|path| path.to_string()).unwrap_or_default(),
+            None if end == memory.program_end.0 => String::from("[stack]"),
+            None if start >= memory.program_end.0 && end <= memory.current_end.0 => String::from("[heap]"),
+            None => String::new(),
+        };
+        writeln!(maps, "{:08x}-{:08x} {} {:08x} 00:00 0          {}",
+            start, end, perm, area.offset, name).unwrap();
+    }
+    maps
+}
@@ -0,0 +1,168 @@
+use alloc::{sync::Arc, vec::Vec};
+use core::mem::size_of;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use super::{File, FileIndex, FileStat, FileType, SeekMode};
+use super::file::PollType;
+use crate::proc::{get_current_task, wait_on, WaitQueue, SIGKILL, SIGSTOP};
+use crate::utils::Error;
+
+pub const SFD_NONBLOCK: usize = 0o4000;
+pub const SFD_CLOEXEC: usize = 0o2000000;
+
+/* read返回的结构, 固定为128字节 */
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct SignalfdSiginfo {
+    pub ssi_signo: u32,
+    pub ssi_errno: i32,
+    pub ssi_code: i32,
+    pub ssi_pid: u32,
+    pub ssi_uid: u32,
+    pub ssi_fd: i32,
+    pub ssi_tid: u32,
+    pub ssi_band: u32,
+    pub ssi_overrun: u32,
+    pub ssi_trapno: u32,
+    pub ssi_status: i32,
+    pub ssi_int: i32,
+    pub ssi_ptr: u64,
+    pub ssi_utime: u64,
+    pub ssi_stime: u64,
+    pub ssi_addr: u64,
+    pub ssi_addr_lsb: u16,
+    __pad2: u16,
+    pub ssi_syscall: i32,
+    pub ssi_call_addr: u64,
+    pub ssi_arch: u32,
+    __pad: [u8; 28],
+}
+
+impl SignalfdSiginfo {
+    fn new(signo: usize, code: i32, addr: usize) -> Self {
+        let mut info: Self = unsafe { core::mem::zeroed() };
+        info.ssi_signo = signo as u32;
+        info.ssi_code = code;
+        info.ssi_addr = addr as u64;
+        info
+    }
+
+    fn as_bytes(&self) -> &[u8] {
+        unsafe { core::slice::from_raw_parts(self as *const Self as *const u8, size_of::<Self>()) }
+    }
+}
+
+/// signalfd: 读取调用者待处理的信号, 被读走的信号不再执行处理函数
+/// 通常和sigprocmask一起使用, 先屏蔽信号, 再从signalfd中读取
+/// 信号到达时不会唤醒文件的等待队列, ppoll和epoll的等待者由wake_signal_waiters唤醒后重新poll
+pub struct SignalFd {
+    mask: AtomicUsize,
+    nonblock: AtomicBool,
+    queue: WaitQueue,
+}
+
+impl SignalFd {
+    pub fn new(mask: usize, flags: usize) -> Arc<Self> {
+        let signalfd = Arc::new(Self {
+            mask: AtomicUsize::new(0),
+            nonblock: AtomicBool::new(flags & SFD_NONBLOCK != 0),
+            queue: WaitQueue::new(),
+        });
+        signalfd.set_mask(mask);
+        signalfd
+    }
+
+    /* SIGKILL和SIGSTOP不能通过signalfd读取 */
+    pub fn set_mask(&self, mask: usize) {
+        let mask = mask & !(1 << (SIGKILL - 1)) & !(1 << (SIGSTOP - 1));
+        self.mask.store(mask, Ordering::Release);
+    }
+}
+
+impl File for SignalFd {
+    fn close(&self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn get_index(&self) -> Result<FileIndex, Error> {
+        Err(Error::EINDEX)
+    }
+
+    /* 一次读出尽可能多的信号, 至少读出一个 */
+    fn read(&self, len: usize) -> Result<Vec<u8>, Error> {
+        let count = len / size_of::<SignalfdSiginfo>();
+        if count == 0 {
+            return Err(Error::EINVAL);
+        }
+        let task = get_current_task().unwrap();
+        let mask = self.mask.load(Ordering::Acquire);
+        let take = || task.dequeue_signal(mask).map(|signal| Ok(signal));
+        let first = match self.nonblock.load(Ordering::Relaxed) {
+            true => take().unwrap_or(Err(Error::EAGAIN)),
+            false => wait_on(&self.queue, take),
+        }?;
+
+        let mut data = Vec::new();
+        let mut next = Some(first);
+        while let Some((signo, info)) = next {
+            data.extend_from_slice(SignalfdSiginfo::new(signo, info.code(), info.addr()).as_bytes());
+            next = match data.len() / size_of::<SignalfdSiginfo>() < count {
+                true => task.dequeue_signal(mask),
+                false => None,
+            };
+        }
+        Ok(data)
+    }
+
+    fn write(&self, _data: Vec<u8>) -> Result<usize, Error> {
+        Err(Error::EINVAL)
+    }
+
+    fn readable(&self) -> bool {
+        true
+    }
+
+    fn writable(&self) -> bool {
+        false
+    }
+
+    fn seek(&self, _pos: usize, _mode: SeekMode) -> Result<isize, Error> {
+        Err(Error::ESPIPE)
+    }
+
+    fn get_size(&self) -> Result<usize, Error> {
+        Ok(0)
+    }
+
+    fn get_type(&self) -> Result<FileType, Error> {
+        Ok(FileType::UNKNOWN)
+    }
+
+    fn read_stat(&self) -> Result<FileStat, Error> {
+        let mut fstat: FileStat = Default::default();
+        fstat.st_nlink = 1;
+        fstat.st_mode = 0o600;
+        Ok(fstat)
+    }
+
+    /* 检查的是调用poll的任务的待处理信号 */
+    fn poll(&self, ptype: PollType) -> Result<bool, Error> {
+        let ret = match ptype {
+            PollType::READ => get_current_task().map_or(false, |task| task.has_signal_in(self.mask.load(Ordering::Acquire))),
+            PollType::WRITE | PollType::ERR | PollType::HUP => false,
+        };
+        Ok(ret)
+    }
+
+    fn as_signalfd(self: Arc<Self>) -> Result<Arc<SignalFd>, Error> {
+        Ok(self)
+    }
+
+    fn as_file<'a>(self: Arc<Self>) -> Arc<dyn File + 'a> where Self: 'a {
+        self
+    }
+
+    fn as_any<'a>(self: Arc<Self>) -> Arc<dyn core::any::Any + Send + Sync + 'a> where Self: 'a {
+        self
+    }
+}
@@ -0,0 +1,212 @@
+use alloc::{sync::{Arc, Weak}, vec::Vec};
+use core::sync::atomic::{AtomicBool, Ordering};
+use spin::{Mutex, MutexGuard};
+
+use super::{File, FileIndex, FileStat, FileType, SeekMode};
+use super::file::PollType;
+use crate::proc::{add_clock_waker, wait_on, WaitQueue, Waker};
+use crate::syscall::time::{Itimerspec, Timespec};
+use crate::timer::get_time;
+use crate::utils::Error;
+
+pub const TFD_NONBLOCK: usize = 0o4000;
+pub const TFD_CLOEXEC: usize = 0o2000000;
+pub const TFD_TIMER_ABSTIME: usize = 1;
+
+/* 内核中所有时钟都从启动时开始计时, 不区分REALTIME和MONOTONIC */
+pub const CLOCK_REALTIME: usize = 0;
+pub const CLOCK_MONOTONIC: usize = 1;
+pub const CLOCK_BOOTTIME: usize = 7;
+
+/* 以时钟周期计时, 避免Timespec的进位计算 */
+struct TimerState {
+    deadline: Option<usize>,
+    interval: usize,
+    /* 上次read之后到期的次数 */
+    expirations: u64,
+}
+
+impl TimerState {
+    /* 根据当前时间累计到期次数, 周期定时器移到下一个到期时间, 返回是否有新的到期 */
+    fn update(&mut self, now: usize) -> bool {
+        let deadline = match self.deadline {
+            Some(deadline) if deadline <= now => deadline,
+            _ => return false,
+        };
+        if self.interval == 0 {
+            self.expirations += 1;
+            self.deadline = None;
+        } else {
+            let times = (now - deadline) / self.interval + 1;
+            self.expirations += times as u64;
+            self.deadline = Some(deadline + times * self.interval);
+        }
+        true
+    }
+}
+
+/// timerfd: 到期时可读, read返回上次读之后到期的次数
+/// 到期时间注册在CLOCK_WAKER中, 到期后唤醒等待者, 周期定时器重新注册下一次到期时间
+pub struct TimerFd {
+    me: Weak<TimerFd>,
+    state: Mutex<TimerState>,
+    nonblock: AtomicBool,
+    queue: WaitQueue,
+}
+
+impl TimerFd {
+    pub fn new(flags: usize) -> Arc<Self> {
+        Arc::new_cyclic(|me| Self {
+            me: me.clone(),
+            state: Mutex::new(TimerState { deadline: None, interval: 0, expirations: 0 }),
+            nonblock: AtomicBool::new(flags & TFD_NONBLOCK != 0),
+            queue: WaitQueue::new(),
+        })
+    }
+
+    fn arm(&self, deadline: usize) {
+        let waker: Weak<dyn Waker> = self.me.clone();
+        add_clock_waker(waker, Timespec::from_tick(deadline));
+    }
+
+    /* 累计到期次数, 有新的到期时注册下一次到期时间并唤醒等待者 */
+    fn refresh(&self) -> MutexGuard<TimerState> {
+        let mut state = self.state.lock();
+        if state.update(get_time()) {
+            if let Some(deadline) = state.deadline {
+                self.arm(deadline);
+            }
+            self.queue.wake_all();
+        }
+        state
+    }
+
+    /* 当前设置, it_value为距离下次到期的时间 */
+    pub fn gettime(&self) -> Itimerspec {
+        let now = get_time();
+        let state = self.refresh();
+        Itimerspec {
+            it_interval: Timespec::from_tick(state.interval),
+            it_value: state.deadline.map_or(Timespec::ZERO, |deadline| Timespec::from_tick(deadline.saturating_sub(now))),
+        }
+    }
+
+    /* 设置新的到期时间并返回原来的设置, it_value为0时停止定时器, 未读的到期次数被清除 */
+    pub fn settime(&self, flags: usize, new: Itimerspec) -> Itimerspec {
+        let old = self.gettime();
+        let now = get_time();
+        let mut state = self.state.lock();
+        state.expirations = 0;
+        state.interval = new.it_interval.to_tick();
+        state.deadline = match new.it_value == Timespec::ZERO {
+            true => None,
+            false if flags & TFD_TIMER_ABSTIME != 0 => Some(new.it_value.to_tick()),
+            false => Some(now + new.it_value.to_tick()),
+        };
+        let deadline = state.deadline;
+        drop(state);
+        if let Some(deadline) = deadline {
+            self.arm(deadline);
+        }
+        old
+    }
+}
+
+impl Waker for TimerFd {
+    /* 由CLOCK_WAKER调用, 可能是已经被settime取消或者被poll处理过的旧到期时间, 此时没有新的到期 */
+    fn wake(&self) {
+        self.refresh();
+    }
+}
+
+impl File for TimerFd {
+    fn close(&self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn get_index(&self) -> Result<FileIndex, Error> {
+        Err(Error::EINDEX)
+    }
+
+    fn read(&self, len: usize) -> Result<Vec<u8>, Error> {
+        if len < 8 {
+            return Err(Error::EINVAL);
+        }
+        let take = || {
+            let mut state = self.refresh();
+            match state.expirations {
+                0 => None,
+                expirations => {
+                    state.expirations = 0;
+                    Some(Ok(expirations))
+                }
+            }
+        };
+        let expirations = match self.nonblock.load(Ordering::Relaxed) {
+            true => take().unwrap_or(Err(Error::EAGAIN)),
+            false => wait_on(&self.queue, take),
+        }?;
+        Ok(expirations.to_ne_bytes().to_vec())
+    }
+
+    fn write(&self, _data: Vec<u8>) -> Result<usize, Error> {
+        Err(Error::EINVAL)
+    }
+
+    fn readable(&self) -> bool {
+        true
+    }
+
+    fn writable(&self) -> bool {
+        false
+    }
+
+    fn seek(&self, _pos: usize, _mode: SeekMode) -> Result<isize, Error> {
+        Err(Error::ESPIPE)
+    }
+
+    fn get_size(&self) -> Result<usize, Error> {
+        Ok(0)
+    }
+
+    fn get_type(&self) -> Result<FileType, Error> {
+        Ok(FileType::UNKNOWN)
+    }
+
+    fn read_stat(&self) -> Result<FileStat, Error> {
+        let mut fstat: FileStat = Default::default();
+        fstat.st_nlink = 1;
+        fstat.st_mode = 0o600;
+        Ok(fstat)
+    }
+
+    fn poll(&self, ptype: PollType) -> Result<bool, Error> {
+        let state = self.refresh();
+        let ret = match ptype {
+            PollType::READ => state.expirations > 0,
+            PollType::WRITE | PollType::ERR | PollType::HUP => false,
+        };
+        Ok(ret)
+    }
+
+    fn register_waker(&self, waker: &Arc<dyn Waker>) -> bool {
+        self.queue.register(waker);
+        true
+    }
+
+    fn unregister_waker(&self, waker: &Arc<dyn Waker>) {
+        self.queue.unregister(waker);
+    }
+
+    fn as_timerfd(self: Arc<Self>) -> Result<Arc<TimerFd>, Error> {
+        Ok(self)
+    }
+
+    fn as_file<'a>(self: Arc<Self>) -> Arc<dyn File + 'a> where Self: 'a {
+        self
+    }
+
+    fn as_any<'a>(self: Arc<Self>) -> Arc<dyn core::any::Any + Send + Sync + 'a> where Self: 'a {
+        self
+    }
+}
@@ -0,0 +1,355 @@
+use alloc::{string::String, sync::Arc, vec::Vec};
+use core::any::Any;
+use spin::Mutex;
+use crate::fs::fifo::Pipe;
+use crate::syscall::time::Timespec;
+use super::*;
+
+/// 打开的tmpfs文件, 多个TmpFile可以指向同一个inode
+pub struct TmpFile {
+    fs: Arc<TmpFS>,
+    pub inode: Arc<TmpInode>,
+    path: Path,
+    mode: FileOpenMode,
+    cursor: Mutex<usize>,
+}
+
+impl TmpFile {
+    pub fn new(fs: Arc<TmpFS>, inode: Arc<TmpInode>, mode: FileOpenMode, path: Path) -> Arc<Self> {
+        Arc::new(Self {
+            fs,
+            inode,
+            path,
+            mode,
+            cursor: Mutex::new(0),
+        })
+    }
+
+    /* 打开有名管道, 返回与其它打开者共享缓冲区的管道端 */
+    fn open_fifo(&self, inode: &Arc<TmpInode>, mode: FileOpenMode) -> Result<Arc<dyn File>, Error> {
+        let inner = inode.read();
+        let buffer = match &inner.data {
+            TmpData::Fifo(buffer) => buffer.clone(),
+            _ => return Err(Error::EINVAL),
+        };
+        drop(inner);
+        if mode.contains(FileOpenMode::RDWR) {
+            let pipe = Arc::new(Pipe::set_read_write(buffer.clone()));
+            let mut ring = buffer.lock();
+            ring.record_write_end(&pipe);
+            ring.record_read_end(&pipe);
+            drop(ring);
+            Ok(pipe)
+        } else if mode.contains(FileOpenMode::WRITE) {
+            let pipe = Arc::new(Pipe::set_wirte(buffer.clone()));
+            buffer.lock().record_write_end(&pipe);
+            Ok(pipe)
+        } else {
+            let pipe = Arc::new(Pipe::set_read(buffer.clone()));
+            buffer.lock().record_read_end(&pipe);
+            Ok(pipe)
+        }
+    }
+}
+
+impl File for TmpFile {
+    fn get_index(&self) -> Result<FileIndex, Error> {
+        Ok(FileIndex(self.fs.id, Fileid(self.inode.ino)))
+    }
+
+    fn read(&self, len: usize) -> Result<Vec<u8>, Error> {
+        if !self.readable() {
+            return Err(Error::EBADF);
+        }
+        let mut inner = self.inode.write();
+        let mut cursor = self.cursor.lock();
+        let data = match &inner.data {
+            TmpData::Regular(data) => data,
+            TmpData::Dir(_) => return Err(Error::EISDIR),
+            _ => return Err(Error::EINVAL),
+        };
+        let start = (*cursor).min(data.len());
+        let end = (start + len).min(data.len());
+        let buf = data[start..end].to_vec();
+        *cursor = end;
+        inner.atime = Timespec::now();
+        Ok(buf)
+    }
+
+    fn write(&self, buf: Vec<u8>) -> Result<usize, Error> {
+        if !self.writable() {
+            return Err(Error::EBADF);
+        }
+        let mut inner = self.inode.write();
+        let mut cursor = self.cursor.lock();
+        let data = match &mut inner.data {
+            TmpData::Regular(data) => data,
+            TmpData::Dir(_) => return Err(Error::EISDIR),
+            _ => return Err(Error::EINVAL),
+        };
+        if self.mode.contains(FileOpenMode::APPEND) {
+            *cursor = data.len();
+        }
+        /* 在文件末尾之后写入时, 中间的空洞填0 */
+        let end = cursor.checked_add(buf.len()).ok_or(Error::EFBIG)?;
+        if end > data.len() {
+            self.inode.resize(data, end)?;
+        }
+        data[*cursor..end].copy_from_slice(&buf);
+        *cursor = end;
+        inner.touch_mtime();
+        Ok(buf.len())
+    }
+
+    fn readable(&self) -> bool {
+        !self.mode.contains(FileOpenMode::WRITE)
+    }
+
+    fn writable(&self) -> bool {
+        self.mode.contains(FileOpenMode::WRITE) || self.mode.contains(FileOpenMode::RDWR)
+        || self.mode.contains(FileOpenMode::SYS)
+    }
+
+    fn seek(&self, pos: usize, mode: SeekMode) -> Result<isize, Error> {
+        let size = self.inode.read().size();
+        let mut cursor = self.cursor.lock();
+        let new = match mode {
+            SeekMode::SET => pos as isize,
+            SeekMode::CUR => *cursor as isize + pos as isize,
+            SeekMode::END => size as isize + pos as isize,
+        };
+        if new < 0 {
+            return Err(Error::EINVAL);
+        }
+        *cursor = new as usize;
+        Ok(new)
+    }
+
+    fn get_size(&self) -> Result<usize, Error> {
+        Ok(self.inode.read().size())
+    }
+
+    fn get_name(&self) -> Result<String, Error> {
+        match self.path.is_root() {
+            true => Ok(String::from("/")),
+            false => Ok(self.path.last().clone()),
+        }
+    }
+
+    fn get_path(&self) -> Result<Path, Error> {
+        Ok(self.path.clone())
+    }
+
+    fn get_type(&self) -> Result<FileType, Error> {
+        Ok(self.inode.read().file_type())
+    }
+
+    fn read_stat(&self) -> Result<FileStat, Error> {
+        Ok(self.inode.read().stat(self.fs.id.0 as u64, self.inode.ino as u64))
+    }
+
+    /* chmod, chown和utimensat都通过write_stat修改inode */
+    fn write_stat(&self, stat: &FileStat) -> Result<(), Error> {
+        let mut inner = self.inode.write();
+        inner.perm = stat.st_mode & 0o7777;
+        inner.uid = stat.st_uid;
+        inner.gid = stat.st_gid;
+        inner.atime = Timespec { tv_sec: stat.st_atime_sec as _, tv_nsec: stat.st_atime_nsec as _ };
+        inner.mtime = Timespec { tv_sec: stat.st_mtime_sec as _, tv_nsec: stat.st_mtime_nsec as _ };
+        inner.touch_ctime();
+        Ok(())
+    }
+
+    fn poll(&self, ptype: PollType) -> Result<bool, Error> {
+        match ptype {
+            PollType::READ => Ok(self.readable()),
+            PollType::WRITE => Ok(self.writable()),
+            PollType::ERR | PollType::HUP => Ok(false),
+        }
+    }
+
+    fn copy(&self) -> Arc<dyn File> {
+        let file = TmpFile::new(self.fs.clone(), self.inode.clone(), self.mode, self.path.clone());
+        *file.cursor.lock() = *self.cursor.lock();
+        file
+    }
+
+    fn vfs(&self) -> Arc<dyn VFS> {
+        self.fs.clone()
+    }
+
+    fn as_dir<'a>(self: Arc<Self>) -> Result<Arc<dyn DirFile + 'a>, Error> where Self: 'a {
+        match self.inode.read().file_type() {
+            FileType::Directory => Ok(self.clone()),
+            _ => Err(Error::ENOTDIR),
+        }
+    }
+
+    fn as_link<'a>(self: Arc<Self>) -> Result<Arc<dyn LinkFile + 'a>, Error> where Self: 'a {
+        match self.inode.read().file_type() {
+            FileType::LinkFile => Ok(self.clone()),
+            _ => Err(Error::EPERM),
+        }
+    }
+
+    fn as_file<'a>(self: Arc<Self>) -> Arc<dyn File + 'a> where Self: 'a {
+        self
+    }
+
+    fn as_any<'a>(self: Arc<Self>) -> Arc<dyn Any + Send + Sync + 'a> where Self: 'a {
+        self
+    }
+}
+
+impl DirFile for TmpFile {
+    fn openat(&self, name: String, mode: FileOpenMode) -> Result<Arc<dyn File>, Error> {
+        let inner = self.inode.read();
+        let inode = inner.dir()?.get(&name).cloned().ok_or(Error::ENOENT)?;
+        drop(inner);
+
+        let mut inner = inode.write();
+        match &mut inner.data {
+            /* 内核以SYS模式打开(stat, access等)时不打开管道, 只返回inode本身 */
+            TmpData::Fifo(_) if !mode.contains(FileOpenMode::SYS) => {
+                drop(inner);
+                return self.open_fifo(&inode, mode);
+            }
+            TmpData::Regular(data) if mode.contains(FileOpenMode::TRUNC) => {
+                inode.resize(data, 0)?;
+                inner.touch_mtime();
+            }
+            _ => {}
+        }
+        drop(inner);
+
+        let mut path = self.path.clone();
+        path.push_back(name);
+        Ok(TmpFile::new(self.fs.clone(), inode, mode, path))
+    }
+
+    fn mknod(&self, name: String, perm: FilePerm, file_type: FileType) -> Result<Arc<dyn File>, Error> {
+        let mut inner = self.inode.write();
+        let entries = inner.dir_mut()?;
+        if entries.contains_key(&name) {
+            return Err(Error::EEXIST);
+        }
+        let inode = TmpInode::new(self.fs.alloc_ino(), file_type, perm.bits(), self.fs.usage.clone(), self.fs.inodes.clone())?;
+        entries.insert(name.clone(), inode.clone());
+        /* 子目录的".."指向父目录 */
+        if file_type == FileType::Directory {
+            inner.nlink += 1;
+        }
+        inner.touch_mtime();
+        drop(inner);
+
+        trace!("tmpfs_mknod: {:?}/{}, ino = {}", self.path, name, inode.ino);
+        let mut path = self.path.clone();
+        path.push_back(name);
+        Ok(TmpFile::new(self.fs.clone(), inode, self.mode, path))
+    }
+
+    fn delete(&self, name: String) -> Result<(), Error> {
+        let mut inner = self.inode.write();
+        let entries = inner.dir_mut()?;
+        let inode = entries.get(&name).cloned().ok_or(Error::ENOENT)?;
+
+        let mut child = inode.write();
+        let is_dir = match child.dir() {
+            Ok(child_entries) if !child_entries.is_empty() => return Err(Error::ENOTEMPTY),
+            Ok(_) => true,
+            Err(_) => false,
+        };
+        entries.remove(&name);
+        /* inode在最后一个打开的文件关闭后才会被释放 */
+        if is_dir {
+            child.nlink = 0;
+            inner.nlink -= 1;
+        } else {
+            child.nlink -= 1;
+        }
+        child.touch_ctime();
+        inner.touch_mtime();
+        Ok(())
+    }
+
+    fn getdent(&self) -> Result<Vec<Dentry>, Error> {
+        let mut cursor = self.cursor.lock();
+        if *cursor != 0 {
+            return Ok(Vec::new())
+        }
+
+        let inner = self.inode.read();
+        let dentrys: Vec<Dentry> = inner
+            .dir()?
+            .iter()
+            .map(|(name, inode)| Dentry {
+                d_ino: inode.ino,
+                d_type: inode.read().file_type(),
+                d_name: name.clone(),
+            })
+            .collect();
+
+        *cursor = dentrys.len().max(1);
+        Ok(dentrys)
+    }
+
+    /* 目标存在时被替换, 与linux相同, 不能用目录替换非空目录 */
+    fn rename(&self, old: String, new: String) -> Result<(), Error> {
+        if old == new {
+            return Ok(());
+        }
+        let mut inner = self.inode.write();
+        let entries = inner.dir_mut()?;
+        let inode = entries.get(&old).cloned().ok_or(Error::ENOENT)?;
+        let is_dir = inode.read().file_type() == FileType::Directory;
+
+        let mut removed_dir = false;
+        if let Some(target) = entries.get(&new) {
+            let mut target = target.write();
+            match (is_dir, target.dir()) {
+                (true, Ok(target_entries)) if !target_entries.is_empty() => return Err(Error::ENOTEMPTY),
+                (true, Ok(_)) => {
+                    target.nlink = 0;
+                    removed_dir = true;
+                }
+                (true, Err(_)) => return Err(Error::ENOTDIR),
+                (false, Ok(_)) => return Err(Error::EISDIR),
+                (false, Err(_)) => target.nlink -= 1,
+            }
+            target.touch_ctime();
+        }
+
+        entries.remove(&old);
+        entries.insert(new, inode.clone());
+        if removed_dir {
+            inner.nlink -= 1;
+        }
+        inner.touch_mtime();
+        drop(inner);
+        inode.write().touch_ctime();
+        Ok(())
+    }
+}
+
+impl LinkFile for TmpFile {
+    fn read_link(&self) -> Result<Path, Error> {
+        resolve_link(&self.path, self.link_name()?.as_str())
+    }
+
+    fn write_link(&self, target: &str) -> Result<(), Error> {
+        let mut inner = self.inode.write();
+        match &mut inner.data {
+            TmpData::Link(link) => *link = String::from(target),
+            _ => return Err(Error::EINVAL),
+        }
+        inner.touch_mtime();
+        Ok(())
+    }
+
+    fn link_name(&self) -> Result<String, Error> {
+        match &self.inode.read().data {
+            TmpData::Link(target) => Ok(target.clone()),
+            _ => Err(Error::EINVAL),
+        }
+    }
+}
@@ -0,0 +1,201 @@
+use alloc::{collections::BTreeMap, string::String, sync::Arc, vec::Vec};
+use core::sync::atomic::{AtomicUsize, Ordering};
+use spin::{Mutex, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use crate::config::{TMPFS_MAX_FILE_SIZE, TMPFS_MAX_INODES, TMPFS_SIZE};
+use crate::fs::fifo::PipeRingBuffer;
+use crate::fs::{FileStat, FileType, StMode};
+use crate::syscall::time::Timespec;
+use crate::utils::Error;
+
+/// inode中保存的文件内容, 由文件类型决定
+pub enum TmpData {
+    Regular(Vec<u8>),
+    /* 目录中的每一项都指向一个inode, 硬连接就是多个目录项指向同一个inode */
+    Dir(BTreeMap<String, Arc<TmpInode>>),
+    /* 软连接的目标, 与symlink传入的内容相同 */
+    Link(String),
+    /* 有名管道, 所有打开该inode的进程共享同一个缓冲区 */
+    Fifo(Arc<Mutex<PipeRingBuffer>>),
+}
+
+pub struct TmpInodeInner {
+    pub data: TmpData,
+    pub perm: u32,
+    pub uid: u32,
+    pub gid: u32,
+    pub nlink: u32,
+    pub atime: Timespec,
+    pub mtime: Timespec,
+    pub ctime: Timespec,
+}
+
+/// tmpfs中的inode, 文件内容全部保存在内存中
+/// 打开的文件持有inode的引用, 所以文件被删除后, 已经打开的文件仍然可以读写
+pub struct TmpInode {
+    pub ino: usize,
+    inner: RwLock<TmpInodeInner>,
+    /* 所在文件系统已使用的字节数, inode释放时归还 */
+    usage: Arc<AtomicUsize>,
+    /* 所在文件系统中存在的inode数量, inode释放时减一 */
+    inodes: Arc<AtomicUsize>,
+}
+
+impl TmpInode {
+    /* inode数量达到TMPFS_MAX_INODES时返回ENOSPC */
+    pub fn new(
+        ino: usize,
+        file_type: FileType,
+        perm: u32,
+        usage: Arc<AtomicUsize>,
+        inodes: Arc<AtomicUsize>,
+    ) -> Result<Arc<Self>, Error> {
+        let (data, nlink) = match file_type {
+            FileType::RegularFile => (TmpData::Regular(Vec::new()), 1),
+            /* 目录的"."也算一个硬连接 */
+            FileType::Directory => (TmpData::Dir(BTreeMap::new()), 2),
+            FileType::LinkFile => (TmpData::Link(String::new()), 1),
+            FileType::FIFOFile => (TmpData::Fifo(Arc::new(Mutex::new(PipeRingBuffer::new()))), 1),
+            _ => return Err(Error::EINVAL),
+        };
+        inodes
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |count| {
+                Some(count + 1).filter(|&count| count <= TMPFS_MAX_INODES)
+            })
+            .map_err(|_| Error::ENOSPC)?;
+        let now = Timespec::now();
+        Ok(Arc::new(Self {
+            ino,
+            inner: RwLock::new(TmpInodeInner {
+                data,
+                perm: perm & 0o7777,
+                uid: 0,
+                gid: 0,
+                nlink,
+                atime: now,
+                mtime: now,
+                ctime: now,
+            }),
+            usage,
+            inodes,
+        }))
+    }
+
+    /* 调整普通文件内容的长度, 超过单个文件的限制返回EFBIG, 超过文件系统的容量返回ENOSPC */
+    pub fn resize(&self, data: &mut Vec<u8>, size: usize) -> Result<(), Error> {
+        if size > TMPFS_MAX_FILE_SIZE {
+            return Err(Error::EFBIG);
+        }
+        if size > data.len() {
+            let grow = size - data.len();
+            self.usage
+                .fetch_update(Ordering::AcqRel, Ordering::Acquire, |used| {
+                    used.checked_add(grow).filter(|&used| used <= TMPFS_SIZE)
+                })
+                .map_err(|_| Error::ENOSPC)?;
+            /* 只申请需要的空间, 避免Vec成倍扩容占用额外的内核堆 */
+            if data.try_reserve_exact(grow).is_err() {
+                self.usage.fetch_sub(grow, Ordering::AcqRel);
+                return Err(Error::ENOSPC);
+            }
+            data.resize(size, 0);
+        } else {
+            self.usage.fetch_sub(data.len() - size, Ordering::AcqRel);
+            data.truncate(size);
+            data.shrink_to_fit();
+        }
+        Ok(())
+    }
+
+    pub fn read(&self) -> RwLockReadGuard<TmpInodeInner> {
+        self.inner.read()
+    }
+
+    pub fn write(&self) -> RwLockWriteGuard<TmpInodeInner> {
+        self.inner.write()
+    }
+}
+
+impl Drop for TmpInode {
+    fn drop(&mut self) {
+        if let TmpData::Regular(data) = &self.inner.get_mut().data {
+            self.usage.fetch_sub(data.len(), Ordering::AcqRel);
+        }
+        self.inodes.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+impl TmpInodeInner {
+    pub fn file_type(&self) -> FileType {
+        match self.data {
+            TmpData::Regular(_) => FileType::RegularFile,
+            TmpData::Dir(_) => FileType::Directory,
+            TmpData::Link(_) => FileType::LinkFile,
+            TmpData::Fifo(_) => FileType::FIFOFile,
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        match &self.data {
+            TmpData::Regular(data) => data.len(),
+            TmpData::Dir(entries) => entries.len(),
+            TmpData::Link(target) => target.len(),
+            TmpData::Fifo(_) => 0,
+        }
+    }
+
+    pub fn dir(&self) -> Result<&BTreeMap<String, Arc<TmpInode>>, Error> {
+        match &self.data {
+            TmpData::Dir(entries) => Ok(entries),
+            _ => Err(Error::ENOTDIR),
+        }
+    }
+
+    pub fn dir_mut(&mut self) -> Result<&mut BTreeMap<String, Arc<TmpInode>>, Error> {
+        match &mut self.data {
+            TmpData::Dir(entries) => Ok(entries),
+            _ => Err(Error::ENOTDIR),
+        }
+    }
+
+    /* 修改inode的元数据时更新ctime */
+    pub fn touch_ctime(&mut self) {
+        self.ctime = Timespec::now();
+    }
+
+    /* 修改文件内容时同时更新mtime和ctime */
+    pub fn touch_mtime(&mut self) {
+        self.mtime = Timespec::now();
+        self.ctime = self.mtime;
+    }
+
+    pub fn stat(&self, dev: u64, ino: u64) -> FileStat {
+        let st_mode = match self.data {
+            TmpData::Regular(_) => StMode::REG,
+            TmpData::Dir(_) => StMode::DIR,
+            TmpData::Link(_) => StMode::LNK,
+            TmpData::Fifo(_) => StMode::IFO,
+        };
+        let size = self.size() as u64;
+        FileStat {
+            st_dev: dev,
+            st_ino: ino,
+            st_mode: st_mode as u32 | self.perm,
+            st_nlink: self.nlink,
+            st_uid: self.uid,
+            st_gid: self.gid,
+            st_size: size,
+            st_blksize: 4096,
+            st_blocks: (size + 511) / 512,
+            st_atime_sec: self.atime.tv_sec as _,
+            st_atime_nsec: self.atime.tv_nsec as _,
+            st_mtime_sec: self.mtime.tv_sec as _,
+            st_mtime_nsec: self.mtime.tv_nsec as _,
+            st_ctime_sec: self.ctime.tv_sec as _,
+            st_ctime_nsec: self.ctime.tv_nsec as _,
+            st_rdev: 0,
+            __pad1: 0,
+            __pad2: 0,
+            __pad3: 0,
+        }
+    }
+}
@@ -0,0 +1,129 @@
+mod inode;
+mod file;
+
+pub use inode::*;
+pub use file::*;
+use super::*;
+use crate::config::{PAGE_SIZE, TMPFS_MAX_INODES, TMPFS_SIZE};
+use crate::utils::{Error, Path};
+use alloc::sync::{Arc, Weak};
+use core::sync::atomic::{AtomicUsize, Ordering};
+use log::*;
+
+/// 内存文件系统, 挂载在/tmp和/dev/shm
+/// 文件内容保存在内核堆中, 卸载或关机后全部丢失
+pub struct TmpFS {
+    pub id: FSid,
+    pub mount_path: Path,
+    root: Arc<TmpInode>,
+    next_ino: AtomicUsize,
+    /* 普通文件内容占用的字节数, 不超过TMPFS_SIZE */
+    usage: Arc<AtomicUsize>,
+    /* 存在的inode数量, 不超过TMPFS_MAX_INODES */
+    inodes: Arc<AtomicUsize>,
+    /* 打开的文件需要持有文件系统的引用(用于分配inode号和硬连接) */
+    this: Weak<TmpFS>,
+}
+
+impl TmpFS {
+    pub fn init(id: FSid, path: Path) -> Arc<Self> {
+        /* 与linux相同, tmpfs的根目录默认带有粘滞位 */
+        let usage = Arc::new(AtomicUsize::new(0));
+        let inodes = Arc::new(AtomicUsize::new(0));
+        let root = TmpInode::new(1, FileType::Directory, 0o1777, usage.clone(), inodes.clone()).unwrap();
+        Arc::new_cyclic(|this| Self {
+            id,
+            mount_path: path,
+            root,
+            next_ino: AtomicUsize::new(2),
+            usage,
+            inodes,
+            this: this.clone(),
+        })
+    }
+
+    pub fn alloc_ino(&self) -> usize {
+        self.next_ino.fetch_add(1, Ordering::Relaxed)
+    }
+
+    fn get_self(&self) -> Arc<Self> {
+        self.this.upgrade().unwrap()
+    }
+
+    /* 在文件系统内部按路径查找inode, 不跟随软连接 */
+    fn lookup(&self, path: &Path) -> Result<Arc<TmpInode>, Error> {
+        let mut inode = self.root.clone();
+        for name in path.iter() {
+            let next = inode.read().dir()?.get(name).cloned().ok_or(Error::ENOENT)?;
+            inode = next;
+        }
+        Ok(inode)
+    }
+}
+
+impl VFS for TmpFS {
+    fn as_vfs<'a>(self: Arc<Self>) -> Arc<dyn VFS + 'a> where Self: 'a {
+        self
+    }
+
+    fn mount_path(&self) -> Path {
+        self.mount_path.clone()
+    }
+
+    fn root_dir(&self, mode: FileOpenMode) -> Result<Arc<dyn DirFile>, Error> {
+        Ok(TmpFile::new(self.get_self(), self.root.clone(), mode, self.mount_path.clone()).as_dir()?)
+    }
+
+    /* 硬连接: 在path的父目录中添加一项, 指向dst_file的inode */
+    fn link(&self, path: Path, dst_file: Arc<dyn File>) -> Result<Arc<dyn File>, Error> {
+        let dst_file = dst_file
+            .as_any()
+            .downcast::<TmpFile>()
+            .map_err(|_| Error::EXDEV)?;
+        let inode = dst_file.inode.clone();
+        if inode.read().file_type() == FileType::Directory {
+            return Err(Error::EPERM);
+        }
+
+        let name = path.last().clone();
+        let parent = self.lookup(&path.remove_tail())?;
+        let mut parent_inner = parent.write();
+        let entries = parent_inner.dir_mut()?;
+        if entries.contains_key(&name) {
+            return Err(Error::EEXIST);
+        }
+        entries.insert(name.clone(), inode.clone());
+        parent_inner.touch_mtime();
+        drop(parent_inner);
+
+        let mut inner = inode.write();
+        inner.nlink += 1;
+        inner.touch_ctime();
+        drop(inner);
+        trace!("tmpfs_link: link {:?} to inode {}", path, inode.ino);
+
+        let mut abs_path = self.mount_path.clone();
+        abs_path.extend(path.iter().cloned());
+        Ok(TmpFile::new(self.get_self(), inode, FileOpenMode::SYS, abs_path))
+    }
+
+    /* 容量为TMPFS_SIZE, 已用空间为所有普通文件内容的长度之和; inode数量最多为TMPFS_MAX_INODES */
+    fn statvfs(&self) -> Result<Statvfs, Error> {
+        let used = self.usage.load(Ordering::Acquire);
+        let free = TMPFS_SIZE.saturating_sub(used) / PAGE_SIZE;
+        let free_inodes = TMPFS_MAX_INODES.saturating_sub(self.inodes.load(Ordering::Acquire));
+        Ok(Statvfs {
+            bsize: PAGE_SIZE,
+            frsize: PAGE_SIZE,
+            blocks: TMPFS_SIZE / PAGE_SIZE,
+            bfree: free,
+            bavail: free,
+            files: TMPFS_MAX_INODES,
+            ffree: free_inodes,
+            favail: free_inodes,
+            namemax: 255,
+            fsid: self.id.0,
+            flag: 0,
+        })
+    }
+}
@@ -38,9 +38,9 @@ impl FSid {
 
 
 pub trait VFS: Send + Sync + Any {
+    /* 硬连接, path为相对于挂载点的路径; 不支持硬连接的文件系统返回EPERM */
     fn link(&self, _path: Path, _dst_path: Arc<dyn File>) -> Result<Arc<dyn File>, Error> {
-        warn!("no implement, something went wrong");
-        Err(Error::EACCES)
+        Err(Error::EPERM)
     }
     fn as_vfs<'a>(self: Arc<Self>) -> Arc<dyn VFS + 'a> where Self: 'a {
         panic!("no implement");
@@ -54,5 +54,9 @@ pub trait VFS: Send + Sync + Any {
     fn statvfs(&self) -> Result<Statvfs, Error> {
         panic!("no implement");
     }
+    /* 将文件系统的所有修改写回块设备, 不使用块设备的文件系统什么都不做 */
+    fn sync(&self) -> Result<(), Error> {
+        Ok(())
+    }
 }
 
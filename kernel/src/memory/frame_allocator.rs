@@ -102,6 +102,16 @@ pub fn get_available_frame_num() -> usize {
     FRAME_ALLOCATOR.lock().available_num()
 }
 
+/* 可供分配的物理页总数(内核镜像之后到MEMORY_END) */
+pub fn get_total_frame_num() -> usize {
+    extern "C" {
+        fn ekernel();
+    }
+    let start: usize = PhysAddr::from(ekernel as usize).ceil_page_num().into();
+    let end: usize = PhysAddr::from(MEMORY_END).floor_page_num().into();
+    end - start
+}
+
 #[allow(unused)]
 pub fn frame_allocator_test() {
     println!("1:{}", get_available_frame_num());
@@ -11,6 +11,12 @@ pub fn alloc_error_handler(layout: core::alloc::Layout) -> ! {
 
 static mut KERNEL_HEAP_SPACE:[u8; KERNEL_HEAP_SIZE] = [0; KERNEL_HEAP_SIZE];
 
+/* 返回(内核堆已分配的字节数, 内核堆的总字节数) */
+pub fn get_heap_usage() -> (usize, usize) {
+    let heap = HEAP_ALLOCATOR.lock();
+    (heap.stats_alloc_actual(), heap.stats_total_bytes())
+}
+
 pub fn init_heap_allocator() {
     unsafe {
         let mut lock = HEAP_ALLOCATOR.lock();
@@ -18,4 +24,4 @@ pub fn init_heap_allocator() {
             KERNEL_HEAP_SPACE.as_ptr() as usize, KERNEL_HEAP_SIZE
         );
     }
-}
\ No newline at end of file
+}
\ No newline at end of file
@@ -16,7 +16,8 @@ use super::*;
 use super::swap::SwapFrame;
 use crate::config::*;
 use crate::board::*;
-use crate::fs::{File, SeekMode};
+use crate::fs::{File, SeekMode, inotify};
+use crate::fs::page_cache::PageCache;
 use crate::memory::pagetable::PTEFlags;
 use crate::proc::get_tid;
 use crate::proc::{get_current_trap_context, get_current_task, trapframe_bottom, Aux};
@@ -164,14 +165,26 @@ impl MapArea {
         }
     }
 
+    /* 只有共享的文件映射需要写回, 私有映射的修改对文件不可见 */
     pub fn write_back(&self, pagetable: &mut PageTable) -> Result<(), Error>{
-        if self.file.is_none() {
+        if self.file.is_none() || !self.share {
             return Ok(());
         }
         let file = self.file.as_ref().unwrap();
         if !file.writable() {
             return Ok(());
         }
+        /* 可写的共享映射可能修改了文件, 在写回(msync, munmap)时通知监听者 */
+        if self.is_writable() {
+            inotify::notify_file(file, inotify::IN_MODIFY);
+        }
+        /* 映射的是页缓存中的页, 写回其中的脏页即可 */
+        if let Some(cache) = file.page_cache() {
+            if self.offset % PAGE_SIZE == 0 {
+                let start = self.offset / PAGE_SIZE;
+                return cache.sync_range(start, start + self.vpn_range.get_end().0 - self.vpn_range.get_start().0);
+            }
+        }
         for vpn in self.vpn_range {
             match pagetable.translate_vpn_to_pte(vpn) {
                 None => {},
@@ -330,6 +343,10 @@ pub struct MemorySet {
     pub program_end: VirtAddr,  
     pub current_end: VirtAddr, //当前进程的数据段结束地址
     pub free_memory_bottom: VirtAddr,
+    /* execve时记录的信息, 供/proc/<pid>使用 */
+    pub exe: Path,
+    pub cmdline: Vec<String>,
+    pub environ: Vec<String>,
 }
 
 impl MemorySet {
@@ -340,6 +357,9 @@ impl MemorySet {
             program_end: VirtAddr(0),
             current_end: VirtAddr(0),
             free_memory_bottom: VirtAddr(FREE_MEMORY_TOP),
+            exe: Path::from_str("/").unwrap(),
+            cmdline: Vec::new(),
+            environ: Vec::new(),
         }
     }
 
@@ -510,6 +530,24 @@ impl MemorySet {
         Ok(())
     }
 
+    /// 写回与[start, end)相交的共享文件映射, 用于msync
+    pub fn sync_area(&mut self, start: VirtAddr, end: VirtAddr) -> Result<(), Error> {
+        let start = start.floor_page_num();
+        let end = end.ceil_page_num();
+        let mut mapped = false;
+        for area in self.areas.iter() {
+            if area.get_end() <= start || area.get_start() >= end {
+                continue;
+            }
+            mapped = true;
+            area.write_back(&mut self.pagetable)?;
+        }
+        if !mapped {
+            return Err(Error::ENOMEM);
+        }
+        Ok(())
+    }
+
 
     pub fn push_user_stack(&mut self, user_stack_bottom: VirtAddr) -> VirtAddr {
         let mut user_stack_bottom:usize = user_stack_bottom.into();
@@ -784,6 +822,9 @@ impl MemorySet {
        memory_set.program_end = existed.program_end;
        memory_set.current_end = existed.current_end;
        memory_set.free_memory_bottom = existed.free_memory_bottom;
+       memory_set.exe = existed.exe.clone();
+       memory_set.cmdline = existed.cmdline.clone();
+       memory_set.environ = existed.environ.clone();
 
        memory_set.map_trampoline();
        memory_set.map_signal_handler();
@@ -800,7 +841,7 @@ impl MemorySet {
                 area.map_prot,
                 new_file,
                 area.offset,
-                false,
+                area.share,
             );
 
             memory_set.areas.push(new_area);
@@ -963,6 +1004,11 @@ impl MemorySet {
             && area.is_user()})
     }
 
+    /* 地址是否落在某个用户段中, 用于区分SEGV_MAPERR与SEGV_ACCERR */
+    pub fn is_mapped(&mut self, virtaddr: VirtAddr) -> bool {
+        Self::find_area(&mut self.areas, virtaddr.floor_page_num()).is_some()
+    }
+
     pub fn page_check(&mut self, virtaddr: VirtAddr, is_store: bool) -> Result<PhysPageNum, &'static str>{
         let vpn = virtaddr.floor_page_num();
         let pagetable = &mut self.pagetable;
@@ -986,6 +1032,9 @@ impl MemorySet {
                 return self.lazy_alloc(vpn, is_store);
                 
             } else if pte.if_cow() {
+                if area.share && area.file.is_some() {
+                    return Self::shared_write(area, pagetable, vpn);
+                }
                 return Self::copy_on_write(pagetable, vpn);  
             } 
 
@@ -1024,6 +1073,55 @@ impl MemorySet {
         return Ok(ppn);
     }
 
+    /* 共享文件映射的页第一次被写入, 不需要复制, 标记页缓存中的页为脏页 */
+    fn shared_write(
+        area: &MapArea,
+        pagetable: &mut PageTable,
+        vpn: VirtPageNum,
+    ) -> Result<PhysPageNum, &'static str> {
+        let offset = area.offset + (vpn.0 - area.get_start().0) * PAGE_SIZE;
+        if let Some(cache) = area.file.as_ref().unwrap().page_cache() {
+            if let Some(page) = cache.find_page(offset / PAGE_SIZE) {
+                page.mark_dirty();
+            }
+        }
+        let pte = pagetable.find_pte(vpn).unwrap();
+        let mut flag = pte.flags();
+        flag.remove(PTEFlags::C);
+        flag.insert(PTEFlags::W);
+        pte.set_flag(flag);
+        Ok(pte.ppn())
+    }
+
+    /* 将页缓存中的页映射到vpn
+     * 共享映射直接使用缓存中的页, 只读访问时设置COW位, 以便在第一次写入时标记脏页
+     * 私有映射只读访问时与页缓存共享, 写入时复制 */
+    fn map_cached_page(
+        area: &mut MapArea,
+        pagetable: &mut PageTable,
+        vpn: VirtPageNum,
+        cache: Arc<PageCache>,
+        index: usize,
+        is_store: bool,
+    ) -> Result<PhysPageNum, &'static str> {
+        let page = cache.get_page(index).or(Err("lazy_alloc: fail to read file"))?;
+        if is_store && !area.share {
+            let ppn = area.map_one(vpn, pagetable)
+                .or_else(|_| Err("run out of memory"))?;
+            ppn.get_byte_array().copy_from_slice(page.data());
+            return Ok(ppn);
+        }
+
+        pagetable.map(vpn, page.ppn(), area.map_prot);
+        pagetable.data_frames.insert(vpn, page.frame());
+        if is_store {
+            page.mark_dirty();
+        } else if area.is_writable() {
+            pagetable.find_pte(vpn).unwrap().set_cow();
+        }
+        Ok(page.ppn())
+    }
+
     fn lazy_alloc(
         &mut self,
         vpn: VirtPageNum,
@@ -1045,6 +1143,11 @@ impl MemorySet {
             Some(file) => {
                 let mut offset = area.offset + 
                     (vpn.0 - area.get_start().0) * PAGE_SIZE;
+                if offset % PAGE_SIZE == 0 {
+                    if let Some(cache) = file.page_cache() {
+                        return Self::map_cached_page(area, pagetable, vpn, cache, offset / PAGE_SIZE, is_store);
+                    }
+                }
                 file.seek(offset, SeekMode::SET).unwrap();
                 if let Ok(data) = file.read(PAGE_SIZE) {
                     let ppn = area.map_one(vpn, pagetable)
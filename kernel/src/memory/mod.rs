@@ -21,6 +21,10 @@ pub fn init_heap_allocator() {
     heap_allocator::init_heap_allocator();
 }
 
+pub fn get_heap_usage() -> (usize, usize) {
+    heap_allocator::get_heap_usage()
+}
+
 pub fn init_frame_allocator() {
     println!("[kernel] init FRAME ALLOCATOR, frame num = 0x{:x}", get_available_frame_num());
 }
@@ -11,6 +11,7 @@ use crate::config::*;
 use crate::console::print;
 use crate::proc::{get_current_task, exit_current, SwapList, get_tid};
 use crate::utils::Error;
+use crate::fs::page_cache::shrink_page_cache;
 
 //[63:54]   Reserved
 //[53:10]   PPN
@@ -233,7 +234,12 @@ impl PageTable {
     }
 
     pub fn alloc_map(&mut self, vpn: VirtPageNum, flags: MapProt) -> Option<PhysPageNum> {
-        if let Some(frame) = frame_safe_alloc() {
+        /* 物理页不足时先淘汰页缓存 */
+        let frame = frame_safe_alloc().or_else(|| {
+            shrink_page_cache(usize::MAX);
+            frame_safe_alloc()
+        });
+        if let Some(frame) = frame {
             let pte = self.find_pte_create(vpn).unwrap();
             *pte = PageTableEntry::new(frame.ppn, PTEFlags::from_map_prot(flags | MapProt::VALID));
             let ppn = frame.ppn;
@@ -345,11 +351,13 @@ impl PageTable {
 
     pub fn change_prot(&mut self, vpn: VirtPageNum, mut prot: MapProt) {
         /* 需要特别注意SWAP位和COW位 */
+        /* 与其它页表或页缓存共享的页不能直接变为可写 */
+        let shared = self.data_frames.get(&vpn).map_or(false, |frame| !frame.is_only_owner());
         if let Some(pte) = self.find_pte(vpn) {
             let flag = pte.flags();
             let mut new_flag = PTEFlags::from_map_prot(prot);
             
-            if flag.contains(PTEFlags::C) {
+            if flag.contains(PTEFlags::C) || (shared && flag.contains(PTEFlags::V)) {
                 if new_flag.contains(PTEFlags::W) {
                     new_flag.remove(PTEFlags::W);
                     new_flag.insert(PTEFlags::C);
@@ -365,7 +373,7 @@ impl PageTable {
                 new_flag.insert(PTEFlags::V);
             }
 
-            assert!(!(new_flag.contains(PTEFlags::C) && !new_flag.contains(PTEFlags::W)));
+            assert!(!(new_flag.contains(PTEFlags::C) && new_flag.contains(PTEFlags::W)));
             pte.set_flag(new_flag);
         }
     }
@@ -551,4 +559,4 @@ pub fn copyout_vec(token: usize, dst: *mut u8, mut data: Vec<u8>) -> Result<(),
         (slice).copy_from_slice(data.split_off(data.len() - slice.len()).as_slice())
     }
     Ok(())
-}
\ No newline at end of file
+}
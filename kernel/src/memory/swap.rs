@@ -14,11 +14,18 @@ use log::*;
 use super::{PhysPageNum, get_available_frame_num};
 type SwapAllocator = StackAllocator;
 
+/* 交换区可以容纳的页数 */
+pub const SWAP_FRAME_NUM: usize = 10000;
+
 pub static SWAP_ALLOCATOR: Lazy<Mutex<SwapAllocator>> = Lazy::new(||{
-    let end = 10000;
-    Mutex::new(SwapAllocator::new(0, end))
+    Mutex::new(SwapAllocator::new(0, SWAP_FRAME_NUM))
 });
 
+/* 交换区中空闲的页数 */
+pub fn get_free_swap_num() -> usize {
+    SWAP_ALLOCATOR.lock().available_num()
+}
+
 pub static SWAP_FILE: Lazy<Arc<dyn File>> = Lazy::new(||{
     open("/buf".into(), FileOpenMode::SYS).unwrap()
  });
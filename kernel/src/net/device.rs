@@ -0,0 +1,71 @@
+use alloc::{sync::Arc, vec, vec::Vec};
+use smoltcp::phy::{Device, DeviceCapabilities, Medium, RxToken, TxToken};
+use smoltcp::time::Instant;
+use crate::config::ETH_MTU;
+use crate::driver::net_device::NetDevice;
+
+/// 将网卡驱动包装成smoltcp的phy设备
+pub struct NetPhy {
+    device: Arc<dyn NetDevice>,
+}
+
+pub struct NetRxToken(Vec<u8>);
+pub struct NetTxToken(Arc<dyn NetDevice>);
+
+impl NetPhy {
+    pub fn new(device: Arc<dyn NetDevice>) -> Self {
+        Self { device }
+    }
+}
+
+impl<'a> Device<'a> for NetPhy {
+    type RxToken = NetRxToken;
+    type TxToken = NetTxToken;
+
+    fn receive(&'a mut self) -> Option<(Self::RxToken, Self::TxToken)> {
+        if !self.device.can_recv() {
+            return None;
+        }
+        let mut buf = vec![0u8; ETH_MTU];
+        let len = self.device.recv(&mut buf).ok()?;
+        buf.truncate(len);
+        Some((NetRxToken(buf), NetTxToken(self.device.clone())))
+    }
+
+    fn transmit(&'a mut self) -> Option<Self::TxToken> {
+        if self.device.can_send() {
+            Some(NetTxToken(self.device.clone()))
+        } else {
+            None
+        }
+    }
+
+    fn capabilities(&self) -> DeviceCapabilities {
+        let mut caps = DeviceCapabilities::default();
+        caps.max_transmission_unit = ETH_MTU;
+        caps.max_burst_size = Some(1);
+        caps.medium = Medium::Ethernet;
+        caps
+    }
+}
+
+impl RxToken for NetRxToken {
+    fn consume<R, F>(mut self, _timestamp: Instant, f: F) -> smoltcp::Result<R>
+    where
+        F: FnOnce(&mut [u8]) -> smoltcp::Result<R>
+    {
+        f(&mut self.0)
+    }
+}
+
+impl TxToken for NetTxToken {
+    fn consume<R, F>(self, _timestamp: Instant, len: usize, f: F) -> smoltcp::Result<R>
+    where
+        F: FnOnce(&mut [u8]) -> smoltcp::Result<R>
+    {
+        let mut buf = vec![0u8; len];
+        let ret = f(&mut buf)?;
+        self.0.send(&buf).map_err(|_| smoltcp::Error::Exhausted)?;
+        Ok(ret)
+    }
+}
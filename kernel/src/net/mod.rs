@@ -1,10 +1,395 @@
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::string::String;
+use alloc::sync::{Arc, Weak};
 use alloc::vec::Vec;
+use alloc::vec;
+use spin::Mutex;
+use spin::lazy::Lazy;
+use smoltcp::iface::{Context, Interface, InterfaceBuilder, NeighborCache, Routes, SocketHandle};
+use smoltcp::phy::{Loopback, Medium};
+use smoltcp::socket::{AnySocket, TcpSocket, TcpState};
+use smoltcp::time::Instant;
+use smoltcp::wire::{EthernetAddress, IpAddress, IpCidr, IpEndpoint, Ipv4Address};
+use crate::config::{ETH_IP_ADDR, ETH_PREFIX_LEN, ETH_GATEWAY};
+use crate::driver::net_device::{NetDevice, NET_DEVICE};
+use crate::memory::{copyin, copyin_vec, copyout, copyout_vec};
+use crate::proc::WaitQueue;
+use crate::timer::get_time_ms;
+use crate::utils::Error;
+use device::NetPhy;
+use log::*;
 
+pub mod device;
 pub mod socket;
+pub mod unix;
 
+pub const AF_UNIX: u16 = 1;
+pub const AF_INET: u16 = 2;
 
-#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
-pub struct Sockaddr {
-    sa_family: usize,
-    sa_data: Vec<usize>,
+/* struct sockaddr_un中sun_path的长度 */
+const UNIX_PATH_MAX: usize = 108;
+
+/* 动态端口的分配范围 */
+const EPHEMERAL_PORT_START: u16 = 49152;
+const EPHEMERAL_PORT_END: u16 = 65535;
+
+/* 一次poll中最多处理的轮数，防止loopback上的包来回弹跳导致死循环 */
+const MAX_POLL_ROUND: usize = 32;
+
+/// 内核中使用的套接字地址
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum SockAddr {
+    Inet(IpEndpoint),
+    /* 第一个字节为'\0'时表示abstract namespace中的名字，空字符串表示未命名 */
+    Unix(String),
+}
+
+/// 用户态的struct sockaddr_in, 端口和地址都是网络字节序
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+struct SockaddrIn {
+    sin_family: u16,
+    sin_port: u16,
+    sin_addr: [u8; 4],
+    sin_zero: [u8; 8],
+}
+
+impl SockAddr {
+    pub fn family(&self) -> u16 {
+        match self {
+            SockAddr::Inet(_) => AF_INET,
+            SockAddr::Unix(_) => AF_UNIX,
+        }
+    }
+
+    /// 从用户空间读取一个套接字地址
+    pub fn from_user(token: usize, addr: usize, addrlen: usize) -> Result<Self, Error> {
+        if addr == 0 {
+            return Err(Error::EFAULT);
+        }
+        if addrlen < core::mem::size_of::<u16>() {
+            return Err(Error::EINVAL);
+        }
+        let mut family: u16 = 0;
+        copyin(token, &mut family, addr as *const u16)?;
+        match family {
+            AF_INET => {
+                if addrlen < core::mem::size_of::<SockaddrIn>() {
+                    return Err(Error::EINVAL);
+                }
+                let mut sockaddr: SockaddrIn = Default::default();
+                copyin(token, &mut sockaddr, addr as *const SockaddrIn)?;
+                let port = u16::from_be(sockaddr.sin_port);
+                let ip = Ipv4Address(sockaddr.sin_addr);
+                let addr = if ip.is_unspecified() {
+                    IpAddress::Unspecified
+                } else {
+                    IpAddress::Ipv4(ip)
+                };
+                Ok(SockAddr::Inet(IpEndpoint::new(addr, port)))
+            }
+            AF_UNIX => {
+                let len = (addrlen - core::mem::size_of::<u16>()).min(UNIX_PATH_MAX);
+                let mut path = copyin_vec(token, (addr + core::mem::size_of::<u16>()) as *const u8, len)?;
+                if path.len() > 0 && path[0] != 0 {
+                    if let Some(end) = path.iter().position(|ch| *ch == 0) {
+                        path.truncate(end);
+                    }
+                }
+                let path = String::from_utf8(path).map_err(|_| Error::EINVAL)?;
+                Ok(SockAddr::Unix(path))
+            }
+            _ => Err(Error::EAFNOSUPPORT),
+        }
+    }
+
+    /// 将套接字地址写回用户空间, addrlen_ptr指向的值是缓冲区长度，返回时修改为地址的实际长度
+    pub fn to_user(&self, token: usize, addr: usize, addrlen_ptr: usize) -> Result<(), Error> {
+        if addr == 0 || addrlen_ptr == 0 {
+            return Ok(());
+        }
+        let mut buf_len: u32 = 0;
+        copyin(token, &mut buf_len, addrlen_ptr as *const u32)?;
+
+        let data = match self {
+            SockAddr::Inet(endpoint) => {
+                let ip = match endpoint.addr {
+                    IpAddress::Ipv4(ip) => ip.0,
+                    _ => [0; 4],
+                };
+                let sockaddr = SockaddrIn {
+                    sin_family: AF_INET,
+                    sin_port: endpoint.port.to_be(),
+                    sin_addr: ip,
+                    sin_zero: [0; 8],
+                };
+                let bytes = unsafe {
+                    core::slice::from_raw_parts(
+                        &sockaddr as *const SockaddrIn as *const u8,
+                        core::mem::size_of::<SockaddrIn>())
+                };
+                bytes.to_vec()
+            }
+            SockAddr::Unix(path) => {
+                let mut bytes = AF_UNIX.to_ne_bytes().to_vec();
+                bytes.extend_from_slice(path.as_bytes());
+                if path.len() > 0 && !path.starts_with('\0') {
+                    bytes.push(0);
+                }
+                bytes
+            }
+        };
+
+        let real_len = data.len() as u32;
+        let mut data = data;
+        data.truncate(buf_len as usize);
+        if data.len() > 0 {
+            copyout_vec(token, addr as *mut u8, data)?;
+        }
+        copyout(token, addrlen_ptr as *mut u32, &real_len)?;
+        Ok(())
+    }
+}
+
+/// 网络接口，不同接口的phy设备类型不同
+pub enum NetInterface {
+    Loopback(Interface<'static, Loopback>),
+    Ethernet(Interface<'static, NetPhy>),
+}
+
+macro_rules! with_iface {
+    ($iface: expr, $inner: ident => $body: expr) => {
+        match $iface {
+            NetInterface::Loopback($inner) => $body,
+            NetInterface::Ethernet($inner) => $body,
+        }
+    };
+}
+
+impl NetInterface {
+    /// lo: 127.0.0.1/8
+    fn new_loopback() -> Self {
+        let device = Loopback::new(Medium::Ethernet);
+        let iface = InterfaceBuilder::new(device, vec![])
+            .hardware_addr(EthernetAddress([0x02, 0, 0, 0, 0, 0x01]).into())
+            .neighbor_cache(NeighborCache::new(BTreeMap::new()))
+            .ip_addrs(vec![IpCidr::new(IpAddress::v4(127, 0, 0, 1), 8)])
+            .finalize();
+        NetInterface::Loopback(iface)
+    }
+
+    /// eth0: 使用qemu user network的地址，默认路由指向网关
+    fn new_ethernet(device: Arc<dyn NetDevice>) -> Self {
+        let mac = device.mac();
+        let mut routes = Routes::new(BTreeMap::new());
+        routes.add_default_ipv4_route(Ipv4Address(ETH_GATEWAY)).unwrap();
+        let iface = InterfaceBuilder::new(NetPhy::new(device), vec![])
+            .hardware_addr(EthernetAddress(mac).into())
+            .neighbor_cache(NeighborCache::new(BTreeMap::new()))
+            .ip_addrs(vec![IpCidr::new(IpAddress::Ipv4(Ipv4Address(ETH_IP_ADDR)), ETH_PREFIX_LEN)])
+            .routes(routes)
+            .finalize();
+        NetInterface::Ethernet(iface)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            NetInterface::Loopback(_) => "lo",
+            NetInterface::Ethernet(_) => "eth0",
+        }
+    }
+
+    pub fn ip_addrs(&self) -> &[IpCidr] {
+        with_iface!(self, iface => iface.ip_addrs())
+    }
+
+    fn poll(&mut self, timestamp: Instant) -> smoltcp::Result<bool> {
+        with_iface!(self, iface => iface.poll(timestamp))
+    }
+
+    fn add_socket<T: AnySocket<'static>>(&mut self, socket: T) -> SocketHandle {
+        with_iface!(self, iface => iface.add_socket(socket))
+    }
+
+    fn get_socket<T: AnySocket<'static>>(&mut self, handle: SocketHandle) -> &mut T {
+        with_iface!(self, iface => iface.get_socket::<T>(handle))
+    }
+
+    fn get_socket_and_context<T: AnySocket<'static>>(&mut self, handle: SocketHandle) -> (&mut T, &mut Context<'static>) {
+        with_iface!(self, iface => iface.get_socket_and_context::<T>(handle))
+    }
+
+    fn remove_socket(&mut self, handle: SocketHandle) {
+        with_iface!(self, iface => { iface.remove_socket(handle); })
+    }
+}
+
+/// 挂在某个接口上的smoltcp socket
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NetHandle(pub usize, pub SocketHandle);
+
+/// 内核协议栈, 第0个接口总是loopback, 如果有网卡则第1个接口是eth0
+pub struct NetStack {
+    ifaces: Vec<NetInterface>,
+    tcp_ports: BTreeSet<u16>,
+    udp_ports: BTreeSet<u16>,
+    next_port: u16,
+    /* 已经被用户关闭, 但还在等待FIN完成的tcp连接 */
+    closing: Vec<NetHandle>,
+    /* 各个inet socket的等待队列, poll收发了数据包(socket状态可能变化)时全部唤醒 */
+    queues: Vec<Weak<WaitQueue>>,
+}
+
+pub static NET_STACK: Lazy<Mutex<NetStack>> = Lazy::new(||{
+    Mutex::new(NetStack::new())
+});
+
+impl NetStack {
+    fn new() -> Self {
+        let mut ifaces = Vec::new();
+        ifaces.push(NetInterface::new_loopback());
+        if let Some(device) = (*NET_DEVICE).clone() {
+            ifaces.push(NetInterface::new_ethernet(device));
+        }
+        Self {
+            ifaces,
+            tcp_ports: BTreeSet::new(),
+            udp_ports: BTreeSet::new(),
+            next_port: EPHEMERAL_PORT_START,
+            closing: Vec::new(),
+            queues: Vec::new(),
+        }
+    }
+
+    pub fn iface_num(&self) -> usize {
+        self.ifaces.len()
+    }
+
+    pub fn ifaces(&self) -> &[NetInterface] {
+        self.ifaces.as_slice()
+    }
+
+    /// 选择发往addr的数据包所使用的接口
+    pub fn route(&self, addr: IpAddress) -> Result<usize, Error> {
+        if addr.is_unspecified() || addr.is_loopback() {
+            return Ok(0);
+        }
+        /* 发给本机其他接口地址的包也走loopback */
+        if self.ifaces.iter().any(|iface| iface.ip_addrs().iter().any(|cidr| cidr.address() == addr)) {
+            return Ok(0);
+        }
+        /* eth0有默认路由 */
+        if self.ifaces.len() > 1 {
+            return Ok(1);
+        }
+        Err(Error::ENETUNREACH)
+    }
+
+    pub fn add_socket<T: AnySocket<'static>>(&mut self, iface: usize, socket: T) -> NetHandle {
+        NetHandle(iface, self.ifaces[iface].add_socket(socket))
+    }
+
+    pub fn get_socket<T: AnySocket<'static>>(&mut self, handle: NetHandle) -> &mut T {
+        self.ifaces[handle.0].get_socket::<T>(handle.1)
+    }
+
+    pub fn get_socket_and_context<T: AnySocket<'static>>(&mut self, handle: NetHandle) -> (&mut T, &mut Context<'static>) {
+        self.ifaces[handle.0].get_socket_and_context::<T>(handle.1)
+    }
+
+    pub fn remove_socket(&mut self, handle: NetHandle) {
+        self.ifaces[handle.0].remove_socket(handle.1);
+    }
+
+    /// 新建的socket在协议栈上登记等待队列
+    pub fn add_queue(&mut self, queue: &Arc<WaitQueue>) {
+        self.queues.retain(|queue| queue.strong_count() > 0);
+        self.queues.push(Arc::downgrade(queue));
+    }
+
+    /// 驱动协议栈收发数据包，并回收已经彻底关闭的tcp连接
+    /// 有数据包被处理时唤醒所有socket的等待者, 由它们重新检查各自的状态
+    pub fn poll(&mut self) {
+        let timestamp = Instant::from_millis(get_time_ms() as i64);
+        let mut changed = false;
+        for iface in self.ifaces.iter_mut() {
+            for _ in 0..MAX_POLL_ROUND {
+                match iface.poll(timestamp) {
+                    Ok(true) => {
+                        changed = true;
+                        continue;
+                    }
+                    Ok(false) => break,
+                    Err(err) => trace!("net: {} poll error: {:?}", iface.name(), err),
+                }
+            }
+        }
+
+        let ifaces = &mut self.ifaces;
+        let finished: Vec<NetHandle> = self.closing
+            .drain_filter(|handle| {
+                let state = ifaces[handle.0].get_socket::<TcpSocket>(handle.1).state();
+                state == TcpState::Closed || state == TcpState::TimeWait
+            })
+            .collect();
+        for handle in finished {
+            self.remove_socket(handle);
+        }
+
+        if changed {
+            self.queues.retain(|queue| queue.strong_count() > 0);
+            for queue in self.queues.iter().filter_map(|queue| queue.upgrade()) {
+                queue.wake_all();
+            }
+        }
+    }
+
+    /// 将一个已经调用过close的tcp连接交给协议栈，等连接结束后再释放
+    pub fn defer_close(&mut self, handle: NetHandle) {
+        self.closing.push(handle);
+        self.poll();
+    }
+
+    fn ports(&mut self, is_tcp: bool) -> &mut BTreeSet<u16> {
+        if is_tcp {
+            &mut self.tcp_ports
+        } else {
+            &mut self.udp_ports
+        }
+    }
+
+    /// 占用端口port, port为0时分配一个动态端口
+    pub fn bind_port(&mut self, is_tcp: bool, port: u16) -> Result<u16, Error> {
+        if port != 0 {
+            if !self.ports(is_tcp).insert(port) {
+                return Err(Error::EADDRINUSE);
+            }
+            return Ok(port);
+        }
+
+        for _ in EPHEMERAL_PORT_START..=EPHEMERAL_PORT_END {
+            let port = self.next_port;
+            self.next_port = if port == EPHEMERAL_PORT_END {
+                EPHEMERAL_PORT_START
+            } else {
+                port + 1
+            };
+            if self.ports(is_tcp).insert(port) {
+                return Ok(port);
+            }
+        }
+        Err(Error::EADDRINUSE)
+    }
+
+    pub fn free_port(&mut self, is_tcp: bool, port: u16) {
+        self.ports(is_tcp).remove(&port);
+    }
+}
+
+/// 由调度器的空闲循环调用, 处理网卡收到的数据包和协议栈的定时器
+/// 协议栈正在被其他核使用时直接返回
+pub fn poll_interfaces() {
+    if let Some(mut stack) = NET_STACK.try_lock() {
+        stack.poll();
+    }
 }
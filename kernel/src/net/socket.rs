@@ -1,88 +1,165 @@
-// use smoltcp::{socket::*, iface::SocketHandle};
-
-// pub struct TcpSocketWrapper {
-//     handle: SocketHandle,
-//     is_listening: bool,
-//     end_point: Option<IpEndpoint>
-// }
-
-// pub struct UdpSocketWrapper {
-//     handle: Handle
-// }
-
-// pub struct UdpSocket {
-
-// }
-
-
 use alloc::{
     sync::Arc,
+    vec,
     vec::Vec,
 };
+use spin::Mutex;
+use core::sync::atomic::{AtomicBool, Ordering};
+use smoltcp::socket::{
+    TcpSocket, TcpSocketBuffer, TcpState,
+    UdpSocket, UdpSocketBuffer, UdpPacketMetadata,
+};
+use smoltcp::wire::{IpAddress, IpEndpoint, Ipv4Address};
 
+use super::{SockAddr, NetHandle, NetStack, NET_STACK, AF_INET};
 use crate::{
-    fs::{fifo::RingBuffer, file::SocketFile, File},
-    proc::get_current_task,
+    config::*,
+    fs::{file::{SocketFile, PollType}, File, FileStat, StMode},
+    proc::{get_current_task, TaskWaker, WaitQueue, Waker},
     utils::Error,
 };
+use log::*;
 
-type SockQueue = RingBuffer<Socket>;
-
-// impl UdpSocketWrapper {
-//     pub fn new() -> Self {
-//         let rx_buffer = UdpSocketBuffer::new(
-//             vec![UdpPacketMetadata::EMPTY; UDP_METADATA_BUF],
-//             vec![0; UDP_RECVBUF],
-//         );
-//         let tx_buffer = UdpSocketBuffer::new(
-//             vec![UdpPacketMetadata::EMPTY; UDP_METADATA_BUF],
-//             vec![0; UDP_SENDBUF],
-//         );
-//         let socket = UdpSocket::new(rx_buffer, tx_buffer);
-//     }
-// }
+pub const SOCK_STREAM: usize = 1;
+pub const SOCK_DGRAM: usize = 2;
+pub const SOCK_NONBLOCK: usize = 0o4000;
+pub const SOCK_CLOEXEC: usize = 0o2000000;
 
+#[derive(Clone, Copy, PartialEq)]
 enum Sockettype {
     TCP,
     UDP,
 }
 
+struct SocketInner {
+    /* tcp: 已连接(或正在连接)的socket, 在connect时根据路由在对应的接口上创建 */
+    handle: Option<NetHandle>,
+    /* udp: 每个接口上各有一个socket, 在绑定端口时创建 */
+    udp: Vec<NetHandle>,
+    /* 正在监听的tcp socket, 每个接口上各有backlog个 */
+    listen: Vec<NetHandle>,
+    /* 绑定的本地地址 */
+    local: Option<IpEndpoint>,
+    /* udp connect设置的默认对端地址 */
+    peer: Option<IpEndpoint>,
+    /* 本地端口是否由该socket占用，accept得到的socket与监听socket共用端口 */
+    own_port: bool,
+}
+
 #[allow(unused)]
 pub struct Socket {
     family: usize,
     socktype: Sockettype,
     protocol: usize,
-    queue: SockQueue,
+    nonblock: AtomicBool,
+    inner: Mutex<SocketInner>,
+    /* 登记在协议栈上, poll处理了数据包时唤醒 */
+    queue: Arc<WaitQueue>,
+}
+
+fn new_queue() -> Arc<WaitQueue> {
+    let queue = Arc::new(WaitQueue::new());
+    NET_STACK.lock().add_queue(&queue);
+    queue
+}
+
+fn new_tcp_socket() -> TcpSocket<'static> {
+    let rx_buffer = TcpSocketBuffer::new(vec![0; TCP_RECV_BUFFER_SIZE]);
+    let tx_buffer = TcpSocketBuffer::new(vec![0; TCP_SEND_BUFFER_SIZE]);
+    TcpSocket::new(rx_buffer, tx_buffer)
+}
+
+fn new_udp_socket() -> UdpSocket<'static> {
+    let rx_buffer = UdpSocketBuffer::new(
+        vec![UdpPacketMetadata::EMPTY; UDP_METADATA_NUM],
+        vec![0; UDP_RECV_BUFFER_SIZE],
+    );
+    let tx_buffer = UdpSocketBuffer::new(
+        vec![UdpPacketMetadata::EMPTY; UDP_METADATA_NUM],
+        vec![0; UDP_SEND_BUFFER_SIZE],
+    );
+    UdpSocket::new(rx_buffer, tx_buffer)
+}
+
+/* 发往0.0.0.0的数据等价于发往127.0.0.1 */
+fn normalize_remote(endpoint: IpEndpoint) -> IpEndpoint {
+    if endpoint.addr.is_unspecified() {
+        IpEndpoint::new(IpAddress::Ipv4(Ipv4Address::new(127, 0, 0, 1)), endpoint.port)
+    } else {
+        endpoint
+    }
+}
+
+fn inet_endpoint(addr: SockAddr) -> Result<IpEndpoint, Error> {
+    match addr {
+        SockAddr::Inet(endpoint) => Ok(endpoint),
+        _ => Err(Error::EAFNOSUPPORT),
+    }
+}
+
+/* 监听socket中已经完成三次握手的连接, 对端可能已经发送了FIN */
+fn is_established(stack: &mut NetStack, handle: NetHandle) -> bool {
+    matches!(stack.get_socket::<TcpSocket>(handle).state(), TcpState::Established | TcpState::CloseWait)
 }
 
 impl Drop for Socket {
-    fn drop(&mut self) {}
+    fn drop(&mut self) {
+        let inner = self.inner.get_mut();
+        let mut stack = NET_STACK.lock();
+        for handle in inner.listen.drain(..) {
+            stack.get_socket::<TcpSocket>(handle).abort();
+            stack.remove_socket(handle);
+        }
+        for handle in inner.udp.drain(..) {
+            stack.remove_socket(handle);
+        }
+        if let Some(handle) = inner.handle.take() {
+            /* 发送FIN, 等连接关闭后由协议栈回收 */
+            stack.get_socket::<TcpSocket>(handle).close();
+            stack.defer_close(handle);
+        }
+        if inner.own_port {
+            if let Some(local) = inner.local {
+                stack.free_port(self.socktype == Sockettype::TCP, local.port);
+            }
+        }
+    }
 }
 
 impl Socket {
     /// family:         套接口协议族
     /// socktype:       套接口类型
     /// protocol:       传输层协议
-    /// res:            输出参数，创建成功的套接口指针
-    /// kern:           由内核还是应用程序创建
     pub fn sock_create(
         family: usize,
         socktype: usize,
         protocol: usize,
     ) -> Result<Arc<Self>, Error> {
-        let t = match socktype {
-            1 => Sockettype::TCP,
-            2 => Sockettype::UDP,
-            _ => Sockettype::TCP,
-            // _ => return Err(Error::EPERM),
+        if family != AF_INET as usize {
+            return Err(Error::EAFNOSUPPORT);
+        }
+        let t = match socktype & 0xf {
+            SOCK_STREAM => Sockettype::TCP,
+            SOCK_DGRAM => Sockettype::UDP,
+            _ => return Err(Error::ESOCKTNOSUPPORT),
         };
         Ok(Arc::new(Socket {
             family,
             socktype: t,
             protocol,
-            queue: SockQueue::new(),
+            nonblock: AtomicBool::new(socktype & SOCK_NONBLOCK != 0),
+            inner: Mutex::new(SocketInner {
+                handle: None,
+                udp: Vec::new(),
+                listen: Vec::new(),
+                local: None,
+                peer: None,
+                own_port: false,
+            }),
+            queue: new_queue(),
         }))
     }
+
     pub fn sock_map_fd(self: Arc<Self>) -> Result<usize, Error> {
         let current = get_current_task().unwrap();
         /* 将file添加到fd_table中*/
@@ -90,131 +167,434 @@ impl Socket {
         let fd = current.get_fd_table().add_file(self, fd_limit)?;
         Ok(fd as usize)
     }
+
+    fn is_tcp(&self) -> bool {
+        self.socktype == Sockettype::TCP
+    }
+
+    /// 阻塞等待，直到f返回Some。f每次调用前都会先poll一次协议栈
+    /// 等待时睡眠在socket的等待队列上, 协议栈处理了数据包后被唤醒
+    fn block_on<T>(&self, mut f: impl FnMut(&mut SocketInner) -> Option<Result<T, Error>>) -> Result<T, Error> {
+        let waker = TaskWaker::new(None);
+        let dyn_waker = waker.as_waker();
+        self.queue.register(&dyn_waker);
+        let ret = loop {
+            let mut inner = self.inner.lock();
+            NET_STACK.lock().poll();
+            if let Some(ret) = f(&mut inner) {
+                break ret;
+            }
+            drop(inner);
+
+            if self.nonblock.load(Ordering::Relaxed) {
+                break Err(Error::EAGAIN);
+            }
+            if get_current_task().unwrap().has_signal() {
+                break Err(Error::EINTR);
+            }
+            waker.sleep(None);
+        };
+        self.queue.unregister(&dyn_waker);
+        ret
+    }
+
+    /// 为socket绑定本地端口，如果已经绑定则什么都不做
+    fn bind_inner(&self, inner: &mut SocketInner, endpoint: IpEndpoint) -> Result<IpEndpoint, Error> {
+        if let Some(local) = inner.local {
+            return Ok(local);
+        }
+        let mut stack = NET_STACK.lock();
+        let port = stack.bind_port(self.is_tcp(), endpoint.port)?;
+        let local = IpEndpoint::new(endpoint.addr, port);
+        if !self.is_tcp() {
+            for iface in 0..stack.iface_num() {
+                let handle = stack.add_socket(iface, new_udp_socket());
+                inner.udp.push(handle);
+                stack.get_socket::<UdpSocket>(handle)
+                    .bind(local)
+                    .map_err(|_| Error::EINVAL)?;
+            }
+        }
+        inner.local = Some(local);
+        inner.own_port = true;
+        Ok(local)
+    }
+
+    /// Bind函数将socket与本机上的一个端口相关联，随后你就可以在该端口监听服务请求
+    pub fn bind(&self, addr: SockAddr) -> Result<(), Error> {
+        let endpoint = inet_endpoint(addr)?;
+        let mut inner = self.inner.lock();
+        if inner.local.is_some() {
+            return Err(Error::EINVAL);
+        }
+        self.bind_inner(&mut inner, endpoint)?;
+        Ok(())
+    }
+
     /// listen函数使socket处于被动的监听模式，并为该socket建立一个输入数据队列，将到达的服务请求保存在此队列中，直到程序处理它们
-    pub fn listen(&self) -> Result<usize, Error> {
-        Ok(0)
+    /// smoltcp中一个监听的socket只能接受一个连接，所以在每个接口上用backlog个socket同时监听同一个端口
+    pub fn listen(&self, backlog: usize) -> Result<(), Error> {
+        if !self.is_tcp() {
+            return Err(Error::EOPNOTSUPP);
+        }
+        let mut inner = self.inner.lock();
+        if inner.listen.len() > 0 {
+            return Ok(());
+        }
+        if inner.handle.is_some() {
+            return Err(Error::EISCONN);
+        }
+        let local = self.bind_inner(&mut inner, IpEndpoint::new(IpAddress::Unspecified, 0))?;
+        let backlog = backlog.max(1).min(MAX_LISTEN_BACKLOG);
+
+        let mut stack = NET_STACK.lock();
+        for iface in 0..stack.iface_num() {
+            for _ in 0..backlog {
+                let handle = stack.add_socket(iface, new_tcp_socket());
+                inner.listen.push(handle);
+                stack.get_socket::<TcpSocket>(handle)
+                    .listen(local)
+                    .map_err(|_| Error::EINVAL)?;
+            }
+        }
+        Ok(())
     }
+
     /// accept()函数让服务器接收客户的连接请求。在建立好输入队列后，服务器就调用accept函数，然后睡眠并等待客户的连接请求
-    pub fn accept(&self) -> Result<usize, Error> {
-        Ok(1)
+    pub fn accept(&self) -> Result<(Arc<Socket>, SockAddr), Error> {
+        if self.inner.lock().listen.len() == 0 {
+            return Err(Error::EINVAL);
+        }
+        let (handle, local, remote) = self.block_on(|inner| {
+            let mut stack = NET_STACK.lock();
+            let pos = inner.listen.iter().position(|handle| is_established(&mut stack, *handle))?;
+
+            /* 将已经建立连接的socket取出，并在同一个接口上补充一个新的监听socket */
+            let handle = inner.listen.remove(pos);
+            let local = inner.local.unwrap();
+            let new_handle = stack.add_socket(handle.0, new_tcp_socket());
+            if stack.get_socket::<TcpSocket>(new_handle).listen(local).is_err() {
+                stack.remove_socket(new_handle);
+            } else {
+                inner.listen.push(new_handle);
+            }
+
+            let socket = stack.get_socket::<TcpSocket>(handle);
+            Some(Ok((handle, socket.local_endpoint(), socket.remote_endpoint())))
+        })?;
+
+        let socket = Arc::new(Socket {
+            family: self.family,
+            socktype: Sockettype::TCP,
+            protocol: self.protocol,
+            nonblock: AtomicBool::new(false),
+            inner: Mutex::new(SocketInner {
+                handle: Some(handle),
+                udp: Vec::new(),
+                listen: Vec::new(),
+                local: Some(local),
+                peer: None,
+                own_port: false,
+            }),
+            queue: new_queue(),
+        });
+        Ok((socket, SockAddr::Inet(remote)))
     }
+
     /// 向连接的客户程序使用Connect函数来配置socket并与远端服务器建立一个TCP连接
-    pub fn connect(&self) -> Result<usize, Error> {
-        Ok(0)
-    }
-    pub fn getsockname(&self) -> Result<usize, Error> {
-        Ok(0)
-    }
-    pub fn setsockopt(&self) -> Result<usize, Error> {
-        Ok(0)
-    }
-    pub fn sendto(&self) -> Result<usize, Error> {
-        Ok(1)
-    }
-    pub fn recvfrom(&self, _len: usize) -> Result<Vec<u8>, Error> {
-        let mut a = Vec::new();
-        a.push('x' as u8);
-        Ok(a)
+    pub fn connect(&self, addr: SockAddr) -> Result<(), Error> {
+        let remote = normalize_remote(inet_endpoint(addr)?);
+        let mut inner = self.inner.lock();
+
+        if !self.is_tcp() {
+            inner.peer = Some(remote);
+            self.bind_inner(&mut inner, IpEndpoint::new(IpAddress::Unspecified, 0))?;
+            return Ok(());
+        }
+
+        if inner.listen.len() > 0 {
+            return Err(Error::EISCONN);
+        }
+        if let Some(handle) = inner.handle {
+            let mut stack = NET_STACK.lock();
+            return match stack.get_socket::<TcpSocket>(handle).state() {
+                TcpState::SynSent => Err(Error::EALREADY),
+                /* 非阻塞connect之后连接失败 */
+                TcpState::Closed => {
+                    stack.remove_socket(handle);
+                    inner.handle = None;
+                    Err(Error::ECONNREFUSED)
+                }
+                _ => Err(Error::EISCONN),
+            };
+        }
+
+        let local = self.bind_inner(&mut inner, IpEndpoint::new(IpAddress::Unspecified, 0))?;
+        {
+            let mut stack = NET_STACK.lock();
+            let iface = stack.route(remote.addr)?;
+            let handle = stack.add_socket(iface, new_tcp_socket());
+            let (socket, cx) = stack.get_socket_and_context::<TcpSocket>(handle);
+            if socket.connect(cx, remote, local.port).is_err() {
+                stack.remove_socket(handle);
+                return Err(Error::EADDRNOTAVAIL);
+            }
+            inner.handle = Some(handle);
+        }
+        drop(inner);
+
+        /* 连接失败时释放handle, 之后可以重新connect, 其他操作返回ENOTCONN */
+        match self.block_on(|inner| {
+            let handle = match inner.handle {
+                Some(handle) => handle,
+                None => return Some(Err(Error::ECONNREFUSED)),
+            };
+            let mut stack = NET_STACK.lock();
+            match stack.get_socket::<TcpSocket>(handle).state() {
+                TcpState::SynSent => None,
+                TcpState::Closed => {
+                    stack.remove_socket(handle);
+                    inner.handle = None;
+                    Some(Err(Error::ECONNREFUSED))
+                }
+                _ => Some(Ok(())),
+            }
+        }) {
+            Err(Error::EAGAIN) => Err(Error::EINPROGRESS),
+            ret => ret,
+        }
+    }
+
+    pub fn getsockname(&self) -> Result<SockAddr, Error> {
+        let inner = self.inner.lock();
+        if let Some(handle) = inner.handle {
+            let mut stack = NET_STACK.lock();
+            let socket = stack.get_socket::<TcpSocket>(handle);
+            if socket.is_open() {
+                return Ok(SockAddr::Inet(socket.local_endpoint()));
+            }
+        }
+        let local = inner.local.unwrap_or(IpEndpoint::new(IpAddress::Unspecified, 0));
+        Ok(SockAddr::Inet(local))
+    }
+
+    pub fn getpeername(&self) -> Result<SockAddr, Error> {
+        let inner = self.inner.lock();
+        if !self.is_tcp() {
+            return inner.peer.map(|peer| SockAddr::Inet(peer)).ok_or(Error::ENOTCONN);
+        }
+        let handle = inner.handle.ok_or(Error::ENOTCONN)?;
+        let mut stack = NET_STACK.lock();
+        let socket = stack.get_socket::<TcpSocket>(handle);
+        if socket.is_active() {
+            Ok(SockAddr::Inet(socket.remote_endpoint()))
+        } else {
+            Err(Error::ENOTCONN)
+        }
+    }
+
+    pub fn setsockopt(&self, level: usize, optname: usize, _optval: &[u8]) -> Result<(), Error> {
+        trace!("socket: setsockopt level = {}, optname = {}, ignored", level, optname);
+        Ok(())
+    }
+
+    pub fn sendto(&self, data: &[u8], addr: Option<SockAddr>) -> Result<usize, Error> {
+        if self.is_tcp() {
+            return self.tcp_send(data);
+        }
+
+        let remote = match addr {
+            Some(addr) => normalize_remote(inet_endpoint(addr)?),
+            None => self.inner.lock().peer.ok_or(Error::EDESTADDRREQ)?,
+        };
+        {
+            let mut inner = self.inner.lock();
+            self.bind_inner(&mut inner, IpEndpoint::new(IpAddress::Unspecified, 0))?;
+        }
+        if data.len() > UDP_SEND_BUFFER_SIZE {
+            return Err(Error::EMSGSIZE);
+        }
+        let iface = NET_STACK.lock().route(remote.addr)?;
+        self.block_on(|inner| {
+            let handle = *inner.udp.iter().find(|handle| handle.0 == iface)?;
+            let mut stack = NET_STACK.lock();
+            let socket = stack.get_socket::<UdpSocket>(handle);
+            if !socket.can_send() {
+                return None;
+            }
+            let ret = socket.send_slice(data, remote)
+                .map(|_| data.len())
+                .map_err(|_| Error::ENOBUFS);
+            stack.poll();
+            Some(ret)
+        })
+    }
+
+    fn tcp_send(&self, data: &[u8]) -> Result<usize, Error> {
+        self.block_on(|inner| {
+            let handle = match inner.handle {
+                Some(handle) => handle,
+                None => return Some(Err(Error::ENOTCONN)),
+            };
+            let mut stack = NET_STACK.lock();
+            let socket = stack.get_socket::<TcpSocket>(handle);
+            if !socket.is_active() {
+                return Some(Err(Error::ENOTCONN));
+            }
+            if !socket.may_send() {
+                return Some(Err(Error::EPIPE));
+            }
+            if !socket.can_send() {
+                return None;
+            }
+            let ret = socket.send_slice(data).map_err(|_| Error::EPIPE);
+            stack.poll();
+            Some(ret)
+        })
+    }
+
+    pub fn recvfrom(&self, len: usize) -> Result<(Vec<u8>, Option<SockAddr>), Error> {
+        let mut buf = vec![0u8; len];
+        if self.is_tcp() {
+            let size = self.block_on(|inner| {
+                let handle = match inner.handle {
+                    Some(handle) => handle,
+                    None => return Some(Err(Error::ENOTCONN)),
+                };
+                let mut stack = NET_STACK.lock();
+                let socket = stack.get_socket::<TcpSocket>(handle);
+                if socket.can_recv() {
+                    let ret = socket.recv_slice(&mut buf).map_err(|_| Error::ECONNRESET);
+                    /* 读走数据后窗口变大，需要通知对端 */
+                    stack.poll();
+                    Some(ret)
+                } else if !socket.may_recv() {
+                    /* 对端已经关闭 */
+                    Some(Ok(0))
+                } else if socket.state() == TcpState::Closed {
+                    Some(Err(Error::ENOTCONN))
+                } else {
+                    None
+                }
+            })?;
+            buf.truncate(size);
+            return Ok((buf, None));
+        }
+
+        if self.inner.lock().udp.len() == 0 {
+            return Err(Error::ENOTCONN);
+        }
+        let (size, remote) = self.block_on(|inner| {
+            let mut stack = NET_STACK.lock();
+            for handle in inner.udp.iter() {
+                let socket = stack.get_socket::<UdpSocket>(*handle);
+                if socket.can_recv() {
+                    return Some(socket.recv_slice(&mut buf).map_err(|_| Error::EAGAIN));
+                }
+            }
+            None
+        })?;
+        buf.truncate(size);
+        Ok((buf, Some(SockAddr::Inet(remote))))
+    }
+
+    fn poll_inner(&self, ptype: PollType) -> bool {
+        let inner = self.inner.lock();
+        let mut stack = NET_STACK.lock();
+        stack.poll();
+
+        if inner.listen.len() > 0 {
+            return match ptype {
+                PollType::READ => inner.listen.iter().any(|handle| is_established(&mut stack, *handle)),
+                _ => false,
+            };
+        }
+
+        match self.socktype {
+            Sockettype::TCP => {
+                let handle = match inner.handle {
+                    Some(handle) => handle,
+                    None => return false,
+                };
+                let socket = stack.get_socket::<TcpSocket>(handle);
+                match ptype {
+                    PollType::READ => socket.can_recv() || (socket.is_active() && !socket.may_recv()),
+                    PollType::WRITE => socket.can_send(),
+                    PollType::ERR => false,
+                    /* 两个方向都已经关闭, 或者连接被重置 */
+                    PollType::HUP => matches!(socket.state(),
+                        TcpState::Closed | TcpState::Closing | TcpState::LastAck | TcpState::TimeWait),
+                }
+            }
+            Sockettype::UDP => {
+                match ptype {
+                    PollType::READ => inner.udp.iter()
+                        .any(|handle| stack.get_socket::<UdpSocket>(*handle).can_recv()),
+                    /* 还没有绑定端口的udp socket发送时会自动绑定 */
+                    PollType::WRITE => inner.udp.len() == 0 || inner.udp.iter()
+                        .any(|handle| stack.get_socket::<UdpSocket>(*handle).can_send()),
+                    PollType::ERR | PollType::HUP => false,
+                }
+            }
+        }
     }
 }
 
 impl File for Socket {
     fn close(&self) -> Result<(), Error> {
-        panic!();
+        Ok(())
     }
 
     fn get_index(&self) -> Result<crate::fs::FileIndex, Error> {
         Err(Error::EINDEX)
     }
 
-    fn read(&self, _len: usize) -> Result<Vec<u8>, Error> {
-        Err(Error::EINVAL)
+    fn read(&self, len: usize) -> Result<Vec<u8>, Error> {
+        self.recvfrom(len).map(|(data, _)| data)
     }
 
-    fn write(&self, _data: Vec<u8>) -> Result<usize, Error> {
-        Err(Error::EINVAL)
+    fn write(&self, data: Vec<u8>) -> Result<usize, Error> {
+        self.sendto(data.as_slice(), None)
     }
 
     fn readable(&self) -> bool {
-        panic!();
+        true
     }
 
     fn writable(&self) -> bool {
-        panic!();
+        true
     }
 
-    fn seek(&self, _pos: usize, _mode: crate::fs::SeekMode) -> Result<isize, Error> {
-        Err(Error::EPERM)
+    fn poll(&self, ptype: PollType) -> Result<bool, Error> {
+        Ok(self.poll_inner(ptype))
     }
 
-    fn get_size(&self) -> Result<usize, Error> {
-        panic!("no implement");
+    fn register_waker(&self, waker: &Arc<dyn Waker>) -> bool {
+        self.queue.register(waker);
+        true
     }
 
-    fn write_stat(&self, _stat: &crate::fs::FileStat) -> Result<(), Error> {
-        Err(Error::EPERM)
+    fn unregister_waker(&self, waker: &Arc<dyn Waker>) {
+        self.queue.unregister(waker);
     }
 
-    fn read_stat(&self) -> Result<crate::fs::FileStat, Error> {
-        Err(Error::EPERM)
-    }
-
-    fn vfs(&self) -> alloc::sync::Arc<dyn crate::fs::vfs::VFS> {
-        panic!();
-    }
-
-    fn as_dir<'a>(
-        self: alloc::sync::Arc<Self>,
-    ) -> Result<alloc::sync::Arc<dyn crate::fs::DirFile + 'a>, Error>
-    where
-        Self: 'a,
-    {
-        Err(Error::EPERM)
-    }
-
-    fn as_link<'a>(
-        self: alloc::sync::Arc<Self>,
-    ) -> Result<alloc::sync::Arc<dyn crate::fs::file::LinkFile + 'a>, Error>
-    where
-        Self: 'a,
-    {
-        Err(Error::EPERM)
-    }
-
-    fn as_device<'a>(
-        self: alloc::sync::Arc<Self>,
-    ) -> Result<alloc::sync::Arc<dyn crate::fs::DeviceFile + 'a>, Error>
-    where
-        Self: 'a,
-    {
-        Err(Error::EPERM)
+    fn seek(&self, _pos: usize, _mode: crate::fs::SeekMode) -> Result<isize, Error> {
+        Err(Error::ESPIPE)
     }
 
-    fn as_block<'a>(
-        self: alloc::sync::Arc<Self>,
-    ) -> Result<alloc::sync::Arc<dyn crate::fs::BlockFile + 'a>, Error>
-    where
-        Self: 'a,
-    {
-        Err(Error::EPERM)
+    fn get_size(&self) -> Result<usize, Error> {
+        Ok(0)
     }
 
-    fn as_char<'a>(
-        self: alloc::sync::Arc<Self>,
-    ) -> Result<alloc::sync::Arc<dyn crate::fs::CharFile + 'a>, Error>
-    where
-        Self: 'a,
-    {
+    fn write_stat(&self, _stat: &FileStat) -> Result<(), Error> {
         Err(Error::EPERM)
     }
 
-    fn as_fifo<'a>(
-        self: alloc::sync::Arc<Self>,
-    ) -> Result<alloc::sync::Arc<dyn crate::fs::file::FIFOFile + 'a>, Error>
-    where
-        Self: 'a,
-    {
-        Err(Error::EPERM)
+    fn read_stat(&self) -> Result<FileStat, Error> {
+        let mut fstat: FileStat = Default::default();
+        fstat.st_nlink = 1;
+        fstat.st_mode = StMode::SOCK as u32 | 0o777;
+        Ok(fstat)
     }
 
     fn as_file<'a>(self: alloc::sync::Arc<Self>) -> alloc::sync::Arc<dyn File + 'a>
@@ -242,29 +622,39 @@ impl File for Socket {
 }
 
 impl SocketFile for Socket {
-    fn listen(&self) -> Result<usize, Error> {
-        self.listen()
+    fn bind(&self, addr: SockAddr) -> Result<(), Error> {
+        self.bind(addr)
     }
-    fn accept(&self) -> Result<usize, Error> {
-        self.accept()
+
+    fn listen(&self, backlog: usize) -> Result<(), Error> {
+        self.listen(backlog)
     }
-    fn connect(&self) -> Result<usize, Error> {
-        self.connect()
+
+    fn accept(&self) -> Result<(Arc<dyn File>, SockAddr), Error> {
+        self.accept().map(|(socket, addr)| (socket as Arc<dyn File>, addr))
+    }
+
+    fn connect(&self, addr: SockAddr) -> Result<(), Error> {
+        self.connect(addr)
     }
 
-    fn getsockname(&self) -> Result<usize, Error> {
+    fn getsockname(&self) -> Result<SockAddr, Error> {
         self.getsockname()
     }
 
-    fn sendto(&self) -> Result<usize, Error> {
-        self.sendto()
+    fn getpeername(&self) -> Result<SockAddr, Error> {
+        self.getpeername()
+    }
+
+    fn sendto(&self, data: &[u8], addr: Option<SockAddr>) -> Result<usize, Error> {
+        self.sendto(data, addr)
     }
 
-    fn recvfrom(&self, len: usize) -> Result<Vec<u8>, Error> {
+    fn recvfrom(&self, len: usize) -> Result<(Vec<u8>, Option<SockAddr>), Error> {
         self.recvfrom(len)
     }
 
-    fn setsockopt(&self) -> Result<usize, Error> {
-        self.setsockopt()
+    fn setsockopt(&self, level: usize, optname: usize, optval: &[u8]) -> Result<(), Error> {
+        self.setsockopt(level, optname, optval)
     }
 }
@@ -0,0 +1,557 @@
+use alloc::{
+    collections::{BTreeMap, VecDeque},
+    string::String,
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use spin::Mutex;
+use spin::lazy::Lazy;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use super::SockAddr;
+use super::socket::{SOCK_STREAM, SOCK_DGRAM, SOCK_NONBLOCK};
+use crate::{
+    fs::{
+        fifo::RingBuffer, file::{SocketFile, PollType},
+        File, FileIndex, FileOpenMode, FilePerm, FileStat, FileType, StMode,
+        mknod_at, open_at,
+    },
+    proc::{get_current_task, wait_on, WaitQueue, Waker},
+    syscall::fs::{get_file, AT_FDCWD},
+    utils::Error,
+};
+use log::*;
+
+/* 每个数据报socket的接收队列中最多缓存的数据报数目 */
+const UNIX_DGRAM_QUEUE_LEN: usize = 64;
+const UNIX_MAX_BACKLOG: usize = 128;
+
+type UnixBuffer = RingBuffer<UnixSocket>;
+
+#[derive(Clone, Copy, PartialEq)]
+enum UnixType {
+    STREAM,
+    DGRAM,
+}
+
+enum UnixState {
+    Unconnected,
+    /* 等待accept的连接，保存的是服务端一侧的socket */
+    Listening(VecDeque<Arc<UnixSocket>>, usize),
+    /* 流式socket的一对缓冲区, rx由对端写入, tx由对端读出 */
+    Connected(Arc<Mutex<UnixBuffer>>, Arc<Mutex<UnixBuffer>>),
+}
+
+struct UnixSocketInner {
+    state: UnixState,
+    /* 绑定的地址 */
+    local: Option<String>,
+    /* 绑定到文件系统中时，对应的socket文件 */
+    node: Option<FileIndex>,
+    /* 对端, 流式socket在连接时设置, 数据报socket由connect设置 */
+    peer: Option<Weak<UnixSocket>>,
+    peer_addr: Option<String>,
+    /* 数据报的接收队列 (数据, 发送方地址) */
+    dgrams: VecDeque<(Vec<u8>, String)>,
+}
+
+pub struct UnixSocket {
+    socktype: UnixType,
+    nonblock: AtomicBool,
+    me: Weak<UnixSocket>,
+    inner: Mutex<UnixSocketInner>,
+    /* 本socket的poll状态变化时唤醒, 由对端的读写, 连接和关闭触发 */
+    queue: WaitQueue,
+}
+
+/* 已经绑定到文件系统中的socket, 以socket文件的FileIndex为键 */
+static UNIX_SOCKET_TABLE: Lazy<Mutex<BTreeMap<FileIndex, Weak<UnixSocket>>>> = Lazy::new(||{
+    Mutex::new(BTreeMap::new())
+});
+
+/* abstract namespace中的socket, 不会出现在文件系统中 */
+static UNIX_ABSTRACT_TABLE: Lazy<Mutex<BTreeMap<String, Weak<UnixSocket>>>> = Lazy::new(||{
+    Mutex::new(BTreeMap::new())
+});
+
+fn is_abstract(path: &String) -> bool {
+    path.starts_with('\0')
+}
+
+fn unix_path(addr: SockAddr) -> Result<String, Error> {
+    match addr {
+        SockAddr::Unix(path) if path.len() > 0 => Ok(path),
+        SockAddr::Unix(_) => Err(Error::EINVAL),
+        _ => Err(Error::EAFNOSUPPORT),
+    }
+}
+
+/// 根据地址找到绑定在该地址上的socket
+fn lookup(path: &String) -> Result<Arc<UnixSocket>, Error> {
+    let socket = if is_abstract(path) {
+        UNIX_ABSTRACT_TABLE.lock().get(path).and_then(|socket| socket.upgrade())
+    } else {
+        let (root, path) = get_file(AT_FDCWD, path.clone())?;
+        let file = open_at(root, path, FileOpenMode::SYS)?;
+        let index = file.get_index()?;
+        UNIX_SOCKET_TABLE.lock().get(&index).and_then(|socket| socket.upgrade())
+    };
+    socket.ok_or(Error::ECONNREFUSED)
+}
+
+impl UnixSocket {
+    fn new_socket(socktype: UnixType, nonblock: bool) -> Arc<Self> {
+        Arc::new_cyclic(|me| Self {
+            socktype,
+            nonblock: AtomicBool::new(nonblock),
+            me: me.clone(),
+            inner: Mutex::new(UnixSocketInner {
+                state: UnixState::Unconnected,
+                local: None,
+                node: None,
+                peer: None,
+                peer_addr: None,
+                dgrams: VecDeque::new(),
+            }),
+            queue: WaitQueue::new(),
+        })
+    }
+
+    pub fn new(socktype: usize) -> Result<Arc<Self>, Error> {
+        let t = match socktype & 0xf {
+            SOCK_STREAM => UnixType::STREAM,
+            SOCK_DGRAM => UnixType::DGRAM,
+            _ => return Err(Error::ESOCKTNOSUPPORT),
+        };
+        Ok(Self::new_socket(t, socktype & SOCK_NONBLOCK != 0))
+    }
+
+    /// 创建一对已经互相连接的socket
+    pub fn new_pair(socktype: usize) -> Result<(Arc<Self>, Arc<Self>), Error> {
+        let socket0 = Self::new(socktype)?;
+        let socket1 = Self::new(socktype)?;
+        if socket0.socktype == UnixType::STREAM {
+            Self::connect_stream(&socket0, &socket1);
+        } else {
+            socket0.inner.lock().peer = Some(Arc::downgrade(&socket1));
+            socket1.inner.lock().peer = Some(Arc::downgrade(&socket0));
+        }
+        Ok((socket0, socket1))
+    }
+
+    /// 为两个流式socket建立连接
+    fn connect_stream(socket0: &Arc<Self>, socket1: &Arc<Self>) {
+        let buffer01 = Arc::new(Mutex::new(UnixBuffer::new()));
+        let buffer10 = Arc::new(Mutex::new(UnixBuffer::new()));
+        buffer01.lock().record_write_end(socket0);
+        buffer01.lock().record_read_end(socket1);
+        buffer10.lock().record_write_end(socket1);
+        buffer10.lock().record_read_end(socket0);
+
+        let mut inner0 = socket0.inner.lock();
+        inner0.state = UnixState::Connected(buffer10.clone(), buffer01.clone());
+        inner0.peer = Some(Arc::downgrade(socket1));
+        let mut inner1 = socket1.inner.lock();
+        inner1.state = UnixState::Connected(buffer01, buffer10);
+        inner1.peer = Some(Arc::downgrade(socket0));
+        inner1.peer_addr = inner0.local.clone();
+        inner0.peer_addr = inner1.local.clone();
+        drop(inner1);
+        drop(inner0);
+        socket0.queue.wake_all();
+        socket1.queue.wake_all();
+    }
+
+    /* 本端的读写和关闭改变了对端的可读写状态 */
+    fn wake_peer(&self) {
+        let peer = self.inner.lock().peer.as_ref().and_then(|peer| peer.upgrade());
+        if let Some(peer) = peer {
+            peer.queue.wake_all();
+        }
+    }
+
+    /// 阻塞等待，直到f返回Some, 与Pipe一样在queue上睡眠
+    /// queue是条件发生变化时会被唤醒的队列: 本端的数据和连接状态由对端唤醒自己的queue,
+    /// 等待对方的空间(监听队列, 数据报接收队列)时在对方的queue上等待
+    fn block_on<T>(&self, queue: &WaitQueue, mut f: impl FnMut() -> Option<Result<T, Error>>) -> Result<T, Error> {
+        if self.nonblock.load(Ordering::Relaxed) {
+            return f().unwrap_or(Err(Error::EAGAIN));
+        }
+        wait_on(queue, f)
+    }
+
+    pub fn bind(&self, addr: SockAddr) -> Result<(), Error> {
+        let path = unix_path(addr)?;
+        let mut inner = self.inner.lock();
+        if inner.local.is_some() {
+            return Err(Error::EINVAL);
+        }
+
+        if is_abstract(&path) {
+            let mut table = UNIX_ABSTRACT_TABLE.lock();
+            if table.get(&path).and_then(|socket| socket.upgrade()).is_some() {
+                return Err(Error::EADDRINUSE);
+            }
+            table.insert(path.clone(), self.me.clone());
+        } else {
+            /* 在文件系统中创建socket文件，如果文件已经存在则地址已被占用 */
+            let (root, fs_path) = get_file(AT_FDCWD, path.clone())?;
+            let umask = get_current_task().unwrap().get_fs_info().umask;
+            let perm = FilePerm::from_bits_truncate(0o777 & !umask);
+            let node = match mknod_at(root, fs_path, FileType::SocketFile, perm) {
+                Ok(node) => node,
+                Err(Error::EEXIST) => return Err(Error::EADDRINUSE),
+                Err(err) => return Err(err),
+            };
+            let index = node.get_index()?;
+            UNIX_SOCKET_TABLE.lock().insert(index, self.me.clone());
+            inner.node = Some(index);
+        }
+        trace!("unix_socket: bind to {:?}", path);
+        inner.local = Some(path);
+        Ok(())
+    }
+
+    pub fn listen(&self, backlog: usize) -> Result<(), Error> {
+        if self.socktype != UnixType::STREAM {
+            return Err(Error::EOPNOTSUPP);
+        }
+        let mut inner = self.inner.lock();
+        if inner.local.is_none() {
+            return Err(Error::EINVAL);
+        }
+        let backlog = backlog.max(1).min(UNIX_MAX_BACKLOG);
+        match &mut inner.state {
+            UnixState::Listening(_, old_backlog) => {
+                *old_backlog = backlog;
+                return Ok(());
+            }
+            UnixState::Connected(_, _) => return Err(Error::EISCONN),
+            UnixState::Unconnected => {},
+        }
+        inner.state = UnixState::Listening(VecDeque::new(), backlog);
+        Ok(())
+    }
+
+    pub fn accept(&self) -> Result<(Arc<UnixSocket>, SockAddr), Error> {
+        if self.socktype != UnixType::STREAM {
+            return Err(Error::EOPNOTSUPP);
+        }
+        let socket = self.block_on(&self.queue, || {
+            let mut inner = self.inner.lock();
+            match &mut inner.state {
+                UnixState::Listening(queue, _) => queue.pop_front().map(|socket| Ok(socket)),
+                _ => Some(Err(Error::EINVAL)),
+            }
+        })?;
+        /* 监听队列有了空间, 唤醒等待的connect */
+        self.queue.wake_all();
+        let peer_addr = socket.inner.lock().peer_addr.clone().unwrap_or_default();
+        Ok((socket, SockAddr::Unix(peer_addr)))
+    }
+
+    pub fn connect(&self, addr: SockAddr) -> Result<(), Error> {
+        let path = unix_path(addr)?;
+        let listener = lookup(&path)?;
+        if listener.socktype != self.socktype {
+            return Err(Error::EPROTOTYPE);
+        }
+
+        if self.socktype == UnixType::DGRAM {
+            let mut inner = self.inner.lock();
+            inner.peer = Some(Arc::downgrade(&listener));
+            inner.peer_addr = Some(path);
+            return Ok(());
+        }
+
+        match self.inner.lock().state {
+            UnixState::Unconnected => {},
+            UnixState::Listening(_, _) => return Err(Error::EINVAL),
+            UnixState::Connected(_, _) => return Err(Error::EISCONN),
+        }
+
+        /* 创建服务端一侧的socket，放入监听队列中，等待accept取走 */
+        let me = self.me.upgrade().unwrap();
+        self.block_on(&listener.queue, || {
+            let mut listener_inner = listener.inner.lock();
+            let local = listener_inner.local.clone();
+            let (queue, backlog) = match &mut listener_inner.state {
+                UnixState::Listening(queue, backlog) => (queue, *backlog),
+                _ => return Some(Err(Error::ECONNREFUSED)),
+            };
+            if queue.len() >= backlog {
+                return None;
+            }
+            let server = UnixSocket::new_socket(UnixType::STREAM, false);
+            server.inner.lock().local = local;
+            UnixSocket::connect_stream(&me, &server);
+            queue.push_back(server);
+            listener.queue.wake_all();
+            Some(Ok(()))
+        })
+    }
+
+    pub fn getsockname(&self) -> Result<SockAddr, Error> {
+        let inner = self.inner.lock();
+        Ok(SockAddr::Unix(inner.local.clone().unwrap_or_default()))
+    }
+
+    pub fn getpeername(&self) -> Result<SockAddr, Error> {
+        let inner = self.inner.lock();
+        match inner.peer {
+            Some(_) => Ok(SockAddr::Unix(inner.peer_addr.clone().unwrap_or_default())),
+            None => Err(Error::ENOTCONN),
+        }
+    }
+
+    pub fn sendto(&self, data: &[u8], addr: Option<SockAddr>) -> Result<usize, Error> {
+        match self.socktype {
+            UnixType::STREAM => self.stream_send(data),
+            UnixType::DGRAM => self.dgram_send(data, addr),
+        }
+    }
+
+    fn stream_send(&self, data: &[u8]) -> Result<usize, Error> {
+        let tx = match &self.inner.lock().state {
+            UnixState::Connected(_, tx) => tx.clone(),
+            _ => return Err(Error::ENOTCONN),
+        };
+
+        let len = data.len();
+        let mut has_write = 0;
+        while has_write < len {
+            let written = self.block_on(&self.queue, || {
+                let mut buffer = tx.lock();
+                if buffer.all_read_ends_closed() {
+                    return Some(Err(Error::EPIPE));
+                }
+                let loop_write = buffer.available_write_bytes();
+                if loop_write == 0 {
+                    return None;
+                }
+                let size = loop_write.min(len - has_write);
+                Some(buffer.write(&data[has_write..has_write + size]))
+            });
+            match written {
+                Ok(size) => {
+                    has_write += size;
+                    self.wake_peer();
+                }
+                /* 已经写入了部分数据, 返回写入的长度 */
+                Err(_) if has_write > 0 => break,
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(has_write)
+    }
+
+    fn dgram_send(&self, data: &[u8], addr: Option<SockAddr>) -> Result<usize, Error> {
+        let target = match addr {
+            Some(addr) => lookup(&unix_path(addr)?)?,
+            None => {
+                let inner = self.inner.lock();
+                let peer = inner.peer.as_ref().ok_or(Error::ENOTCONN)?;
+                peer.upgrade().ok_or(Error::ECONNREFUSED)?
+            }
+        };
+        if target.socktype != UnixType::DGRAM {
+            return Err(Error::EPROTOTYPE);
+        }
+        let local = self.inner.lock().local.clone().unwrap_or_default();
+
+        self.block_on(&target.queue, || {
+            let mut target_inner = target.inner.lock();
+            if target_inner.dgrams.len() >= UNIX_DGRAM_QUEUE_LEN {
+                return None;
+            }
+            target_inner.dgrams.push_back((data.to_vec(), local.clone()));
+            target.queue.wake_all();
+            Some(Ok(data.len()))
+        })
+    }
+
+    pub fn recvfrom(&self, len: usize) -> Result<(Vec<u8>, Option<SockAddr>), Error> {
+        if self.socktype == UnixType::DGRAM {
+            let (mut data, from) = self.block_on(&self.queue, || {
+                self.inner.lock().dgrams.pop_front().map(|dgram| Ok(dgram))
+            })?;
+            /* 接收队列有了空间, 唤醒等待的发送方 */
+            self.queue.wake_all();
+            /* 数据报多余的部分被丢弃 */
+            data.truncate(len);
+            return Ok((data, Some(SockAddr::Unix(from))));
+        }
+
+        let rx = match &self.inner.lock().state {
+            UnixState::Connected(rx, _) => rx.clone(),
+            _ => return Err(Error::ENOTCONN),
+        };
+        let data = self.block_on(&self.queue, || {
+            let mut buffer = rx.lock();
+            let loop_read = buffer.available_read_bytes();
+            if loop_read == 0 {
+                /* 对端已经关闭, 返回EOF */
+                if buffer.all_wirte_ends_closed() {
+                    return Some(Ok(Vec::new()));
+                }
+                return None;
+            }
+            Some(Ok(buffer.read(loop_read.min(len))))
+        })?;
+        self.wake_peer();
+        Ok((data, None))
+    }
+
+    fn poll_inner(&self, ptype: PollType) -> bool {
+        let inner = self.inner.lock();
+        match &inner.state {
+            UnixState::Listening(queue, _) => match ptype {
+                PollType::READ => queue.len() > 0,
+                _ => false,
+            },
+            UnixState::Connected(rx, tx) => match ptype {
+                PollType::READ => {
+                    let rx = rx.lock();
+                    rx.available_read_bytes() > 0 || rx.all_wirte_ends_closed()
+                }
+                PollType::WRITE => {
+                    let tx = tx.lock();
+                    tx.available_write_bytes() > 0 || tx.all_read_ends_closed()
+                }
+                PollType::ERR => tx.lock().all_read_ends_closed(),
+                PollType::HUP => rx.lock().all_wirte_ends_closed(),
+            },
+            UnixState::Unconnected => match ptype {
+                PollType::READ => inner.dgrams.len() > 0,
+                PollType::WRITE => self.socktype == UnixType::DGRAM,
+                PollType::ERR | PollType::HUP => false,
+            },
+        }
+    }
+}
+
+impl Drop for UnixSocket {
+    fn drop(&mut self) {
+        let inner = self.inner.get_mut();
+        if let Some(index) = inner.node.take() {
+            UNIX_SOCKET_TABLE.lock().remove(&index);
+        } else if let Some(path) = inner.local.as_ref() {
+            if is_abstract(path) {
+                UNIX_ABSTRACT_TABLE.lock().remove(path);
+            }
+        }
+        /* 对端读到EOF或者写入时得到EPIPE */
+        if let Some(peer) = inner.peer.as_ref().and_then(|peer| peer.upgrade()) {
+            peer.queue.wake_all();
+        }
+    }
+}
+
+impl File for UnixSocket {
+    fn close(&self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn get_index(&self) -> Result<FileIndex, Error> {
+        Err(Error::EINDEX)
+    }
+
+    fn read(&self, len: usize) -> Result<Vec<u8>, Error> {
+        self.recvfrom(len).map(|(data, _)| data)
+    }
+
+    fn write(&self, data: Vec<u8>) -> Result<usize, Error> {
+        self.sendto(data.as_slice(), None)
+    }
+
+    fn readable(&self) -> bool {
+        true
+    }
+
+    fn writable(&self) -> bool {
+        true
+    }
+
+    fn poll(&self, ptype: PollType) -> Result<bool, Error> {
+        Ok(self.poll_inner(ptype))
+    }
+
+    fn register_waker(&self, waker: &Arc<dyn Waker>) -> bool {
+        self.queue.register(waker);
+        true
+    }
+
+    fn unregister_waker(&self, waker: &Arc<dyn Waker>) {
+        self.queue.unregister(waker);
+    }
+
+    fn seek(&self, _pos: usize, _mode: crate::fs::SeekMode) -> Result<isize, Error> {
+        Err(Error::ESPIPE)
+    }
+
+    fn get_size(&self) -> Result<usize, Error> {
+        Ok(0)
+    }
+
+    fn write_stat(&self, _stat: &FileStat) -> Result<(), Error> {
+        Err(Error::EPERM)
+    }
+
+    fn read_stat(&self) -> Result<FileStat, Error> {
+        let mut fstat: FileStat = Default::default();
+        fstat.st_nlink = 1;
+        fstat.st_mode = StMode::SOCK as u32 | 0o777;
+        Ok(fstat)
+    }
+
+    fn as_file<'a>(self: Arc<Self>) -> Arc<dyn File + 'a> where Self: 'a {
+        self
+    }
+
+    fn as_any<'a>(self: Arc<Self>) -> Arc<dyn core::any::Any + Send + Sync + 'a> where Self: 'a {
+        self
+    }
+
+    fn as_socket<'a>(self: Arc<Self>) -> Result<Arc<dyn SocketFile + 'a>, Error> where Self: 'a {
+        Ok(self)
+    }
+}
+
+impl SocketFile for UnixSocket {
+    fn bind(&self, addr: SockAddr) -> Result<(), Error> {
+        self.bind(addr)
+    }
+
+    fn listen(&self, backlog: usize) -> Result<(), Error> {
+        self.listen(backlog)
+    }
+
+    fn accept(&self) -> Result<(Arc<dyn File>, SockAddr), Error> {
+        self.accept().map(|(socket, addr)| (socket as Arc<dyn File>, addr))
+    }
+
+    fn connect(&self, addr: SockAddr) -> Result<(), Error> {
+        self.connect(addr)
+    }
+
+    fn getsockname(&self) -> Result<SockAddr, Error> {
+        self.getsockname()
+    }
+
+    fn getpeername(&self) -> Result<SockAddr, Error> {
+        self.getpeername()
+    }
+
+    fn sendto(&self, data: &[u8], addr: Option<SockAddr>) -> Result<usize, Error> {
+        self.sendto(data, addr)
+    }
+
+    fn recvfrom(&self, len: usize) -> Result<(Vec<u8>, Option<SockAddr>), Error> {
+        self.recvfrom(len)
+    }
+
+    fn setsockopt(&self, level: usize, optname: usize, _optval: &[u8]) -> Result<(), Error> {
+        trace!("unix_socket: setsockopt level = {}, optname = {}, ignored", level, optname);
+        Ok(())
+    }
+}
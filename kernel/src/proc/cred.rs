@@ -0,0 +1,146 @@
+use alloc::vec::Vec;
+use crate::fs::{FileStat, StMode};
+use crate::utils::Error;
+use super::get_current_task;
+
+/* 附加组的最大数量 */
+pub const NGROUPS_MAX: usize = 65536;
+
+bitflags! {
+    /* 与access的R_OK, W_OK, X_OK相同 */
+    pub struct Access: u32 {
+        const R = 4;
+        const W = 2;
+        const X = 1;
+    }
+}
+
+/* 进程的用户和组身份, 同一线程组共享, fork时复制, execve时保留 */
+#[derive(Debug, Clone)]
+pub struct Credentials {
+    pub ruid: u32,
+    pub euid: u32,
+    pub suid: u32,
+    pub rgid: u32,
+    pub egid: u32,
+    pub sgid: u32,
+    pub groups: Vec<u32>,
+}
+
+impl Credentials {
+    pub fn root() -> Self {
+        Self { ruid: 0, euid: 0, suid: 0, rgid: 0, egid: 0, sgid: 0, groups: Vec::new() }
+    }
+
+    /* 只有euid为0的进程有特权, 不支持capabilities */
+    pub fn is_root(&self) -> bool {
+        self.euid == 0
+    }
+
+    pub fn in_group(&self, gid: u32) -> bool {
+        self.egid == gid || self.groups.contains(&gid)
+    }
+
+    /* access按照真实id检查权限 */
+    pub fn real(&self) -> Self {
+        Self { euid: self.ruid, egid: self.rgid, ..self.clone() }
+    }
+
+    /* 按照文件的mode位检查访问权限, root可以读写任何文件, 但执行需要至少一个x位 */
+    pub fn may_access(&self, stat: &FileStat, access: Access) -> bool {
+        let mode = stat.st_mode;
+        if self.is_root() {
+            return !access.contains(Access::X)
+                || mode & 0o170000 == StMode::DIR as u32
+                || mode & 0o111 != 0;
+        }
+        let bits = if stat.st_uid == self.euid {
+            (mode >> 6) & 0o7
+        } else if self.in_group(stat.st_gid) {
+            (mode >> 3) & 0o7
+        } else {
+            mode & 0o7
+        };
+        bits & access.bits() == access.bits()
+    }
+
+    /* 文件的所有者或root才能修改文件的mode和时间 */
+    pub fn is_owner(&self, stat: &FileStat) -> bool {
+        self.is_root() || stat.st_uid == self.euid
+    }
+
+    /* execve set-user-ID和set-group-ID程序时改变有效id, 保存的id总是等于新的有效id */
+    pub fn exec(&mut self, stat: &FileStat) {
+        if stat.st_mode & 0o4000 != 0 {
+            self.euid = stat.st_uid;
+        }
+        /* 没有组执行位时set-group-ID表示强制锁, 不改变身份 */
+        if stat.st_mode & 0o2010 == 0o2010 {
+            self.egid = stat.st_gid;
+        }
+        self.suid = self.euid;
+        self.sgid = self.egid;
+    }
+
+    /* 特权进程同时设置三个uid, 否则只能把euid设为ruid或suid */
+    pub fn setuid(&mut self, uid: u32) -> Result<(), Error> {
+        if self.is_root() {
+            self.ruid = uid;
+            self.suid = uid;
+        } else if uid != self.ruid && uid != self.suid {
+            return Err(Error::EPERM);
+        }
+        self.euid = uid;
+        Ok(())
+    }
+
+    pub fn setgid(&mut self, gid: u32) -> Result<(), Error> {
+        if self.is_root() {
+            self.rgid = gid;
+            self.sgid = gid;
+        } else if gid != self.rgid && gid != self.sgid {
+            return Err(Error::EPERM);
+        }
+        self.egid = gid;
+        Ok(())
+    }
+
+    /* u32::MAX(-1)表示不修改, 非特权进程只能使用当前的三个id之一 */
+    pub fn setresuid(&mut self, ruid: u32, euid: u32, suid: u32) -> Result<(), Error> {
+        let ids = [self.ruid, self.euid, self.suid];
+        if !self.is_root() && [ruid, euid, suid].iter().any(|id| *id != u32::MAX && !ids.contains(id)) {
+            return Err(Error::EPERM);
+        }
+        if ruid != u32::MAX { self.ruid = ruid; }
+        if euid != u32::MAX { self.euid = euid; }
+        if suid != u32::MAX { self.suid = suid; }
+        Ok(())
+    }
+
+    pub fn setresgid(&mut self, rgid: u32, egid: u32, sgid: u32) -> Result<(), Error> {
+        let ids = [self.rgid, self.egid, self.sgid];
+        if !self.is_root() && [rgid, egid, sgid].iter().any(|id| *id != u32::MAX && !ids.contains(id)) {
+            return Err(Error::EPERM);
+        }
+        if rgid != u32::MAX { self.rgid = rgid; }
+        if egid != u32::MAX { self.egid = egid; }
+        if sgid != u32::MAX { self.sgid = sgid; }
+        Ok(())
+    }
+
+    pub fn setgroups(&mut self, groups: Vec<u32>) -> Result<(), Error> {
+        if !self.is_root() {
+            return Err(Error::EPERM);
+        }
+        if groups.len() > NGROUPS_MAX {
+            return Err(Error::EINVAL);
+        }
+        self.groups = groups;
+        Ok(())
+    }
+}
+
+/* 当前任务的身份, 内核初始化时没有当前任务, 返回None表示不检查权限 */
+pub fn current_cred() -> Option<Credentials> {
+    get_current_task().map(|task| task.get_cred().clone())
+}
@@ -7,11 +7,12 @@ use alloc::vec::Vec;
 use spin::lazy::Lazy;
 use spin::{Mutex, MutexGuard};
 use crate::config::*;
+use crate::board::CLOCK_FREQ;
 use crate::sbi::sbi_putchar;
 use crate::syscall::time::Timespec;
 use crate::timer::get_time;
 use super::task::CHAN_ALLOCATOR;
-use super::{TaskControlBlock, get_hartid};
+use super::{TaskControlBlock, Waker, get_hartid};
 use log::*;
 
 #[derive(Debug, Clone, Copy)]
@@ -32,12 +33,19 @@ pub static CLOCK_WAKER: Lazy<Mutex<ClockWaker>> = Lazy::new(||{
 });
 
 pub struct ClockWaker {
-    pub list: Vec<(Timespec, Weak<TaskControlBlock>)>
+    pub list: Vec<(Timespec, Weak<TaskControlBlock>)>,
+    /* 到期时调用wake(), 由等待者自己决定如何处理 */
+    pub wakers: Vec<(Timespec, Weak<dyn Waker>)>,
 }
 
 impl ClockWaker {
     pub fn new() -> Self {
-        Self { list: Vec::new() }
+        Self { list: Vec::new(), wakers: Vec::new() }
+    }
+
+    pub fn wait_waker(&mut self, waker: Weak<dyn Waker>, time: Timespec) {
+        self.wakers.retain(|(_, waker)| waker.strong_count() > 0);
+        self.wakers.push((time, waker));
     }
 
     pub fn wait(&mut self, task: Weak<TaskControlBlock>, time: Timespec) {
@@ -45,7 +53,8 @@ impl ClockWaker {
         self.list.push((time, task));
     }
 
-    pub fn wake(&mut self) {
+    /* 唤醒到期的任务, 返回到期的waker, 由调用者在释放CLOCK_WAKER后调用, waker中可以重新注册 */
+    pub fn wake(&mut self) -> Vec<Arc<dyn Waker>> {
         //trace!("clock: try wake task, now = {:?}", Timespec::now());
         self.list
         .drain_filter(|(time, _)| time.pass())
@@ -57,6 +66,10 @@ impl ClockWaker {
                 wake_task(*task.get_channel()); 
             } 
         });
+        self.wakers
+        .drain_filter(|(time, _)| time.pass())
+        .filter_map(|(_, waker)| waker.upgrade())
+        .collect()
     }
 
     fn debug_print(&self) {
@@ -68,13 +81,21 @@ impl ClockWaker {
 }
 
 pub fn wake_clock_futex_task() {
-    CLOCK_WAKER.lock().wake();
+    let wakers = CLOCK_WAKER.lock().wake();
+    for waker in wakers {
+        waker.wake();
+    }
 }
 
 pub fn add_clock_futex_task(task: Weak<TaskControlBlock>, time: Timespec) {
     CLOCK_WAKER.lock().wait(task, time);
 }
 
+/* 到达time时调用waker.wake() */
+pub fn add_clock_waker(waker: Weak<dyn Waker>, time: Timespec) {
+    CLOCK_WAKER.lock().wait_waker(waker, time);
+}
+
 pub struct TaskManager {
     pub ready_tasks: ReadyList,
     pub stopped_tasks: StoppedList,
@@ -216,6 +237,19 @@ impl TaskManager {
         None
     }
 
+    /* 返回所有未退出的任务, 按tid排序 */
+    pub fn get_all_tasks(&self) -> Vec<Arc<TaskControlBlock>> {
+        let mut tasks: Vec<Arc<TaskControlBlock>> = self.ready_tasks.list.iter().cloned().collect();
+        for task in self.running_tasks.iter().flatten() {
+            tasks.push(task.clone());
+        }
+        for (_, task) in self.stopped_tasks.list.iter() {
+            tasks.push(task.clone());
+        }
+        tasks.sort_by_key(|task| task.tid);
+        tasks
+    }
+
     pub fn debug_print(&self) {
         for task in self.ready_tasks.list.iter() {
             println!("task: {}   --Ready", task.tid);
@@ -269,6 +303,55 @@ pub fn get_task_by_tid(tid: i32) -> Option<Arc<TaskControlBlock>> {
     TASK_MANAGER.lock().get_task_by_tid(tid)
 }
 
+pub fn get_all_tasks() -> Vec<Arc<TaskControlBlock>> {
+    TASK_MANAGER.lock().get_all_tasks()
+}
+
+/* 与linux相同，每5秒用可运行的任务数更新一次1/5/15分钟的平均负载，11位定点小数 */
+pub const FSHIFT: usize = 11;
+pub const FIXED_1: usize = 1 << FSHIFT;
+const LOAD_FREQ: usize = 5 * CLOCK_FREQ;
+const EXP: [usize; 3] = [1884, 2014, 2037];
+
+pub struct LoadAvg {
+    pub avenrun: [usize; 3],
+    next_update: usize,
+}
+
+pub static LOAD_AVG: Lazy<Mutex<LoadAvg>> = Lazy::new(||{
+    Mutex::new(LoadAvg { avenrun: [0; 3], next_update: get_time() + LOAD_FREQ })
+});
+
+/* 返回(可运行的任务数，任务总数) */
+pub fn get_task_count() -> (usize, usize) {
+    let manager = TASK_MANAGER.lock();
+    let running = manager.ready_tasks.list.len()
+        + manager.running_tasks.iter().filter(|task| task.is_some()).count();
+    (running, running + manager.stopped_tasks.list.len())
+}
+
+/* 在时钟中断中调用; 空闲时没有时钟中断，错过的周期按当前任务数一并补上 */
+pub fn calc_load() {
+    let now = get_time();
+    let mut loadavg = LOAD_AVG.lock();
+    if now < loadavg.next_update {
+        return;
+    }
+    let missed = (now - loadavg.next_update) / LOAD_FREQ + 1;
+    let active = get_task_count().0 * FIXED_1;
+    for _ in 0..missed.min(1000) {
+        for (load, exp) in loadavg.avenrun.iter_mut().zip(EXP.iter()) {
+            *load = (*load * exp + active * (FIXED_1 - exp)) >> FSHIFT;
+        }
+    }
+    loadavg.next_update += missed * LOAD_FREQ;
+}
+
+pub fn get_loadavg() -> [usize; 3] {
+    calc_load();
+    LOAD_AVG.lock().avenrun
+}
+
 pub static TASK_MANAGER: Lazy<Mutex<TaskManager>> = Lazy::new(||{
     Mutex::new(TaskManager::new())
-});
\ No newline at end of file
+});
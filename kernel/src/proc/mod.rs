@@ -9,6 +9,8 @@ mod sig_handlers;
 mod signal;
 mod futex;
 mod thread;
+mod cred;
+mod wait_queue;
 
 use core::sync::atomic::Ordering;
 use spin::{lazy::Lazy, MutexGuard};
@@ -24,6 +26,8 @@ pub use manager::*;
 pub use processor::*;
 pub use futex::*;
 pub use thread::*;
+pub use cred::*;
+pub use wait_queue::*;
 pub use kernel_stack::KernelStack;
 use crate::{loader::get_app_data_by_name, memory::copyout};
 
@@ -105,7 +109,7 @@ pub fn exit_current_group(exit_code: i32) {
     }
     thread_group.list.clear();
     let mut fdtable = current_task.get_fd_table();
-    fdtable.table.clear();
+    fdtable.close_all(current_task.pid);
     drop(fdtable);
     drop(thread_group);
 
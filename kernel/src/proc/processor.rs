@@ -1,9 +1,12 @@
 use core::arch::asm;
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use alloc::sync::Arc;
 use alloc::vec::Vec;
 use spin::lazy::Lazy;
 use super::*;
 use crate::config::MAX_CPU_NUM;
+use crate::fs::{flush_tick, poll_input};
+use crate::net::poll_interfaces;
 use crate::sbi::sbi_putchar;
 use crate::timer::get_time;
 use crate::trap::TrapContext;
@@ -57,6 +60,39 @@ pub static PROCESSOR_LIST: Lazy<Vec<UPSafeCell<Processor>>> = Lazy::new(||{
     list
 });
 
+/* 每个CPU的时间统计(单位为时钟周期), 用于/proc/stat */
+pub struct CpuStat {
+    pub online: AtomicBool,
+    pub user: AtomicU64,
+    pub system: AtomicU64,
+    pub idle: AtomicU64,
+    pub switches: AtomicU64,    /* 上下文切换次数 */
+}
+
+impl CpuStat {
+    pub fn new() -> Self {
+        Self {
+            online: AtomicBool::new(false),
+            user: AtomicU64::new(0),
+            system: AtomicU64::new(0),
+            idle: AtomicU64::new(0),
+            switches: AtomicU64::new(0),
+        }
+    }
+}
+
+pub static CPU_STAT_LIST: Lazy<Vec<CpuStat>> = Lazy::new(||{
+    let mut list = Vec::new();
+    for _ in 0..MAX_CPU_NUM {
+        list.push(CpuStat::new());
+    }
+    list
+});
+
+pub fn get_cpu_stat() -> &'static CpuStat {
+    &CPU_STAT_LIST[get_hartid()]
+}
+
 #[no_mangle]
 #[inline(always)]
 pub fn get_hartid() -> usize {
@@ -103,7 +139,13 @@ pub fn sched(switched_task_cx_ptr: *mut TaskContext) {
 }
 
 pub fn scheduler() {
+    let cpu_stat = get_cpu_stat();
+    cpu_stat.online.store(true, Ordering::Release);
+    /* 没有任务运行的时间计为idle */
+    let mut idle_start = get_time() as u64;
     loop {
+        /* 调度器中没有持有任何锁, 写回过期的脏页和脏块 */
+        flush_tick();
         let id = get_hartid();
         let mut processor = PROCESSOR_LIST[id].lock();
 
@@ -116,6 +158,9 @@ pub fn scheduler() {
             let task_info = task.get_task_info();
             let next_task_cx_ptr = &task_info.context as *const TaskContext;
             task.set_status(TaskStatus::RUNNING);
+            task.time_info.lock().update_time_resumed();
+            cpu_stat.idle.fetch_add(get_time() as u64 - idle_start, Ordering::Relaxed);
+            cpu_stat.switches.fetch_add(1, Ordering::Relaxed);
 
             drop(task_info);
             processor.current = Some(task);
@@ -123,6 +168,13 @@ pub fn scheduler() {
             unsafe{
                 __switch(switch_task_cx_ptr, next_task_cx_ptr);
             }
+            idle_start = get_time() as u64;
+        } else {
+            drop(processor);
+            /* 没有就绪的任务时检查定时睡眠是否到期, 并处理网卡和终端的输入(它们没有中断) */
+            wake_clock_futex_task();
+            poll_interfaces();
+            poll_input();
         }
     }
 }
@@ -164,6 +164,13 @@ impl Sigaction {
 }
 
 
+//si_code
+pub const SI_USER    : i32 = 0;     //由kill等系统调用发送
+pub const SEGV_MAPERR: i32 = 1;     //地址没有被映射
+pub const SEGV_ACCERR: i32 = 2;     //地址被映射但没有访问权限
+pub const ILL_ILLOPC : i32 = 1;     //非法指令
+pub const BUS_ADRALN : i32 = 1;     //地址未对齐
+
 #[allow(unused)]
 #[repr(C)]
 #[derive(Copy, Clone, PartialEq,Debug)]
@@ -172,8 +179,7 @@ pub struct SigInfo {
     si_errno     :i32,	/* An errno value */
     si_code      :i32,	/* Signal code */
     si_trapno    :i32,	/* Trap number that caused hardware-generated signal (unused on most architectures) */
-    si_pid       :u32,	/* Sending process ID */
-    si_uid       :u32,	/* Real user ID of sending process */
+    si_addr      :usize,	/* Memory location which caused fault, shares space with si_pid/si_uid */
     si_status    :i32,	/* Exit value or signal */
     si_utime     :i32,	/* User time consumed */
     si_stime     :i32,	/* System time consumed */
@@ -183,15 +189,35 @@ impl SigInfo{
         SigInfo{
             si_signo     :0,	
             si_errno     :0,	
-            si_code      :0,	
+            si_code      :SI_USER,	
             si_trapno    :0,	
-            si_pid       :0,	
-            si_uid       :0,	
+            si_addr      :0,	
             si_status    :0,	
             si_utime     :0,	
             si_stime     :0,	
         }
     }
+
+    /* 由硬件异常产生的信号, 需要带上出错原因和出错地址 */
+    pub fn new_fault(signo: usize, code: i32, addr: usize) -> SigInfo {
+        let mut info = SigInfo::new();
+        info.si_signo = signo as i32;
+        info.si_code = code;
+        info.si_addr = addr;
+        info
+    }
+
+    pub fn set_signo(&mut self, signo: usize) {
+        self.si_signo = signo as i32;
+    }
+
+    pub fn code(&self) -> i32 {
+        self.si_code
+    }
+
+    pub fn addr(&self) -> usize {
+        self.si_addr
+    }
 }
 
 
@@ -251,6 +277,11 @@ impl SignalContext {
         self.ucontext.uc_sigmask.__val[0] = mask;
     }
 
+    pub fn set_info(&mut self, mut info: SigInfo) {
+        info.set_signo(self.signum);
+        self.info = info;
+    }
+
     pub fn pc(&self) -> usize {
         self.ucontext.uc_mcontext.greps[0]
     }
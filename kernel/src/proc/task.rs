@@ -15,6 +15,8 @@ use crate::{memory::{MemorySet, PhysPageNum, VirtAddr, copyout, get_kernel_space
 use crate::trap::{TrapContext, trap_handler};
 use crate::config::*;
 use crate::fs::{File, PTS, FileOpenMode};
+use crate::fs::lock::{release_locks, release_process_locks};
+use crate::fs::inotify;
 use crate::utils::{Path, Error, allocator::IdAllocator};
 use super::*;
 
@@ -102,6 +104,14 @@ impl FdTable {
         }
     }
 
+    /* 关闭所有文件, 释放进程在这些文件上的锁 */
+    pub fn close_all(&mut self, pid: i32) {
+        for (_, file) in core::mem::take(&mut self.table) {
+            release_locks(pid, &file);
+            inotify::notify_close(&file);
+        }
+    }
+
     pub fn delete_file(&mut self, fd: u32) -> Result<u32, Error> {
         match self.table.remove(&fd) {
             Some(_) => Ok(fd),
@@ -126,7 +136,9 @@ impl FdTable {
 
 /* file system information struct */
 pub struct FsStruct {
-    pub cwd: Path
+    pub cwd: Path,
+    /* 创建文件时从权限中去除的位 */
+    pub umask: u32,
 }
 
 /* 包含了线程间不会共享的一些数据 */
@@ -167,17 +179,28 @@ impl TimeStruct {
 
     pub fn update_time_scheduled(&mut self) {
         self.timestamp_exit_smode = get_time() as u64;
-        self.stime += self.timestamp_exit_smode - self.timestamp_enter_smode;
+        let delta = self.timestamp_exit_smode - self.timestamp_enter_smode;
+        self.stime += delta;
+        get_cpu_stat().system.fetch_add(delta, Ordering::Relaxed);
     } 
 
+    /* 任务被重新调度时，等待调度的时间不计入stime */
+    pub fn update_time_resumed(&mut self) {
+        self.timestamp_enter_smode = get_time() as u64;
+    }
+
     pub fn update_time_enter_kernel(&mut self) {
         self.timestamp_enter_smode = get_time() as u64;
-        self.utime += self.timestamp_enter_smode - self.timestamp_exit_smode;
+        let delta = self.timestamp_enter_smode - self.timestamp_exit_smode;
+        self.utime += delta;
+        get_cpu_stat().user.fetch_add(delta, Ordering::Relaxed);
     }
 
     pub fn update_time_exit_kernel(&mut self) {
         self.timestamp_exit_smode = get_time() as u64;
-        self.stime += self.timestamp_exit_smode - self.timestamp_enter_smode;
+        let delta = self.timestamp_exit_smode - self.timestamp_enter_smode;
+        self.stime += delta;
+        get_cpu_stat().system.fetch_add(delta, Ordering::Relaxed);
     }
 
     pub fn update_time_child_exit(&mut self, child_time_info: &TimeStruct) {
@@ -200,11 +223,13 @@ impl SigHandlers {
 
 pub struct SigPending {
     pub pendings: Vec<usize>,
+    /* 硬件异常产生的信号附带的siginfo */
+    pub infos: BTreeMap<usize, SigInfo>,
 }
 
 impl SigPending {
     pub fn new() -> Self {
-        Self { pendings: Vec::new() }
+        Self { pendings: Vec::new(), infos: BTreeMap::new() }
     }
 
     fn block(signo: usize, mask: usize) -> bool {
@@ -243,6 +268,15 @@ impl SigPending {
             }
         }
     }
+
+    pub fn pending_signal_info(&mut self, signum: usize, info: SigInfo) {
+        self.pending_signal(signum);
+        self.infos.insert(signum, info);
+    }
+
+    pub fn take_info(&mut self, signum: usize) -> Option<SigInfo> {
+        self.infos.remove(&signum)
+    }
 }
 
 
@@ -302,6 +336,7 @@ pub struct TaskControlBlock {
     pub parent      :Mutex< Option<Weak<TaskControlBlock>>>,
     pub time_info   :Mutex< TimeStruct>,
     pub sig_mask    :Mutex< usize>,
+    pub saved_mask  :Mutex< Option<usize>>,   /* ppoll, epoll_pwait等临时替换掩码时保存的原掩码 */
     pub t_pending   :Mutex< SigPending>,  
     pub robust_list :Mutex< Option<RobustList>>,
     
@@ -312,6 +347,7 @@ pub struct TaskControlBlock {
     pub child       :Arc<Mutex< Vec<Arc<TaskControlBlock>>>>,
     pub rlim        :Arc<Mutex< RlimArr>>,
     pub fs_info     :Arc<Mutex< FsStruct>>,
+    pub cred        :Arc<Mutex< Credentials>>,
     pub fd_table    :Arc<Mutex< FdTable>>,
     pub handlers    :Arc<Mutex< SigHandlers>>,
     pub p_pending   :Arc<Mutex< SigPending>>,
@@ -340,6 +376,10 @@ impl TaskControlBlock {
         self.fs_info.lock()
     }
 
+    pub fn get_cred(&self) -> MutexGuard<Credentials> {
+        self.cred.lock()
+    }
+
     pub fn get_user_token(&self) -> usize {
         self.get_memory().token()
     }
@@ -451,6 +491,58 @@ impl TaskControlBlock {
         || self.p_pending.lock().has_signal(mask)
     }
 
+    /// signalfd取走set中的一个待处理信号, 不论是否被屏蔽, 取走后不再递送
+    pub fn dequeue_signal(&self, set: usize) -> Option<(usize, SigInfo)> {
+        for pending in [&self.t_pending, &*self.p_pending] {
+            let mut pending = pending.lock();
+            if let Some(signo) = pending.get_signal(!set) {
+                let info = pending.take_info(signo).unwrap_or(SigInfo::new());
+                return Some((signo, info));
+            }
+        }
+        None
+    }
+
+    pub fn has_signal_in(&self, set: usize) -> bool {
+        self.t_pending.lock().has_signal(!set)
+        || self.p_pending.lock().has_signal(!set)
+    }
+
+    /// ppoll, pselect6, epoll_pwait等待期间临时使用mask作为信号掩码
+    pub fn set_temp_mask(&self, mask: usize) {
+        let mut sig_mask = self.sig_mask.lock();
+        *self.saved_mask.lock() = Some(*sig_mask);
+        *sig_mask = mask;
+    }
+
+    /// 系统调用返回前恢复原来的掩码
+    /// 被信号中断时保留临时掩码, 信号在临时掩码下递送, 处理函数返回后才恢复原掩码
+    pub fn restore_mask(&self, interrupted: bool) {
+        if interrupted {
+            return;
+        }
+        if let Some(mask) = self.saved_mask.lock().take() {
+            *self.sig_mask.lock() = mask;
+        }
+    }
+
+    /// 发送由硬件异常产生的信号(SIGSEGV/SIGILL/SIGBUS等)
+    /// 与linux的force_sig_info相同: 如果该信号被屏蔽或者被忽略, 则解除屏蔽并恢复默认处理, 避免进程在出错的指令上死循环
+    pub fn force_signal(&self, signo: usize, info: SigInfo) {
+        let mut handlers = self.get_handlers();
+        let mut sig_mask = self.sig_mask.lock();
+        let blocked = *sig_mask & (1 << (signo - 1)) != 0;
+        if let Some(sigaction) = handlers.table.get_mut(&signo) {
+            if blocked || sigaction.sa_handler == SIG_IGN {
+                sigaction.sa_handler = SIG_DFL;
+            }
+        }
+        *sig_mask &= !(1 << (signo - 1));
+        drop(sig_mask);
+        drop(handlers);
+        self.t_pending.lock().pending_signal_info(signo, info);
+    }
+
     pub fn is_leader(&self) -> bool {
         self.get_thread_group().get_tgid() == self.tid
     }
@@ -461,8 +553,9 @@ impl TaskControlBlock {
         let mut handler = self.get_handlers();
         handler.table.clear();
         let mut fd_table = self.get_fd_table();
-        fd_table.table.clear();
-        
+        fd_table.close_all(self.pid);
+        drop(fd_table);
+        release_process_locks(self.pid);
     }
 
     pub fn handle_signal(&self) {
@@ -471,9 +564,16 @@ impl TaskControlBlock {
             fn signal_default_handlers();
         }
 
+        /* 临时掩码只在本次信号递送中生效, 没有可递送的信号时直接恢复 */
+        let saved_mask = self.saved_mask.lock().take();
+        let restore = || if let Some(mask) = saved_mask {
+            *self.sig_mask.lock() = mask;
+        };
+
         if self.t_pending.lock().pendings.len() == 0 
          && self.p_pending.lock().pendings.len() == 0 
         {
+            restore();
             return;
         }
         
@@ -481,16 +581,21 @@ impl TaskControlBlock {
             Some(signo) => {
                 signo
             }
-            None => return
+            None => {
+                restore();
+                return;
+            }
         };
 
-        let mask = *self.sig_mask.lock();
+        /* 信号处理函数返回时恢复的是调用ppoll等之前的掩码 */
+        let mask = saved_mask.unwrap_or(*self.sig_mask.lock());
         let mut handlers = self.get_handlers();
 
         let sigaction = match handlers.table.get_mut(&signo) {
             Some(sigaction) => sigaction,
             None => {
                 warn!("handle signal: sigaction is null");
+                restore();
                 return;
             }
         };
@@ -501,13 +606,23 @@ impl TaskControlBlock {
         //创建信号处理上下文
         let mut signal_context = SignalContext::new(signo);
         signal_context.set_mask(mask);
+        let info = self.t_pending.lock().take_info(signo)
+            .or_else(|| self.p_pending.lock().take_info(signo));
+        signal_context.set_info(info.unwrap_or(SigInfo::new()));
         /* 将SignalContext数据放入栈中 */
         trap_context.x[2] = trap_context.x[2] - core::mem::size_of::<SignalContext>();//栈空出SignalContext的位置
         let signal_context_addr = trap_context.x[2];  //获取SignalContext的用户地址
         let ucontext_addr = signal_context_addr ;   //获取ucontext的用户地址
         let siginfo_addr  = signal_context_addr + core::mem::size_of::<UContext>();//获取siginfo的用户地址
         let user_token = self.get_user_token();
-        copyout(user_token,signal_context_addr as *mut SignalContext,&mut signal_context).unwrap();//将SignalContext数据放入栈中
+        //将SignalContext数据放入栈中
+        if copyout(user_token,signal_context_addr as *mut SignalContext,&mut signal_context).is_err() {
+            /* 用户栈已经不可用, 无法执行信号处理函数, 只能结束进程 */
+            warn!("handle signal: bad user stack {:#x}, kill tid = {}", signal_context_addr, self.tid);
+            drop(handlers);
+            exit_current_group(-(signo as i32));
+            unreachable!();
+        }
 
         /* 更新当前的signal_context指针 */
         let mut signal_context_ptr = self.signal_context_ptr.lock();
@@ -594,6 +709,7 @@ impl TaskControlBlock {
             parent: Mutex::new(None),
             time_info: Mutex::new(TimeStruct::new()),
             sig_mask:  Mutex::new(0),
+            saved_mask: Mutex::new(None),
             t_pending: Mutex::new(SigPending::new()),
             robust_list: Mutex::new(None),
 
@@ -609,7 +725,8 @@ impl TaskControlBlock {
             swap    :Arc::new(Mutex::new(SwapList::new())),
             child   :Arc::new(Mutex::new(Vec::new())),
             rlim    :Arc::new(Mutex::new(Default::default())),
-            fs_info :Arc::new(Mutex::new(FsStruct { cwd: "/".into() })),
+            fs_info :Arc::new(Mutex::new(FsStruct { cwd: "/".into(), umask: 0o022 })),
+            cred    :Arc::new(Mutex::new(Credentials::root())),
             fd_table:Arc::new(Mutex::new(FdTable { table: fd_table })),
             handlers:Arc::new(Mutex::new(SigHandlers::new())),
             p_pending: Arc::new(Mutex::new(SigPending::new())),
@@ -704,13 +821,18 @@ impl TaskControlBlock {
     /* 负责sys_exec的大部分工作 */
    pub fn exec(
         &self, 
+        exe: Path,
         elf_file: Arc<dyn File>, 
         arg_strings: Vec<String>, 
         env_strings: Vec<String>
     ) -> Result<isize, Error> {
+    let stat = elf_file.read_stat()?;
      /* 根据elf_data构造新的memory_set */
     let (mut memory_set, user_sp, entry_point,mut auxv) = MemorySet::from_elf_file(elf_file);
     memory_set.push_trapframe(self.private_tid);
+    memory_set.exe = exe;
+    memory_set.cmdline = arg_strings.clone();
+    memory_set.environ = env_strings.clone();
 
     let trap_cx_ppn = memory_set
         .translate_vpn_to_pte(VirtAddr::from(TRAPFRAME).into())
@@ -723,6 +845,9 @@ impl TaskControlBlock {
     let (current_sp, arvp_ptr, envp_ptr, auxv_ptr) = 
         self.init_user_stack(user_sp, arg_strings, env_strings, auxv)?;
 
+    /* 新的映像和用户栈都建好后, set-user-ID和set-group-ID程序才改变进程的有效id */
+    self.get_cred().exec(&stat);
+
     /* execve需要清空sig_handlers */
     *self.handlers.lock() = SigHandlers::new();;
 
@@ -769,12 +894,21 @@ impl TaskControlBlock {
         if clone_flags.contains(CloneFlags::FS) {
             self.fs_info.clone()
         } else {
+            let fs_info = self.fs_info.lock();
             Arc::new(Mutex::new(
-                FsStruct { cwd: self.fs_info.lock().cwd.clone() }
+                FsStruct { cwd: fs_info.cwd.clone(), umask: fs_info.umask }
             ))
         }
     }
 
+    fn copy_cred(&self, clone_flags: CloneFlags) -> Arc<Mutex<Credentials>> {
+        if clone_flags.contains(CloneFlags::THREAD) {
+            self.cred.clone()
+        } else {
+            Arc::new(Mutex::new(self.cred.lock().clone()))
+        }
+    }
+
     fn copy_sighander(&self, clone_flags: CloneFlags) -> Arc<Mutex<SigHandlers>> {
         if clone_flags.contains(CloneFlags::SIGHAND) || clone_flags.contains(CloneFlags::THREAD) {
             self.handlers.clone()
@@ -789,8 +923,12 @@ impl TaskControlBlock {
         if clone_flags.contains(CloneFlags::THREAD) {
             self.p_pending.clone()
         } else {
+            let p_pending = self.p_pending.lock();
             Arc::new(Mutex::new(
-                SigPending { pendings: self.p_pending.lock().pendings.clone() }
+                SigPending {
+                    pendings: p_pending.pendings.clone(),
+                    infos: p_pending.infos.clone(),
+                }
             ))
         }
     }
@@ -889,6 +1027,7 @@ impl TaskControlBlock {
             parent: Mutex::new(parent),
             time_info: Mutex::new( TimeStruct::new()),
             sig_mask : Mutex::new(*self.sig_mask.lock()),
+            saved_mask: Mutex::new(None),
             t_pending: Mutex::new(SigPending::new()),
             robust_list: Mutex::new(None),
 
@@ -901,6 +1040,7 @@ impl TaskControlBlock {
             groups,
             rlim, 
             fs_info: self.copy_fs(clone_flags),
+            cred: self.copy_cred(clone_flags),
             fd_table: self.copy_fd_table(clone_flags),
             handlers: self.copy_sighander(clone_flags),
             p_pending: self.copy_signal(clone_flags),
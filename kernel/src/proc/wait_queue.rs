@@ -0,0 +1,167 @@
+use alloc::{
+    collections::BTreeMap,
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use spin::{Mutex, lazy::Lazy};
+
+use crate::syscall::time::Timespec;
+use crate::utils::Error;
+use super::{TaskControlBlock, get_current_task, sleep_current, wake_task, add_clock_waker};
+
+/// 等待队列: 文件状态变化时通知注册在上面的等待者
+/// 1, 等待者实现Waker, 可以是睡眠的任务(ppoll, epoll_wait)或者epoll中监听的一项
+/// 2, 队列中只保存弱引用, 等待者释放后自动失效
+/// 3, wake()可能在持有文件内部锁时被调用, 实现中不能再获取文件的锁
+pub trait Waker: Send + Sync {
+    fn wake(&self);
+}
+
+#[derive(Default)]
+pub struct WaitQueue {
+    waiters: Mutex<Vec<Weak<dyn Waker>>>,
+}
+
+fn same_waker(weak: &Weak<dyn Waker>, waker: &Arc<dyn Waker>) -> bool {
+    weak.as_ptr() as *const () == Arc::as_ptr(waker) as *const ()
+}
+
+impl WaitQueue {
+    pub fn new() -> Self {
+        Self { waiters: Mutex::new(Vec::new()) }
+    }
+
+    pub fn register(&self, waker: &Arc<dyn Waker>) {
+        let mut waiters = self.waiters.lock();
+        waiters.retain(|weak| weak.strong_count() > 0);
+        if !waiters.iter().any(|weak| same_waker(weak, waker)) {
+            waiters.push(Arc::downgrade(waker));
+        }
+    }
+
+    pub fn unregister(&self, waker: &Arc<dyn Waker>) {
+        self.waiters.lock().retain(|weak| weak.strong_count() > 0 && !same_waker(weak, waker));
+    }
+
+    pub fn wake_all(&self) {
+        let waiters: Vec<Arc<dyn Waker>> = self.waiters
+            .lock()
+            .iter()
+            .filter_map(|weak| weak.upgrade())
+            .collect();
+        for waker in waiters {
+            waker.wake();
+        }
+    }
+}
+
+/* 正在等待的任务, 信号到达时唤醒, tid -> (pid, waker) */
+static SIGNAL_WAKERS: Lazy<Mutex<BTreeMap<i32, (i32, Weak<TaskWaker>)>>> = Lazy::new(|| Mutex::new(BTreeMap::new()));
+
+/// 在多个等待队列上睡眠的任务, 被唤醒, 超时或者收到信号时返回
+/// woken记录睡眠前到达的唤醒, 检查就绪状态和睡眠之间的唤醒不会丢失
+pub struct TaskWaker {
+    task: Weak<TaskControlBlock>,
+    tid: i32,
+    woken: Mutex<bool>,
+}
+
+impl TaskWaker {
+    /* 为当前任务创建, deadline为None时没有超时 */
+    pub fn new(deadline: Option<Timespec>) -> Arc<Self> {
+        let task = get_current_task().unwrap();
+        let waker = Arc::new(Self {
+            task: Arc::downgrade(&task),
+            tid: task.tid,
+            woken: Mutex::new(false),
+        });
+        SIGNAL_WAKERS.lock().insert(task.tid, (task.pid, Arc::downgrade(&waker)));
+        if let Some(deadline) = deadline {
+            let weak: Weak<dyn Waker> = Arc::downgrade(&waker);
+            add_clock_waker(weak, deadline);
+        }
+        waker
+    }
+
+    pub fn as_waker(self: &Arc<Self>) -> Arc<dyn Waker> {
+        self.clone()
+    }
+
+    /* 睡眠直到被唤醒, tick不为None时最多睡眠到tick(用于不支持唤醒的文件) */
+    pub fn sleep(self: &Arc<Self>, tick: Option<Timespec>) {
+        if let Some(tick) = tick {
+            let weak: Weak<TaskWaker> = Arc::downgrade(self);
+            add_clock_waker(weak, tick);
+        }
+        let task = get_current_task().unwrap();
+        let woken = self.woken.lock();
+        if !*woken {
+            let chan = *task.get_channel();
+            drop(task);
+            sleep_current(chan, woken);
+        } else {
+            drop(woken);
+        }
+        *self.woken.lock() = false;
+    }
+}
+
+impl Waker for TaskWaker {
+    fn wake(&self) {
+        let mut woken = self.woken.lock();
+        *woken = true;
+        if let Some(task) = self.task.upgrade() {
+            wake_task(*task.get_channel());
+        }
+    }
+}
+
+impl Drop for TaskWaker {
+    fn drop(&mut self) {
+        SIGNAL_WAKERS.lock().remove(&self.tid);
+    }
+}
+
+/// 在queue上阻塞直到f返回Some, 收到信号时返回EINTR
+/// 用于eventfd, timerfd等状态变化时总会唤醒自己队列的文件
+pub fn wait_on<T>(queue: &WaitQueue, f: impl FnMut() -> Option<Result<T, Error>>) -> Result<T, Error> {
+    wait_on_all(&[queue], f)
+}
+
+/// 同时在多个queue上等待, 任意一个被唤醒时重新调用f, 用于splice等涉及两个管道的操作
+pub fn wait_on_all<T>(queues: &[&WaitQueue], mut f: impl FnMut() -> Option<Result<T, Error>>) -> Result<T, Error> {
+    let waker = TaskWaker::new(None);
+    let dyn_waker = waker.as_waker();
+    for queue in queues {
+        queue.register(&dyn_waker);
+    }
+    let ret = loop {
+        if let Some(ret) = f() {
+            break ret;
+        }
+        if get_current_task().unwrap().has_signal() {
+            break Err(Error::EINTR);
+        }
+        waker.sleep(None);
+    };
+    for queue in queues {
+        queue.unregister(&dyn_waker);
+    }
+    ret
+}
+
+/* 向进程(tid为None)或线程发送信号后唤醒正在等待的任务 */
+pub fn wake_signal_waiters(pid: i32, tid: Option<i32>) {
+    let wakers: Vec<Arc<TaskWaker>> = SIGNAL_WAKERS
+        .lock()
+        .iter()
+        .filter(|(waiter_tid, (waiter_pid, _))| match tid {
+            Some(tid) => **waiter_tid == tid,
+            None => *waiter_pid == pid,
+        })
+        .filter_map(|(_, (_, waker))| waker.upgrade())
+        .collect();
+    for waker in wakers {
+        waker.wake();
+    }
+}
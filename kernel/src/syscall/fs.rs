@@ -1,18 +1,25 @@
-use crate::config::MAX_FD_LEN;
+use crate::config::{MAX_FD_LEN, PAGE_SIZE, POLL_RECHECK_MS, PIPE_MAX_SIZE, PIPE_HARD_MAX_SIZE};
 use crate::fs::file::PollType;
 use crate::fs::vfs::Statvfs;
-use crate::memory::{copyin_vec, copyout_vec, translate_str, copyout, copyin};
+use crate::memory::{copyin_vec, copyout_vec, translate_str, copyout, copyin, frame_safe_alloc};
 use crate::proc::{ 
-      get_current_user_token, get_current_task, suspend_current,
+      get_current_user_token, get_current_task, current_cred, Access, TaskWaker,
 };
-use crate::fs::fifo::create_pipe;
+use crate::fs::fifo::{create_pipe, Pipe, PipeBuf};
+use crate::fs::page_cache::PageCache;
+use crate::fs::epoll::{Epoll, EpollEvent, EPOLL_CTL_DEL, poll_events};
+use crate::fs::eventfd::{EventFd, EFD_SEMAPHORE, EFD_NONBLOCK, EFD_CLOEXEC};
+use crate::fs::inotify::{self, Inotify, IN_NONBLOCK, IN_CLOEXEC, IN_ONLYDIR, IN_DONT_FOLLOW};
+use crate::fs::lock::{FileLock, LockOwner, LockType, lock_index, release_locks, set_lock, test_lock};
 use crate::trap::flush_tlb;
 use crate::utils::mem_buffer::MemBuffer;
 use crate::utils::{Path, Error};
 use crate::fs::{File, open, open_at, mknod_at, FileOpenMode, 
-    FileType, FilePerm, FileStat, mount, umount, delete_at, SeekMode, get_vfs};
+    FileType, FilePerm, FileStat, StMode, mount, umount, delete_at, link_at, rename_at, SeekMode, get_vfs,
+    check_access_with};
 use alloc::borrow::ToOwned;
 use alloc::{
+    collections::VecDeque,
     sync::Arc,
     string::String,
     vec::Vec,
@@ -27,6 +34,14 @@ use core::{usize, panic};
 use super::time::{Timespec, Timeval};
 
 pub const AT_FDCWD: i32 = -100;
+pub const AT_SYMLINK_NOFOLLOW: u32 = 0x100;
+pub const AT_EACCESS: u32 = 0x200;
+/* unlinkat的flags, 与AT_EACCESS的值相同 */
+pub const AT_REMOVEDIR: u32 = 0x200;
+pub const AT_SYMLINK_FOLLOW: u32 = 0x400;
+pub const AT_EMPTY_PATH: u32 = 0x1000;
+pub const S_IFMT: u32 = 0o170000;
+pub const RENAME_NOREPLACE: u32 = 1;
 
 #[repr(C)]
 #[derive(Clone, Copy, Debug, Default)]
@@ -109,17 +124,100 @@ pub fn sys_dup3(old_fd: u32, new_fd: u32) -> Result<isize, Error> {
 
     /* If the file descriptor newfd was previously open,
     it is closed before being reused */
-    fd_table.table.insert(new_fd, file);
+    if let Some(old_file) = fd_table.table.insert(new_fd, file) {
+        release_locks(task.pid, &old_file);
+        inotify::notify_close(&old_file);
+    }
     
     Ok(new_fd as isize)
 }
 
 //只实现了测试程序需要用到的flag
+/* fcntl记录锁使用的结构 */
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Flock {
+    pub l_type: i16,
+    pub l_whence: i16,
+    pub l_start: i64,
+    pub l_len: i64,
+    pub l_pid: i32,
+}
+
+const F_GETLK: u32 = 5;
+const F_SETLK: u32 = 6;
+const F_SETLKW: u32 = 7;
+const F_RDLCK: i16 = 0;
+const F_WRLCK: i16 = 1;
+const F_UNLCK: i16 = 2;
+
+/* 将flock描述的范围换算为[start, end), l_len为0表示到文件末尾, 为负数表示l_start之前的字节 */
+fn flock_range(file: &Arc<dyn File>, flock: &Flock) -> Result<(u64, u64), Error> {
+    let base = match flock.l_whence {
+        0 => 0,
+        1 => file.seek(0, SeekMode::CUR)? as i64,
+        2 => file.read_stat()?.st_size as i64,
+        _ => return Err(Error::EINVAL),
+    };
+    let start = base.checked_add(flock.l_start).ok_or(Error::EOVERFLOW)?;
+    let (start, end) = match flock.l_len {
+        0 => (start, i64::MAX),
+        len if len > 0 => (start, start.checked_add(len).ok_or(Error::EOVERFLOW)?),
+        len => (start + len, start),
+    };
+    if start < 0 {
+        return Err(Error::EINVAL);
+    }
+    let end = if end == i64::MAX { u64::MAX } else { end as u64 };
+    Ok((start as u64, end))
+}
+
+fn fcntl_lock(file: Arc<dyn File>, request: u32, arg: *mut Flock) -> Result<isize, Error> {
+    let task = get_current_task().unwrap();
+    let token = task.get_user_token();
+    let mut flock = Flock::default();
+    copyin(token, &mut flock, arg)?;
+
+    let index = lock_index(&file)?;
+    let (start, end) = flock_range(&file, &flock)?;
+    let ltype = match flock.l_type {
+        F_RDLCK => Some(LockType::Read),
+        F_WRLCK => Some(LockType::Write),
+        F_UNLCK => None,
+        _ => return Err(Error::EINVAL),
+    };
+    let lock = FileLock { owner: LockOwner::Process(task.pid), ltype: LockType::Read, start, end };
+
+    if request == F_GETLK {
+        let ltype = ltype.ok_or(Error::EINVAL)?;
+        match test_lock(index, &FileLock { ltype, ..lock }) {
+            Some(held) => {
+                flock.l_type = if held.ltype == LockType::Write { F_WRLCK } else { F_RDLCK };
+                flock.l_whence = 0;
+                flock.l_start = held.start as i64;
+                flock.l_len = if held.end == u64::MAX { 0 } else { (held.end - held.start) as i64 };
+                flock.l_pid = match held.owner {
+                    LockOwner::Process(pid) => pid,
+                    LockOwner::File(_) => -1,
+                };
+            }
+            None => flock.l_type = F_UNLCK,
+        }
+        copyout(token, arg, &flock)?;
+        return Ok(0);
+    }
+    drop(task);
+    set_lock(index, lock, ltype, request == F_SETLKW)?;
+    Ok(0)
+}
+
 pub fn sys_fcntl(fd: u32, request: u32, arg: usize) -> Result<isize, Error> {
     const F_GETFD: u32 = 1;
     const F_SETFD: u32 = 2;
     const F_GETFL: u32 = 3;
     const F_DUPFD_CLOEXEC: u32 = 1030;
+    const F_SETPIPE_SZ: u32 = 1031;
+    const F_GETPIPE_SZ: u32 = 1032;
     trace!("sys_fcntl: fd = {}. request = {}, arg_ptr = {}", fd, request, arg);
 
     let task = get_current_task().unwrap();
@@ -151,6 +249,27 @@ pub fn sys_fcntl(fd: u32, request: u32, arg: usize) -> Result<isize, Error> {
         F_GETFL => {
             return Ok(0xffff);
         }
+        F_GETLK | F_SETLK | F_SETLKW => {
+            return fcntl_lock(file, request, arg as *mut Flock);
+        }
+        /* 返回实际设置的容量, 非特权进程不能超过PIPE_MAX_SIZE */
+        F_SETPIPE_SZ => {
+            let pipe = file.as_pipe().map_err(|_| Error::EBADF)?;
+            /* arg是int, 负数和超过PIPE_HARD_MAX_SIZE的值在取整之前拒绝 */
+            let size = arg as i32;
+            if size <= 0 || size as usize > PIPE_HARD_MAX_SIZE {
+                return Err(Error::EINVAL);
+            }
+            let size = size as usize;
+            if size > PIPE_MAX_SIZE && current_cred().map_or(false, |cred| !cred.is_root()) {
+                return Err(Error::EPERM);
+            }
+            return Ok(pipe.set_pipe_size(size)? as isize);
+        }
+        F_GETPIPE_SZ => {
+            let pipe = file.as_pipe().map_err(|_| Error::EBADF)?;
+            return Ok(pipe.pipe_size() as isize);
+        }
 
         _ => {
             info!("sys_fcntl: unsupported request: {}!, return 0", request);
@@ -159,6 +278,30 @@ pub fn sys_fcntl(fd: u32, request: u32, arg: usize) -> Result<isize, Error> {
     }
 }
 
+/* flock锁属于打开的文件, 通过dup和fork共享; 转换锁的类型不是原子的 */
+pub fn sys_flock(fd: u32, operation: u32) -> Result<isize, Error> {
+    const LOCK_SH: u32 = 1;
+    const LOCK_EX: u32 = 2;
+    const LOCK_NB: u32 = 4;
+    const LOCK_UN: u32 = 8;
+    trace!("sys_flock: fd = {}, operation = {}", fd, operation);
+
+    let file = get_current_task().unwrap().get_file(fd)?;
+    let ltype = match operation & !LOCK_NB {
+        LOCK_SH => Some(LockType::Read),
+        LOCK_EX => Some(LockType::Write),
+        LOCK_UN => None,
+        _ => return Err(Error::EINVAL),
+    };
+    let index = lock_index(&file)?;
+    let lock = FileLock { owner: LockOwner::of_file(&file), ltype: LockType::Read, start: 0, end: u64::MAX };
+    if ltype.is_some() {
+        set_lock(index, lock, None, false)?;
+    }
+    set_lock(index, lock, ltype, operation & LOCK_NB == 0)?;
+    Ok(0)
+}
+
 pub fn sys_ioctl(fd: u32, request: u32, _arg: usize) -> Result<isize, Error> {
     info!("sys_ioctl: fd = {}. request = {}", fd, request);
     return Ok(0);
@@ -188,7 +331,7 @@ pub fn sys_chdir(path: *const u8) -> Result<isize, Error> {
     Ok(0)
 }
 
-pub fn sys_open(fd: i32, filename: *const u8, flags: u32, _mode: u32) -> Result<isize, Error> {
+pub fn sys_open(fd: i32, filename: *const u8, flags: u32, mode: u32) -> Result<isize, Error> {
     let current = get_current_task().unwrap();
     let token =current.get_user_token();
 
@@ -212,7 +355,7 @@ pub fn sys_open(fd: i32, filename: *const u8, flags: u32, _mode: u32) -> Result<
         trace!("sys_open: try to make new file");
         let ret = mknod_at(
             root_file.clone(), path.clone(), 
-            FileType::RegularFile, FilePerm::NONE);
+            FileType::RegularFile, create_perm(mode));
         match ret {
             Ok(file) => file,
             Err(Error::EEXIST) => open_at(root_file, path, open_mode)?,
@@ -222,6 +365,11 @@ pub fn sys_open(fd: i32, filename: *const u8, flags: u32, _mode: u32) -> Result<
         trace!("sys_open: open file");
         open_at(root_file, path, open_mode)?
     };
+    /* O_TRUNC截断了已有的文件(ftruncate还不支持, 这是唯一的截断路径) */
+    if open_mode.contains(FileOpenMode::TRUNC) && file.get_type()? == FileType::RegularFile {
+        inotify::notify_file(&file, inotify::IN_MODIFY);
+    }
+    inotify::notify_file(&file, inotify::IN_OPEN);
 
     /* 将file添加到fd_table中*/
     let fd_limit = current.get_max_fd();
@@ -237,8 +385,10 @@ pub fn sys_close(fd: u32) -> Result<isize, Error> {
     let mut fd_table = task.get_fd_table();
 
     match fd_table.table.remove(&fd) {
-        Some(_) => {
+        Some(file) => {
             //trace!("sys_close: success");
+            release_locks(task.pid, &file);
+            inotify::notify_close(&file);
             Ok(0)
         },
         None => {
@@ -315,6 +465,7 @@ pub fn sys_read(fd: u32, buf: *mut u8, len: usize) -> Result<isize, Error> {
     // let mem = MemBuffer::from_user_space(buf, len, token, true);
     // let len = file.read_to_buffer(mem)?;
     let data = file.read(len)?;
+    inotify::notify_file(&file, inotify::IN_ACCESS);
     let len = data.len();
     copyout_vec(token, buf, data)?;
     Ok(len as isize)
@@ -332,6 +483,7 @@ pub fn sys_write(fd: u32, buf: *const u8, len: usize) -> Result<isize, Error> {
     let task = get_current_task().unwrap();
     let file = task.get_file(fd)?;
     let len = file.write(data)?;
+    inotify::notify_file(&file, inotify::IN_MODIFY);
     Ok(len as isize)
 }
 
@@ -353,6 +505,7 @@ pub fn sys_readv(fd: u32, iovec_ptr: *const Iovec, cnt: usize) -> Result<isize,
     }
 
     let mut data = file.read(sum_len)?;
+    inotify::notify_file(&file, inotify::IN_ACCESS);
     let sum_len = data.len();
 
     let mut out_len = 0;
@@ -395,6 +548,7 @@ pub fn sys_writev(fd: u32, iovec_ptr: *const Iovec, cnt: usize) -> Result<isize,
     }
     
     let len = file.write(data)?;
+    inotify::notify_file(&file, inotify::IN_MODIFY);
     Ok(len as isize)
 }
 
@@ -409,6 +563,7 @@ pub fn sys_pread(fd: u32, buf: *mut u8, len: usize, offset: usize) -> Result<isi
     file.seek(offset, SeekMode::SET)?;
     let data = file.read(len)?;
     file.seek(old_cursor as usize, SeekMode::SET)?;
+    inotify::notify_file(&file, inotify::IN_ACCESS);
     let len = data.len();
     copyout_vec(token, buf, data)?;
     Ok(len as isize)
@@ -427,6 +582,7 @@ pub fn sys_pwrite(fd: u32, buf: *const u8, len: usize, offset: usize) -> Result<
     file.seek(offset, SeekMode::SET)?;
     let len = file.write(data)?;
     file.seek(old_cursor as usize, SeekMode::SET)?;
+    inotify::notify_file(&file, inotify::IN_MODIFY);
     Ok(len as isize)
 }
 
@@ -444,7 +600,7 @@ pub fn sys_sendfile(
 
     let count = count.min(1000);        /* 防止count过大 */
 
-    if !offset_ptr.is_null() {
+    let data = if !offset_ptr.is_null() {
         let mut offset: u32 = 0;
         copyin(current.get_user_token(), &mut offset, offset_ptr)?;
         /* 从offset位置开始读 */
@@ -452,57 +608,159 @@ pub fn sys_sendfile(
         i_file.seek(offset as usize, SeekMode::SET)?;
         let data = i_file.read(count)?;
         i_file.seek(old_cursor as usize, SeekMode::SET)?;
-        Ok(o_file.write(data)? as isize)
+        data
     } else {
-        let data=i_file.read(count)?;
-        Ok(o_file.write(data)? as isize)
-    }
+        i_file.read(count)?
+    };
+    let len = o_file.write(data)?;
+    inotify::notify_file(&i_file, inotify::IN_ACCESS);
+    inotify::notify_file(&o_file, inotify::IN_MODIFY);
+    Ok(len as isize)
 }
 
-pub fn sys_fsync() -> Result<isize, Error> {
-    return Ok(0)
+/* fdatasync也写回元数据, 与fsync相同 */
+pub fn sys_fsync(fd: u32) -> Result<isize, Error> {
+    let file = get_current_task().unwrap().get_file(fd)?;
+    file.fsync()?;
+    Ok(0)
+}
+
+pub fn sys_sync() -> Result<isize, Error> {
+    crate::fs::sync_all();
+    Ok(0)
+}
+
+pub fn sys_syncfs(fd: u32) -> Result<isize, Error> {
+    let file = get_current_task().unwrap().get_file(fd)?;
+    crate::fs::syncfs(file)?;
+    Ok(0)
 }
 
 pub fn sys_umask(umask: usize) -> Result<isize, Error> {
-    return Ok(0)
+    let task = get_current_task().unwrap();
+    let mut fs_info = task.get_fs_info();
+    let old = fs_info.umask;
+    fs_info.umask = umask as u32 & 0o777;
+    Ok(old as isize)
 }
 
+/* 新建文件的权限为mode去掉umask中的位 */
+fn create_perm(mode: u32) -> FilePerm {
+    let umask = get_current_task().unwrap().get_fs_info().umask;
+    FilePerm::from_bits_truncate(mode & !umask)
+}
 
-// from rcore
-// tofix: add license
-#[derive(Default, Clone, Copy, Debug)]
-#[repr(C)]
-pub struct FdSet(u128);
+
+/* select的fd集合, 与用户的fd_set一样按位保存, 只读写前nfds位所在的字 */
+#[derive(Clone, Debug)]
+pub struct FdSet(Vec<usize>);
 
 impl FdSet {
-    const ZERO: Self =  FdSet(0);
-    pub fn empty() -> Self {
-        Self(0)
+    const BITS: usize = usize::BITS as usize;
+
+    pub fn empty(nfds: usize) -> Self {
+        Self(vec![0; (nfds + Self::BITS - 1) / Self::BITS])
     }
-    pub fn contains(&self, no: u128) -> bool {
-        (self.0 >> no & 1) != 0
+    /* ptr为NULL时返回空集合 */
+    pub fn from_user(token: usize, ptr: *const usize, nfds: usize) -> Result<Self, Error> {
+        let mut set = Self::empty(nfds);
+        if !ptr.is_null() {
+            for (i, word) in set.0.iter_mut().enumerate() {
+                copyin(token, word, unsafe { ptr.add(i) })?;
+            }
+        }
+        Ok(set)
     }
-    pub fn add(&mut self, no: u128) {
-        self.0 |= 1 << no;
+    pub fn to_user(&self, token: usize, ptr: *mut usize) -> Result<(), Error> {
+        if !ptr.is_null() {
+            for (i, word) in self.0.iter().enumerate() {
+                copyout(token, unsafe { ptr.add(i) }, word)?;
+            }
+        }
+        Ok(())
     }
-    pub fn add_set(&mut self, set: &Self) {
-        self.0 |= set.0;
+    pub fn contains(&self, fd: usize) -> bool {
+        (self.0[fd / Self::BITS] >> (fd % Self::BITS) & 1) != 0
     }
-    pub fn remove(&mut self, no: u128) {
-        self.0 ^= self.0 & (1 << no);
+    pub fn add(&mut self, fd: usize) {
+        self.0[fd / Self::BITS] |= 1 << (fd % Self::BITS);
     }
-    pub fn remove_set(&mut self, set: &Self) {
-        self.0 ^= self.0 & set.0;
+    pub fn clear(&mut self) {
+        self.0.iter_mut().for_each(|word| *word = 0);
     }
 }
 
+/* 用户传入的超时时间转换为截止时间, NULL表示一直等待 */
+fn poll_deadline(token: usize, timeout: *const Timespec) -> Result<Option<Timespec>, Error> {
+    if timeout.is_null() {
+        return Ok(None);
+    }
+    let mut time = Timespec::ZERO;
+    copyin(token, &mut time, timeout)?;
+    if time.tv_sec < 0 || time.tv_nsec < 0 || time.tv_nsec >= 1_000_000_000 {
+        return Err(Error::EINVAL);
+    }
+    Ok(Some(Timespec::now() + time))
+}
 
+/* 与linux一样把剩余时间写回用户的timeout, 写回失败时忽略, 不影响已经得到的结果 */
+fn poll_remaining(token: usize, timeout: *const Timespec, deadline: Option<Timespec>) {
+    if let Some(deadline) = deadline {
+        let _ = copyout(token, timeout as *mut Timespec, &deadline.remaining());
+    }
+}
+
+/// ppoll和pselect6的等待, 在所有文件的等待队列上注册同一个TaskWaker
+/// 1, check返回就绪的数目, 为0时睡眠直到有文件唤醒, 超时或者收到信号
+/// 2, 有文件不会主动唤醒时每POLL_RECHECK_MS重新检查
+/// 3, sigmask不为None时等待期间临时替换信号掩码
+fn poll_files(
+    files: &Vec<Arc<dyn File>>,
+    deadline: Option<Timespec>,
+    sigmask: Option<usize>,
+    mut check: impl FnMut() -> Result<usize, Error>,
+) -> Result<usize, Error> {
+    let task = get_current_task().unwrap();
+    let waker = TaskWaker::new(deadline);
+    let dyn_waker = waker.as_waker();
+    let mut recheck = false;
+    for file in files.iter() {
+        recheck |= !file.register_waker(&dyn_waker);
+    }
+    if let Some(mask) = sigmask {
+        task.set_temp_mask(mask);
+    }
+    let ret = loop {
+        match check() {
+            Ok(0) => {},
+            ret => break ret,
+        }
+        if deadline.map_or(false, |deadline| deadline.pass()) {
+            break Ok(0);
+        }
+        if task.has_signal() {
+            break Err(Error::EINTR);
+        }
+        let tick = match recheck {
+            true => Some(Timespec::now() + Timespec::from_ms(POLL_RECHECK_MS)),
+            false => None,
+        };
+        waker.sleep(tick);
+    };
+    if sigmask.is_some() {
+        task.restore_mask(matches!(ret, Err(Error::EINTR)));
+    }
+    for file in files.iter() {
+        file.unregister_waker(&dyn_waker);
+    }
+    ret
+}
 
 pub fn sys_pselect(
     nfds: i32,
-    readfds: *mut FdSet,
-    writefds: *mut FdSet,
-    exceptfds: *mut FdSet,
+    readfds: *mut usize,
+    writefds: *mut usize,
+    exceptfds: *mut usize,
     timeout_ptr: *const Timespec,
     sigmask_ptr: *const usize,
 ) -> Result<isize, Error> {
@@ -511,160 +769,262 @@ pub fn sys_pselect(
         nfds, readfds, writefds, exceptfds, timeout_ptr);
     let task = get_current_task().unwrap();
     let token = task.get_user_token();
+    if nfds < 0 {
+        return Err(Error::EINVAL);
+    }
+    let nfds = (nfds as usize).min(task.get_max_fd());
+
+    let r_fds = FdSet::from_user(token, readfds, nfds)?;
+    let w_fds = FdSet::from_user(token, writefds, nfds)?;
+    let e_fds = FdSet::from_user(token, exceptfds, nfds)?;
+    let deadline = poll_deadline(token, timeout_ptr)?;
+
+    /* 第6个参数指向{ const sigset_t *ss; size_t ss_len; } */
+    let sigmask = match sigmask_ptr.is_null() {
+        true => None,
+        false => {
+            let mut sigset = [0usize; 2];
+            copyin(token, &mut sigset, sigmask_ptr as *const [usize; 2])?;
+            match sigset[0] {
+                0 => None,
+                ss => {
+                    let mut mask = 0;
+                    copyin(token, &mut mask, ss as *const usize)?;
+                    Some(mask)
+                }
+            }
+        }
+    };
+    trace!("nfds = {}, readfds = {:?}, writefds = {:?}, exceptfds: {:?}
+        , deadline = {:?}", nfds, r_fds, w_fds, e_fds, deadline);
+
+    /* 集合中的fd必须都已经打开 */
+    let mut fds = Vec::new();
+    let mut files = Vec::new();
+    for fd in 0..nfds {
+        if r_fds.contains(fd) || w_fds.contains(fd) || e_fds.contains(fd) {
+            files.push(task.get_file(fd as u32)?);
+            fds.push(fd);
+        }
+    }
+    drop(task);
+
+    let mut ready_rfds = FdSet::empty(nfds);
+    let mut ready_wfds = FdSet::empty(nfds);
+    let mut ready_efds = FdSet::empty(nfds);
+    let ready = poll_files(&files, deadline, sigmask, || {
+        ready_rfds.clear();
+        ready_wfds.clear();
+        ready_efds.clear();
+        let mut ready = 0;
+        for (fd, file) in fds.iter().zip(files.iter()) {
+            let fd = *fd;
+            if r_fds.contains(fd) && file.poll(PollType::READ)? {
+                ready_rfds.add(fd);
+                ready += 1;
+            }
+            if w_fds.contains(fd) && file.poll(PollType::WRITE)? {
+                ready_wfds.add(fd);
+                ready += 1;
+            }
+            if e_fds.contains(fd) && file.poll(PollType::ERR)? {
+                ready_efds.add(fd);
+                ready += 1;
+            }
+        }
+        Ok(ready)
+    });
+    poll_remaining(token, timeout_ptr, deadline);
+    let ready = ready?;
+    ready_rfds.to_user(token, readfds)?;
+    ready_wfds.to_user(token, writefds)?;
+    ready_efds.to_user(token, exceptfds)?;
+    Ok(ready as isize)
+}
 
-    fn get_arg<T: Default>(token: usize, src: *const T) -> Result<Option<T>, Error> {
-        if src.is_null() {
-            return Ok(None);
-        } else {
-            let mut dst: T = T::default();
-            copyin(token, &mut dst, src)?;
-            return Ok(Some(dst));
-        }        
+pub fn sys_eventfd2(initval: u32, flags: usize) -> Result<isize, Error> {
+    if flags & !(EFD_SEMAPHORE | EFD_NONBLOCK | EFD_CLOEXEC) != 0 {
+        return Err(Error::EINVAL);
     }
+    let task = get_current_task().unwrap();
+    let fd_limit = task.get_max_fd();
+    let fd = task.get_fd_table().add_file(EventFd::new(initval, flags), fd_limit)?;
+    trace!("sys_eventfd2: initval = {}, flags = {:#x}, fd = {}", initval, flags, fd);
+    Ok(fd as isize)
+}
 
-    fn ret_arg<T>(token: usize, dst: *mut T, src: &T) -> Result<(), Error> {
-        if dst.is_null() {
-            return Ok(());
-        } else {
-            copyout(token, dst, src)?;
-            return Ok(());
-        }
+pub fn sys_inotify_init1(flags: usize) -> Result<isize, Error> {
+    if flags & !(IN_NONBLOCK | IN_CLOEXEC) != 0 {
+        return Err(Error::EINVAL);
     }
+    let task = get_current_task().unwrap();
+    let fd_limit = task.get_max_fd();
+    let fd = task.get_fd_table().add_file(Inotify::new(flags), fd_limit)?;
+    trace!("sys_inotify_init1: flags = {:#x}, fd = {}", flags, fd);
+    Ok(fd as isize)
+}
 
-    let r_fds = get_arg(token, readfds)?.unwrap_or(FdSet(0));
-    let w_fds = get_arg(token, writefds)?.unwrap_or(FdSet(0));
-    let e_fds = get_arg(token, exceptfds)?.unwrap_or(FdSet(0));
+/* 监听路径指向的文件, 需要对文件有读权限; 返回wd, 同一文件再次添加时返回原来的wd */
+pub fn sys_inotify_add_watch(fd: u32, path: *const u8, mask: u32) -> Result<isize, Error> {
+    let task = get_current_task().unwrap();
+    let token = task.get_user_token();
+    let inotify = task.get_file(fd)?.as_inotify().map_err(|_| Error::EINVAL)?;
+    let path = translate_str(token, path)?;
+    trace!("sys_inotify_add_watch: fd = {}, path = {}, mask = {:#x}", fd, path, mask);
 
-    let mut non_block = false;
+    let mut mode = FileOpenMode::SYS;
+    if mask & IN_DONT_FOLLOW != 0 {
+        mode |= FileOpenMode::NOFOLLOW;
+    }
+    let (root_file, path) = get_file(AT_FDCWD, path)?;
+    let file = open_at(root_file, path, mode)?;
+    if mask & IN_ONLYDIR != 0 && file.get_type()? != FileType::Directory {
+        return Err(Error::ENOTDIR);
+    }
+    if let Some(cred) = current_cred() {
+        check_access_with(&cred, &file, Access::R)?;
+    }
+    let wd = inotify.add_watch(file.get_index()?, mask)?;
+    Ok(wd as isize)
+}
+
+pub fn sys_inotify_rm_watch(fd: u32, wd: i32) -> Result<isize, Error> {
+    trace!("sys_inotify_rm_watch: fd = {}, wd = {}", fd, wd);
+    let task = get_current_task().unwrap();
+    let inotify = task.get_file(fd)?.as_inotify().map_err(|_| Error::EINVAL)?;
+    inotify.rm_watch(wd)?;
+    Ok(0)
+}
+
+pub fn sys_epoll_create1(flags: usize) -> Result<isize, Error> {
+    const EPOLL_CLOEXEC: usize = 0o2000000;
+    if flags & !EPOLL_CLOEXEC != 0 {
+        return Err(Error::EINVAL);
+    }
+    let task = get_current_task().unwrap();
+    let fd_limit = task.get_max_fd();
+    let fd = task.get_fd_table().add_file(Epoll::new(), fd_limit)?;
+    trace!("sys_epoll_create1: fd = {}", fd);
+    Ok(fd as isize)
+}
 
-    let mut timeout = get_arg(token, timeout_ptr)?;
-    if timeout.is_none() || timeout.unwrap() == Timespec::ZERO {
-        non_block = true;
+pub fn sys_epoll_ctl(epfd: u32, op: usize, fd: u32, event: *const EpollEvent) -> Result<isize, Error> {
+    let task = get_current_task().unwrap();
+    let token = task.get_user_token();
+    let epoll = task.get_file(epfd)?.as_epoll().map_err(|_| Error::EINVAL)?;
+    let file = task.get_file(fd)?;
+    if epfd == fd {
+        return Err(Error::EINVAL);
     }
-    if timeout.is_none() {
-        timeout = Some(Timespec::ZERO);
+    /* EPOLL_CTL_DEL忽略event */
+    let mut epoll_event = EpollEvent::default();
+    if op != EPOLL_CTL_DEL {
+        copyin(token, &mut epoll_event, event)?;
     }
-    let timeout = timeout.unwrap() + Timespec::now();
+    trace!("sys_epoll_ctl: epfd = {}, op = {}, fd = {}, event = {:x?}", epfd, op, fd, epoll_event);
+    epoll.ctl(op, fd as usize, file, epoll_event)?;
+    Ok(0)
+}
 
-    let sigmask = get_arg(token, sigmask_ptr)?;
-    trace!("nfds = {}, readfds = {:?}, writefds = {:?}, exceptfds: {:?}
-        , timeout = {:?}", nfds, r_fds, w_fds, e_fds, timeout);
-
-    let mut ready = 0;
-    let mut ready_rfds = FdSet::ZERO;
-    let mut ready_wfds = FdSet::ZERO;
-    let mut ready_efds = FdSet::ZERO;
-    let mut ret;
-    loop {
-        let task = get_current_task().unwrap();
-        let fd_table = task.get_fd_table();
-        for i in 0..nfds as u32 {
-            if let Ok(file) = fd_table.get_file(i) {
-                if r_fds.contains(i as u128) && file.poll(PollType::READ)? {
-                    ready_rfds.add(i as u128);
-                    ready += 1;
-                }
-                if w_fds.contains(i as u128) && file.poll(PollType::WRITE)? {
-                    ready_wfds.add(i as u128);
-                    ready += 1;
-                }
-                if e_fds.contains(i as u128) && file.poll(PollType::ERR)? {
-                    ready_efds.add(i as u128);
-                    ready += 1;
-                }
-            } else {
-                continue;                
-            }
-        }
-        if ready != 0 || non_block {
-            ret = Ok(ready);
-            break;
-        } else if timeout.pass() {
-            ret = Err(Error::EINTR);
-            break;
-        } else {
-            drop(fd_table);
-            drop(task);
-            suspend_current();
-        }
+pub fn sys_epoll_pwait(
+    epfd: u32,
+    events: *mut EpollEvent,
+    maxevents: i32,
+    timeout: i32,
+    sigmask: *const usize,
+) -> Result<isize, Error> {
+    let task = get_current_task().unwrap();
+    let token = task.get_user_token();
+    if maxevents <= 0 {
+        return Err(Error::EINVAL);
+    }
+    let epoll = task.get_file(epfd)?.as_epoll().map_err(|_| Error::EINVAL)?;
+    /* timeout为-1时一直等待, 单位为毫秒 */
+    let deadline = match timeout {
+        timeout if timeout < 0 => None,
+        timeout => Some(Timespec::now() + Timespec::from_ms(timeout as usize)),
+    };
+    if !sigmask.is_null() {
+        let mut mask = 0;
+        copyin(token, &mut mask, sigmask)?;
+        task.set_temp_mask(mask);
     }
-    ret_arg(token, readfds, &ready_rfds)?;
-    ret_arg(token, writefds, &ready_wfds)?;
-    ret_arg(token, exceptfds, &ready_efds)?;
-    return ret;
+    let ret = epoll.wait(maxevents as usize, deadline);
+    if !sigmask.is_null() {
+        task.restore_mask(matches!(ret, Err(Error::EINTR)));
+    }
+    let ready = ret?;
+    trace!("sys_epoll_pwait: epfd = {}, ready = {:x?}", epfd, ready);
+    for (i, event) in ready.iter().enumerate() {
+        copyout(token, unsafe { events.add(i) }, event)?;
+    }
+    Ok(ready.len() as isize)
 }
 
-// 仅仅是为了sh实现的系统调用：伪实现
 pub fn sys_ppoll(
     fds: *mut Pollfd, 
     nfds: usize, 
     timeout: *const Timespec, 
-    _sigmask: *const usize) -> Result<isize, Error> 
+    sigmask: *const usize) -> Result<isize, Error> 
 {   
-    return Ok(1);
-    const POLLIN: u16 = 0x01;
-    const POLLHUP: u16 = 0x10;
+    /* 与epoll的事件位相同, 不属于任何已打开文件的fd报告POLLNVAL */
+    const POLLNVAL: u16 = 0x20;
 
     let task = get_current_task().unwrap();
     let token = task.get_user_token();
+    if nfds > task.get_max_fd() {
+        return Err(Error::EINVAL);
+    }
     let mut pollfd_vec = Vec::new();
     let mut pollfd = Pollfd::default();
-    
-    trace!("timeout = {:x?}", timeout);
-
     for i in 0..nfds {
         unsafe{ copyin(token, &mut pollfd, fds.add(i))? }
         pollfd_vec.push(pollfd);   
     }
-
-    let time = {
-        if !timeout.is_null() {
-            let mut time = Timespec::ZERO;
-            copyin(token, &mut time, timeout)?;
-            time + Timespec::now()
-        } else {
-            Timespec {
-                tv_sec: isize::MAX,
-                tv_nsec: 0,
-            }
+    let deadline = poll_deadline(token, timeout)?;
+    let sigmask = match sigmask.is_null() {
+        true => None,
+        false => {
+            let mut mask = 0;
+            copyin(token, &mut mask, sigmask)?;
+            Some(mask)
         }
     };
-    trace!("time = {:?}, pollfd_vec = {:?}", time, pollfd_vec);
-
-    let mut ready = 0;
-
-    loop {
-        let fd_table = task.get_fd_table();
-        for i in 0..nfds {
-            let poll = &mut pollfd_vec[i];
-            let file = fd_table.get_file(poll.fd as u32)?;
-            if poll.events == POLLIN {
-                if file.poll(PollType::READ)? {
-                    poll.revents = POLLIN;
-                    ready += 1;
-                } else {
-                    poll.revents &= !POLLIN;
-                }
-            } 
-        }
-        if ready != 0 {
-            break;
-        }
-        if time.pass() {
-            break;
+    trace!("sys_ppoll: deadline = {:?}, pollfd_vec = {:?}", deadline, pollfd_vec);
+
+    /* fd为负数时忽略该项 */
+    let targets: Vec<Option<Arc<dyn File>>> = pollfd_vec
+        .iter()
+        .map(|poll| match poll.fd {
+            fd if fd < 0 => None,
+            fd => task.get_file(fd as u32).ok(),
+        })
+        .collect();
+    let files: Vec<Arc<dyn File>> = targets.iter().flatten().cloned().collect();
+    drop(task);
+
+    let ready = poll_files(&files, deadline, sigmask, || {
+        let mut ready = 0;
+        for (poll, file) in pollfd_vec.iter_mut().zip(targets.iter()) {
+            poll.revents = match file {
+                None if poll.fd < 0 => 0,
+                None => POLLNVAL,
+                Some(file) => poll_events(file, poll.events as u32)? as u16,
+            };
+            if poll.revents != 0 {
+                ready += 1;
+            }
         }
-
-        drop(fd_table);
-        suspend_current();
-    }
+        Ok(ready)
+    });
+    poll_remaining(token, timeout, deadline);
+    let ready = ready?;
 
     trace!("pollfd_vec = {:?}", pollfd_vec);
     for i in 0..nfds {
         unsafe{ copyout(token, fds.add(i), &mut pollfd_vec[i])?; }
     }
-
-
-    return Ok(ready);
+    Ok(ready as isize)
 }
 
 
@@ -731,6 +1091,7 @@ pub fn sys_utimensat(fd: i32, path_ptr: *const u8, timespec: *const Timespec, _f
      }
     
      file.write_stat(&fstat)?;
+     inotify::notify_file(&file, inotify::IN_ATTRIB);
 
     Ok(0)
 }
@@ -741,45 +1102,85 @@ pub fn sys_readlinkat(
     buf: *mut u8,
     size: usize
 ) -> Result<isize, Error> {
-    let token =get_current_user_token();
+    if size == 0 {
+        return Err(Error::EINVAL);
+    }
+    let token = get_current_user_token();
     let path = translate_str(token, path)?;
 
-    if path.as_str() != "/proc/self/exe" {
-        /* proc文件系统还未实现 */
-        return Ok(0);
-        unimplemented!();
-    } else {
-        let mut string = String::from("/lmbench_all\0");
-        copyout_vec(token, buf, string.as_bytes().to_vec())?;
-        return Ok((string.len() - 1) as isize);
-    }
+    let (root_file, path) = get_file(dirfd, path)?;
+    let link = open_at(root_file, path, FileOpenMode::SYS | FileOpenMode::NOFOLLOW)?
+        .as_link()
+        .map_err(|_| Error::EINVAL)?;
+
+    /* readlink不会在结尾添加'\0', 超过size的部分被截断 */
+    let mut name = link.link_name()?.into_bytes();
+    name.truncate(size);
+    let len = name.len();
+    copyout_vec(token, buf, name)?;
+    Ok(len as isize)
 }
 
-//todo
+/* 默认不跟随oldpath最后一项的软连接; AT_EMPTY_PATH时链接oldfd本身 */
 pub fn sys_linkat(
-    _oldfd: isize, 
-    _oldpath: *const u8, 
-    _newfd: isize, 
-    _newpath: *const u8, 
-    _flags: u32
+    oldfd: i32, 
+    oldpath: *const u8, 
+    newfd: i32, 
+    newpath: *const u8, 
+    flags: u32
 ) -> Result<isize, Error> {
-    todo!()
+    let token = get_current_user_token();
+    let oldpath = translate_str(token, oldpath)?;
+    let newpath = translate_str(token, newpath)?;
+    trace!("sys_linkat: oldfd = {}, oldpath = {}, newfd = {}, newpath = {}, flags = {:x}",
+        oldfd, oldpath, newfd, newpath, flags);
+    if flags & !(AT_SYMLINK_FOLLOW | AT_EMPTY_PATH) != 0 {
+        return Err(Error::EINVAL);
+    }
+
+    let mut mode = FileOpenMode::SYS;
+    if flags & AT_SYMLINK_FOLLOW == 0 {
+        mode |= FileOpenMode::NOFOLLOW;
+    }
+    let (old_src, old_path) = if oldpath.is_empty() {
+        if flags & AT_EMPTY_PATH == 0 {
+            return Err(Error::ENOENT);
+        }
+        let file = get_current_task().unwrap().get_file(oldfd as u32)?;
+        (file, Path::from_str("/")?)
+    } else {
+        get_file(oldfd, oldpath)?
+    };
+    let (new_src, new_path) = get_file(newfd, newpath)?;
+    link_at(old_src, old_path, new_src, new_path, mode)?;
+    Ok(0)
 }
 
-pub fn sys_unlinkat(fd: i32, path: *const u8, _flags: u32) -> Result<isize, Error> {
+pub fn sys_unlinkat(fd: i32, path: *const u8, flags: u32) -> Result<isize, Error> {
     let token = get_current_user_token();
 
     let path = translate_str(token, path)?;
-    info!("sys_unlinkat: fd = {}, path = {}, flags = {:b}", fd, path, _flags);
+    info!("sys_unlinkat: fd = {}, path = {}, flags = {:b}", fd, path, flags);
+    if flags & !AT_REMOVEDIR != 0 {
+        return Err(Error::EINVAL);
+    }
 
     let (root_file, path) = get_file(fd, path)?;
-    
+    /* unlink不能删除目录, AT_REMOVEDIR(rmdir)只能删除目录 */
+    let is_dir = open_at(root_file.clone(), path.clone(), FileOpenMode::SYS | FileOpenMode::NOFOLLOW)?
+        .get_type()? == FileType::Directory;
+    match (is_dir, flags & AT_REMOVEDIR != 0) {
+        (true, false) => return Err(Error::EISDIR),
+        (false, true) => return Err(Error::ENOTDIR),
+        _ => {}
+    }
+
     delete_at(root_file, path)?;
 
     Ok(0)
 }
 
-pub fn sys_mkdir(fd: i32, path: *const u8, _mode: u32) -> Result<isize, Error> {
+pub fn sys_mkdir(fd: i32, path: *const u8, mode: u32) -> Result<isize, Error> {
     let token = get_current_user_token();
 
     /* 从用户空间获取path */
@@ -788,10 +1189,119 @@ pub fn sys_mkdir(fd: i32, path: *const u8, _mode: u32) -> Result<isize, Error> {
 
     /* 创建目录 */
     let (root_file, path) = get_file(fd, path)?;
-    mknod_at(root_file, path, FileType::Directory, FilePerm::NONE)?;
+    mknod_at(root_file, path, FileType::Directory, create_perm(mode))?;
+    Ok(0)
+}
+
+pub fn sys_mknodat(fd: i32, path: *const u8, mode: u32, _dev: usize) -> Result<isize, Error> {
+    let token = get_current_user_token();
+
+    let path = translate_str(token, path)?;
+    trace!("sys_mknodat: fd = {}, path = {}, mode = {:o}", fd, path, mode);
+
+    /* 不支持创建设备文件 */
+    let file_type = match mode & S_IFMT {
+        0 => FileType::RegularFile,
+        m if m == StMode::REG as u32 => FileType::RegularFile,
+        m if m == StMode::IFO as u32 => FileType::FIFOFile,
+        m if m == StMode::SOCK as u32 => FileType::SocketFile,
+        m if m == StMode::DIR as u32 => return Err(Error::EINVAL),
+        _ => return Err(Error::EPERM),
+    };
+
+    let (root_file, path) = get_file(fd, path)?;
+    mknod_at(root_file, path, file_type, create_perm(mode))?;
+    Ok(0)
+}
+
+pub fn sys_symlinkat(target: *const u8, fd: i32, linkpath: *const u8) -> Result<isize, Error> {
+    let token = get_current_user_token();
+
+    let target = translate_str(token, target)?;
+    let linkpath = translate_str(token, linkpath)?;
+    trace!("sys_symlinkat: target = {}, fd = {}, linkpath = {}", target, fd, linkpath);
+    if target.is_empty() {
+        return Err(Error::ENOENT);
+    }
+
+    /* 目标原样保存, 相对路径在查找时以软连接所在的目录为起点解析 */
+    let (root_file, path) = get_file(fd, linkpath)?;
+    let link = mknod_at(root_file.clone(), path.clone(), 
+        FileType::LinkFile, FilePerm::from_bits_truncate(0o777))?;
+    /* 文件系统不支持软连接时, 删除刚创建的文件 */
+    if let Err(err) = link.as_link().and_then(|link| link.write_link(target.as_str())) {
+        delete_at(root_file, path)?;
+        return Err(err);
+    }
+    Ok(0)
+}
+
+/* 打开fchmodat, fchownat指定的文件 */
+fn get_file_at(fd: i32, path: *const u8, flag: u32) -> Result<Arc<dyn File>, Error> {
+    let token = get_current_user_token();
+    let path = translate_str(token, path)?;
+
+    let mut mode = FileOpenMode::SYS;
+    if flag & AT_SYMLINK_NOFOLLOW != 0 {
+        mode |= FileOpenMode::NOFOLLOW;
+    }
+    let (root_file, path) = get_file(fd, path)?;
+    open_at(root_file, path, mode)
+}
+
+fn chmod(file: Arc<dyn File>, mode: u32) -> Result<isize, Error> {
+    let mut fstat = file.read_stat()?;
+    /* 只有文件的所有者和root可以修改mode */
+    if current_cred().map_or(false, |cred| !cred.is_owner(&fstat)) {
+        return Err(Error::EPERM);
+    }
+    fstat.st_mode = (fstat.st_mode & S_IFMT) | (mode & 0o7777);
+    file.write_stat(&fstat)?;
+    inotify::notify_file(&file, inotify::IN_ATTRIB);
     Ok(0)
 }
 
+/* uid或gid为-1时不修改 */
+fn chown(file: Arc<dyn File>, uid: u32, gid: u32) -> Result<isize, Error> {
+    let mut fstat = file.read_stat()?;
+    /* 非特权进程不能改变所有者, 所有者只能把组改为自己所在的组 */
+    if let Some(cred) = current_cred().filter(|cred| !cred.is_root()) {
+        if (uid != u32::MAX && uid != fstat.st_uid)
+            || (gid != u32::MAX && (fstat.st_uid != cred.euid || !cred.in_group(gid))) {
+            return Err(Error::EPERM);
+        }
+    }
+    if uid != u32::MAX {
+        fstat.st_uid = uid;
+    }
+    if gid != u32::MAX {
+        fstat.st_gid = gid;
+    }
+    file.write_stat(&fstat)?;
+    inotify::notify_file(&file, inotify::IN_ATTRIB);
+    Ok(0)
+}
+
+pub fn sys_fchmodat(fd: i32, path: *const u8, mode: u32, flag: u32) -> Result<isize, Error> {
+    trace!("sys_fchmodat: fd = {}, mode = {:o}, flag = {:x}", fd, mode, flag);
+    chmod(get_file_at(fd, path, flag)?, mode)
+}
+
+pub fn sys_fchmod(fd: u32, mode: u32) -> Result<isize, Error> {
+    let file = get_current_task().unwrap().get_file(fd)?;
+    chmod(file, mode)
+}
+
+pub fn sys_fchownat(fd: i32, path: *const u8, uid: u32, gid: u32, flag: u32) -> Result<isize, Error> {
+    trace!("sys_fchownat: fd = {}, uid = {}, gid = {}, flag = {:x}", fd, uid, gid, flag);
+    chown(get_file_at(fd, path, flag)?, uid, gid)
+}
+
+pub fn sys_fchown(fd: u32, uid: u32, gid: u32) -> Result<isize, Error> {
+    let file = get_current_task().unwrap().get_file(fd)?;
+    chown(file, uid, gid)
+}
+
 pub fn sys_umount(path: *const u8, _flags: usize) -> Result<isize, Error> {
     /* 获取path */
     let token = get_current_user_token();
@@ -805,17 +1315,21 @@ pub fn sys_mount(
     dir: *const u8, 
     fstype: *const u8, 
     _flags: usize, 
-    _data: *const u8
+    data: *const u8
 ) -> Result<isize, Error> {
     let token = get_current_user_token();
 
     let dev = Path::from_string(translate_str(token, dev)?)?;
     let dir = Path::from_string(translate_str(token, dir)?)?;
     let fstype = translate_str(token, fstype)?;
+    let data = match data.is_null() {
+        true => String::new(),
+        false => translate_str(token, data)?,
+    };
 
-    info!("sys_mount: dev = {:?}, dir = {:?}, fstype = {:?}", dev, dir, fstype);
+    info!("sys_mount: dev = {:?}, dir = {:?}, fstype = {:?}, data = {:?}", dev, dir, fstype, data);
 
-    mount(dir, dev, fstype.as_str())?;
+    mount(dir, dev, fstype.as_str(), data.as_str())?;
     Ok(0)
 }
 
@@ -834,15 +1348,31 @@ pub fn sys_statfs(path: *const u8, buf: *mut Statvfs) -> Result<isize, Error> {
 }  
 
 /* 只要文件存在，则返回0 */
-pub fn sys_faccessat(fd: i32, path: *const u8, mode: u32, _flag: u32) -> Result<isize, Error> {
-    let token = get_current_user_token();
+/* mode为0(F_OK)时只检查文件是否存在, 默认按照真实id检查, AT_EACCESS时按照有效id检查 */
+pub fn sys_faccessat(fd: i32, path: *const u8, mode: u32, flag: u32) -> Result<isize, Error> {
+    let task = get_current_task().unwrap();
+    let token = task.get_user_token();
 
     let path = translate_str(token, path)?;
-    trace!("sys_faccessat: fd = {}, path = {}, mode = {}, _flag = {}", fd, path, mode, _flag);
+    trace!("sys_faccessat: fd = {}, path = {}, mode = {}, flag = {}", fd, path, mode, flag);
+
+    let access = Access::from_bits(mode).ok_or(Error::EINVAL)?;
+    let mut open_mode = FileOpenMode::SYS;
+    if flag & AT_SYMLINK_NOFOLLOW != 0 {
+        open_mode |= FileOpenMode::NOFOLLOW;
+    }
 
     let (root_file, path) = get_file(fd, path)?;
 
-    open_at(root_file, path, FileOpenMode::SYS)?;
+    let file = open_at(root_file, path, open_mode)?;
+    if !access.is_empty() {
+        let cred = task.get_cred().clone();
+        let cred = match flag & AT_EACCESS {
+            0 => cred.real(),
+            _ => cred,
+        };
+        check_access_with(&cred, &file, access)?;
+    }
     Ok(0)
 }
 
@@ -861,15 +1391,20 @@ pub fn sys_fstat(fd: u32, kst: *mut FileStat) -> Result<isize, Error> {
     Ok(0)
 }
 
-pub fn sys_newfstatat(fd: i32, path: *const u8, buf: *mut FileStat, _flag: u32) 
+pub fn sys_newfstatat(fd: i32, path: *const u8, buf: *mut FileStat, flag: u32) 
     -> Result<isize, Error> {    
     let token = get_current_user_token();
 
     let path = translate_str(token, path)?;
 
+    /* AT_SYMLINK_NOFOLLOW: 路径最后一项是软连接时，返回软连接本身的信息 */
+    let mut mode = FileOpenMode::SYS;
+    if flag & AT_SYMLINK_NOFOLLOW != 0 {
+        mode |= FileOpenMode::NOFOLLOW;
+    }
 
     let (root_file, path) = get_file(fd, path)?;
-    let file = open_at(root_file, path, FileOpenMode::SYS)?;
+    let file = open_at(root_file, path, mode)?;
     let fstat = file.read_stat()?;
 
     /* 将fstat复制到用户地址空间 */
@@ -903,6 +1438,254 @@ pub fn sys_pipe(pipe_fd_ptr: *mut u32, _flag: usize) -> Result<isize, Error> {
     Ok(0)
 }
 
+pub const SPLICE_F_MOVE: u32 = 1;
+pub const SPLICE_F_NONBLOCK: u32 = 2;
+pub const SPLICE_F_MORE: u32 = 4;
+pub const SPLICE_F_GIFT: u32 = 8;
+
+/* splice的偏移指针, 为NULL时使用并更新文件的偏移 */
+fn splice_offset(token: usize, ptr: *mut i64) -> Result<Option<usize>, Error> {
+    if ptr.is_null() {
+        return Ok(None);
+    }
+    let mut offset: i64 = 0;
+    copyin(token, &mut offset, ptr)?;
+    if offset < 0 {
+        return Err(Error::EINVAL);
+    }
+    Ok(Some(offset as usize))
+}
+
+/* 有页缓存的文件直接引用缓存中的页, 不复制数据 */
+fn cached_pipe_bufs(file: &Arc<dyn File>, cache: &PageCache, pos: usize, len: usize) -> Result<Vec<PipeBuf>, Error> {
+    let end = (pos + len).min(file.get_size()?);
+    let mut bufs = Vec::new();
+    if pos >= end {
+        return Ok(bufs);
+    }
+    cache.readahead(pos / PAGE_SIZE, (end + PAGE_SIZE - 1) / PAGE_SIZE);
+    let mut cur = pos;
+    while cur < end {
+        let page = cache.get_page(cur / PAGE_SIZE)?;
+        let offset = cur % PAGE_SIZE;
+        let n = (PAGE_SIZE - offset).min(end - cur);
+        bufs.push(PipeBuf::new(page.frame(), offset, n));
+        cur += n;
+    }
+    Ok(bufs)
+}
+
+/* 其它文件读入新分配的页, 指定了偏移时不改变文件的偏移 */
+fn read_pipe_bufs(file: &Arc<dyn File>, offset: Option<usize>, len: usize) -> Result<Vec<PipeBuf>, Error> {
+    let mut frames = Vec::new();
+    let mut mem = MemBuffer::new(VecDeque::new());
+    for start in (0..len).step_by(PAGE_SIZE) {
+        let frame = frame_safe_alloc().ok_or(Error::ENOMEM)?;
+        mem.add_slice(frame.ppn.get_byte_array().as_mut_ptr(), (len - start).min(PAGE_SIZE));
+        frames.push(frame);
+    }
+    let read = match offset {
+        Some(pos) => {
+            let old_cursor = file.seek(0, SeekMode::CUR)?;
+            file.seek(pos, SeekMode::SET)?;
+            let read = file.read_to_buffer(mem);
+            file.seek(old_cursor as usize, SeekMode::SET)?;
+            read?
+        }
+        None => file.read_to_buffer(mem)?,
+    };
+    Ok(frames
+        .into_iter()
+        .enumerate()
+        .map(|(i, frame)| PipeBuf::new(frame, 0, read.saturating_sub(i * PAGE_SIZE).min(PAGE_SIZE)))
+        .filter(|buf| buf.len() > 0)
+        .collect())
+}
+
+fn splice_file_to_pipe(
+    file: &Arc<dyn File>,
+    offset: Option<usize>,
+    pipe: &Pipe,
+    len: usize,
+    nonblock: bool
+) -> Result<usize, Error> {
+    if !file.readable() {
+        return Err(Error::EBADF);
+    }
+    /* 先等待管道中有空间, 确定这次读入的长度 */
+    let len = len.min(pipe.wait_room(nonblock)?.available_write_bytes());
+    let len = match file.page_cache() {
+        Some(cache) => {
+            let pos = match offset {
+                Some(pos) => pos,
+                None => file.seek(0, SeekMode::CUR)? as usize,
+            };
+            let len = pipe.push_bufs(cached_pipe_bufs(file, &cache, pos, len)?, nonblock)?;
+            if offset.is_none() {
+                file.seek(pos + len, SeekMode::SET)?;
+            }
+            len
+        }
+        None => pipe.push_bufs(read_pipe_bufs(file, offset, len)?, nonblock)?,
+    };
+    inotify::notify_file(file, inotify::IN_ACCESS);
+    Ok(len)
+}
+
+/* 先写出管道中的页, 再从管道中丢弃已经写入文件的部分; 写入出错时数据全部留在管道中 */
+fn splice_pipe_to_file(
+    pipe: &Pipe,
+    file: &Arc<dyn File>,
+    offset: Option<usize>,
+    len: usize,
+    nonblock: bool
+) -> Result<usize, Error> {
+    if !file.writable() {
+        return Err(Error::EBADF);
+    }
+    let bufs = match pipe.wait_data(nonblock)? {
+        Some(buffer) => buffer.peek_bufs(len),
+        None => return Ok(0),
+    };
+    let mut mem = MemBuffer::new(VecDeque::new());
+    for buf in bufs.iter() {
+        mem.add_slice(buf.data().as_mut_ptr(), buf.len());
+    }
+    let written = match offset {
+        Some(pos) => {
+            let old_cursor = file.seek(0, SeekMode::CUR)?;
+            file.seek(pos, SeekMode::SET)?;
+            let written = file.write_from_buffer(mem);
+            file.seek(old_cursor as usize, SeekMode::SET)?;
+            written?
+        }
+        None => file.write_from_buffer(mem)?,
+    };
+    pipe.consume(written);
+    inotify::notify_file(file, inotify::IN_MODIFY);
+    Ok(written)
+}
+
+/// 在管道和文件(或另一个管道)之间移动数据, 数据以页为单位移动, 不经过内核中的Vec<u8>
+/// 管道一端的偏移必须为NULL; 文件一端的偏移不为NULL时不改变文件的偏移, 而是更新*off
+pub fn sys_splice(
+    fd_in: u32,
+    off_in: *mut i64,
+    fd_out: u32,
+    off_out: *mut i64,
+    len: usize,
+    flags: u32
+) -> Result<isize, Error> {
+    trace!("sys_splice: fd_in = {}, fd_out = {}, len = {}, flags = {:#x}", fd_in, fd_out, len, flags);
+    let task = get_current_task().unwrap();
+    let token = task.get_user_token();
+    let in_file = task.get_file(fd_in)?;
+    let out_file = task.get_file(fd_out)?;
+    drop(task);
+    let nonblock = flags & SPLICE_F_NONBLOCK != 0;
+    if len == 0 {
+        return Ok(0);
+    }
+
+    match (in_file.clone().as_pipe(), out_file.clone().as_pipe()) {
+        (Ok(src), Ok(dst)) => {
+            if !off_in.is_null() || !off_out.is_null() {
+                return Err(Error::ESPIPE);
+            }
+            Ok(src.splice_to(&dst, len, nonblock, true)? as isize)
+        }
+        (Ok(src), Err(_)) => {
+            if !off_in.is_null() {
+                return Err(Error::ESPIPE);
+            }
+            let offset = splice_offset(token, off_out)?;
+            let len = splice_pipe_to_file(&src, &out_file, offset, len, nonblock)?;
+            if let Some(pos) = offset {
+                copyout(token, off_out, &((pos + len) as i64))?;
+            }
+            Ok(len as isize)
+        }
+        (Err(_), Ok(dst)) => {
+            if !off_out.is_null() {
+                return Err(Error::ESPIPE);
+            }
+            let offset = splice_offset(token, off_in)?;
+            let len = splice_file_to_pipe(&in_file, offset, &dst, len, nonblock)?;
+            if let Some(pos) = offset {
+                copyout(token, off_in, &((pos + len) as i64))?;
+            }
+            Ok(len as isize)
+        }
+        (Err(_), Err(_)) => Err(Error::EINVAL),
+    }
+}
+
+/// 复制管道中的数据到另一个管道, 两者共享数据所在的页, fd_in中的数据不被消耗
+pub fn sys_tee(fd_in: u32, fd_out: u32, len: usize, flags: u32) -> Result<isize, Error> {
+    trace!("sys_tee: fd_in = {}, fd_out = {}, len = {}, flags = {:#x}", fd_in, fd_out, len, flags);
+    let task = get_current_task().unwrap();
+    let src = task.get_file(fd_in)?.as_pipe().map_err(|_| Error::EINVAL)?;
+    let dst = task.get_file(fd_out)?.as_pipe().map_err(|_| Error::EINVAL)?;
+    drop(task);
+    if len == 0 {
+        return Ok(0);
+    }
+    Ok(src.splice_to(&dst, len, flags & SPLICE_F_NONBLOCK != 0, false)? as isize)
+}
+
+/// 管道的写端: 把用户的iovec写入管道; 读端: 把管道中的数据读到iovec中
+/// 直接在用户页和管道的页之间复制, SPLICE_F_GIFT被忽略
+pub fn sys_vmsplice(fd: u32, iovec_ptr: *const Iovec, cnt: usize, flags: u32) -> Result<isize, Error> {
+    trace!("sys_vmsplice: fd = {}, cnt = {}, flags = {:#x}", fd, cnt, flags);
+    let task = get_current_task().unwrap();
+    let token = task.get_user_token();
+    let pipe = task.get_file(fd)?.as_pipe().map_err(|_| Error::EBADF)?;
+    drop(task);
+    let nonblock = flags & SPLICE_F_NONBLOCK != 0;
+
+    /* 写入管道时读用户内存, 从管道读出时写用户内存 */
+    let mut mem = MemBuffer::new(VecDeque::new());
+    let mut iovec = Iovec::default();
+    let mut iovec_ptr = iovec_ptr;
+    for _ in 0..cnt {
+        copyin(token, &mut iovec, iovec_ptr)?;
+        unsafe {iovec_ptr = iovec_ptr.add(1);}
+        let mut part = MemBuffer::from_user_space(iovec.base as *mut u8, iovec.len, token, pipe.readable());
+        mem.buffer.append(&mut part.buffer);
+    }
+    if mem.len() == 0 {
+        return Ok(0);
+    }
+
+    if pipe.readable() {
+        let len = match pipe.wait_data(nonblock)? {
+            Some(mut buffer) => {
+                let len = buffer.read_to_buffer(&mut mem);
+                buffer.queue().wake_all();
+                len
+            }
+            None => 0,
+        };
+        return Ok(len as isize);
+    }
+
+    let mut has_write = 0;
+    while mem.len() > 0 {
+        let written = pipe.wait_room(nonblock).and_then(|mut buffer| {
+            let written = buffer.write_from_buffer(&mut mem)?;
+            buffer.queue().wake_all();
+            Ok(written)
+        });
+        match written {
+            Ok(written) => has_write += written,
+            /* 已经写入了部分数据, 返回写入的长度 */
+            Err(_) if has_write > 0 => break,
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(has_write as isize)
+}
+
 pub fn sys_renameat(
     oldfd: i32, 
     oldpath: *const u8, 
@@ -914,22 +1697,22 @@ pub fn sys_renameat(
     let token = task.get_user_token();
     let old_string = translate_str(token, oldpath)?;
     let new_string = translate_str(token, newpath)?;
-    let old_path = Path::from_string(old_string.clone())?;
-    let new_path = Path::from_string(new_string.clone())?;
     trace!("sys_renameat: oldfd = {}, newfd = {}, 
-        oldpath = {:?}, newpath = {:?}, flags = {:b}", 
-        oldfd, newfd, old_path, new_path, flags);
-    /* 目前只支持改名操作 */
-    /* 必须要在同一个文件夹内 */
-    if old_path.clone().remove_tail() != new_path.clone().remove_tail() {
-        unimplemented!();
-    }
-    if flags != 0 {
-        unimplemented!();
+        oldpath = {}, newpath = {}, flags = {:b}", 
+        oldfd, newfd, old_string, new_string, flags);
+    /* 只支持RENAME_NOREPLACE, 不支持RENAME_EXCHANGE和RENAME_WHITEOUT */
+    if flags & !RENAME_NOREPLACE != 0 {
+        return Err(Error::EINVAL);
     }
 
-    let (root_file, path) = get_file(oldfd, old_string.clone())?;
-    root_file.as_dir()?.rename(old_string, new_string)?;
+    let (old_root, old_path) = get_file(oldfd, old_string)?;
+    let (new_root, new_path) = get_file(newfd, new_string)?;
+    if flags & RENAME_NOREPLACE != 0
+        && open_at(new_root.clone(), new_path.clone(), FileOpenMode::SYS | FileOpenMode::NOFOLLOW).is_ok() {
+        return Err(Error::EEXIST);
+    }
+    /* 在父目录中修改文件名, 不同目录之间的移动返回EXDEV */
+    rename_at(old_root, old_path, new_root, new_path)?;
     Ok(0)
 }
 
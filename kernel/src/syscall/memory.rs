@@ -173,7 +173,26 @@ bitflags! {
     }
 }
 
-pub fn sys_msync() -> Result<isize, Error> {
+bitflags! {
+    pub struct MsyncFlag: u32 {
+        const ASYNC         = 0x1;
+        const INVALIDATE    = 0x2;
+        const SYNC          = 0x4;
+    }
+}
+
+pub fn sys_msync(addr: usize, len: usize, flags: u32) -> Result<isize, Error> {
+    let flags = MsyncFlag::from_bits(flags).ok_or(Error::EINVAL)?;
+    if addr % PAGE_SIZE != 0
+    || flags.contains(MsyncFlag::ASYNC | MsyncFlag::SYNC) {
+        return Err(Error::EINVAL);
+    }
+    trace!("sys_msync: addr = {:x}, len = {:x}, flags = {:?}", addr, len, flags);
+
+    /* 映射的页与页缓存共享, MS_ASYNC也立即写回 */
+    let task = get_current_task().unwrap();
+    let mut memory_set = task.get_memory();
+    memory_set.sync_area(VirtAddr::from(addr), VirtAddr::from(addr + len))?;
     Ok(0)
 }
 
@@ -207,4 +226,4 @@ bitflags! {
 //必须确保remap的段权限一致
 pub fn sys_mremap(old_address:usize,old_size:usize,new_size:usize,flags:u32,new_address:usize) -> Result<isize,Error> {
     return Ok(-1);
-}
\ No newline at end of file
+}
@@ -6,6 +6,7 @@ pub mod other;
 pub mod time;
 pub mod signal;
 pub mod net;
+pub mod strace;
 
 use fs::*;
 use memory::*;
@@ -17,126 +18,46 @@ use net::*;
 use log::*;
 
 use crate::fs::vfs::Statvfs;
+use crate::fs::epoll::EpollEvent;
 use crate::{fs::FileStat, proc::Rlimit};
 use crate::proc::{get_current_task, get_current_trap_context, TASK_MANAGER};
+use crate::utils::Error;
 use alloc::{
-    collections::BTreeMap
+    collections::{BTreeMap, BTreeSet},
+    vec::Vec,
 };
-use spin::lazy::Lazy;
-
-pub static SYSCALL_NAMES: Lazy<BTreeMap<usize, &'static str>> = Lazy::new(||{
-    let mut map = BTreeMap::new();
-        map.insert(17 , "GETCWD         ");
-        map.insert(23 , "DUP            ");
-        map.insert(24 , "DUP3           ");
-        map.insert(25 , "FCNTL          ");
-        map.insert(29 , "IOCTL          ");
-        map.insert(34 , "MKDIRAT        ");
-        map.insert(35 , "UNLINKAT       ");
-        map.insert(37 , "LINKAT         ");
-        map.insert(39 , "UMOUNT         ");
-        map.insert(40 , "MOUNT          ");
-        map.insert(43 , "STATFS         ");
-        map.insert(48 , "FACCESSAT      ");
-        map.insert(49 , "CHDIR          ");
-        map.insert(53 , "FCHMODAT       ");
-        map.insert(56 , "OPENAT         ");
-        map.insert(57 , "CLOSE          ");
-        map.insert(59 , "PIPE2          ");
-        map.insert(61 , "GETDENTS       ");
-        map.insert(62 , "LSEEK          ");
-        map.insert(63 , "READ           ");
-        map.insert(64 , "WRITE          ");
-        map.insert(65 , "READV          ");
-        map.insert(66 , "WRITEV         ");
-        map.insert(67 , "PREAD          ");
-        map.insert(68 , "PWRITE         ");
-        map.insert(71 , "SENDFILE       ");
-        map.insert(72 , "PSELECT6       ");
-        map.insert(73 , "PPOLL          ");
-        map.insert(78 , "READLINKAT     ");
-        map.insert(79 , "NEWFSTATAT     ");
-        map.insert(80 , "FSTAT          ");
-        map.insert(88 , "UTIMENSAT      ");
-        map.insert(93 , "EXIT           ");
-        map.insert(94 , "EXIT_GROUP     ");
-        map.insert(96 , "SET_TID_ADDRESS");
-        map.insert(98 , "FUTEX          ");
-        map.insert(99 , "SET_ROBUST_LIST");
-        map.insert(100 , "GET_ROBUST_LIST");
-        map.insert(101 , "NANOSLEEP      ");
-        map.insert(103 , "SETITIMER      ");
-        map.insert(113 , "CLOCKGETTIME   ");
-        map.insert(116 , "SYSLOG         ");
-        map.insert(124 , "SCHED_YIELD    ");
-        map.insert(129 , "KILL           ");
-        map.insert(130 , "TKILL          ");
-        map.insert(131 , "TGKILL         ");
-        map.insert(132 , "SIGALTSTACK    ");
-        map.insert(134 , "SIGACTION      ");
-        map.insert(135 , "SIGPROCMASK    ");
-        map.insert(137 , "RT_SIGTIMEDWAIT");
-        map.insert(139 , "SIGRETURN      ");
-        map.insert(153 , "TIMES          ");
-        map.insert(155 , "GETPGID        ");
-        map.insert(160 , "UNAME          ");
-        map.insert(165 , "GETRUSAGE      ");
-        map.insert(166 , "UMASK          "); 
-        map.insert(167 , "PRCTL          "); 
-        map.insert(169 , "GETTIMEOFDAY   ");
-        map.insert(172 , "GETPID         ");
-        map.insert(173 , "GETPPID        ");
-        map.insert(174 , "GETUID         ");
-        map.insert(175 , "GETEUID        ");
-        map.insert(176 , "GETGID         ");
-        map.insert(177 , "GETEGID        ");
-        map.insert(178 , "GETTID         ");
-        map.insert(179 , "SYSINFO        ");
-        map.insert(198 , "SOCKET         ");
-        map.insert(200 , "BIND           ");
-        map.insert(201 , "LISTEN         ");
-        map.insert(202 , "ACCEPT         ");
-        map.insert(203 , "CONNECT        ");
-        map.insert(204 , "GETSOCKNAME    ");
-        map.insert(206 , "SENDTO         ");
-        map.insert(207 , "RECVFROM       ");
-        map.insert(208 , "SETSOCKOPT     ");
-        map.insert(214 , "BRK            ");
-        map.insert(215 , "MUNMAP         ");
-        map.insert(216 , "MREMAP         ");
-        map.insert(220 , "CLONE          ");
-        map.insert(221 , "EXECVE         ");
-        map.insert(222 , "MMAP           ");
-        map.insert(226 , "MPROTECT       ");
-        map.insert(233 , "MADVISE        ");
-        map.insert(260 , "WAIT           ");
-        map.insert(261 , "PRLIMIT        ");
-        map.insert(276 , "RENAMEAT2      ");
-        map.insert(278 , "GETRANDOM      ");
-        map.insert(283 , "MEMBARRIER     ");
-        map.insert(998 , "STOP           ");
-        map.insert(999 , "SHUTDOWN       ");
-        map
-    }
-);
-
+use spin::{lazy::Lazy, Mutex};
 
 pub const SYSCALL_GETCWD            :usize = 17;
+pub const SYSCALL_EVENTFD2          :usize = 19;
+pub const SYSCALL_EPOLL_CREATE1     :usize = 20;
+pub const SYSCALL_EPOLL_CTL         :usize = 21;
+pub const SYSCALL_EPOLL_PWAIT       :usize = 22;
 pub const SYSCALL_DUP               :usize = 23;
 pub const SYSCALL_DUP3              :usize = 24;
 pub const SYSCALL_FCNTL             :usize = 25;
+pub const SYSCALL_INOTIFY_INIT1     :usize = 26;
+pub const SYSCALL_INOTIFY_ADD_WATCH :usize = 27;
+pub const SYSCALL_INOTIFY_RM_WATCH  :usize = 28;
 pub const SYSCALL_IOCTL             :usize = 29;
+pub const SYSCALL_FLOCK             :usize = 32;
+pub const SYSCALL_MKNODAT           :usize = 33;
 pub const SYSCALL_MKDIRAT           :usize = 34;
 pub const SYSCALL_UNLINKAT          :usize = 35;
+pub const SYSCALL_SYMLINKAT         :usize = 36;
 pub const SYSCALL_LINKAT            :usize = 37;
 pub const SYSCALL_UMOUNT            :usize = 39;
 pub const SYSCALL_MOUNT             :usize = 40;
 pub const SYSCALL_STATFS            :usize = 43;
+pub const SYSCALL_TRUNCATE          :usize = 45;
+pub const SYSCALL_FTRUNCATE         :usize = 46;
 pub const SYSCALL_FACCESSAT         :usize = 48;
 pub const SYSCALL_CHDIR             :usize = 49;
 pub const SYSCALL_FCHDIR            :usize = 50;
+pub const SYSCALL_FCHMOD            :usize = 52;
 pub const SYSCALL_FCHMODAT          :usize = 53;
 pub const SYSCALL_FCHOWNAT          :usize = 54;
+pub const SYSCALL_FCHOWN            :usize = 55;
 pub const SYSCALL_OPENAT            :usize = 56;
 pub const SYSCALL_CLOSE             :usize = 57;
 pub const SYSCALL_PIPE2             :usize = 59;
@@ -151,34 +72,60 @@ pub const SYSCALL_PWRITE            :usize = 68;
 pub const SYSCALL_SENDFILE          :usize = 71;
 pub const SYSCALL_PSELECT6          :usize = 72;
 pub const SYSCALL_PPOLL             :usize = 73;
+pub const SYSCALL_SIGNALFD4         :usize = 74;
+pub const SYSCALL_VMSPLICE          :usize = 75;
+pub const SYSCALL_SPLICE            :usize = 76;
+pub const SYSCALL_TEE               :usize = 77;
 pub const SYSCALL_READLINKAT        :usize = 78;
 pub const SYSCALL_NEWFSTATAT        :usize = 79;
 pub const SYSCALL_FSTAT             :usize = 80;
+pub const SYSCALL_SYNC              :usize = 81;
 pub const SYSCALL_FSYNC             :usize = 82;
+pub const SYSCALL_FDATASYNC         :usize = 83;
+pub const SYSCALL_TIMERFD_CREATE    :usize = 85;
+pub const SYSCALL_TIMERFD_SETTIME   :usize = 86;
+pub const SYSCALL_TIMERFD_GETTIME   :usize = 87;
 pub const SYSCALL_UTIMENSAT         :usize = 88;
 pub const SYSCALL_EXIT              :usize = 93;
 pub const SYSCALL_EXIT_GROUP        :usize = 94;
+pub const SYSCALL_WAITID            :usize = 95;
 pub const SYSCALL_SET_TID_ADDRESS   :usize = 96;
 pub const SYSCALL_FUTEX             :usize = 98;
 pub const SYSCALL_SET_ROBUST_LIST   :usize = 99;
 pub const SYSCALL_GET_ROBUST_LIST   :usize = 100;
 pub const SYSCALL_NANOSLEEP         :usize = 101;
+pub const SYSCALL_GETITIMER         :usize = 102;
 pub const SYSCALL_SETITIMER         :usize = 103;
 pub const SYSCALL_CLOCKGETTIME      :usize = 113;
+pub const SYSCALL_CLOCK_GETRES      :usize = 114;
+pub const SYSCALL_CLOCK_NANOSLEEP   :usize = 115;
 pub const SYSCALL_SYSLOG            :usize = 116;
+pub const SYSCALL_SCHED_SETAFFINITY :usize = 122;
+pub const SYSCALL_SCHED_GETAFFINITY :usize = 123;
 pub const SYSCALL_SCHED_YIELD       :usize = 124;
 pub const SYSCALL_KILL              :usize = 129;
 pub const SYSCALL_TKILL             :usize = 130;
 pub const SYSCALL_TGKILL            :usize = 131;
 pub const SYSCALL_SIGALTSTACK       :usize = 132;
+pub const SYSCALL_RT_SIGSUSPEND     :usize = 133;
 pub const SYSCALL_SIGACTION         :usize = 134;
 pub const SYSCALL_SIGPROCMASK       :usize = 135;
+pub const SYSCALL_RT_SIGPENDING     :usize = 136;
 pub const SYSCALL_RT_SIGTIMEDWAIT   :usize = 137;
 pub const SYSCALL_SIGRETURN         :usize = 139;
+pub const SYSCALL_SETGID            :usize = 144;
+pub const SYSCALL_SETUID            :usize = 146;
+pub const SYSCALL_SETRESUID         :usize = 147;
+pub const SYSCALL_GETRESUID         :usize = 148;
+pub const SYSCALL_SETRESGID         :usize = 149;
+pub const SYSCALL_GETRESGID         :usize = 150;
 pub const SYSCALL_TIMES             :usize = 153;
 pub const SYSCALL_SETPGID           :usize = 154;
 pub const SYSCALL_GETPGID           :usize = 155;
 pub const SYSCALL_GETSID            :usize = 156;
+pub const SYSCALL_SETSID            :usize = 157;
+pub const SYSCALL_GETGROUPS         :usize = 158;
+pub const SYSCALL_SETGROUPS         :usize = 159;
 pub const SYSCALL_UNAME             :usize = 160;
 pub const SYSCALL_GETRUSAGE         :usize = 165;
 pub const SYSCALL_UMASK             :usize = 166; 
@@ -193,14 +140,19 @@ pub const SYSCALL_GETEGID           :usize = 177;
 pub const SYSCALL_GETTID            :usize = 178;
 pub const SYSCALL_SYSINFO           :usize = 179;
 pub const SYSCALL_SOCKET            :usize = 198;
+pub const SYSCALL_SOCKETPAIR        :usize = 199;
 pub const SYSCALL_BIND              :usize = 200;
 pub const SYSCALL_LISTEN            :usize = 201;
 pub const SYSCALL_ACCEPT            :usize = 202;
 pub const SYSCALL_CONNECT           :usize = 203;
 pub const SYSCALL_GETSOCKNAME       :usize = 204;
+pub const SYSCALL_GETPEERNAME       :usize = 205;
 pub const SYSCALL_SENDTO            :usize = 206;
 pub const SYSCALL_RECVFROM          :usize = 207;
 pub const SYSCALL_SETSOCKOPT        :usize = 208;
+pub const SYSCALL_GETSOCKOPT        :usize = 209;
+pub const SYSCALL_SENDMSG           :usize = 211;
+pub const SYSCALL_RECVMSG           :usize = 212;
 pub const SYSCALL_BRK               :usize = 214;
 pub const SYSCALL_MUNMAP            :usize = 215;
 pub const SYSCALL_MREMAP            :usize = 216;
@@ -211,8 +163,10 @@ pub const SYSCALL_FADVACE64         :usize = 223;
 pub const SYSCALL_MPROTECT          :usize = 226;
 pub const SYSCALL_MSYNC             :usize = 227;
 pub const SYSCALL_MADVISE           :usize = 233;
+pub const SYSCALL_ACCEPT4           :usize = 242;
 pub const SYSCALL_WAIT              :usize = 260;
 pub const SYSCALL_PRLIMIT           :usize = 261;
+pub const SYSCALL_SYNCFS            :usize = 267;
 pub const SYSCALL_RENAMEAT2         :usize = 276;
 pub const SYSCALL_GETRANDOM         :usize = 278;
 pub const SYSCALL_MEMBARRIER        :usize = 283;
@@ -221,145 +175,234 @@ pub const SYSCALL_STOP              :usize = 998;
 pub const SYSCALL_SHUTDOWN          :usize = 999;
 
 
-pub fn strace(id: usize,args: [usize; 6] ,r:isize) -> bool {
-    let tid = get_current_task().unwrap().tid;
-    if id != SYSCALL_WAIT && id != SYSCALL_SCHED_YIELD  && tid != 0  {
-        let name = SYSCALL_NAMES.get(&id).unwrap_or(&"UNKONWN");
-        warn!("{}: tid = {} , args[0]: 0x{:x} , args[1]: 0x{:x} , args[2]: 0x{:x} , args[3]: 0x{:x} , args[4]: 0x{:x} ,", name, tid ,args[0],args[1],args[2],args[3],args[4]);
-        trace!("return: 0x{:x}",r as usize);
-        true
-    } else {
-        false
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyscallStatus {
+    /* 已经实现 */
+    Implemented,
+    /* 直接返回成功，没有实际效果 */
+    Stubbed,
+    /* 没有实现，返回ENOSYS */
+    Unimplemented,
+}
+
+pub type SyscallHandler = fn([usize; 6]) -> Result<isize, Error>;
+
+/// 系统调用表中的一项，handler负责从寄存器中解码参数并调用对应的sys_函数
+pub struct SyscallEntry {
+    pub name: &'static str,
+    pub status: SyscallStatus,
+    pub handler: SyscallHandler,
+}
+
+/* 运行时遇到的不在表中的系统调用号 */
+static UNKNOWN_SYSCALLS: Lazy<Mutex<BTreeSet<usize>>> = Lazy::new(|| Mutex::new(BTreeSet::new()));
+
+pub static SYSCALL_TABLE: Lazy<BTreeMap<usize, SyscallEntry>> = Lazy::new(||{
+    use SyscallStatus::*;
+    let mut table = BTreeMap::new();
+    let mut register = |id: usize, name: &'static str, status: SyscallStatus, handler: SyscallHandler| {
+        if table.insert(id, SyscallEntry { name, status, handler }).is_some() {
+            panic!("syscall {} ({}) registered twice", id, name);
+        }
+    };
+    let enosys: SyscallHandler = |_| Err(Error::ENOSYS);
+    let stub: SyscallHandler = |_| Ok(0);
+
+    register(SYSCALL_GETCWD,            "GETCWD",           Implemented,    |args| sys_getcwd(args[0] as *mut u8, args[1]));
+    register(SYSCALL_EVENTFD2,          "EVENTFD2",         Implemented,    |args| sys_eventfd2(args[0] as u32, args[1]));
+    register(SYSCALL_EPOLL_CREATE1,     "EPOLL_CREATE1",    Implemented,    |args| sys_epoll_create1(args[0]));
+    register(SYSCALL_EPOLL_CTL,         "EPOLL_CTL",        Implemented,    |args| sys_epoll_ctl(args[0] as u32, args[1], args[2] as u32, args[3] as *const EpollEvent));
+    register(SYSCALL_EPOLL_PWAIT,       "EPOLL_PWAIT",      Implemented,    |args| sys_epoll_pwait(args[0] as u32, args[1] as *mut EpollEvent, args[2] as i32, args[3] as i32, args[4] as *const usize));
+    register(SYSCALL_DUP,               "DUP",              Implemented,    |args| sys_dup(args[0] as u32));
+    register(SYSCALL_DUP3,              "DUP3",             Implemented,    |args| sys_dup3(args[0] as u32, args[1] as u32));
+    register(SYSCALL_FCNTL,             "FCNTL",            Implemented,    |args| sys_fcntl(args[0] as u32, args[1] as u32, args[2]));
+    register(SYSCALL_INOTIFY_INIT1,     "INOTIFY_INIT1",    Implemented,    |args| sys_inotify_init1(args[0]));
+    register(SYSCALL_INOTIFY_ADD_WATCH, "INOTIFY_ADD_WATCH",Implemented,    |args| sys_inotify_add_watch(args[0] as u32, args[1] as *const u8, args[2] as u32));
+    register(SYSCALL_INOTIFY_RM_WATCH,  "INOTIFY_RM_WATCH", Implemented,    |args| sys_inotify_rm_watch(args[0] as u32, args[1] as i32));
+    register(SYSCALL_IOCTL,             "IOCTL",            Implemented,    |args| sys_ioctl(args[0] as u32, args[1] as u32, args[2] as usize));
+    register(SYSCALL_FLOCK,             "FLOCK",            Implemented,    |args| sys_flock(args[0] as u32, args[1] as u32));
+    register(SYSCALL_MKNODAT,           "MKNODAT",          Implemented,    |args| sys_mknodat(args[0] as i32, args[1] as *const u8, args[2] as u32, args[3]));
+    register(SYSCALL_MKDIRAT,           "MKDIRAT",          Implemented,    |args| sys_mkdir(args[0] as i32, args[1] as *const u8, args[2] as u32));
+    register(SYSCALL_UNLINKAT,          "UNLINKAT",         Implemented,    |args| sys_unlinkat(args[0] as i32, args[1] as *const u8, args[2] as u32));
+    register(SYSCALL_SYMLINKAT,         "SYMLINKAT",        Implemented,    |args| sys_symlinkat(args[0] as *const u8, args[1] as i32, args[2] as *const u8));
+    register(SYSCALL_LINKAT,            "LINKAT",           Implemented,    |args| sys_linkat(args[0] as i32, args[1] as *const u8, args[2] as i32, args[3] as *const u8, args[4] as u32));
+    register(SYSCALL_UMOUNT,            "UMOUNT",           Implemented,    |args| sys_umount(args[0] as *const u8, args[1]));
+    register(SYSCALL_MOUNT,             "MOUNT",            Implemented,    |args| sys_mount(args[0] as *const u8, args[1] as *const u8, args[2] as *const u8, args[3], args[4] as *const u8));
+    register(SYSCALL_STATFS,            "STATFS",           Implemented,    |args| sys_statfs(args[0] as *const u8, args[1] as *mut Statvfs));
+    register(SYSCALL_TRUNCATE,          "TRUNCATE",         Unimplemented,  enosys);
+    register(SYSCALL_FTRUNCATE,         "FTRUNCATE",        Unimplemented,  enosys);
+    register(SYSCALL_FACCESSAT,         "FACCESSAT",        Implemented,    |args| sys_faccessat(args[0] as i32, args[1] as *const u8, args[2] as u32, 0));
+    register(SYSCALL_CHDIR,             "CHDIR",            Implemented,    |args| sys_chdir(args[0] as *const u8));
+    register(SYSCALL_FCHDIR,            "FCHDIR",           Stubbed,        stub);
+    register(SYSCALL_FCHMOD,            "FCHMOD",           Implemented,    |args| sys_fchmod(args[0] as u32, args[1] as u32));
+    register(SYSCALL_FCHMODAT,          "FCHMODAT",         Implemented,    |args| sys_fchmodat(args[0] as i32, args[1] as *const u8, args[2] as u32, args[3] as u32));
+    register(SYSCALL_FCHOWNAT,          "FCHOWNAT",         Implemented,    |args| sys_fchownat(args[0] as i32, args[1] as *const u8, args[2] as u32, args[3] as u32, args[4] as u32));
+    register(SYSCALL_FCHOWN,            "FCHOWN",           Implemented,    |args| sys_fchown(args[0] as u32, args[1] as u32, args[2] as u32));
+    register(SYSCALL_OPENAT,            "OPENAT",           Implemented,    |args| sys_open(args[0] as i32, args[1] as *const u8, args[2] as u32, args[3] as u32));
+    register(SYSCALL_CLOSE,             "CLOSE",            Implemented,    |args| sys_close(args[0] as u32));
+    register(SYSCALL_PIPE2,             "PIPE2",            Implemented,    |args| sys_pipe(args[0] as *mut u32,args[1]));
+    register(SYSCALL_GETDENTS,          "GETDENTS",         Implemented,    |args| sys_getdents(args[0] as u32, args[1] as *mut u8, args[2]));
+    register(SYSCALL_LSEEK,             "LSEEK",            Implemented,    |args| sys_lseek(args[0] as u32, args[1], args[2] as u32));
+    register(SYSCALL_READ,              "READ",             Implemented,    |args| sys_read(args[0] as u32, args[1] as *mut u8, args[2]));
+    register(SYSCALL_WRITE,             "WRITE",            Implemented,    |args| sys_write(args[0] as u32, args[1] as *const u8, args[2]));
+    register(SYSCALL_READV,             "READV",            Implemented,    |args| sys_readv(args[0] as u32, args[1] as *const Iovec, args[2]));
+    register(SYSCALL_WRITEV,            "WRITEV",           Implemented,    |args| sys_writev(args[0] as u32, args[1] as *const Iovec, args[2]));
+    register(SYSCALL_PREAD,             "PREAD",            Implemented,    |args| sys_pread(args[0] as u32, args[1] as *mut u8, args[2], args[3]));
+    register(SYSCALL_PWRITE,            "PWRITE",           Implemented,    |args| sys_pwrite(args[0] as u32, args[1] as *const u8, args[2], args[3]));
+    register(SYSCALL_SENDFILE,          "SENDFILE",         Implemented,    |args| sys_sendfile(args[0] as u32, args[1] as _, args[2] as _, args[3] as _));
+    register(SYSCALL_PSELECT6,          "PSELECT6",         Implemented,    |args| sys_pselect(args[0] as _, args[1] as _, args[2] as _, args[3] as _, args[4] as _, args[5] as _));
+    register(SYSCALL_PPOLL,             "PPOLL",            Implemented,    |args| sys_ppoll(args[0] as *mut Pollfd, args[1], args[2] as *const Timespec, args[3] as *const usize));
+    register(SYSCALL_SIGNALFD4,         "SIGNALFD4",        Implemented,    |args| sys_signalfd4(args[0] as i32, args[1] as *const usize, args[2], args[3]));
+    register(SYSCALL_VMSPLICE,          "VMSPLICE",         Implemented,    |args| sys_vmsplice(args[0] as u32, args[1] as *const Iovec, args[2], args[3] as u32));
+    register(SYSCALL_SPLICE,            "SPLICE",           Implemented,    |args| sys_splice(args[0] as u32, args[1] as *mut i64, args[2] as u32, args[3] as *mut i64, args[4], args[5] as u32));
+    register(SYSCALL_TEE,               "TEE",              Implemented,    |args| sys_tee(args[0] as u32, args[1] as u32, args[2], args[3] as u32));
+    register(SYSCALL_READLINKAT,        "READLINKAT",       Implemented,    |args| sys_readlinkat(args[0] as _, args[1] as _, args[2] as _, args[3] as _));
+    register(SYSCALL_NEWFSTATAT,        "NEWFSTATAT",       Implemented,    |args| sys_newfstatat(args[0] as i32, args[1] as *const u8, args[2] as *mut FileStat, args[3] as u32));
+    register(SYSCALL_FSTAT,             "FSTAT",            Implemented,    |args| sys_fstat(args[0] as u32, args[1] as *mut FileStat));
+    register(SYSCALL_SYNC,              "SYNC",             Implemented,    |_| sys_sync());
+    register(SYSCALL_FSYNC,             "FSYNC",            Implemented,    |args| sys_fsync(args[0] as u32));
+    register(SYSCALL_FDATASYNC,         "FDATASYNC",        Implemented,    |args| sys_fsync(args[0] as u32));
+    register(SYSCALL_SYNCFS,            "SYNCFS",           Implemented,    |args| sys_syncfs(args[0] as u32));
+    register(SYSCALL_TIMERFD_CREATE,    "TIMERFD_CREATE",   Implemented,    |args| sys_timerfd_create(args[0], args[1]));
+    register(SYSCALL_TIMERFD_SETTIME,   "TIMERFD_SETTIME",  Implemented,    |args| sys_timerfd_settime(args[0] as u32, args[1], args[2] as *const Itimerspec, args[3] as *mut Itimerspec));
+    register(SYSCALL_TIMERFD_GETTIME,   "TIMERFD_GETTIME",  Implemented,    |args| sys_timerfd_gettime(args[0] as u32, args[1] as *mut Itimerspec));
+    register(SYSCALL_UTIMENSAT,         "UTIMENSAT",        Implemented,    |args| sys_utimensat(args[0] as i32, args[1] as *const u8, args[2] as *const Timespec, args[3] as i32));
+    register(SYSCALL_EXIT,              "EXIT",             Implemented,    |args| sys_exit(args[0] as i32));
+    register(SYSCALL_EXIT_GROUP,        "EXIT_GROUP",       Implemented,    |args| sys_exit_group(args[0] as i32));
+    register(SYSCALL_WAITID,            "WAITID",           Unimplemented,  enosys);
+    register(SYSCALL_SET_TID_ADDRESS,   "SET_TID_ADDRESS",  Implemented,    |args| sys_set_tid_address(args[0] as *mut i32));
+    register(SYSCALL_FUTEX,             "FUTEX",            Implemented,    |args| sys_futex(args[0] as *const u32, args[1] as i32, args[2], args[3], args[4] as _, args[5] as _));
+    register(SYSCALL_SET_ROBUST_LIST,   "SET_ROBUST_LIST",  Implemented,    |args| sys_set_robust_list(args[0], args[1]));
+    register(SYSCALL_GET_ROBUST_LIST,   "GET_ROBUST_LIST",  Implemented,    |args| sys_get_robust_list(args[0] as i32, args[1], args[2]));
+    register(SYSCALL_NANOSLEEP,         "NANOSLEEP",        Implemented,    |args| sys_nanosleep(args[0] as *const Timespec, args[1] as *mut Timespec));
+    register(SYSCALL_GETITIMER,         "GETITIMER",        Unimplemented,  enosys);
+    register(SYSCALL_SETITIMER,         "SETITIMER",        Stubbed,        |_| sys_setitimer());
+    register(SYSCALL_CLOCKGETTIME,      "CLOCKGETTIME",     Implemented,    |args| sys_clock_gettime(args[0] as i32, args[1] as *mut Timespec));
+    register(SYSCALL_CLOCK_GETRES,      "CLOCK_GETRES",     Unimplemented,  enosys);
+    register(SYSCALL_CLOCK_NANOSLEEP,   "CLOCK_NANOSLEEP",  Unimplemented,  enosys);
+    register(SYSCALL_SYSLOG,            "SYSLOG",           Implemented,    |args| sys_syslog(args[0] as _, args[1] as _, args[2] as _));
+    register(SYSCALL_SCHED_SETAFFINITY, "SCHED_SETAFFINITY",Unimplemented,  enosys);
+    register(SYSCALL_SCHED_GETAFFINITY, "SCHED_GETAFFINITY",Unimplemented,  enosys);
+    register(SYSCALL_SCHED_YIELD,       "SCHED_YIELD",      Implemented,    |_| sys_sched_yield());
+    register(SYSCALL_KILL,              "KILL",             Implemented,    |args| sys_kill(args[0] as i32, args[1] as usize));
+    register(SYSCALL_TKILL,             "TKILL",            Implemented,    |args| sys_tkill(args[0] as i32, args[1] as usize));
+    register(SYSCALL_TGKILL,            "TGKILL",           Implemented,    |args| sys_tgkill(args[0] as i32, args[1] as i32, args[2] as usize));
+    register(SYSCALL_SIGALTSTACK,       "SIGALTSTACK",      Stubbed,        |args| sys_sigaltstack(args[0] as usize, args[1] as usize));
+    register(SYSCALL_RT_SIGSUSPEND,     "RT_SIGSUSPEND",    Unimplemented,  enosys);
+    register(SYSCALL_SIGACTION,         "SIGACTION",        Implemented,    |args| sys_sigaction(args[0] as usize,args[1] as *mut Sigaction,args[2] as *mut Sigaction));
+    register(SYSCALL_SIGPROCMASK,       "SIGPROCMASK",      Implemented,    |args| sys_sigprocmask(args[0] as usize,args[1] as *mut usize,args[2] as *mut usize));
+    register(SYSCALL_RT_SIGPENDING,     "RT_SIGPENDING",    Unimplemented,  enosys);
+    register(SYSCALL_RT_SIGTIMEDWAIT,   "RT_SIGTIMEDWAIT",  Stubbed,        |_| sys_sigtimedwait());
+    register(SYSCALL_SIGRETURN,         "SIGRETURN",        Implemented,    |_| sys_sigreturn());
+    register(SYSCALL_SETGID,            "SETGID",           Implemented,    |args| sys_setgid(args[0] as u32));
+    register(SYSCALL_SETUID,            "SETUID",           Implemented,    |args| sys_setuid(args[0] as u32));
+    register(SYSCALL_SETRESUID,         "SETRESUID",        Implemented,    |args| sys_setresuid(args[0] as u32, args[1] as u32, args[2] as u32));
+    register(SYSCALL_GETRESUID,         "GETRESUID",        Implemented,    |args| sys_getresuid(args[0] as *mut u32, args[1] as *mut u32, args[2] as *mut u32));
+    register(SYSCALL_SETRESGID,         "SETRESGID",        Implemented,    |args| sys_setresgid(args[0] as u32, args[1] as u32, args[2] as u32));
+    register(SYSCALL_GETRESGID,         "GETRESGID",        Implemented,    |args| sys_getresgid(args[0] as *mut u32, args[1] as *mut u32, args[2] as *mut u32));
+    register(SYSCALL_TIMES,             "TIMES",            Implemented,    |args| sys_times(args[0] as *mut Tms));
+    register(SYSCALL_SETPGID,           "SETPGID",          Stubbed,        stub);
+    register(SYSCALL_GETPGID,           "GETPGID",          Implemented,    |args| sys_getpgid(args[0] as _));
+    register(SYSCALL_GETSID,            "GETSID",           Stubbed,        stub);
+    register(SYSCALL_SETSID,            "SETSID",           Unimplemented,  enosys);
+    register(SYSCALL_GETGROUPS,         "GETGROUPS",        Implemented,    |args| sys_getgroups(args[0] as i32, args[1] as *mut u32));
+    register(SYSCALL_SETGROUPS,         "SETGROUPS",        Implemented,    |args| sys_setgroups(args[0], args[1] as *const u32));
+    register(SYSCALL_UNAME,             "UNAME",            Implemented,    |args| sys_uname(args[0] as *mut Utsname));
+    register(SYSCALL_GETRUSAGE,         "GETRUSAGE",        Implemented,    |args| sys_getrusage(args[0] as _, args[1] as _));
+    register(SYSCALL_UMASK,             "UMASK",            Implemented,    |args| sys_umask(args[0] as _));
+    register(SYSCALL_PRCTL,             "PRCTL",            Stubbed,        stub);
+    register(SYSCALL_GETTIMEOFDAY,      "GETTIMEOFDAY",     Implemented,    |args| sys_gettimeofday(args[0] as *mut Timeval));
+    register(SYSCALL_GETPID,            "GETPID",           Implemented,    |_| sys_getpid());
+    register(SYSCALL_GETPPID,           "GETPPID",          Implemented,    |_| sys_getppid());
+    register(SYSCALL_GETUID,            "GETUID",           Implemented,    |_| sys_getuid());
+    register(SYSCALL_GETEUID,           "GETEUID",          Implemented,    |_| sys_geteuid());
+    register(SYSCALL_GETGID,            "GETGID",           Implemented,    |_| sys_getgid());
+    register(SYSCALL_GETEGID,           "GETEGID",          Implemented,    |_| sys_getegid());
+    register(SYSCALL_GETTID,            "GETTID",           Implemented,    |_| sys_gettid());
+    register(SYSCALL_SYSINFO,           "SYSINFO",          Implemented,    |args| sys_sysinfo(args[0] as _));
+    register(SYSCALL_SOCKET,            "SOCKET",           Implemented,    |args| sys_socket(args[0],args[1],args[2]));
+    register(SYSCALL_SOCKETPAIR,        "SOCKETPAIR",       Implemented,    |args| sys_socketpair(args[0],args[1],args[2],args[3] as _));
+    register(SYSCALL_BIND,              "BIND",             Implemented,    |args| sys_bind(args[0],args[1],args[2]));
+    register(SYSCALL_LISTEN,            "LISTEN",           Implemented,    |args| sys_listen(args[0],args[1]));
+    register(SYSCALL_ACCEPT,            "ACCEPT",           Implemented,    |args| sys_accept(args[0],args[1],args[2]));
+    register(SYSCALL_CONNECT,           "CONNECT",          Implemented,    |args| sys_connect(args[0],args[1],args[2]));
+    register(SYSCALL_GETSOCKNAME,       "GETSOCKNAME",      Implemented,    |args| sys_getsockname(args[0],args[1],args[2]));
+    register(SYSCALL_GETPEERNAME,       "GETPEERNAME",      Implemented,    |args| sys_getpeername(args[0],args[1],args[2]));
+    register(SYSCALL_SENDTO,            "SENDTO",           Implemented,    |args| sys_sendto(args[0],args[1] as _,args[2],args[3],args[4] ,args[5]));
+    register(SYSCALL_RECVFROM,          "RECVFROM",         Implemented,    |args| sys_recvfrom(args[0],args[1] as _,args[2],args[3],args[4],args[5]));
+    register(SYSCALL_SETSOCKOPT,        "SETSOCKOPT",       Implemented,    |args| sys_setsockopt(args[0],args[1],args[2],args[3] as _,args[4]));
+    register(SYSCALL_GETSOCKOPT,        "GETSOCKOPT",       Unimplemented,  enosys);
+    register(SYSCALL_SENDMSG,           "SENDMSG",          Unimplemented,  enosys);
+    register(SYSCALL_RECVMSG,           "RECVMSG",          Unimplemented,  enosys);
+    register(SYSCALL_BRK,               "BRK",              Implemented,    |args| sys_brk(args[0] as usize));
+    register(SYSCALL_MUNMAP,            "MUNMAP",           Implemented,    |args| sys_munmap(args[0] as usize,args[1] as usize));
+    register(SYSCALL_MREMAP,            "MREMAP",           Implemented,    |args| sys_mremap(args[0] as usize,args[1] as usize,args[2] as usize,args[3] as u32,args[4] as usize));
+    register(SYSCALL_CLONE,             "CLONE",            Implemented,    |args| sys_clone( args[0] as u32, args[1], args[2] as *mut i32, args[3] as *mut usize, args[4] as *mut i32));
+    register(SYSCALL_EXECVE,            "EXECVE",           Implemented,    |args| sys_execve(args[0] as *const u8, args[1] as *const usize, args[2] as *const usize));
+    register(SYSCALL_MMAP,              "MMAP",             Implemented,    |args| sys_mmap(args[0],args[1],args[2] as u32,args[3] as u32,args[4] as i32,args[5]));
+    register(SYSCALL_FADVACE64,         "FADVISE64",        Stubbed,        stub);
+    register(SYSCALL_MPROTECT,          "MPROTECT",         Implemented,    |args| sys_mprotect(args[0] as usize,args[1] as usize,args[2] as _));
+    register(SYSCALL_MSYNC,             "MSYNC",            Implemented,    |args| sys_msync(args[0] as usize, args[1] as usize, args[2] as u32));
+    register(SYSCALL_MADVISE,           "MADVISE",          Implemented,    |args| sys_madvise(args[0] as _,args[1] as _,args[2]as _));
+    register(SYSCALL_ACCEPT4,           "ACCEPT4",          Unimplemented,  enosys);
+    register(SYSCALL_WAIT,              "WAIT",             Implemented,    |args| sys_wait4(args[0] as i32, args[1] as *mut i32, args[2] as i32));
+    register(SYSCALL_PRLIMIT,           "PRLIMIT",          Implemented,    |args| sys_prlimit(args[0] as i32, args[1] as i32, args[2] as *const Rlimit, args[3] as *mut Rlimit));
+    register(SYSCALL_RENAMEAT2,         "RENAMEAT2",        Implemented,    |args| sys_renameat(args[0] as _, args[1] as _, args[2] as _, args[3] as _, args[4] as _));
+    register(SYSCALL_GETRANDOM,         "GETRANDOM",        Implemented,    |args| sys_getrandom(args[0] as usize,args[1] as usize,args[2] as u32));
+    register(SYSCALL_MEMBARRIER,        "MEMBARRIER",       Implemented,    |args| sys_membarrier(args[0] as i32, args[1] as u32, args[2] as u32));
+    register(SYSCALL_FACCESSAT2,        "FACCESSAT2",       Implemented,    |args| sys_faccessat(args[0] as i32, args[1] as *const u8, args[2] as u32, args[3] as u32));
+    register(SYSCALL_STOP,              "STOP",             Implemented,    |_| sys_stop());
+    register(SYSCALL_SHUTDOWN,          "SHUTDOWN",         Implemented,    |_| sys_shutdown());
+    table
+});
+
+pub fn syscall_name(id: usize) -> &'static str {
+    SYSCALL_TABLE.get(&id).map(|entry| entry.name).unwrap_or("UNKNOWN")
+}
+
+/// 列出所有没有真正实现的系统调用(包括只返回0的桩函数)，以及运行时遇到的未知系统调用号
+pub fn unimplemented_syscalls() -> Vec<(usize, &'static str, SyscallStatus)> {
+    let mut list: Vec<_> = SYSCALL_TABLE
+        .iter()
+        .filter(|(_, entry)| entry.status != SyscallStatus::Implemented)
+        .map(|(id, entry)| (*id, entry.name, entry.status))
+        .collect();
+    for id in UNKNOWN_SYSCALLS.lock().iter() {
+        list.push((*id, "UNKNOWN", SyscallStatus::Unimplemented));
     }
+    list
 }
 
-pub fn strace2(id: usize) -> bool {
-    let tid = get_current_task().unwrap().tid;
-    if id != SYSCALL_WAIT && id != SYSCALL_SCHED_YIELD  
-    && id != SYSCALL_GETRUSAGE && id != SYSCALL_CLOCKGETTIME && tid != 0  {
-        let name = SYSCALL_NAMES.get(&id).unwrap_or(&"UNKONWN");
-        warn!("{}: tid = {}", name, tid );
-        true
-    } else {
-        false
+pub fn print_unimplemented_syscalls() {
+    for (id, name, status) in unimplemented_syscalls() {
+        info!("syscall {:>3} {:<18} {:?}", id, name, status);
     }
 }
 
 //note: 所有sys_开头的实现函数的返回类型应该为 isize 或 !
 pub fn syscall(id: usize, args: [usize; 6]) -> isize {
-    let strace = strace2(id);
-    let ret = match id {
-        SYSCALL_GETCWD          => sys_getcwd(args[0] as *mut u8, args[1]),
-        SYSCALL_PIPE2           => sys_pipe(args[0] as *mut u32,args[1]),
-        SYSCALL_DUP             => sys_dup(args[0] as u32),
-        SYSCALL_DUP3            => sys_dup3(args[0] as u32, args[1] as u32),
-        SYSCALL_FCNTL           => sys_fcntl(args[0] as u32, args[1] as u32, args[3]),
-        SYSCALL_IOCTL           => sys_ioctl(args[0] as u32, args[1] as u32, args[2] as usize),
-        SYSCALL_CHDIR           => sys_chdir(args[0] as *const u8),
-        SYSCALL_OPENAT          => sys_open(args[0] as i32, args[1] as *const u8, args[2] as u32, args[3] as u32),
-        SYSCALL_CLOSE           => sys_close(args[0] as u32),
-        SYSCALL_GETDENTS        => sys_getdents(args[0] as u32, args[1] as *mut u8, args[2]),
-        SYSCALL_LINKAT          => sys_linkat(args[0] as isize, args[1] as *const u8, args[2] as isize, args[3] as *const u8, args[4] as u32),
-        SYSCALL_UNLINKAT        => sys_unlinkat(args[0] as i32, args[1] as *const u8, args[3] as u32),
-        SYSCALL_MKDIRAT         => sys_mkdir(args[0] as i32, args[1] as *const u8, args[3] as u32),
-        SYSCALL_UMOUNT          => sys_umount(args[0] as *const u8, args[1]),
-        SYSCALL_MOUNT           => sys_mount(args[0] as *const u8, args[1] as *const u8, args[2] as *const u8, args[3], args[4] as *const u8),
-        SYSCALL_STATFS          => sys_statfs(args[0] as *const u8, args[1] as *mut Statvfs),
-        SYSCALL_FACCESSAT       => sys_faccessat(args[0] as i32, args[1] as *const u8, args[2] as u32, args[3] as u32),
-        SYSCALL_LSEEK           => sys_lseek(args[0] as u32, args[1], args[2] as u32),
-        SYSCALL_READ            => sys_read(args[0] as u32, args[1] as *mut u8, args[2]),
-        SYSCALL_WRITE           => sys_write(args[0] as u32, args[1] as *const u8, args[2]),
-        SYSCALL_READV           => sys_readv(args[0] as u32, args[1] as *const Iovec, args[2]),
-        SYSCALL_WRITEV          => sys_writev(args[0] as u32, args[1] as *const Iovec, args[2]),
-        SYSCALL_PREAD           => sys_pread(args[0] as u32, args[1] as *mut u8, args[2], args[3]),
-        SYSCALL_PWRITE          => sys_pwrite(args[0] as u32, args[1] as *const u8, args[2], args[3]),
-        SYSCALL_SENDFILE        => sys_sendfile(args[0] as u32, args[1] as _, args[2] as _, args[3] as _),
-        SYSCALL_PSELECT6        => sys_pselect(args[0] as _, args[1] as _, args[2] as _, args[3] as _, args[4] as _, args[5] as _),
-        SYSCALL_PPOLL           => sys_ppoll(args[0] as *mut Pollfd, args[1], args[2] as *const Timespec, args[3] as *const usize),
-        SYSCALL_READLINKAT      => sys_readlinkat(args[0] as _, args[1] as _, args[2] as _, args[3] as _),
-        SYSCALL_UTIMENSAT       => sys_utimensat(args[0] as i32, args[1] as *const u8, args[2] as *const Timespec, args[3] as i32),
-        SYSCALL_NEWFSTATAT      => sys_newfstatat(args[0] as i32, args[1] as *const u8, args[2] as *mut FileStat, args[3] as u32),
-        SYSCALL_FSTAT           => sys_fstat(args[0] as u32, args[1] as *mut FileStat),
-        SYSCALL_FSYNC           => sys_fsync(),
-        SYSCALL_SET_TID_ADDRESS => sys_set_tid_address(args[0] as *mut i32),
-        SYSCALL_FUTEX           => sys_futex(args[0] as *const u32, args[1] as i32, args[2], args[3], args[4] as _, args[5] as _),
-        SYSCALL_SET_ROBUST_LIST => sys_set_robust_list(args[0], args[1]),
-        SYSCALL_GET_ROBUST_LIST => sys_get_robust_list(args[0] as i32, args[1], args[2]),
-        SYSCALL_EXIT            => sys_exit(args[0] as i32),
-        SYSCALL_EXIT_GROUP      => sys_exit_group(args[0] as i32),
-        SYSCALL_NANOSLEEP       => sys_nanosleep(args[0] as *const Timespec, args[1] as *mut Timespec),
-        SYSCALL_SETITIMER       => sys_setitimer(),
-        SYSCALL_CLOCKGETTIME    => sys_clock_gettime(args[0] as i32, args[1] as *mut Timespec),
-        SYSCALL_SYSLOG          => sys_syslog(args[0] as _, args[1] as _, args[2] as _),
-        SYSCALL_SCHED_YIELD     => sys_sched_yield(),
-        SYSCALL_TIMES           => sys_times(args[0] as *mut Tms),
-        SYSCALL_GETPGID         => sys_getpgid(args[0] as _),
-        SYSCALL_UNAME           => sys_uname(args[0] as *mut Utsname),
-        SYSCALL_GETRUSAGE       => sys_getrusage(args[0] as _, args[1] as _),
-        SYSCALL_UMASK           => sys_umask(args[0] as _),
-        SYSCALL_GETTIMEOFDAY    => sys_gettimeofday(args[0] as *mut Timeval),
-        SYSCALL_GETPID          => sys_getpid(),
-        SYSCALL_GETPPID         => sys_getppid(),
-        SYSCALL_GETUID          => sys_getuid(),
-        SYSCALL_GETEUID         => sys_geteuid(),
-        SYSCALL_GETEGID         => sys_getegid(),
-        SYSCALL_GETTID          => sys_gettid(),
-        SYSCALL_SOCKET          => sys_socket(args[0],args[1],args[2]),
-        SYSCALL_BIND            => sys_bind(args[0],args[1],args[2]),
-        SYSCALL_LISTEN          => sys_listen(args[0],args[1]),
-        SYSCALL_ACCEPT          => sys_accept(args[0],args[1],args[2]),
-        SYSCALL_CONNECT         => sys_connect(args[0],args[1],args[2]),
-        SYSCALL_GETSOCKNAME     => sys_getsockname(args[0],args[1],args[2]),
-        SYSCALL_SENDTO          => sys_sendto(args[0],args[1] as _,args[2],args[3],args[4] ,args[5]),
-        SYSCALL_RECVFROM        => sys_recvfrom(args[0],args[1] as _,args[2],args[3],args[4],args[5]),
-        SYSCALL_SETSOCKOPT      => sys_setsockopt(args[0],args[1],args[2],args[3] as _,args[4]),
-        SYSCALL_BRK             => sys_brk(args[0] as usize),
-        SYSCALL_MUNMAP          => sys_munmap(args[0] as usize,args[1] as usize),
-        SYSCALL_CLONE           => sys_clone( args[0] as u32, args[1], args[2] as *mut i32, args[3] as *mut usize, args[4] as *mut i32),
-        SYSCALL_EXECVE          => sys_execve(args[0] as *const u8, args[1] as *const usize, args[2] as *const usize),
-        SYSCALL_MMAP            => sys_mmap(args[0],args[1],args[2] as u32,args[3] as u32,args[4] as i32,args[5]),
-        SYSCALL_MSYNC           => sys_msync(),
-        SYSCALL_WAIT            => sys_wait4(args[0] as i32, args[1] as *mut i32, args[2] as i32),
-        SYSCALL_KILL            => sys_kill(args[0] as i32, args[1] as usize),
-        SYSCALL_TKILL           => sys_tkill(args[0] as i32, args[1] as usize),
-        SYSCALL_SIGRETURN       => sys_sigreturn(),
-        SYSCALL_SIGPROCMASK     => sys_sigprocmask(args[0] as usize,args[1] as *mut usize,args[2] as *mut usize),
-        SYSCALL_RT_SIGTIMEDWAIT => sys_sigtimedwait(),
-        SYSCALL_MADVISE         => sys_madvise(args[0] as _,args[1] as _,args[2]as _),
-        SYSCALL_SIGACTION       => sys_sigaction(args[0] as usize,args[1] as *mut Sigaction,args[2] as *mut Sigaction),
-        SYSCALL_SYSINFO         => sys_sysinfo(args[0] as _),
-        SYSCALL_PRLIMIT         => sys_prlimit(args[0] as i32, args[1] as i32, args[2] as *const Rlimit, args[3] as *mut Rlimit),
-        SYSCALL_MPROTECT        => sys_mprotect(args[0] as usize,args[1] as usize,args[2] as _),
-        SYSCALL_RENAMEAT2       => sys_renameat(args[0] as _, args[1] as _, args[2] as _, args[3] as _, args[4] as _),
-        SYSCALL_MEMBARRIER      => sys_membarrier(args[0] as i32, args[1] as u32, args[2] as u32),
-        SYSCALL_STOP            => sys_stop(),
-        SYSCALL_SHUTDOWN        => sys_shutdown(),
-        SYSCALL_GETPGID         => sys_getpgid(args[0] as _),
-        SYSCALL_UMASK           => Ok(0),
-        SYSCALL_FCHMODAT        => Ok(0),
-        SYSCALL_SIGALTSTACK     => sys_sigaltstack(args[0] as usize, args[1] as usize),
-        SYSCALL_GETRANDOM       => sys_getrandom(args[0] as usize,args[1] as usize,args[2] as u32),
-        SYSCALL_PRCTL           => Ok(0),
-        SYSCALL_MREMAP          => sys_mremap(args[0] as usize,args[1] as usize,args[2] as usize,args[3] as u32,args[4] as usize),
-        SYSCALL_GETGID          => Ok(0),
-        SYSCALL_SETPGID         => Ok(0),
-        SYSCALL_GETSID          => Ok(0),
-        SYSCALL_FACCESSAT2      => sys_faccessat(args[0] as i32, args[1] as *const u8, args[2] as u32, args[3] as u32),
-        SYSCALL_FADVACE64       => Ok(0),
-        SYSCALL_FCHOWNAT        => Ok(0),
-        SYSCALL_FCHDIR          => Ok(0),
-        _ => panic!("Unsupported syscall_id: {}", id),
+    let record = strace::trace_enter(id, &args);
+    let ret = match SYSCALL_TABLE.get(&id) {
+        Some(entry) => {
+            if entry.status == SyscallStatus::Unimplemented {
+                debug!("syscall {} ({}) is not implemented", id, entry.name);
+            }
+            (entry.handler)(args)
+        }
+        None => {
+            if UNKNOWN_SYSCALLS.lock().insert(id) {
+                warn!("unknown syscall_id: {}", id);
+            }
+            Err(Error::ENOSYS)
+        }
     };
 
+    if let Some(record) = record {
+        strace::trace_exit(record, &ret);
+    }
 
     match ret {
-        Ok(r) => {
-            if strace {info!("syscall_ret: {:x}", r);}
-            r
-        },
-        Err(err) => {
-            if strace {warn!("syscall_ret: {:?}", err);}
-            -(err as isize)
-        }
+        Ok(r) => r,
+        Err(err) => -(err as isize),
     }
-}
\ No newline at end of file
+}
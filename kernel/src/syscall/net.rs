@@ -1,61 +1,123 @@
-use crate::memory::copyout_vec;
+use alloc::sync::Arc;
+use core::mem::size_of;
+use crate::fs::file::SocketFile;
+use crate::memory::{copyin_vec, copyout, copyout_vec};
 use crate::utils::Error;
+use crate::net::{SockAddr, AF_UNIX};
 use crate::net::socket::Socket;
+use crate::net::unix::UnixSocket;
 use crate::proc::{get_current_task, get_current_user_token};
 
+fn get_socket(sockfd: usize) -> Result<Arc<dyn SocketFile>, Error> {
+    let task = get_current_task().unwrap();
+    let file = task.get_fd_table().get_file(sockfd as u32)?;
+    file.as_socket().map_err(|_| Error::ENOTSOCK)
+}
+
 /// socket（）创建通信端点，
 /// 并返回引用该端点的文件描述符。
 /// 成功调用返回的文件描述符将是当前未为进程打开的编号最低的文件描述符。
 pub fn sys_socket(family: usize, socktype: usize, protocol: usize) -> Result<isize, Error> {
+    if family == AF_UNIX as usize {
+        let sock = UnixSocket::new(socktype)?;
+        let task = get_current_task().unwrap();
+        let fd_limit = task.get_max_fd();
+        let fd = task.get_fd_table().add_file(sock, fd_limit)?;
+        return Ok(fd as _);
+    }
     let sock = Socket::sock_create(family, socktype, protocol)?;
-    let fd = sock.sock_map_fd().unwrap();
+    let fd = sock.sock_map_fd()?;
     Ok(fd as _)
 }
+
+/// 创建一对互相连接的匿名socket, 目前只支持AF_UNIX
+pub fn sys_socketpair(family: usize, socktype: usize, _protocol: usize, sv: *mut i32) -> Result<isize, Error> {
+    if family != AF_UNIX as usize {
+        return Err(Error::EOPNOTSUPP);
+    }
+    let (sock0, sock1) = UnixSocket::new_pair(socktype)?;
+
+    let task = get_current_task().unwrap();
+    let token = task.get_user_token();
+    let mut fd_table = task.get_fd_table();
+    let fd_limit = task.get_max_fd();
+    let fd0 = fd_table.add_file(sock0, fd_limit)?;
+    let fd1 = match fd_table.add_file(sock1, fd_limit) {
+        Ok(fd) => fd,
+        Err(err) => {
+            fd_table.delete_file(fd0).unwrap();
+            return Err(err);
+        }
+    };
+    drop(fd_table);
+
+    copyout(token, sv, &(fd0 as i32))?;
+    copyout(token, (sv as usize + size_of::<i32>()) as *mut i32, &(fd1 as i32))?;
+    Ok(0)
+}
+
 /// Bind函数将socket与本机上的一个端口相关联，随后你就可以在该端口监听服务请求
-pub fn sys_bind(_sockfd: usize, _addr: usize, _addrlen: usize) -> Result<isize, Error> {
+pub fn sys_bind(sockfd: usize, addr: usize, addrlen: usize) -> Result<isize, Error> {
+    let socket = get_socket(sockfd)?;
+    let addr = SockAddr::from_user(get_current_user_token(), addr, addrlen)?;
+    socket.bind(addr)?;
     Ok(0)
 }
+
 /// listen函数使socket处于被动的监听模式，并为该socket建立一个输入数据队列，将到达的服务请求保存在此队列中，直到程序处理它们
-pub fn sys_listen(sockfd: usize, _backlog: usize) -> Result<isize, Error> {
-    // Ok(0)
-    let task = get_current_task().unwrap();
-    let socket = task.get_fd_table().get_file(sockfd as u32)?;
-    socket.as_socket()?.listen().map(|a| a as _)
+pub fn sys_listen(sockfd: usize, backlog: usize) -> Result<isize, Error> {
+    let socket = get_socket(sockfd)?;
+    socket.listen(backlog)?;
+    Ok(0)
 }
 
-pub fn sys_accept(sockfd: usize, _addr: usize, _addrlen: usize) -> Result<isize, Error> {
-    // Ok(1)
+pub fn sys_accept(sockfd: usize, addr: usize, addrlen: usize) -> Result<isize, Error> {
+    let socket = get_socket(sockfd)?;
+    let (new_socket, peer) = socket.accept()?;
+    peer.to_user(get_current_user_token(), addr, addrlen)?;
+
     let task = get_current_task().unwrap();
-    let socket = task.get_fd_table().get_file(sockfd as u32)?;
-    socket.as_socket()?.accept().map(|a| a as _)
+    let fd_limit = task.get_max_fd();
+    let fd = task.get_fd_table().add_file(new_socket, fd_limit)?;
+    Ok(fd as isize)
 }
 
-pub fn sys_connect(sockfd: usize, _addr: usize, _addrlen: usize) -> Result<isize, Error> {
-    // Ok(0)
-    let task = get_current_task().unwrap();
-    let socket = task.get_fd_table().get_file(sockfd as u32)?;
-    socket.as_socket()?.connect().map(|a| a as _)
+pub fn sys_connect(sockfd: usize, addr: usize, addrlen: usize) -> Result<isize, Error> {
+    let socket = get_socket(sockfd)?;
+    let addr = SockAddr::from_user(get_current_user_token(), addr, addrlen)?;
+    socket.connect(addr)?;
+    Ok(0)
 }
 
-pub fn sys_getsockname(sockfd: usize, _addr: usize, _addrlen: usize) -> Result<isize, Error> {
-    // Ok(0)
-    let task = get_current_task().unwrap();
-    let socket = task.get_fd_table().get_file(sockfd as u32)?;
-    socket.as_socket()?.getsockname().map(|a| a as _)
+pub fn sys_getsockname(sockfd: usize, addr: usize, addrlen: usize) -> Result<isize, Error> {
+    let socket = get_socket(sockfd)?;
+    socket.getsockname()?.to_user(get_current_user_token(), addr, addrlen)?;
+    Ok(0)
+}
+
+pub fn sys_getpeername(sockfd: usize, addr: usize, addrlen: usize) -> Result<isize, Error> {
+    let socket = get_socket(sockfd)?;
+    socket.getpeername()?.to_user(get_current_user_token(), addr, addrlen)?;
+    Ok(0)
 }
 
 pub fn sys_sendto(
     sockfd: usize,
-    _buf: usize,
-    _len: usize,
+    buf: *const u8,
+    len: usize,
     _flags: usize,
-    _addr: usize,
-    _addrlen: usize,
+    addr: usize,
+    addrlen: usize,
 ) -> Result<isize, Error> {
-    // Ok(1)
-    let task = get_current_task().unwrap();
-    let socket = task.get_fd_table().get_file(sockfd as u32)?;
-    socket.as_socket()?.sendto().map(|a| a as _)
+    let socket = get_socket(sockfd)?;
+    let token = get_current_user_token();
+    let addr = if addr == 0 {
+        None
+    } else {
+        Some(SockAddr::from_user(token, addr, addrlen)?)
+    };
+    let data = copyin_vec(token, buf, len)?;
+    socket.sendto(data.as_slice(), addr).map(|size| size as isize)
 }
 
 pub fn sys_recvfrom(
@@ -63,27 +125,35 @@ pub fn sys_recvfrom(
     buf: *mut u8,
     len: usize,
     _flags: usize,
-    _addr: usize,
-    _addrlen: usize,
+    addr: usize,
+    addrlen: usize,
 ) -> Result<isize, Error> {
-    let task = get_current_task().unwrap();
-    let socket = task.get_fd_table().get_file(sockfd as u32)?;
+    let socket = get_socket(sockfd)?;
     let token = get_current_user_token();
-    let data = socket.as_socket().unwrap().recvfrom(len)?;
-    copyout_vec(token, buf, data)?;
-    Ok(1)
-    // socket.as_socket()?.recvfrom().map(|a| a as _)
+    let (data, peer) = socket.recvfrom(len)?;
+    let size = data.len();
+    if size > 0 {
+        copyout_vec(token, buf, data)?;
+    }
+    if let Some(peer) = peer {
+        peer.to_user(token, addr, addrlen)?;
+    }
+    Ok(size as isize)
 }
 
 pub fn sys_setsockopt(
     sockfd: usize,
-    _level: usize,
-    _optname: usize,
-    _optval: usize,
-    _optlen: usize,
+    level: usize,
+    optname: usize,
+    optval: *const u8,
+    optlen: usize,
 ) -> Result<isize, Error> {
-    // Ok(0)
-    let task = get_current_task().unwrap();
-    let socket = task.get_fd_table().get_file(sockfd as u32)?;
-    socket.as_socket()?.setsockopt().map(|a| a as _)
+    let socket = get_socket(sockfd)?;
+    let optval = if optval.is_null() {
+        alloc::vec::Vec::new()
+    } else {
+        copyin_vec(get_current_user_token(), optval, optlen)?
+    };
+    socket.setsockopt(level, optname, optval.as_slice())?;
+    Ok(0)
 }
@@ -1,7 +1,7 @@
 use crate::config::*;
-use crate::fs::{open, FileOpenMode, SeekMode};
+use crate::fs::{open, FileOpenMode, SeekMode, MemStat};
 use crate::memory::{copyout, copyout_vec};
-use crate::proc::{get_current_user_token, get_current_task};
+use crate::proc::{get_current_user_token, get_current_task, get_loadavg, get_task_count, FSHIFT};
 use crate::timer::get_time_ms;
 use crate::utils::Error;
 use super::time::Timeval;
@@ -69,14 +69,31 @@ pub struct SysInfo {
     pub bufferram   :usize,
     pub totalswap   :usize,
     pub freeswap    :usize,
-    pub reserve     :[u8; 22]
+    pub procs       :u16,
+    pub pad         :u16,
+    pub totalhigh   :usize,
+    pub freehigh    :usize,
+    pub mem_unit    :u32,
 }
 
 pub fn sys_sysinfo(info_ptr: *mut SysInfo) -> Result<isize, Error> {
+    /* sysinfo中的负载为16位定点小数 */
+    const SI_LOAD_SHIFT: usize = 16;
     let mut info: SysInfo = Default::default();
     const MSEC_PER_SEC: usize = 1000;
     info.uptime = get_time_ms() / MSEC_PER_SEC;
-    info.totalram = 0x80_000_000;
+    let loads = get_loadavg();
+    for i in 0..3 {
+        info.loads[i] = loads[i] << (SI_LOAD_SHIFT - FSHIFT);
+    }
+    let mem = MemStat::now();
+    info.totalram = mem.total;
+    info.freeram = mem.free;
+    info.bufferram = mem.buffers;
+    info.totalswap = mem.swap_total;
+    info.freeswap = mem.swap_free;
+    info.procs = get_task_count().1 as u16;
+    info.mem_unit = 1;
     let token = get_current_user_token();
     copyout(token, info_ptr, &info)?;
     Ok(0)
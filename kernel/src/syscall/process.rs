@@ -1,7 +1,7 @@
 use core::char::MAX;
 use core::sync::atomic::Ordering;
 
-use alloc::string::{String, ToString};
+use alloc::string::String;
 use alloc::sync::Arc;
 use alloc::vec::Vec;
 use crate::memory::{translate_str,  copyout, copyin};
@@ -10,11 +10,12 @@ use crate::proc::{
     Rlimit, RLIMIT, CloneFlags,
     exit_current,
     suspend_current, get_task_by_tid,
-    get_current_task, sleep_current, add_clock_futex_task, wake_clock_futex_task
+    get_current_task, sleep_current, add_clock_futex_task, wake_clock_futex_task,
+    Access, NGROUPS_MAX
 };
 use crate::sbi::{sbi_remote_sfence_vma_all, sbi_putchar};
 use crate::utils::{Path, Error};
-use crate::fs::{open, FileOpenMode};
+use crate::fs::{open, check_access, FileOpenMode, FileType};
 use log::*;
 
 use super::time::Timespec;
@@ -50,16 +51,93 @@ pub fn sys_getppid() -> Result<isize, Error> {
     Ok(ret)
 }
 
-/* 不支持uid, euid, egid */
 pub fn sys_getuid() -> Result<isize, Error> {
-    Ok(0)
+    Ok(get_current_task().unwrap().get_cred().ruid as isize)
 }
 
 pub fn sys_geteuid() -> Result<isize, Error> {
-    Ok(0)
+    Ok(get_current_task().unwrap().get_cred().euid as isize)
+}
+
+pub fn sys_getgid() -> Result<isize, Error> {
+    Ok(get_current_task().unwrap().get_cred().rgid as isize)
 }
 
 pub fn sys_getegid() -> Result<isize, Error> {
+    Ok(get_current_task().unwrap().get_cred().egid as isize)
+}
+
+pub fn sys_setuid(uid: u32) -> Result<isize, Error> {
+    get_current_task().unwrap().get_cred().setuid(uid)?;
+    Ok(0)
+}
+
+pub fn sys_setgid(gid: u32) -> Result<isize, Error> {
+    get_current_task().unwrap().get_cred().setgid(gid)?;
+    Ok(0)
+}
+
+pub fn sys_setresuid(ruid: u32, euid: u32, suid: u32) -> Result<isize, Error> {
+    get_current_task().unwrap().get_cred().setresuid(ruid, euid, suid)?;
+    Ok(0)
+}
+
+pub fn sys_setresgid(rgid: u32, egid: u32, sgid: u32) -> Result<isize, Error> {
+    get_current_task().unwrap().get_cred().setresgid(rgid, egid, sgid)?;
+    Ok(0)
+}
+
+pub fn sys_getresuid(ruid: *mut u32, euid: *mut u32, suid: *mut u32) -> Result<isize, Error> {
+    let task = get_current_task().unwrap();
+    let token = task.get_user_token();
+    let cred = task.get_cred().clone();
+    copyout(token, ruid, &cred.ruid)?;
+    copyout(token, euid, &cred.euid)?;
+    copyout(token, suid, &cred.suid)?;
+    Ok(0)
+}
+
+pub fn sys_getresgid(rgid: *mut u32, egid: *mut u32, sgid: *mut u32) -> Result<isize, Error> {
+    let task = get_current_task().unwrap();
+    let token = task.get_user_token();
+    let cred = task.get_cred().clone();
+    copyout(token, rgid, &cred.rgid)?;
+    copyout(token, egid, &cred.egid)?;
+    copyout(token, sgid, &cred.sgid)?;
+    Ok(0)
+}
+
+/* size为0时只返回附加组的数量 */
+pub fn sys_getgroups(size: i32, list: *mut u32) -> Result<isize, Error> {
+    let task = get_current_task().unwrap();
+    let token = task.get_user_token();
+    let groups = task.get_cred().groups.clone();
+    if size < 0 {
+        return Err(Error::EINVAL);
+    }
+    if size == 0 {
+        return Ok(groups.len() as isize);
+    }
+    if (size as usize) < groups.len() {
+        return Err(Error::EINVAL);
+    }
+    for (i, gid) in groups.iter().enumerate() {
+        copyout(token, unsafe { list.add(i) }, gid)?;
+    }
+    Ok(groups.len() as isize)
+}
+
+pub fn sys_setgroups(size: usize, list: *const u32) -> Result<isize, Error> {
+    let task = get_current_task().unwrap();
+    let token = task.get_user_token();
+    if size > NGROUPS_MAX {
+        return Err(Error::EINVAL);
+    }
+    let mut groups = vec![0u32; size];
+    for (i, gid) in groups.iter_mut().enumerate() {
+        copyin(token, gid, unsafe { list.add(i) })?;
+    }
+    task.get_cred().setgroups(groups)?;
     Ok(0)
 }
 
@@ -157,8 +235,6 @@ pub fn sys_execve(
     if let Some(string) = argv_strings.get(1) {
         if string == "lmdd" {
             argv_strings[4] = String::from("move=1m");
-        } else if string == "bw_pipe" {
-            argv_strings.append(&mut vec!["-M".to_string(), "10k".to_string(), "-m".to_string(), "1k".to_string()])
         }
     }
 
@@ -182,15 +258,22 @@ pub fn sys_execve(
 
     /* 打开可执行文件 */
     let file;
+    let exe: Path;
     if filename.ends_with(".sh") {
         argv_strings.insert(0, String::from("busybox"));
         argv_strings.insert(1, String::from("sh"));
-        file = open("busybox".into(), FileOpenMode::SYS)?;
+        exe = "busybox".into();
     } else {
-        file = open(path, FileOpenMode::SYS)?;
+        exe = path;
+    }
+    file = open(exe.clone(), FileOpenMode::SYS)?;
+    /* 只能执行有执行权限的普通文件 */
+    if file.get_type()? != FileType::RegularFile {
+        return Err(Error::EACCES);
     }
+    check_access(&file, Access::X)?;
     /* 根据可执行文件构造TCB */
-    let ret = task.exec(file, argv_strings,envp_strings)?;
+    let ret = task.exec(exe, file, argv_strings,envp_strings)?;
     task.get_memory().trace_areas();
     
     Ok(ret)
@@ -379,6 +462,7 @@ pub fn sys_wait4(pid: i32, exit_code_ptr: *mut i32, _options: i32) -> Result<isi
             }
             task.time_info.lock().update_time_child_exit(&child_task.time_info.lock());
             let found_pid = child_task.get_pid();
+            super::strace::strace_release(found_pid);
             let exit_code = child_task.get_exit_code() << 8;   
 
             if exit_code_ptr as i32 != 0 {
@@ -457,6 +541,8 @@ pub fn sys_stop() -> Result<isize, Error> {
 
 pub fn sys_shutdown() -> ! {
     println!("shutdown at time = {:?}", Timespec::now());
+    super::print_unimplemented_syscalls();
+    crate::fs::sync_all();
     crate::sbi::shutdown()
 }
 
@@ -2,6 +2,8 @@ use crate::memory::{copyout, copyin};
 use crate::proc::{get_current_task, get_current_user_token, get_task_by_pid,get_task_by_tid,get_current_trap_context};
 pub use crate::proc::{Sigaction,UContext,SignalContext,SigInfo,SiganlStack_info};
 use crate::proc::{SIGKILL,SIGSTOP};
+use crate::proc::wake_signal_waiters;
+use crate::fs::signalfd::{SignalFd, SFD_NONBLOCK, SFD_CLOEXEC};
 use crate::utils::Error;
 use core::ptr;
 use log::*;
@@ -91,6 +93,7 @@ pub fn sys_kill(pid: i32, signum: usize) -> Result<isize, Error> {
         _ => get_task_by_pid(pid).ok_or(Error::ESRCH)?,
     };
     task.p_pending.lock().pending_signal(signum);
+    wake_signal_waiters(task.pid, None);
     Ok(0)
 }
 
@@ -99,6 +102,7 @@ pub fn sys_tkill(tid: i32, signum: usize) -> Result<isize, Error>{
 
     let task = get_task_by_tid(tid).ok_or(Error::ESRCH)?;
     task.t_pending.lock().pending_signal(signum);
+    wake_signal_waiters(task.pid, Some(task.tid));
     Ok(0)
 }
 
@@ -110,6 +114,7 @@ pub fn sys_tgkill(tgid: i32, tid: i32, signum: usize) -> Result<isize, Error> {
         todo!("send signum to certain group member");
     }
     task.p_pending.lock().pending_signal(signum);
+    wake_signal_waiters(task.pid, None);
     Ok(0)
 }
 
@@ -146,3 +151,22 @@ pub fn sys_sigaltstack(signal_stack:usize,old_signal_stack:usize) -> Result<isiz
     let mut signal_context = SignalContext::new(0);
     Ok(0)
 }
+
+/* fd为-1时创建新的signalfd, 否则修改已有signalfd的信号集合 */
+pub fn sys_signalfd4(fd: i32, mask: *const usize, sizemask: usize, flags: usize) -> Result<isize, Error> {
+    if sizemask != core::mem::size_of::<usize>() || flags & !(SFD_NONBLOCK | SFD_CLOEXEC) != 0 {
+        return Err(Error::EINVAL);
+    }
+    let task = get_current_task().unwrap();
+    let mut set = 0;
+    copyin(task.get_user_token(), &mut set, mask)?;
+    trace!("sys_signalfd4: fd = {}, mask = {:#x}, flags = {:#x}", fd, set, flags);
+    if fd != -1 {
+        let signalfd = task.get_file(fd as u32)?.as_signalfd().map_err(|_| Error::EINVAL)?;
+        signalfd.set_mask(set);
+        return Ok(fd as isize);
+    }
+    let fd_limit = task.get_max_fd();
+    let fd = task.get_fd_table().add_file(SignalFd::new(set, flags), fd_limit)?;
+    Ok(fd as isize)
+}
@@ -0,0 +1,410 @@
+//! 类似strace的系统调用追踪
+//! 通过向/proc/<pid>/strace写入"on"/"off"/"tid <tid>"开关对某个进程(或其中某个线程)的追踪,
+//! 追踪结果保存在每个进程的环形缓冲区中, 读/proc/<pid>/strace取出。
+//! 打开strace feature时追踪所有进程。
+use alloc::{
+    collections::{BTreeMap, BTreeSet, VecDeque},
+    string::String,
+    vec::Vec,
+};
+use core::fmt::Write;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use spin::{lazy::Lazy, Mutex};
+
+use super::*;
+use crate::board::CLOCK_FREQ;
+use crate::config::{STRACE_BUFFER_SIZE, STRACE_MAX_STR_LEN};
+use crate::fs::FileOpenMode;
+use crate::memory::translate_str;
+use crate::proc::{get_current_task, CloneFlags};
+use crate::syscall::memory::MmapFlag;
+use crate::syscall::fs::AT_FDCWD;
+use crate::timer::get_time;
+use crate::utils::Error;
+
+/// 参数的解码方式
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum ArgType {
+    Int,
+    Uint,
+    Hex,
+    Oct,
+    Fd,
+    DirFd,
+    Path,
+    OpenFlags,
+    CloneFlags,
+    MmapProt,
+    MmapFlags,
+    Signal,
+}
+
+fn arg_types(id: usize) -> &'static [ArgType] {
+    use ArgType::*;
+    match id {
+        SYSCALL_GETCWD          => &[Hex, Uint],
+        SYSCALL_EVENTFD2        => &[Uint, OpenFlags],
+        SYSCALL_EPOLL_CREATE1   => &[OpenFlags],
+        SYSCALL_EPOLL_CTL       => &[Fd, Int, Fd, Hex],
+        SYSCALL_EPOLL_PWAIT     => &[Fd, Hex, Int, Int, Hex, Uint],
+        SYSCALL_INOTIFY_INIT1   => &[OpenFlags],
+        SYSCALL_INOTIFY_ADD_WATCH => &[Fd, Path, Hex],
+        SYSCALL_INOTIFY_RM_WATCH => &[Fd, Int],
+        SYSCALL_VMSPLICE        => &[Fd, Hex, Uint, Hex],
+        SYSCALL_SPLICE          => &[Fd, Hex, Fd, Hex, Uint, Hex],
+        SYSCALL_TEE             => &[Fd, Fd, Uint, Hex],
+        SYSCALL_DUP             => &[Fd],
+        SYSCALL_DUP3            => &[Fd, Fd, OpenFlags],
+        SYSCALL_FCNTL           => &[Fd, Int, Hex],
+        SYSCALL_FLOCK           => &[Fd, Int],
+        SYSCALL_IOCTL           => &[Fd, Hex, Hex],
+        SYSCALL_MKNODAT         => &[DirFd, Path, Oct, Hex],
+        SYSCALL_MKDIRAT         => &[DirFd, Path, Oct],
+        SYSCALL_SYMLINKAT       => &[Path, DirFd, Path],
+        SYSCALL_FCHMOD          => &[Fd, Oct],
+        SYSCALL_FCHMODAT        => &[DirFd, Path, Oct, Hex],
+        SYSCALL_FCHOWN          => &[Fd, Int, Int],
+        SYSCALL_FCHOWNAT        => &[DirFd, Path, Int, Int, Hex],
+        SYSCALL_UMASK           => &[Oct],
+        SYSCALL_UNLINKAT        => &[DirFd, Path, Hex],
+        SYSCALL_LINKAT          => &[DirFd, Path, DirFd, Path, Hex],
+        SYSCALL_UMOUNT          => &[Path, Hex],
+        SYSCALL_MOUNT           => &[Path, Path, Path, Hex, Path],
+        SYSCALL_STATFS          => &[Path, Hex],
+        SYSCALL_FACCESSAT
+        | SYSCALL_FACCESSAT2    => &[DirFd, Path, Oct, Hex],
+        SYSCALL_CHDIR           => &[Path],
+        SYSCALL_OPENAT          => &[DirFd, Path, OpenFlags, Oct],
+        SYSCALL_CLOSE           => &[Fd],
+        SYSCALL_PIPE2           => &[Hex, OpenFlags],
+        SYSCALL_GETDENTS        => &[Fd, Hex, Uint],
+        SYSCALL_LSEEK           => &[Fd, Int, Int],
+        SYSCALL_READ
+        | SYSCALL_WRITE         => &[Fd, Hex, Uint],
+        SYSCALL_READV
+        | SYSCALL_WRITEV        => &[Fd, Hex, Uint],
+        SYSCALL_PREAD
+        | SYSCALL_PWRITE        => &[Fd, Hex, Uint, Int],
+        SYSCALL_SENDFILE        => &[Fd, Fd, Hex, Uint],
+        SYSCALL_PSELECT6        => &[Int, Hex, Hex, Hex, Hex, Hex],
+        SYSCALL_PPOLL           => &[Hex, Uint, Hex, Hex, Uint],
+        SYSCALL_SIGNALFD4       => &[Int, Hex, Uint, OpenFlags],
+        SYSCALL_READLINKAT      => &[DirFd, Path, Hex, Uint],
+        SYSCALL_NEWFSTATAT      => &[DirFd, Path, Hex, Hex],
+        SYSCALL_FSTAT           => &[Fd, Hex],
+        SYSCALL_FSYNC
+        | SYSCALL_FDATASYNC
+        | SYSCALL_SYNCFS        => &[Fd],
+        SYSCALL_TIMERFD_CREATE  => &[Int, OpenFlags],
+        SYSCALL_TIMERFD_SETTIME => &[Fd, Hex, Hex, Hex],
+        SYSCALL_TIMERFD_GETTIME => &[Fd, Hex],
+        SYSCALL_UTIMENSAT       => &[DirFd, Path, Hex, Hex],
+        SYSCALL_EXIT
+        | SYSCALL_EXIT_GROUP    => &[Int],
+        SYSCALL_SET_TID_ADDRESS => &[Hex],
+        SYSCALL_FUTEX           => &[Hex, Int, Int, Hex, Hex, Int],
+        SYSCALL_NANOSLEEP       => &[Hex, Hex],
+        SYSCALL_CLOCKGETTIME    => &[Int, Hex],
+        SYSCALL_KILL
+        | SYSCALL_TKILL         => &[Int, Signal],
+        SYSCALL_TGKILL          => &[Int, Int, Signal],
+        SYSCALL_SIGACTION       => &[Signal, Hex, Hex],
+        SYSCALL_SIGPROCMASK     => &[Int, Hex, Hex],
+        SYSCALL_GETPGID         => &[Int],
+        SYSCALL_SETUID
+        | SYSCALL_SETGID        => &[Int],
+        SYSCALL_SETRESUID
+        | SYSCALL_SETRESGID     => &[Int, Int, Int],
+        SYSCALL_GETRESUID
+        | SYSCALL_GETRESGID     => &[Hex, Hex, Hex],
+        SYSCALL_GETGROUPS
+        | SYSCALL_SETGROUPS     => &[Int, Hex],
+        SYSCALL_SOCKET          => &[Int, Int, Int],
+        SYSCALL_SOCKETPAIR      => &[Int, Int, Int, Hex],
+        SYSCALL_BIND
+        | SYSCALL_CONNECT
+        | SYSCALL_ACCEPT
+        | SYSCALL_GETSOCKNAME
+        | SYSCALL_GETPEERNAME   => &[Fd, Hex, Uint],
+        SYSCALL_LISTEN          => &[Fd, Int],
+        SYSCALL_SENDTO
+        | SYSCALL_RECVFROM      => &[Fd, Hex, Uint, Hex, Hex, Uint],
+        SYSCALL_SETSOCKOPT      => &[Fd, Int, Int, Hex, Uint],
+        SYSCALL_BRK             => &[Hex],
+        SYSCALL_MUNMAP          => &[Hex, Uint],
+        SYSCALL_MREMAP          => &[Hex, Uint, Uint, Hex, Hex],
+        SYSCALL_CLONE           => &[CloneFlags, Hex, Hex, Hex, Hex],
+        SYSCALL_EXECVE          => &[Path, Hex, Hex],
+        SYSCALL_MMAP            => &[Hex, Uint, MmapProt, MmapFlags, Fd, Hex],
+        SYSCALL_MPROTECT        => &[Hex, Uint, MmapProt],
+        SYSCALL_MSYNC           => &[Hex, Uint, Hex],
+        SYSCALL_MADVISE         => &[Hex, Uint, Int],
+        SYSCALL_WAIT            => &[Int, Hex, Hex],
+        SYSCALL_PRLIMIT         => &[Int, Int, Hex, Hex],
+        SYSCALL_RENAMEAT2       => &[DirFd, Path, DirFd, Path, Hex],
+        SYSCALL_GETRANDOM       => &[Hex, Uint, Hex],
+        _ => &[],
+    }
+}
+
+/* 不会返回的系统调用，在进入时就记录 */
+fn is_noreturn(id: usize) -> bool {
+    id == SYSCALL_EXIT || id == SYSCALL_EXIT_GROUP || id == SYSCALL_SHUTDOWN
+}
+
+struct Strace {
+    /* 追踪整个进程 */
+    all: bool,
+    /* 只追踪进程中的部分线程 */
+    tids: BTreeSet<i32>,
+    buffer: VecDeque<u8>,
+}
+
+impl Strace {
+    fn new() -> Self {
+        Self {
+            all: false,
+            tids: BTreeSet::new(),
+            buffer: VecDeque::new(),
+        }
+    }
+
+    fn is_traced(&self, tid: i32) -> bool {
+        self.all || self.tids.contains(&tid)
+    }
+
+    fn is_active(&self) -> bool {
+        self.all || !self.tids.is_empty()
+    }
+
+    fn push_line(&mut self, line: &str) {
+        let bytes = line.as_bytes();
+        /* 缓冲区满时丢弃最早的整行 */
+        while self.buffer.len() + bytes.len() > STRACE_BUFFER_SIZE && !self.buffer.is_empty() {
+            match self.buffer.iter().position(|c| *c == b'\n') {
+                Some(pos) => { self.buffer.drain(..=pos); }
+                None => self.buffer.clear(),
+            }
+        }
+        self.buffer.extend(bytes.iter().skip(bytes.len().saturating_sub(STRACE_BUFFER_SIZE)));
+    }
+}
+
+/* pid -> 该进程的追踪状态 */
+static STRACE_TABLE: Lazy<Mutex<BTreeMap<i32, Strace>>> = Lazy::new(|| Mutex::new(BTreeMap::new()));
+/* 正在被追踪的进程数, 为0时syscall不需要查表 */
+static TRACED_NUM: AtomicUsize = AtomicUsize::new(0);
+
+pub struct TraceRecord {
+    pid: i32,
+    line: String,
+    start: usize,
+}
+
+/* 停止追踪后还没有读完的缓冲区仍然保留在表中, 只统计正在追踪的进程 */
+fn update_traced_num(table: &BTreeMap<i32, Strace>) {
+    TRACED_NUM.store(table.values().filter(|strace| strace.is_active()).count(), Ordering::Relaxed);
+}
+
+fn tracing_enabled() -> bool {
+    cfg!(feature = "strace") || TRACED_NUM.load(Ordering::Relaxed) != 0
+}
+
+fn is_traced(pid: i32, tid: i32) -> bool {
+    let mut table = STRACE_TABLE.lock();
+    if cfg!(feature = "strace") && !table.contains_key(&pid) {
+        let mut strace = Strace::new();
+        strace.all = true;
+        table.insert(pid, strace);
+        update_traced_num(&table);
+    }
+    table.get(&pid).map_or(false, |strace| strace.is_traced(tid))
+}
+
+fn decode_arg(line: &mut String, token: usize, ty: ArgType, arg: usize) {
+    let _ = match ty {
+        ArgType::Int     => write!(line, "{}", arg as isize),
+        ArgType::Uint    => write!(line, "{}", arg),
+        ArgType::Hex     => write!(line, "{:#x}", arg),
+        ArgType::Oct     => write!(line, "{:#o}", arg),
+        ArgType::Fd      => write!(line, "{}", arg as i32),
+        ArgType::DirFd   => {
+            if arg as i32 == AT_FDCWD {
+                write!(line, "AT_FDCWD")
+            } else {
+                write!(line, "{}", arg as i32)
+            }
+        }
+        ArgType::Path    => {
+            if arg == 0 {
+                write!(line, "NULL")
+            } else {
+                match translate_str(token, arg as *const u8) {
+                    Ok(s) if s.len() > STRACE_MAX_STR_LEN => {
+                        write!(line, "{:?}...", &s[..s.char_indices().nth(STRACE_MAX_STR_LEN).map_or(s.len(), |(i, _)| i)])
+                    }
+                    Ok(s) => write!(line, "{:?}", s),
+                    Err(_) => write!(line, "{:#x}", arg),
+                }
+            }
+        }
+        ArgType::OpenFlags => {
+            let flags = FileOpenMode::from_bits_truncate(arg as u32);
+            let rest = arg as u32 & !FileOpenMode::all().bits();
+            /* O_RDONLY为0, 需要单独处理 */
+            if !flags.intersects(FileOpenMode::WRITE | FileOpenMode::RDWR) {
+                let _ = write!(line, "READ");
+                if !flags.is_empty() {
+                    let _ = write!(line, " | {:?}", flags);
+                }
+            } else {
+                let _ = write!(line, "{:?}", flags);
+            }
+            if rest != 0 {
+                let _ = write!(line, " | {:#o}", rest);
+            }
+            Ok(())
+        }
+        ArgType::CloneFlags => {
+            let flags = CloneFlags::from_bits_truncate(arg as u32);
+            /* 低8位为子进程退出时发送给父进程的信号 */
+            let _ = write!(line, "{:?}", flags);
+            if arg & 0xff != 0 {
+                let _ = write!(line, " | SIG{}", arg & 0xff);
+            }
+            Ok(())
+        }
+        ArgType::MmapProt => {
+            const PROT_NAMES: [(usize, &str); 3] = [(1, "PROT_READ"), (2, "PROT_WRITE"), (4, "PROT_EXEC")];
+            let names: Vec<&str> = PROT_NAMES.iter()
+                .filter(|(bit, _)| arg & bit != 0)
+                .map(|(_, name)| *name)
+                .collect();
+            if names.is_empty() {
+                write!(line, "PROT_NONE")
+            } else {
+                write!(line, "{}", names.join("|"))
+            }
+        }
+        ArgType::MmapFlags => {
+            let flags = MmapFlag::from_bits_truncate(arg as u32);
+            let rest = arg as u32 & !MmapFlag::all().bits();
+            let _ = write!(line, "{:?}", flags);
+            if rest != 0 {
+                let _ = write!(line, " | {:#x}", rest);
+            }
+            Ok(())
+        }
+        ArgType::Signal  => write!(line, "SIG{}", arg),
+    };
+}
+
+/// 在系统调用执行前调用, 如果当前线程被追踪则返回记录
+pub fn trace_enter(id: usize, args: &[usize; 6]) -> Option<TraceRecord> {
+    if !tracing_enabled() {
+        return None;
+    }
+    let task = get_current_task()?;
+    let (pid, tid) = (task.pid, task.tid);
+    if !is_traced(pid, tid) {
+        return None;
+    }
+    let token = task.get_user_token();
+    drop(task);
+
+    let start = get_time();
+    let mut line = String::new();
+    let _ = write!(line, "[{:>5}.{:06}] {} {}(", start / CLOCK_FREQ, (start % CLOCK_FREQ) * 1_000_000 / CLOCK_FREQ, tid, syscall_name(id).to_ascii_lowercase());
+    let types = arg_types(id);
+    if types.is_empty() {
+        /* 没有登记参数类型时显示前三个参数 */
+        let _ = write!(line, "{:#x}, {:#x}, {:#x}", args[0], args[1], args[2]);
+    } else {
+        for (i, ty) in types.iter().enumerate() {
+            if i != 0 {
+                line.push_str(", ");
+            }
+            decode_arg(&mut line, token, *ty, args[i]);
+        }
+    }
+    line.push(')');
+
+    if is_noreturn(id) {
+        line.push_str(" = ?\n");
+        if let Some(strace) = STRACE_TABLE.lock().get_mut(&pid) {
+            strace.push_line(&line);
+        }
+        return None;
+    }
+    Some(TraceRecord { pid, line, start })
+}
+
+/// 在系统调用返回后调用, 记录返回值与耗时
+pub fn trace_exit(record: TraceRecord, ret: &Result<isize, Error>) {
+    let TraceRecord { pid, mut line, start } = record;
+    let _ = match ret {
+        Ok(r) if *r >= 0 && (*r as usize) < 0x10000 => write!(line, " = {}", r),
+        Ok(r) => write!(line, " = {:#x}", r),
+        Err(err) => write!(line, " = -1 {:?}", err),
+    };
+    let usec = (get_time() - start) * 1_000_000 / CLOCK_FREQ;
+    let _ = write!(line, " <{}.{:06}>\n", usec / 1_000_000, usec % 1_000_000);
+    if let Some(strace) = STRACE_TABLE.lock().get_mut(&pid) {
+        strace.push_line(&line);
+    }
+}
+
+/// 处理写入/proc/<pid>/strace的控制命令
+pub fn strace_ctl(pid: i32, cmd: &str) -> Result<(), Error> {
+    let mut table = STRACE_TABLE.lock();
+    let mut words = cmd.split_whitespace();
+    match (words.next(), words.next()) {
+        (Some("on"), None) | (Some("1"), None) => {
+            table.entry(pid).or_insert_with(Strace::new).all = true;
+        }
+        (Some("off"), None) | (Some("0"), None) => {
+            /* 缓冲区保留到被读完或者进程退出 */
+            if let Some(strace) = table.get_mut(&pid) {
+                strace.all = false;
+                strace.tids.clear();
+                if strace.buffer.is_empty() {
+                    table.remove(&pid);
+                }
+            }
+        }
+        (Some("tid"), Some(tid)) => {
+            let tid = tid.parse::<i32>().map_err(|_| Error::EINVAL)?;
+            table.entry(pid).or_insert_with(Strace::new).tids.insert(tid);
+        }
+        (Some("clear"), None) => {
+            if let Some(strace) = table.get_mut(&pid) {
+                strace.buffer.clear();
+            }
+        }
+        _ => return Err(Error::EINVAL),
+    }
+    update_traced_num(&table);
+    Ok(())
+}
+
+/// 从进程的追踪缓冲区中读取(并取走)至多len字节, 已经停止追踪的进程读完后释放缓冲区
+pub fn strace_read(pid: i32, len: usize) -> Vec<u8> {
+    let mut table = STRACE_TABLE.lock();
+    let strace = match table.get_mut(&pid) {
+        Some(strace) => strace,
+        None => return Vec::new(),
+    };
+    let len = len.min(strace.buffer.len());
+    let data = strace.buffer.drain(..len).collect();
+    if !strace.is_active() && strace.buffer.is_empty() {
+        table.remove(&pid);
+    }
+    data
+}
+
+/// 进程退出时释放追踪缓冲区
+pub fn strace_release(pid: i32) {
+    let mut table = STRACE_TABLE.lock();
+    table.remove(&pid);
+    update_traced_num(&table);
+}
@@ -5,6 +5,7 @@ use crate::proc::{get_current_task, get_current_user_token, suspend_current};
 use crate::memory::{copyout,copyin};
 use crate::board::CLOCK_FREQ;
 use crate::utils::Error;
+use crate::fs::timerfd::{TimerFd, TFD_NONBLOCK, TFD_CLOEXEC, TFD_TIMER_ABSTIME, CLOCK_REALTIME, CLOCK_MONOTONIC, CLOCK_BOOTTIME};
 use log::*;
 
 const MSEC_PER_SEC: usize = 1_000;
@@ -53,6 +54,28 @@ impl Timespec {
         self.tv_sec as usize * CLOCK_FREQ 
             + self.tv_nsec as usize * CLOCK_FREQ / NSEC_PER_SEC
     }
+
+    /* 距离截止时间还剩的时间, 已经过去时为0 */
+    pub fn remaining(&self) -> Self {
+        let now = Self::now();
+        let mut sec = self.tv_sec - now.tv_sec;
+        let mut nsec = self.tv_nsec - now.tv_nsec;
+        if nsec < 0 {
+            sec -= 1;
+            nsec += NSEC_PER_SEC as isize;
+        }
+        match sec < 0 {
+            true => Self::ZERO,
+            false => Self { tv_sec: sec, tv_nsec: nsec },
+        }
+    }
+
+    pub fn from_ms(ms: usize) -> Self {
+        Self {
+            tv_sec: (ms / MSEC_PER_SEC) as isize,
+            tv_nsec: ((ms % MSEC_PER_SEC) * (NSEC_PER_SEC / MSEC_PER_SEC)) as isize,
+        }
+    }
  
     pub fn pass(&self) -> bool {
         let now = Self::now();
@@ -66,6 +89,13 @@ impl Timespec {
     }
 }
 
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Itimerspec {
+    pub it_interval: Timespec,
+    pub it_value: Timespec,
+}
+
 #[repr(C)]
 #[derive(Default, Clone, Copy, Debug)]
 pub struct Timeval {
@@ -155,4 +185,51 @@ pub fn sys_clock_gettime(clk_id: i32, tp: *mut Timespec) -> Result<isize, Error>
     let token = get_current_user_token();
     copyout(token, tp, &Timespec::now())?;
     Ok(0)
-}
\ No newline at end of file
+}
+pub fn sys_timerfd_create(clockid: usize, flags: usize) -> Result<isize, Error> {
+    if !matches!(clockid, CLOCK_REALTIME | CLOCK_MONOTONIC | CLOCK_BOOTTIME) {
+        return Err(Error::EINVAL);
+    }
+    if flags & !(TFD_NONBLOCK | TFD_CLOEXEC) != 0 {
+        return Err(Error::EINVAL);
+    }
+    let task = get_current_task().unwrap();
+    let fd_limit = task.get_max_fd();
+    let fd = task.get_fd_table().add_file(TimerFd::new(flags), fd_limit)?;
+    trace!("sys_timerfd_create: clockid = {}, fd = {}", clockid, fd);
+    Ok(fd as isize)
+}
+
+pub fn sys_timerfd_settime(
+    fd: u32,
+    flags: usize,
+    new_value: *const Itimerspec,
+    old_value: *mut Itimerspec,
+) -> Result<isize, Error> {
+    let task = get_current_task().unwrap();
+    let token = task.get_user_token();
+    let timerfd = task.get_file(fd)?.as_timerfd().map_err(|_| Error::EINVAL)?;
+    if flags & !TFD_TIMER_ABSTIME != 0 {
+        return Err(Error::EINVAL);
+    }
+    let mut new = Itimerspec::default();
+    copyin(token, &mut new, new_value)?;
+    let valid = |time: &Timespec| time.tv_sec >= 0 && time.tv_nsec >= 0 && time.tv_nsec < NSEC_PER_SEC as isize;
+    if !valid(&new.it_value) || !valid(&new.it_interval) {
+        return Err(Error::EINVAL);
+    }
+    trace!("sys_timerfd_settime: fd = {}, flags = {}, new = {:?}", fd, flags, new);
+    let old = timerfd.settime(flags, new);
+    if !old_value.is_null() {
+        copyout(token, old_value, &old)?;
+    }
+    Ok(0)
+}
+
+pub fn sys_timerfd_gettime(fd: u32, curr_value: *mut Itimerspec) -> Result<isize, Error> {
+    let task = get_current_task().unwrap();
+    let token = task.get_user_token();
+    let timerfd = task.get_file(fd)?.as_timerfd().map_err(|_| Error::EINVAL)?;
+    copyout(token, curr_value, &timerfd.gettime())?;
+    Ok(0)
+}
@@ -84,11 +84,9 @@ pub fn pagefault_handler(scause: Scause, vaddr: VirtAddr) {
         Trap::Exception(Exception::LoadFault) => false,
         Trap::Exception(Exception::InstructionPageFault) => false,
         Trap::Exception(Exception::InstructionFault) => false,
-        _ => panic!()
+        _ => unreachable!()
     };
     let result = memory_set.page_check(vaddr, is_store); 
-    drop(memory_set);
-    drop(task);
     
     match result {
         Ok(_) => {
@@ -96,24 +94,47 @@ pub fn pagefault_handler(scause: Scause, vaddr: VirtAddr) {
             //trace!("[kernel] handle a pagefault, vaddr = {:x} tid = {} is_store={:?}", vaddr.0, tid, is_store);
         }
         Err(string) => {
-            let task = get_current_task().unwrap();
-            task.get_memory().trace_areas();
-            let trap_cx = task.get_trap_cx();
-            trace!("trap_cx = {:x?}", trap_cx);
-            println!(
-                "[kernel] {:?} in application, bad addr = {:#x}, bad instruction = {:#x}, {}",
+            /* 地址不在任何段中为SEGV_MAPERR, 否则是违反了段的访问权限 */
+            let code = if memory_set.is_mapped(vaddr) {
+                SEGV_ACCERR
+            } else {
+                SEGV_MAPERR
+            };
+            debug!(
+                "[kernel] {:?} in application, tid = {}, bad addr = {:#x}, bad instruction = {:#x}, {}",
                 scause.cause(),
+                tid,
                 vaddr.0,
                 task.get_trap_cx().sepc,
                 string,
             );
-            panic!();
-            drop(task);
-            exit_current(-2);
+            drop(memory_set);
+            task.force_signal(SIGSEGV, SigInfo::new_fault(SIGSEGV, code, vaddr.0));
         }
     };
 }
 
+/* 非法指令与地址未对齐, 分别发送SIGILL与SIGBUS */
+pub fn exception_handler(scause: Scause, stval: usize) {
+    let task = get_current_task().unwrap();
+    let sepc = task.get_trap_cx().sepc;
+    let (signo, code, addr) = match scause.cause() {
+        Trap::Exception(Exception::IllegalInstruction) => (SIGILL, ILL_ILLOPC, sepc),
+        Trap::Exception(Exception::InstructionMisaligned)
+        | Trap::Exception(Exception::LoadMisaligned)
+        | Trap::Exception(Exception::StoreMisaligned) => (SIGBUS, BUS_ADRALN, stval),
+        _ => unreachable!()
+    };
+    debug!(
+        "[kernel] {:?} in application, tid = {}, stval = {:#x}, bad instruction = {:#x}",
+        scause.cause(),
+        task.tid,
+        stval,
+        sepc,
+    );
+    task.force_signal(signo, SigInfo::new_fault(signo, code, addr));
+}
+
 #[no_mangle]
 pub fn trap_handler(){
 
@@ -125,7 +146,6 @@ pub fn trap_handler(){
 
     let stval = stval::read();
     //println!("enter trap handler, scause = {:?}", scause.cause());
-    //task.time_info.lock().update_time_enter_kernel();
     //wake_clock_futex_task();
     unsafe{
         asm!(
@@ -134,6 +154,7 @@ pub fn trap_handler(){
         );
     }
     let task = get_current_task().unwrap();
+    task.time_info.lock().update_time_enter_kernel();
     match scause.cause() {
         Trap::Exception(Exception::UserEnvCall) => {
             let mut cx = get_current_trap_context();
@@ -152,8 +173,17 @@ pub fn trap_handler(){
             pagefault_handler(scause, stval.into());
         }
 
+        Trap::Exception(Exception::IllegalInstruction)
+        | Trap::Exception(Exception::InstructionMisaligned)
+        | Trap::Exception(Exception::LoadMisaligned)
+        | Trap::Exception(Exception::StoreMisaligned) => {
+            exception_handler(scause, stval);
+        }
+
         Trap::Interrupt(Interrupt::SupervisorTimer) => {
             set_next_trigger();
+            calc_load();
+            wake_clock_futex_task();
             suspend_current();
         }
 
@@ -183,6 +213,7 @@ pub fn trap_return() {
     let userret_va = userret as usize - uservec as usize + TRAMPOLINE;
     let trap_cx_ptr = trapframe_bottom(task.private_tid).0;
     let user_satp = task.get_user_token();
+    task.time_info.lock().update_time_exit_kernel();
 
 
     set_user_trap_entry();
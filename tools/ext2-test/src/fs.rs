@@ -0,0 +1,241 @@
+//! 内核fs模块中ext2和块缓存用到的部分
+//! 文件接口只保留ext2实现的方法, 定义与kernel/src/fs/file.rs保持一致
+
+/* 与内核共用的源码保持内核的写法, 不按主机的clippy规则修改 */
+#[allow(clippy::all)]
+#[path = "../../../kernel/src/fs/vfs.rs"]
+pub mod vfs;
+#[allow(clippy::all)]
+#[path = "../../../kernel/src/fs/block_cache.rs"]
+pub mod block_cache;
+#[path = "../../../kernel/src/fs/link.rs"]
+pub mod link;
+#[path = "../../../kernel/src/fs/ext2/mod.rs"]
+pub mod ext2;
+
+use alloc::{string::String, sync::Arc, vec::Vec};
+use core::any::Any;
+use crate::utils::{Error, Path};
+
+pub use vfs::{FSid, Statvfs, VFS};
+pub use block_cache::get_block_cache;
+pub use link::resolve_link;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum FileType {
+    UNKNOWN     = 0,
+    FIFOFile    = 1,
+    CharDevice  = 2,
+    Directory   = 4,
+    BlockDevice = 6,
+    RegularFile = 8,
+    LinkFile    = 10,
+    SocketFile  = 12,
+}
+
+pub enum PollType {
+    READ,
+    WRITE,
+    ERR,
+    HUP,
+}
+
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+pub struct FileIndex(pub FSid, pub Fileid);
+
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+pub struct Fileid(pub usize);
+
+pub trait File: Send + Sync {
+    fn get_index(&self) -> Result<FileIndex, Error> {
+        Err(Error::EINDEX)
+    }
+    fn read(&self, _len: usize) -> Result<Vec<u8>, Error> {
+        unimplemented!();
+    }
+    fn write(&self, _data: Vec<u8>) -> Result<usize, Error> {
+        unimplemented!();
+    }
+    fn readable(&self) -> bool {
+        unimplemented!();
+    }
+    fn writable(&self) -> bool {
+        unimplemented!();
+    }
+    fn seek(&self, _pos: usize, _mode: SeekMode) -> Result<isize, Error> {
+        Ok(0)
+    }
+    fn get_size(&self) -> Result<usize, Error> {
+        unimplemented!();
+    }
+    fn get_name(&self) -> Result<String, Error> {
+        unimplemented!();
+    }
+    fn get_path(&self) -> Result<Path, Error> {
+        Err(Error::ENOENT)
+    }
+    fn get_type(&self) -> Result<FileType, Error> {
+        unimplemented!();
+    }
+    fn fsync(&self) -> Result<(), Error> {
+        Ok(())
+    }
+    fn write_stat(&self, _stat: &FileStat) -> Result<(), Error> {
+        Err(Error::EPERM)
+    }
+    fn read_stat(&self) -> Result<FileStat, Error> {
+        unimplemented!();
+    }
+    fn poll(&self, ptype: PollType) -> Result<bool, Error> {
+        match ptype {
+            PollType::READ | PollType::WRITE => Ok(true),
+            PollType::ERR | PollType::HUP => Ok(false),
+        }
+    }
+    fn copy(&self) -> Arc<dyn File> {
+        unimplemented!();
+    }
+    fn vfs(&self) -> Arc<dyn VFS> {
+        unimplemented!();
+    }
+    fn as_dir<'a>(self: Arc<Self>) -> Result<Arc<dyn DirFile + 'a>, Error> where Self: 'a {
+        Err(Error::EPERM)
+    }
+    fn as_link<'a>(self: Arc<Self>) -> Result<Arc<dyn LinkFile + 'a>, Error> where Self: 'a {
+        Err(Error::EPERM)
+    }
+    fn as_block<'a>(self: Arc<Self>) -> Result<Arc<dyn BlockFile + 'a>, Error> where Self: 'a {
+        Err(Error::EPERM)
+    }
+    fn as_file<'a>(self: Arc<Self>) -> Arc<dyn File + 'a> where Self: 'a {
+        unimplemented!();
+    }
+    fn as_any<'a>(self: Arc<Self>) -> Arc<dyn Any + Send + Sync + 'a> where Self: 'a {
+        unimplemented!();
+    }
+}
+
+pub trait DirFile: File {
+    fn openat(&self, _name: String, _mode: FileOpenMode) -> Result<Arc<dyn File>, Error> {
+        unimplemented!();
+    }
+    fn mknod(&self, _name: String, _perm: FilePerm, _file_type: FileType) -> Result<Arc<dyn File>, Error> {
+        unimplemented!();
+    }
+    fn delete(&self, _name: String) -> Result<(), Error> {
+        unimplemented!();
+    }
+    fn getdent(&self) -> Result<Vec<Dentry>, Error> {
+        unimplemented!();
+    }
+    fn rename(&self, _old: String, _new: String) -> Result<(), Error> {
+        unimplemented!();
+    }
+}
+
+pub trait DeviceFile: File {
+    fn get_id(&self) -> usize {
+        unimplemented!();
+    }
+}
+
+pub trait BlockFile: DeviceFile {
+    fn block_size(&self) -> usize {
+        crate::config::BLOCK_SIZE
+    }
+    fn read_block(&self, _block_id: usize, _buf: &mut [u8]) {
+        unimplemented!();
+    }
+    fn read_blocks(&self, block_id: usize, buf: &mut [u8]) {
+        let block_size = self.block_size();
+        for (i, chunk) in buf.chunks_mut(block_size).enumerate() {
+            self.read_block(block_id + i, chunk);
+        }
+    }
+    fn write_block(&self, _block_id: usize, _buf: &[u8]) {
+        unimplemented!();
+    }
+}
+
+pub trait LinkFile: File {
+    fn read_link(&self) -> Result<Path, Error>;
+    fn write_link(&self, target: &str) -> Result<(), Error>;
+    fn link_name(&self) -> Result<String, Error> {
+        Ok(self.read_link()?.to_string())
+    }
+}
+
+bitflags! {
+    pub struct FileOpenMode: u32 {
+        const READ      = 0;
+        const WRITE     = 0o0000001;
+        const RDWR      = 0o0000002;
+        const SYS       = 0o0000004;
+        const CREATE    = 0o0000100;
+        const EXCL      = 0o0000200;
+        const NOCTTY    = 0o0000400;
+        const TRUNC     = 0o0001000;
+        const APPEND    = 0o0002000;
+        const NONBLOCK  = 0o0004000;
+        const LARGE     = 0o0100000;
+        const DIR       = 0o0200000;
+        const NOFOLLOW  = 0o0400000;
+        const CLOEXEC   = 0o2000000;
+    }
+}
+
+bitflags! {
+    pub struct FilePerm: u32 {
+        const NONE    = 0;
+        const OWNER_R = 0o400;
+        const OWNER_W = 0o200;
+        const OWNER_X = 0o100;
+        const GROUP_R = 0o040;
+        const GROUP_W = 0o020;
+        const GROUP_X = 0o010;
+        const OTHER_R = 0o004;
+        const OTHER_W = 0o002;
+        const OTHER_X = 0o001;
+        const SET_UID = 0o4000;
+        const SET_GID = 0o2000;
+        const STICKY  = 0o1000;
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Default)]
+pub struct FileStat {
+    pub st_dev: u64,
+    pub st_ino: u64,
+    pub st_mode: u32,
+    pub st_nlink: u32,
+    pub st_uid: u32,
+    pub st_gid: u32,
+    pub st_rdev: u64,
+    pub __pad1: u64,
+    pub st_size: u64,
+    pub st_blksize: u32,
+    pub __pad2: u32,
+    pub st_blocks: u64,
+    pub st_atime_sec: i64,
+    pub st_atime_nsec: i64,
+    pub st_mtime_sec: i64,
+    pub st_mtime_nsec: i64,
+    pub st_ctime_sec: i64,
+    pub st_ctime_nsec: i64,
+    pub __pad3: u64,
+}
+
+#[derive(Debug)]
+pub struct Dentry {
+    pub d_ino: usize,
+    pub d_type: FileType,
+    pub d_name: String,
+}
+
+#[derive(Debug)]
+pub enum SeekMode {
+    SET = 0,
+    CUR = 1,
+    END = 2,
+}
@@ -0,0 +1,58 @@
+use std::fs::File as StdFile;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use crate::config::BLOCK_SIZE;
+use crate::fs::{BlockFile, DeviceFile, File};
+use crate::utils::Error;
+
+/* 块缓存以设备号区分设备, 每个打开的镜像使用不同的设备号 */
+static NEXT_DEV_ID: AtomicUsize = AtomicUsize::new(1);
+
+/// 以512字节扇区访问的磁盘镜像
+pub struct ImageFile {
+    file: Mutex<StdFile>,
+    id: usize,
+}
+
+impl ImageFile {
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Arc<Self>> {
+        let file = StdFile::options().read(true).write(true).open(path)?;
+        Ok(Arc::new(Self {
+            file: Mutex::new(file),
+            id: NEXT_DEV_ID.fetch_add(1, Ordering::Relaxed),
+        }))
+    }
+}
+
+impl File for ImageFile {
+    fn as_file<'a>(self: Arc<Self>) -> Arc<dyn File + 'a> where Self: 'a {
+        self
+    }
+
+    fn as_block<'a>(self: Arc<Self>) -> Result<Arc<dyn BlockFile + 'a>, Error> where Self: 'a {
+        Ok(self)
+    }
+}
+
+impl DeviceFile for ImageFile {
+    fn get_id(&self) -> usize {
+        self.id
+    }
+}
+
+impl BlockFile for ImageFile {
+    fn read_block(&self, block_id: usize, buf: &mut [u8]) {
+        let mut file = self.file.lock().unwrap();
+        file.seek(SeekFrom::Start((block_id * BLOCK_SIZE) as u64)).unwrap();
+        file.read_exact(buf).unwrap();
+    }
+
+    fn write_block(&self, block_id: usize, buf: &[u8]) {
+        let mut file = self.file.lock().unwrap();
+        file.seek(SeekFrom::Start((block_id * BLOCK_SIZE) as u64)).unwrap();
+        file.write_all(buf).unwrap();
+    }
+}
@@ -0,0 +1,46 @@
+//! 内核ext2实现的主机测试环境
+//! ext2, 块缓存, vfs, Path和Error直接使用内核的源码, fs中只提供ext2用到的文件接口
+
+#[macro_use]
+extern crate bitflags;
+extern crate alloc;
+
+/* 与内核共用的源码保持内核的写法, 不按主机的clippy规则修改 */
+#[allow(clippy::all)]
+#[path = "../../../kernel/src/config.rs"]
+pub mod config;
+pub mod utils;
+pub mod fs;
+mod image;
+
+pub use image::ImageFile;
+
+pub mod syscall {
+    pub mod time {
+        use std::time::{SystemTime, UNIX_EPOCH};
+
+        #[derive(Debug, Default, Clone, Copy, PartialEq)]
+        pub struct Timespec {
+            pub tv_sec: isize,
+            pub tv_nsec: isize,
+        }
+
+        impl Timespec {
+            pub fn now() -> Self {
+                let time = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+                Self {
+                    tv_sec: time.as_secs() as isize,
+                    tv_nsec: time.subsec_nanos() as isize,
+                }
+            }
+        }
+    }
+}
+
+pub mod timer {
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    pub fn get_time_ms() -> usize {
+        SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as usize
+    }
+}
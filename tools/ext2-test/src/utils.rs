@@ -0,0 +1,10 @@
+/* 与内核共用的源码保持内核的写法, 不按主机的clippy规则修改 */
+#[allow(clippy::all)]
+#[path = "../../../kernel/src/utils/error.rs"]
+mod error;
+#[allow(clippy::all)]
+#[path = "../../../kernel/src/utils/path.rs"]
+mod path;
+
+pub use error::Error;
+pub use path::Path;
@@ -0,0 +1,25 @@
+use std::sync::Arc;
+
+use ext2_test::fs::block_cache::ResizedBlockFile;
+use ext2_test::fs::{get_block_cache, BlockFile};
+use ext2_test::ImageFile;
+
+/* 同一个设备以512字节和4K两种块大小访问, 两边看到的数据必须一致 */
+#[test]
+fn resized_access_is_coherent() {
+    let path = std::env::temp_dir().join(format!("block-cache-test-{}.img", std::process::id()));
+    std::fs::write(&path, vec![0u8; 64 * 1024]).unwrap();
+    let image: Arc<dyn BlockFile> = ImageFile::open(&path).unwrap();
+    let resized: Arc<dyn BlockFile> = ResizedBlockFile::new(image.clone(), 4096);
+
+    /* 512字节的第1块是4K第0块中的[512, 1024) */
+    get_block_cache(1, image.clone()).write().modify_slice(|bytes: &mut [u8]| bytes.fill(0x5a));
+    let seen = get_block_cache(0, resized.clone()).read().read_slice(|bytes: &[u8]| bytes[512..1024].to_vec());
+    assert_eq!(seen, vec![0x5a; 512]);
+
+    get_block_cache(0, resized.clone()).write().modify(600, |byte: &mut u8| *byte = 0xa5);
+    let seen = get_block_cache(1, image.clone()).read().read(600 - 512, |byte: &u8| *byte);
+    assert_eq!(seen, 0xa5);
+
+    let _ = std::fs::remove_file(&path);
+}
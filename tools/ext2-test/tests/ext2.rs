@@ -0,0 +1,243 @@
+use std::path::PathBuf;
+use std::process::Command;
+use std::sync::Arc;
+use std::thread;
+
+use ext2_test::fs::ext2::Ext2FileSystem;
+use ext2_test::fs::{DirFile, File, FileOpenMode, FilePerm, FileType, FSid, VFS};
+use ext2_test::utils::{Error, Path};
+use ext2_test::ImageFile;
+
+/// mke2fs生成的4M镜像, 测试结束后删除
+struct Image {
+    path: PathBuf,
+}
+
+impl Image {
+    fn new(name: &str, block_size: usize) -> Self {
+        let path = std::env::temp_dir().join(format!("ext2-test-{}-{}-{}.img", name, block_size, std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let output = Command::new("mke2fs")
+            .args(["-q", "-F", "-t", "ext2", "-b", &block_size.to_string(), "-N", "512"])
+            .arg(&path)
+            .arg("4M")
+            .output()
+            .expect("mke2fs not found");
+        assert!(output.status.success(), "mke2fs: {}", String::from_utf8_lossy(&output.stderr));
+        Self { path }
+    }
+
+    fn mount(&self) -> Result<Arc<Ext2FileSystem>, Error> {
+        let image = ImageFile::open(&self.path).unwrap();
+        Ext2FileSystem::init(image, FSid::new(), Path::from("/"))
+    }
+
+    /* 文件系统卸载(sync)后由e2fsck检查, 不允许有任何错误 */
+    fn fsck(&self) {
+        let output = Command::new("e2fsck").arg("-fn").arg(&self.path).output().expect("e2fsck not found");
+        assert!(output.status.success(), "e2fsck:\n{}{}",
+            String::from_utf8_lossy(&output.stdout), String::from_utf8_lossy(&output.stderr));
+    }
+
+    /* 用debugfs读出文件内容, 与内核的实现无关 */
+    fn cat(&self, path: &str) -> Vec<u8> {
+        let output = Command::new("debugfs")
+            .arg("-R").arg(format!("cat {}", path))
+            .arg(&self.path)
+            .output()
+            .expect("debugfs not found");
+        assert!(output.status.success());
+        output.stdout
+    }
+
+    fn patch(&self, offset: usize, bytes: &[u8]) {
+        let mut data = std::fs::read(&self.path).unwrap();
+        data[offset..offset + bytes.len()].copy_from_slice(bytes);
+        std::fs::write(&self.path, data).unwrap();
+    }
+}
+
+impl Drop for Image {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+fn open_dir(fs: &Arc<Ext2FileSystem>, path: &str) -> Arc<dyn DirFile> {
+    let mut dir = fs.root_dir(FileOpenMode::SYS).unwrap();
+    for name in Path::from(path).iter() {
+        dir = dir.openat(name.clone(), FileOpenMode::SYS).unwrap().as_dir().unwrap();
+    }
+    dir
+}
+
+fn create(dir: &Arc<dyn DirFile>, name: &str, file_type: FileType) -> Arc<dyn File> {
+    dir.mknod(name.to_string(), FilePerm::from_bits_truncate(0o644), file_type).unwrap()
+}
+
+fn names(dir: &Arc<dyn DirFile>) -> Vec<String> {
+    let mut names: Vec<String> = dir.getdent().unwrap().into_iter().map(|dentry| dentry.d_name).collect();
+    names.sort();
+    names
+}
+
+fn pattern(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i * 7 + i / 251) as u8).collect()
+}
+
+fn unmount(fs: Arc<Ext2FileSystem>) {
+    fs.sync().unwrap();
+    drop(fs);
+}
+
+#[test]
+fn write_and_read_back() {
+    for block_size in [1024, 4096] {
+        let image = Image::new("rw", block_size);
+        let fs = image.mount().unwrap();
+        /* 1K块时超过12 + 256块, 用到二级间接块 */
+        let data = pattern(300 * 1024 + 123);
+        let dir = create(&fs.root_dir(FileOpenMode::SYS).unwrap(), "dir", FileType::Directory)
+            .as_dir()
+            .unwrap();
+        let file = create(&dir, "file", FileType::RegularFile);
+        assert_eq!(file.write(data.clone()).unwrap(), data.len());
+
+        let file = dir.openat("file".to_string(), FileOpenMode::RDWR).unwrap();
+        assert_eq!(file.get_size().unwrap(), data.len());
+        assert_eq!(file.read(data.len() + 100).unwrap(), data);
+        unmount(fs);
+
+        image.fsck();
+        assert_eq!(image.cat("/dir/file"), data);
+        let fs = image.mount().unwrap();
+        let file = open_dir(&fs, "/dir").openat("file".to_string(), FileOpenMode::RDWR).unwrap();
+        assert_eq!(file.read(data.len()).unwrap(), data);
+    }
+}
+
+#[test]
+fn rename_link_and_delete() {
+    let image = Image::new("rename", 1024);
+    let fs = image.mount().unwrap();
+    let root = fs.root_dir(FileOpenMode::SYS).unwrap();
+    let a = create(&root, "a", FileType::RegularFile);
+    a.write(b"hello".to_vec()).unwrap();
+    create(&root, "other", FileType::RegularFile).write(b"other".to_vec()).unwrap();
+
+    fs.link(Path::from("/b"), a.clone()).unwrap();
+    assert_eq!(a.read_stat().unwrap().st_nlink, 2);
+    root.rename("a".to_string(), "c".to_string()).unwrap();
+    /* 覆盖已有的文件, other的inode被释放 */
+    root.rename("c".to_string(), "other".to_string()).unwrap();
+    root.delete("b".to_string()).unwrap();
+    assert_eq!(a.read_stat().unwrap().st_nlink, 1);
+    assert_eq!(root.openat("a".to_string(), FileOpenMode::SYS).err(), Some(Error::ENOENT));
+
+    let sub = create(&root, "sub", FileType::Directory).as_dir().unwrap();
+    create(&sub, "inner", FileType::RegularFile);
+    assert_eq!(root.delete("sub".to_string()), Err(Error::ENOTEMPTY));
+    sub.delete("inner".to_string()).unwrap();
+    root.delete("sub".to_string()).unwrap();
+    assert_eq!(names(&root), ["lost+found", "other"]);
+    drop((a, sub, root));
+    unmount(fs);
+
+    image.fsck();
+    assert_eq!(image.cat("/other"), b"hello");
+}
+
+#[test]
+fn large_directory() {
+    let image = Image::new("dir", 1024);
+    let fs = image.mount().unwrap();
+    let dir = create(&fs.root_dir(FileOpenMode::SYS).unwrap(), "many", FileType::Directory)
+        .as_dir()
+        .unwrap();
+    /* 目录项超过一个块 */
+    for i in 0..300 {
+        create(&dir, &format!("file-with-a-long-name-{:04}", i), FileType::RegularFile);
+    }
+    for i in (0..300).step_by(2) {
+        dir.delete(format!("file-with-a-long-name-{:04}", i)).unwrap();
+    }
+    assert_eq!(names(&dir).len(), 150);
+    assert_eq!(dir.mknod("file-with-a-long-name-0001".to_string(), FilePerm::empty(), FileType::RegularFile).err(),
+        Some(Error::EEXIST));
+    drop(dir);
+    unmount(fs);
+    image.fsck();
+}
+
+#[test]
+fn concurrent_mknod() {
+    let image = Image::new("concurrent", 1024);
+    let fs = image.mount().unwrap();
+    let dir = create(&fs.root_dir(FileOpenMode::SYS).unwrap(), "shared", FileType::Directory);
+    drop(dir);
+    let threads: Vec<_> = (0..4)
+        .map(|t| {
+            let fs = fs.clone();
+            thread::spawn(move || {
+                let dir = open_dir(&fs, "/shared");
+                for i in 0..50 {
+                    create(&dir, &format!("t{}-{}", t, i), FileType::RegularFile);
+                    /* 所有线程都尝试创建同一个文件, 只有一个能成功 */
+                    let _ = dir.mknod(format!("same-{}", i), FilePerm::empty(), FileType::RegularFile);
+                }
+            })
+        })
+        .collect();
+    for thread in threads {
+        thread.join().unwrap();
+    }
+    assert_eq!(names(&open_dir(&fs, "/shared")).len(), 4 * 50 + 50);
+    unmount(fs);
+    image.fsck();
+}
+
+#[test]
+fn truncate_frees_blocks() {
+    let image = Image::new("trunc", 1024);
+    let fs = image.mount().unwrap();
+    let root = fs.root_dir(FileOpenMode::SYS).unwrap();
+    let free = fs.statvfs().unwrap().bfree;
+    create(&root, "big", FileType::RegularFile).write(pattern(100 * 1024)).unwrap();
+    assert!(fs.statvfs().unwrap().bfree < free);
+
+    let file = root.openat("big".to_string(), FileOpenMode::RDWR | FileOpenMode::TRUNC).unwrap();
+    assert_eq!(file.get_size().unwrap(), 0);
+    assert_eq!(fs.statvfs().unwrap().bfree, free);
+    drop((file, root));
+    unmount(fs);
+    image.fsck();
+}
+
+#[test]
+fn symlink_target_is_verbatim() {
+    let image = Image::new("symlink", 1024);
+    let fs = image.mount().unwrap();
+    let dir = create(&fs.root_dir(FileOpenMode::SYS).unwrap(), "dir", FileType::Directory)
+        .as_dir()
+        .unwrap();
+    let long = format!("../{}", "x".repeat(100));
+    for (name, target) in [("short", "../target"), ("long", long.as_str())] {
+        let link = create(&dir, name, FileType::LinkFile).as_link().unwrap();
+        link.write_link(target).unwrap();
+        assert_eq!(link.link_name().unwrap(), target);
+        assert_eq!(link.read_link().unwrap(), Path::from(&target[2..]));
+    }
+    drop(dir);
+    unmount(fs);
+    image.fsck();
+}
+
+#[test]
+fn rejects_bad_superblock() {
+    const SB: usize = 1024;
+    for (offset, value) in [(SB + 24, 30u32), (SB + 32, 0), (SB + 40, 0), (SB + 40, u32::MAX)] {
+        let image = Image::new("badsb", 1024);
+        image.patch(offset, &value.to_le_bytes());
+        assert_eq!(image.mount().err(), Some(Error::EINVAL), "offset {}, value {}", offset, value);
+    }
+}
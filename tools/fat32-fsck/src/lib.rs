@@ -0,0 +1,95 @@
+//! 内核FAT32一致性检查的主机版本, 用于检查和修复磁盘镜像
+//! 检查逻辑和FAT32的磁盘格式直接使用内核的源码, 这里只提供它们用到的Error, Timespec和config
+
+extern crate alloc;
+
+/* 与内核共用的源码保持内核的写法, 不按主机的clippy规则修改 */
+#[allow(clippy::all)]
+#[path = "../../../kernel/src/config.rs"]
+pub mod config;
+#[allow(clippy::all)]
+#[path = "../../../kernel/src/fs/fat32/bpb.rs"]
+pub mod bpb;
+#[allow(clippy::all)]
+#[path = "../../../kernel/src/fs/fat32/disk_dirent.rs"]
+pub mod disk_dirent;
+#[path = "../../../kernel/src/fs/fat32/fsck.rs"]
+pub mod fsck;
+#[allow(clippy::all)]
+#[path = "../../../kernel/src/utils/error.rs"]
+mod error;
+
+pub mod utils {
+    pub use super::error::Error;
+}
+
+pub mod syscall {
+    pub mod time {
+        #[derive(Debug, Default, Clone, Copy, PartialEq)]
+        pub struct Timespec {
+            pub tv_sec: isize,
+            pub tv_nsec: isize,
+        }
+    }
+}
+
+use std::cell::RefCell;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+pub use bpb::Geometry;
+pub use fsck::{fsck, FsckDisk, FsckReport};
+
+/// 磁盘镜像文件
+pub struct ImageFile {
+    file: RefCell<File>,
+    sector_size: usize,
+}
+
+impl ImageFile {
+    pub fn open(path: impl AsRef<Path>, writable: bool) -> io::Result<(Self, Geometry)> {
+        let mut file = File::options().read(true).write(writable).open(path)?;
+        let mut boot = vec![0u8; 512];
+        file.read_exact(&mut boot)?;
+        let geometry = Geometry::from_boot_sector(&boot)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "not a valid FAT32 image"))?;
+        let image = Self {
+            file: RefCell::new(file),
+            sector_size: geometry.sector_size,
+        };
+        Ok((image, geometry))
+    }
+}
+
+impl FsckDisk for ImageFile {
+    fn read_sector(&self, sector: usize, buf: &mut [u8]) {
+        let mut file = self.file.borrow_mut();
+        file.seek(SeekFrom::Start((sector * self.sector_size) as u64)).unwrap();
+        file.read_exact(buf).unwrap();
+    }
+
+    fn write_sector(&self, sector: usize, buf: &[u8]) {
+        let mut file = self.file.borrow_mut();
+        file.seek(SeekFrom::Start((sector * self.sector_size) as u64)).unwrap();
+        file.write_all(buf).unwrap();
+    }
+}
+
+/// 内存中的磁盘, 用于测试
+pub struct MemDisk {
+    pub data: RefCell<Vec<u8>>,
+    pub sector_size: usize,
+}
+
+impl FsckDisk for MemDisk {
+    fn read_sector(&self, sector: usize, buf: &mut [u8]) {
+        let start = sector * self.sector_size;
+        buf.copy_from_slice(&self.data.borrow()[start..start + self.sector_size]);
+    }
+
+    fn write_sector(&self, sector: usize, buf: &[u8]) {
+        let start = sector * self.sector_size;
+        self.data.borrow_mut()[start..start + self.sector_size].copy_from_slice(buf);
+    }
+}
@@ -0,0 +1,35 @@
+use std::process::exit;
+
+use fat32_fsck::{fsck, ImageFile};
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let repair = args.iter().any(|arg| arg == "-r");
+    let image = match args.iter().find(|arg| !arg.starts_with('-')) {
+        Some(image) => image,
+        None => {
+            eprintln!("usage: fat32-fsck [-r] <image>");
+            exit(2);
+        }
+    };
+
+    let (disk, geometry) = match ImageFile::open(image, repair) {
+        Ok(pair) => pair,
+        Err(err) => {
+            eprintln!("{}: {}", image, err);
+            exit(2);
+        }
+    };
+    let report = fsck(&disk, &geometry, repair);
+    for problem in report.problems.iter() {
+        println!("{}", problem);
+    }
+    match (report.is_clean(), repair) {
+        (true, _) => println!("{}: clean", image),
+        (false, true) => println!("{}: {} problems repaired", image, report.problems.len()),
+        (false, false) => {
+            println!("{}: {} problems found, run with -r to repair", image, report.problems.len());
+            exit(1);
+        }
+    }
+}
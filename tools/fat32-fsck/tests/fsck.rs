@@ -0,0 +1,358 @@
+use std::cell::RefCell;
+
+use fat32_fsck::bpb::{cluster_type, ClusterType};
+use fat32_fsck::disk_dirent::DiskDirEntry;
+use fat32_fsck::{fsck, FsckReport, Geometry, MemDisk};
+
+const SECTOR: usize = 512;
+const RESERVED: usize = 32;
+const FAT_NUM: usize = 2;
+const SECTORS_PER_FAT: usize = 32;
+const TOTAL: usize = 4096;
+const DATA_START: usize = RESERVED + FAT_NUM * SECTORS_PER_FAT;
+const CLUSTER_COUNT: u32 = (TOTAL - DATA_START) as u32;
+const ROOT: u32 = 2;
+const END: u32 = 0x0FFF_FFFF;
+
+/// 在内存中构造一个每簇一个sector的FAT32镜像
+struct Image {
+    disk: MemDisk,
+    geometry: Geometry,
+    next_free: u32,
+}
+
+impl Image {
+    fn new() -> Self {
+        let mut data = vec![0u8; TOTAL * SECTOR];
+        let boot = &mut data[..SECTOR];
+        boot[0..3].copy_from_slice(&[0xEB, 0x58, 0x90]);
+        boot[3..11].copy_from_slice(b"MSWIN4.1");
+        put_u16(boot, 0x0B, SECTOR as u16);
+        boot[0x0D] = 1;
+        put_u16(boot, 0x0E, RESERVED as u16);
+        boot[0x10] = FAT_NUM as u8;
+        boot[0x15] = 0xF8;
+        put_u32(boot, 0x20, TOTAL as u32);
+        put_u32(boot, 0x24, SECTORS_PER_FAT as u32);
+        put_u32(boot, 0x2C, ROOT);
+        put_u16(boot, 0x30, 1);
+        put_u16(boot, 0x1FE, 0xAA55);
+
+        let geometry = Geometry::from_boot_sector(&data[..SECTOR]).unwrap();
+        let mut image = Self {
+            disk: MemDisk { data: RefCell::new(data), sector_size: SECTOR },
+            geometry,
+            next_free: ROOT,
+        };
+        image.set_fat(0, 0x0FFF_FFF8);
+        image.set_fat(1, END);
+        assert_eq!(image.alloc_chain(1), vec![ROOT]);
+        image
+    }
+
+    fn set_fat(&mut self, cluster: u32, value: u32) {
+        for fat in 0..FAT_NUM {
+            self.set_fat_copy(fat, cluster, value);
+        }
+    }
+
+    fn set_fat_copy(&mut self, fat: usize, cluster: u32, value: u32) {
+        let pos = (RESERVED + fat * SECTORS_PER_FAT) * SECTOR + cluster as usize * 4;
+        put_u32(&mut self.disk.data.borrow_mut(), pos, value);
+    }
+
+    fn get_fat(&self, cluster: u32) -> u32 {
+        get_u32(&self.disk.data.borrow(), RESERVED * SECTOR + cluster as usize * 4)
+    }
+
+    fn alloc_chain(&mut self, len: usize) -> Vec<u32> {
+        let chain: Vec<u32> = (self.next_free..self.next_free + len as u32).collect();
+        for (i, &cluster) in chain.iter().enumerate() {
+            let next = chain.get(i + 1).copied().unwrap_or(END);
+            self.set_fat(cluster, next);
+        }
+        self.next_free += len as u32;
+        self.write_fsinfo(CLUSTER_COUNT + 2 - self.next_free);
+        chain
+    }
+
+    fn write_fsinfo(&mut self, free_count: u32) {
+        let mut data = self.disk.data.borrow_mut();
+        let info = &mut data[SECTOR..2 * SECTOR];
+        put_u32(info, 0, 0x41615252);
+        put_u32(info, 0x1E4, 0x61417272);
+        put_u32(info, 0x1E8, free_count);
+        put_u32(info, 0x1EC, 2);
+        put_u32(info, 0x1FC, 0xAA550000);
+    }
+
+    fn cluster_offset(cluster: u32) -> usize {
+        (DATA_START + (cluster - 2) as usize) * SECTOR
+    }
+
+    /// 在目录的第一个簇中找到空闲位置写入目录项, 返回目录项在镜像中的偏移
+    fn push_entry(&mut self, dir: u32, entry: [u8; 32]) -> usize {
+        let mut data = self.disk.data.borrow_mut();
+        let start = Self::cluster_offset(dir);
+        let pos = (start..start + SECTOR)
+            .step_by(32)
+            .find(|&pos| data[pos] == 0)
+            .expect("directory is full");
+        data[pos..pos + 32].copy_from_slice(&entry);
+        pos
+    }
+
+    fn add_file(&mut self, dir: u32, name: &[u8; 11], size: usize) -> (usize, Vec<u32>) {
+        let chain = match size {
+            0 => Vec::new(),
+            _ => self.alloc_chain(size.div_ceil(SECTOR)),
+        };
+        let pos = self.push_entry(dir, short_entry(name, 0x20, chain.first().copied().unwrap_or(0), size));
+        (pos, chain)
+    }
+
+    fn add_dir(&mut self, dir: u32, name: &[u8; 11]) -> u32 {
+        let cluster = self.alloc_chain(1)[0];
+        self.push_entry(dir, short_entry(name, 0x10, cluster, 0));
+        self.push_entry(cluster, short_entry(b".          ", 0x10, cluster, 0));
+        self.push_entry(cluster, short_entry(b"..         ", 0x10, if dir == ROOT { 0 } else { dir }, 0));
+        cluster
+    }
+
+    /// 与内核相同的方式写入长文件名
+    fn add_long_file(&mut self, dir: u32, long_name: &str, short: &[u8; 11]) -> Vec<usize> {
+        let units: Vec<u16> = long_name.encode_utf16().collect();
+        let parts: Vec<&[u16]> = units.chunks(13).collect();
+        let mut short_entry = DiskDirEntry::new();
+        short_entry.set_name(&short[..8], &short[8..]);
+        let checksum = short_entry.check_sum();
+        let mut positions = Vec::new();
+        for (i, part) in parts.iter().enumerate().rev() {
+            let mut entry = [0u8; 32];
+            entry[0] = (i + 1) as u8 | if i + 1 == parts.len() { 0x40 } else { 0 };
+            entry[11] = 0x0F;
+            entry[13] = checksum;
+            let mut chars = [0xFFFFu16; 13];
+            chars[..part.len()].copy_from_slice(part);
+            if part.len() < 13 {
+                chars[part.len()] = 0;
+            }
+            let offsets = [1, 3, 5, 7, 9, 14, 16, 18, 20, 22, 24, 28, 30];
+            for (ch, off) in chars.iter().zip(offsets) {
+                put_u16(&mut entry, off, *ch);
+            }
+            positions.push(self.push_entry(dir, entry));
+        }
+        let (pos, _) = self.add_file(dir, short, 100);
+        positions.push(pos);
+        positions
+    }
+
+    fn check(&self, repair: bool) -> FsckReport {
+        fsck(&self.disk, &self.geometry, repair)
+    }
+
+    /// 修复后再次检查应该没有问题
+    fn repair_and_verify(&self) -> FsckReport {
+        let report = self.check(true);
+        assert!(!report.is_clean());
+        assert!(report.repaired);
+        let again = self.check(false);
+        assert!(again.is_clean(), "problems left after repair: {:?}", again.problems);
+        report
+    }
+
+    fn byte(&self, pos: usize) -> u8 {
+        self.disk.data.borrow()[pos]
+    }
+}
+
+fn short_entry(name: &[u8; 11], attr: u8, cluster: u32, size: usize) -> [u8; 32] {
+    let mut entry = [0u8; 32];
+    entry[..11].copy_from_slice(name);
+    entry[11] = attr;
+    put_u16(&mut entry, 0x14, (cluster >> 16) as u16);
+    put_u16(&mut entry, 0x1A, cluster as u16);
+    put_u32(&mut entry, 0x1C, size as u32);
+    entry
+}
+
+fn put_u16(buf: &mut [u8], pos: usize, value: u16) {
+    buf[pos..pos + 2].copy_from_slice(&value.to_le_bytes());
+}
+
+fn put_u32(buf: &mut [u8], pos: usize, value: u32) {
+    buf[pos..pos + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+fn get_u32(buf: &[u8], pos: usize) -> u32 {
+    u32::from_le_bytes(buf[pos..pos + 4].try_into().unwrap())
+}
+
+fn sample_tree() -> Image {
+    let mut image = Image::new();
+    image.add_file(ROOT, b"HELLO   TXT", 1300);
+    image.add_file(ROOT, b"EMPTY      ", 0);
+    let sub = image.add_dir(ROOT, b"SUB        ");
+    image.add_file(sub, b"DATA    BIN", 512);
+    image.add_long_file(sub, "a rather long file name.txt", b"~0000001   ");
+    image
+}
+
+#[test]
+fn clean_image() {
+    let image = sample_tree();
+    let report = image.check(true);
+    assert!(report.is_clean(), "{:?}", report.problems);
+    assert!(!report.repaired);
+}
+
+#[test]
+fn rejects_invalid_boot_sector() {
+    let image = Image::new();
+    let mut boot = image.disk.data.borrow()[..SECTOR].to_vec();
+    boot[0x1FE] = 0;
+    assert!(Geometry::from_boot_sector(&boot).is_none());
+}
+
+#[test]
+fn lost_clusters_are_freed() {
+    let mut image = sample_tree();
+    let lost = image.alloc_chain(3);
+    let report = image.repair_and_verify();
+    assert_eq!(report.lost_clusters, 3);
+    for cluster in lost {
+        assert_eq!(image.get_fat(cluster), 0);
+    }
+}
+
+#[test]
+fn check_only_does_not_write() {
+    let mut image = sample_tree();
+    image.alloc_chain(2);
+    let before = image.disk.data.borrow().clone();
+    let report = image.check(false);
+    assert!(!report.is_clean());
+    assert!(!report.repaired);
+    assert!(*image.disk.data.borrow() == before);
+}
+
+#[test]
+fn cross_linked_file_is_removed() {
+    let mut image = sample_tree();
+    let (_, chain) = image.add_file(ROOT, b"FIRST      ", 1024);
+    let pos = image.push_entry(ROOT, short_entry(b"SECOND     ", 0x20, chain[0], 1024));
+    image.repair_and_verify();
+    assert_eq!(image.byte(pos), 0xE5);
+    assert_eq!(image.get_fat(chain[0]), chain[1]);
+}
+
+#[test]
+fn cross_linked_chain_is_truncated() {
+    let mut image = sample_tree();
+    let (_, first) = image.add_file(ROOT, b"FIRST      ", 1024);
+    let (pos, second) = image.add_file(ROOT, b"SECOND     ", 1024);
+    /* SECOND的第二个簇指向FIRST的第二个簇 */
+    image.set_fat(second[0], first[1]);
+    let report = image.repair_and_verify();
+    assert!(report.problems.iter().any(|p| p.contains("cross-linked")));
+    assert_eq!(cluster_type(image.get_fat(second[0])), ClusterType::End);
+    assert_eq!(image.get_fat(second[1]), 0);
+    /* 文件长度被修正为簇链的长度 */
+    assert_eq!(get_u32(&image.disk.data.borrow(), pos + 0x1C), SECTOR as u32);
+}
+
+#[test]
+fn chain_loop_is_broken() {
+    let mut image = sample_tree();
+    let (_, chain) = image.add_file(ROOT, b"LOOP       ", 1536);
+    image.set_fat(chain[2], chain[0]);
+    image.repair_and_verify();
+    assert_eq!(cluster_type(image.get_fat(chain[2])), ClusterType::End);
+}
+
+#[test]
+fn chain_pointing_to_free_cluster() {
+    let mut image = sample_tree();
+    let (_, chain) = image.add_file(ROOT, b"BROKEN     ", 1024);
+    image.set_fat(chain[1], 0);
+    image.repair_and_verify();
+    assert_eq!(cluster_type(image.get_fat(chain[1])), ClusterType::End);
+}
+
+#[test]
+fn long_chain_is_truncated() {
+    let mut image = sample_tree();
+    let (pos, chain) = image.add_file(ROOT, b"SHORT      ", 2048);
+    /* 长度改为100字节, 只保留第一个簇 */
+    put_u32(&mut image.disk.data.borrow_mut(), pos + 0x1C, 100);
+    let report = image.repair_and_verify();
+    assert_eq!(report.lost_clusters, 3);
+    assert_eq!(cluster_type(image.get_fat(chain[0])), ClusterType::End);
+    assert_eq!(image.get_fat(chain[1]), 0);
+}
+
+#[test]
+fn lfn_with_bad_checksum_is_removed() {
+    let mut image = sample_tree();
+    let positions = image.add_long_file(ROOT, "another long name for a file", b"~0000099   ");
+    let short = *positions.last().unwrap();
+    image.disk.data.borrow_mut()[short] = b'X';
+    image.repair_and_verify();
+    for &pos in &positions[..positions.len() - 1] {
+        assert_eq!(image.byte(pos), 0xE5);
+    }
+    assert_eq!(image.byte(short), b'X');
+}
+
+#[test]
+fn lfn_out_of_sequence_is_removed() {
+    let mut image = sample_tree();
+    let positions = image.add_long_file(ROOT, "another long name for a file", b"~0000099   ");
+    /* 第二个长目录项的序号错误 */
+    image.disk.data.borrow_mut()[positions[1]] = 5;
+    image.repair_and_verify();
+    assert_eq!(image.byte(positions[0]), 0xE5);
+    assert_eq!(image.byte(positions[1]), 0xE5);
+}
+
+#[test]
+fn orphan_lfn_is_removed() {
+    let mut image = sample_tree();
+    let positions = image.add_long_file(ROOT, "deleted file", b"~0000099   ");
+    /* 与内核的delete相同, 只删除短目录项 */
+    image.disk.data.borrow_mut()[positions[1]] = 0xE5;
+    image.repair_and_verify();
+    assert_eq!(image.byte(positions[0]), 0xE5);
+}
+
+#[test]
+fn fat_copies_are_synced() {
+    let mut image = sample_tree();
+    image.set_fat_copy(1, 100, 0x1234);
+    let report = image.repair_and_verify();
+    assert!(report.problems.iter().any(|p| p.contains("FAT copy 1")));
+    let data = image.disk.data.borrow();
+    let fat = |n: usize| {
+        let start = (RESERVED + n * SECTORS_PER_FAT) * SECTOR;
+        data[start..start + SECTORS_PER_FAT * SECTOR].to_vec()
+    };
+    assert!(fat(0) == fat(1));
+}
+
+#[test]
+fn fsinfo_free_count_is_fixed() {
+    let mut image = sample_tree();
+    image.write_fsinfo(7);
+    image.repair_and_verify();
+    let free = CLUSTER_COUNT + 2 - image.next_free;
+    assert_eq!(get_u32(&image.disk.data.borrow(), SECTOR + 0x1E8), free);
+}
+
+#[test]
+fn directory_with_bad_cluster_is_removed() {
+    let mut image = sample_tree();
+    let pos = image.push_entry(ROOT, short_entry(b"BADDIR     ", 0x10, CLUSTER_COUNT + 10, 0));
+    image.repair_and_verify();
+    assert_eq!(image.byte(pos), 0xE5);
+}